@@ -0,0 +1,31 @@
+//! The library surface of rlox, re-exporting the `rlox_*` crates which make up the
+//! implementation so that embedders depend on a single crate.
+//!
+//! Running a script is a matter of [`Lox`]; the modules give access to each phase on its own,
+//! e.g. to inspect the syntax tree or to compile to bytecode.
+//!
+//! ```
+//! use rlox::{Lox, Value};
+//!
+//! let mut lox = Lox::new();
+//! lox.run("fun twice(n) { return n * 2; }").unwrap();
+//! assert_eq!(Value::Number(4.0), lox.eval("twice(2)").unwrap());
+//! ```
+
+pub use rlox_interpreter::{Lox, Value};
+pub use rlox_syntax::{LoxError, SourceOrigin};
+
+/// Tokens, syntax trees and diagnostics shared by every phase.
+pub use rlox_syntax as syntax;
+
+/// Scanner, parser and optimizer producing syntax trees from source.
+pub use rlox_parser as parser;
+
+/// Resolver and tree-walking interpreter.
+pub use rlox_interpreter as interpreter;
+
+/// Bytecode compiler for the virtual machine.
+pub use rlox_compiler as compiler;
+
+/// Virtual machine running compiled bytecode.
+pub use rlox_vm as vm;
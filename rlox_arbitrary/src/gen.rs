@@ -0,0 +1,300 @@
+use crate::Rng;
+use rlox_syntax::*;
+use std::sync::{Arc, RwLock};
+
+/// Bounds on the size of generated programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenConfig {
+    /// Nesting of expressions, a literal or a variable being of depth 1.
+    pub max_expr_depth: usize,
+    /// Nesting of blocks, including function bodies and the branches of `if` and `while`.
+    pub max_block_depth: usize,
+    /// Statements of a block or of the whole program.
+    pub max_statements: usize,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        Self {
+            max_expr_depth: 4,
+            max_block_depth: 3,
+            max_statements: 6,
+        }
+    }
+}
+
+/// Types which can be generated at random, in the context of a [`Gen`].
+pub trait Arbitrary: Sized {
+    fn arbitrary(gen: &mut Gen) -> Self;
+}
+
+/// A declared name, with the number of parameters of functions.
+struct Name {
+    name: String,
+    arity: Option<usize>,
+}
+
+/// Generator of syntax trees.
+pub struct Gen {
+    rng: Rng,
+    config: GenConfig,
+    expr_depth: usize,
+    block_depth: usize,
+    // Names visible from the current scope, by scope
+    scopes: Vec<Vec<Name>>,
+    // Number of functions the current statement is in
+    function_depth: usize,
+    // For fresh names
+    declared: usize,
+}
+
+impl Gen {
+    pub fn new(seed: u64, config: GenConfig) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            config,
+            expr_depth: 0,
+            block_depth: 0,
+            scopes: vec![Vec::new()],
+            function_depth: 0,
+            declared: 0,
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Top level statements of a program, of which there is at least one.
+    pub fn program(&mut self) -> Vec<Statement> {
+        let count = 1 + self.rng.below(self.config.max_statements);
+        (0..count).map(|_| Statement::arbitrary(self)).collect()
+    }
+
+    fn declare(&mut self, prefix: &str, arity: Option<usize>) -> String {
+        let name = format!("{prefix}{}", self.declared);
+        self.declared += 1;
+        self.scopes.last_mut().unwrap().push(Name {
+            name: name.clone(),
+            arity,
+        });
+        name
+    }
+
+    /// A visible name, a variable rather than a function if `variable`.
+    fn visible(&mut self, variable: bool) -> Option<(String, Option<usize>)> {
+        let names: Vec<_> = self
+            .scopes
+            .iter()
+            .flatten()
+            .filter(|n| !variable || n.arity.is_none())
+            .collect();
+        if names.is_empty() {
+            return None;
+        }
+        let name = names[self.rng.below(names.len())];
+        Some((name.name.clone(), name.arity))
+    }
+
+    fn literal(&mut self) -> Expr {
+        let literal = match self.rng.below(4) {
+            // Quarters, which print back to the same number
+            0 => Literal::Number(self.rng.below(400) as f64 / 4.0),
+            1 => {
+                let len = self.rng.below(6);
+                let text: String = (0..len)
+                    .map(|_| *self.rng.choose(b"abc xyz") as char)
+                    .collect();
+                Literal::String(text.into())
+            }
+            2 => Literal::Boolean(self.rng.chance(1, 2)),
+            _ => Literal::Nil,
+        };
+        expr::Literal::new_wrapped(Span::default(), literal)
+    }
+
+    /// An expression which binds at least as tightly as `min`, grouped otherwise, so that it
+    /// parses back to the same tree where an operand of that precedence is expected.
+    fn operand(&mut self, min: u8) -> Expr {
+        let e = Expr::arbitrary(self);
+        if precedence(&e) < min {
+            expr::Grouping::new_wrapped(Span::default(), e)
+        } else {
+            e
+        }
+    }
+
+    fn block(&mut self) -> Statement {
+        self.block_depth += 1;
+        self.scopes.push(Vec::new());
+        let count = self.rng.below(self.config.max_statements + 1);
+        let statements = (0..count).map(|_| Statement::arbitrary(self)).collect();
+        self.scopes.pop();
+        self.block_depth -= 1;
+        statement::Block::new_wrapped(Span::default(), statements)
+    }
+}
+
+/// How tightly an expression binds, from comma expressions to primary expressions.
+pub fn precedence(e: &Expr) -> u8 {
+    match e {
+        // The body of a lambda takes in what follows it, as the value of an assignment does
+        Expr::Assign(_) | Expr::Set(_) | Expr::IndexSet(_) | Expr::Lambda(_) => 1,
+        Expr::Logical(e) if e.operator == TokenKind::Or => 2,
+        Expr::Logical(_) => 3,
+        Expr::Binary(e) => binary_precedence(e.operator),
+        Expr::Unary(_) => 8,
+        Expr::Call(_) | Expr::Get(_) | Expr::Index(_) | Expr::Slice(_) => 9,
+        Expr::Grouping(_)
+        | Expr::List(_)
+        | Expr::Literal(_)
+        | Expr::Variable(_)
+        | Expr::This(_)
+        | Expr::Super(_) => 10,
+    }
+}
+
+fn binary_precedence(operator: TokenKind) -> u8 {
+    match operator {
+        TokenKind::EqualEqual | TokenKind::BangEqual => 4,
+        TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => 5,
+        TokenKind::Minus | TokenKind::Plus => 6,
+        TokenKind::Comma => 0,
+        _ => 7,
+    }
+}
+
+const BINARY_OPERATORS: &[TokenKind] = &[
+    TokenKind::Comma,
+    TokenKind::Plus,
+    TokenKind::Minus,
+    TokenKind::Star,
+    TokenKind::Slash,
+    TokenKind::EqualEqual,
+    TokenKind::BangEqual,
+    TokenKind::Greater,
+    TokenKind::GreaterEqual,
+    TokenKind::Less,
+    TokenKind::LessEqual,
+];
+
+impl Arbitrary for Expr {
+    fn arbitrary(gen: &mut Gen) -> Self {
+        let span = Span::default();
+        if gen.expr_depth + 1 >= gen.config.max_expr_depth || gen.rng.chance(1, 4) {
+            return match gen.visible(true) {
+                Some((name, _)) if gen.rng.chance(1, 2) => {
+                    expr::Variable::new_wrapped(span, name, 0, None)
+                }
+                _ => gen.literal(),
+            };
+        }
+
+        gen.expr_depth += 1;
+        let e = match gen.rng.below(7) {
+            0 | 1 => {
+                let operator = *gen.rng.choose(BINARY_OPERATORS);
+                let p = binary_precedence(operator);
+                let left = gen.operand(p);
+                let right = gen.operand(p + 1);
+                expr::Binary::new_wrapped(span, left, operator, right)
+            }
+            2 => {
+                let operator = *gen.rng.choose(&[TokenKind::And, TokenKind::Or]);
+                let p = if operator == TokenKind::Or { 2 } else { 3 };
+                let left = gen.operand(p);
+                let right = gen.operand(p + 1);
+                expr::Logical::new_wrapped(span, left, operator, right)
+            }
+            3 => {
+                let operator = *gen.rng.choose(&[TokenKind::Bang, TokenKind::Minus]);
+                let right = gen.operand(8);
+                expr::Unary::new_wrapped(span, operator, right)
+            }
+            4 => {
+                let e = Expr::arbitrary(gen);
+                expr::Grouping::new_wrapped(span, e)
+            }
+            5 => match gen.visible(true) {
+                Some((name, _)) => {
+                    let value = gen.operand(1);
+                    expr::Assign::new_wrapped(span, name, value, 0, None)
+                }
+                None => gen.literal(),
+            },
+            _ => match gen.visible(false) {
+                Some((name, Some(arity))) => {
+                    let callee = expr::Variable::new_wrapped(span, name, 0, None);
+                    let arguments = (0..arity).map(|_| gen.operand(1)).collect();
+                    expr::Call::new_wrapped(span, callee, arguments)
+                }
+                // Calling something which isn't a function is a runtime error worth covering.
+                _ => {
+                    let callee = gen.operand(9);
+                    expr::Call::new_wrapped(span, callee, vec![])
+                }
+            },
+        };
+        gen.expr_depth -= 1;
+        e
+    }
+}
+
+impl Arbitrary for Statement {
+    fn arbitrary(gen: &mut Gen) -> Self {
+        let span = Span::default();
+        let nested = gen.block_depth < gen.config.max_block_depth;
+        match gen.rng.below(if nested { 9 } else { 4 }) {
+            0 => statement::Expression::new_wrapped(span, Expr::arbitrary(gen)),
+            1 => {
+                let count = 1 + gen.rng.below(3);
+                let values = (0..count).map(|_| gen.operand(1)).collect();
+                statement::Print::new_wrapped(span, values)
+            }
+            2 => {
+                // Declared after the initializer, which can't refer to it
+                let value = gen.rng.chance(3, 4).then(|| gen.operand(1));
+                let name = gen.declare("v", None);
+                statement::VariableDecl::new_wrapped(span, name, value, false)
+            }
+            3 if gen.function_depth > 0 => {
+                let value = gen.rng.chance(3, 4).then(|| Expr::arbitrary(gen));
+                statement::Return::new_wrapped(span, value)
+            }
+            3 => statement::Print::new_wrapped(span, vec![gen.literal()]),
+            4 => gen.block(),
+            5 | 6 => {
+                let condition = Expr::arbitrary(gen);
+                let then_branch = gen.block();
+                let else_branch = gen.rng.chance(1, 2).then(|| gen.block());
+                statement::If::new_wrapped(span, condition, then_branch, else_branch)
+            }
+            7 => {
+                let condition = Expr::arbitrary(gen);
+                let body = gen.block();
+                statement::While::new_wrapped(span, condition, body)
+            }
+            _ => {
+                let arity = gen.rng.below(3);
+                // Declared before the body, which can call it
+                let name = gen.declare("f", Some(arity));
+                gen.function_depth += 1;
+                // Parameters are in a scope of their own, around the one of the body.
+                gen.scopes.push(Vec::new());
+                let params = (0..arity).map(|_| gen.declare("p", None)).collect();
+                let body = gen.block();
+                gen.scopes.pop();
+                gen.function_depth -= 1;
+                statement::Function::new_wrapped(
+                    span,
+                    name,
+                    params,
+                    false,
+                    Arc::new(RwLock::new(body)),
+                    false,
+                    None,
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Random syntax trees for property tests, and their source code.
+//!
+//! [`Gen`] builds programs which parse back to the same tree once [`unparse`]d and which mostly
+//! resolve: variables are read after their declaration, functions are called with the right
+//! number of arguments and `return` only appears in functions. How deep and long they get is
+//! set by a [`GenConfig`]. Generation is deterministic for a given seed, so that a failing case
+//! can be reproduced from its seed alone.
+
+mod gen;
+mod rng;
+mod unparse;
+
+pub use gen::*;
+pub use rng::*;
+pub use unparse::*;
@@ -0,0 +1,180 @@
+use rlox_syntax::*;
+use std::fmt::Write;
+use std::sync::PoisonError;
+
+/// Source code of `statements`, one statement per line. Groupings are printed as they are, so
+/// the trees must have them wherever precedence requires, as parsed trees do.
+pub fn unparse(statements: &[Statement]) -> String {
+    let mut out = String::new();
+    for s in statements {
+        write_statement(&mut out, s, 0);
+    }
+    out
+}
+
+fn write_statement(out: &mut String, stmt: &Statement, depth: usize) {
+    out.push_str(&"    ".repeat(depth));
+    match stmt {
+        Statement::Expression(s) => writeln!(out, "{};", unparse_expr(&s.expr)).unwrap(),
+        Statement::Print(s) => {
+            let values: Vec<_> = s.exprs.iter().map(unparse_expr).collect();
+            writeln!(out, "print {};", values.join(", ")).unwrap()
+        }
+        Statement::VariableDecl(s) => match &s.expr {
+            Some(e) => writeln!(out, "{} {} = {};", s.keyword(), s.name, unparse_expr(e)).unwrap(),
+            None => writeln!(out, "var {};", s.name).unwrap(),
+        },
+        Statement::Block(s) => {
+            out.push_str("{\n");
+            write_block_end(out, &s.statements, depth);
+        }
+        Statement::If(s) => {
+            write!(out, "if ({}) ", unparse_expr(&s.condition)).unwrap();
+            write_body(out, &s.then_branch, depth);
+            if let Some(else_branch) = &s.else_branch {
+                out.push_str(&"    ".repeat(depth));
+                out.push_str("else ");
+                write_body(out, else_branch, depth);
+            }
+        }
+        Statement::While(s) => {
+            write!(out, "while ({}) ", unparse_expr(&s.condition)).unwrap();
+            write_body(out, &s.body, depth);
+        }
+        Statement::Function(s) => {
+            write!(out, "fun {}({}) ", s.name, s.written_params().join(", ")).unwrap();
+            let body = s.body.read().unwrap_or_else(PoisonError::into_inner);
+            write_body(out, &body, depth);
+        }
+        Statement::Return(s) => match &s.value {
+            Some(value) => writeln!(out, "return {};", unparse_expr(value)).unwrap(),
+            None => out.push_str("return;\n"),
+        },
+        Statement::Class(s) => {
+            write!(out, "class {} ", s.name).unwrap();
+            if let Some(superclass) = &s.superclass {
+                write!(out, "< {} ", unparse_expr(superclass)).unwrap();
+            }
+            if s.methods.is_empty() {
+                out.push_str("{}\n");
+                return;
+            }
+            out.push_str("{\n");
+            for method in &s.methods {
+                let Statement::Function(method) = method else {
+                    continue;
+                };
+                out.push_str(&"    ".repeat(depth + 1));
+                if method.is_static {
+                    out.push_str("static ");
+                }
+                write!(
+                    out,
+                    "{}({}) ",
+                    method.name,
+                    method.written_params().join(", ")
+                )
+                .unwrap();
+                let body = method.body.read().unwrap_or_else(PoisonError::into_inner);
+                write_body(out, &body, depth + 1);
+            }
+            out.push_str(&"    ".repeat(depth));
+            out.push_str("}\n");
+        }
+    }
+}
+
+/// Writes a statement following a header on the same line.
+fn write_body(out: &mut String, stmt: &Statement, depth: usize) {
+    match stmt {
+        Statement::Block(s) => {
+            out.push_str("{\n");
+            write_block_end(out, &s.statements, depth);
+        }
+        _ => {
+            out.push('\n');
+            write_statement(out, stmt, depth + 1);
+        }
+    }
+}
+
+fn write_block_end(out: &mut String, statements: &[Statement], depth: usize) {
+    for s in statements {
+        write_statement(out, s, depth + 1);
+    }
+    out.push_str(&"    ".repeat(depth));
+    out.push_str("}\n");
+}
+
+pub fn unparse_expr(e: &Expr) -> String {
+    match e {
+        Expr::Binary(e) => format!(
+            "{} {} {}",
+            unparse_expr(&e.left),
+            operator(e.operator),
+            unparse_expr(&e.right)
+        ),
+        Expr::Logical(e) => format!(
+            "{} {} {}",
+            unparse_expr(&e.left),
+            operator(e.operator),
+            unparse_expr(&e.right)
+        ),
+        Expr::Unary(e) => format!("{}{}", operator(e.operator), unparse_expr(&e.right)),
+        Expr::Grouping(e) => format!("({})", unparse_expr(&e.expr)),
+        Expr::Literal(e) => match &e.literal {
+            Literal::Number(n) => n.to_string(),
+            Literal::String(s) => format!("\"{s}\""),
+            Literal::Boolean(b) => b.to_string(),
+            Literal::Nil => "nil".to_owned(),
+        },
+        Expr::Variable(e) => e.name.clone(),
+        Expr::Assign(e) => format!("{} = {}", e.name, unparse_expr(&e.value)),
+        Expr::Call(e) => {
+            let arguments: Vec<_> = e.arguments.iter().map(unparse_expr).collect();
+            format!("{}({})", unparse_expr(&e.callee), arguments.join(", "))
+        }
+        Expr::Get(e) => format!("{}{}{}", unparse_expr(&e.object), e.dot(), e.name),
+        Expr::Set(e) => format!(
+            "{}.{} = {}",
+            unparse_expr(&e.object),
+            e.name,
+            unparse_expr(&e.value)
+        ),
+        Expr::This(_) => "this".to_owned(),
+        Expr::Super(e) => format!("super.{}", e.method),
+        Expr::List(e) => {
+            let elements: Vec<_> = e.elements.iter().map(unparse_expr).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Expr::Index(e) => format!("{}[{}]", unparse_expr(&e.object), unparse_expr(&e.index)),
+        Expr::Slice(e) => format!(
+            "{}[{}:{}]",
+            unparse_expr(&e.object),
+            e.start.as_ref().map(unparse_expr).unwrap_or_default(),
+            e.end.as_ref().map(unparse_expr).unwrap_or_default()
+        ),
+        Expr::IndexSet(e) => format!(
+            "{}[{}] = {}",
+            unparse_expr(&e.object),
+            unparse_expr(&e.index),
+            unparse_expr(&e.value)
+        ),
+        Expr::Lambda(e) => {
+            let body = e.body.read().unwrap_or_else(PoisonError::into_inner);
+            let Some(value) = expr::Lambda::returned(&body) else {
+                unreachable!("body of lambda must be desugared from an expression");
+            };
+            format!(
+                "fun ({}) -> {}",
+                e.written_params().join(", "),
+                unparse_expr(value)
+            )
+        }
+    }
+}
+
+fn operator(kind: TokenKind) -> &'static str {
+    kind.lexeme()
+        .unwrap_or_else(|| unreachable!("{kind:?} is not an operator"))
+}
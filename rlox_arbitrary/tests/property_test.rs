@@ -0,0 +1,84 @@
+use rlox_arbitrary::{unparse, Gen, GenConfig};
+use rlox_interpreter::{Environment, Interpreter, Limits, Printer, Resolver, Scope};
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{AstPrinter, LoxError, Statement};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+const CASES: u64 = 300;
+
+struct NullPrinter;
+
+impl Printer for NullPrinter {
+    fn print(&mut self, _message: &str) {}
+}
+
+fn parse(source: &str) -> Result<Vec<Statement>, LoxError> {
+    Parser::new(Scanner::new(source).scan_tokens()?).parse()
+}
+
+#[test]
+fn test_parse_unparse_round_trip() {
+    let printer = AstPrinter::new(false);
+    for seed in 0..CASES {
+        let program = Gen::new(seed, GenConfig::default()).program();
+        let source = unparse(&program);
+        let parsed = parse(&source).unwrap_or_else(|e| panic!("seed {seed}: {e}\n{source}"));
+        assert_eq!(
+            printer.print_program(&program),
+            printer.print_program(&parsed),
+            "seed {seed}:\n{source}"
+        );
+    }
+}
+
+#[test]
+fn test_generated_programs_resolve() {
+    let resolved = (0..CASES)
+        .filter(|&seed| {
+            let source = unparse(&Gen::new(seed, GenConfig::default()).program());
+            let mut statements = parse(&source).unwrap();
+            let scope = Scope::new_globals_ptr();
+            statements
+                .iter_mut()
+                .all(|s| Resolver.resolve_statement(&scope, s).is_ok())
+        })
+        .count() as u64;
+    assert_eq!(CASES, resolved);
+}
+
+#[test]
+fn test_interpreter_never_panics() {
+    let config = GenConfig {
+        max_expr_depth: 5,
+        max_block_depth: 4,
+        max_statements: 8,
+    };
+    for seed in 0..CASES {
+        let source = unparse(&Gen::new(seed, config).program());
+        let mut statements = parse(&source).unwrap();
+        let scope = Scope::new_globals_ptr();
+        for s in &mut statements {
+            Resolver.resolve_statement(&scope, s).unwrap();
+        }
+        let run = catch_unwind(AssertUnwindSafe(|| {
+            let mut printer = NullPrinter;
+            let mut input: &[u8] = &[];
+            let mut interpreter = Interpreter::builder()
+                .printer(&mut printer)
+                .input(&mut input)
+                .deterministic(true)
+                .limits(Limits {
+                    max_steps: Some(10_000),
+                    // Deeper recursion can overflow the stack of test threads in debug builds.
+                    max_call_depth: Some(16),
+                })
+                .build();
+            let environment = Environment::new_globals_ptr();
+            // Runtime errors are fine, only panics aren't.
+            let _ = statements
+                .iter()
+                .try_for_each(|s| interpreter.evaluate_stmt(&environment, s));
+        }));
+        assert!(run.is_ok(), "seed {seed} panicked:\n{source}");
+    }
+}
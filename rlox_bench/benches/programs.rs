@@ -0,0 +1,33 @@
+//! Runs every program on every backend, e.g. `cargo bench -p rlox_bench -- fib` for the programs
+//! whose name contains "fib".
+
+use rlox_bench::{Samples, BACKENDS, PROGRAMS};
+use std::time::Duration;
+
+fn main() {
+    // Cargo passes `--bench`
+    let filters: Vec<_> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    for program in &PROGRAMS {
+        if !filters.is_empty() && !filters.iter().any(|f| program.name.contains(f.as_str())) {
+            continue;
+        }
+        let source = program.source(program.size);
+        for backend in BACKENDS {
+            let samples = Samples::measure(Duration::from_secs(2), || {
+                if let Err(e) = backend.run(&source) {
+                    panic!("{}/{}: {}", program.name, backend.name(), e);
+                }
+            });
+            println!(
+                "{:<32} median {:>10.3?}  min {:>10.3?}  ({} runs)",
+                format!("{}/{}", program.name, backend.name()),
+                samples.median(),
+                samples.min(),
+                samples.len()
+            );
+        }
+    }
+}
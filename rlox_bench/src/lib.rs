@@ -0,0 +1,185 @@
+//! Representative Lox programs and the backends to run them with, measured by `cargo bench -p
+//! rlox_bench`.
+
+use rlox_interpreter::{Interpreter, Limits, Lox, Resolver, Scope, WritePrinter};
+use rlox_parser::{optimize, Parser, Scanner};
+use rlox_syntax::LoxError;
+use std::time::{Duration, Instant};
+
+/// A program printing a single result, whose amount of work grows with `size`.
+pub struct Program {
+    pub name: &'static str,
+    /// Size used by the benchmarks.
+    pub size: usize,
+    source: fn(usize) -> String,
+}
+
+impl Program {
+    pub fn source(&self, size: usize) -> String {
+        (self.source)(size)
+    }
+}
+
+pub const PROGRAMS: [Program; 4] = [
+    Program {
+        name: "fib",
+        size: 22,
+        source: |n| {
+            format!(
+                "fun fib(n) {{ if (n < 2) return n; return fib(n - 1) + fib(n - 2); }}
+                print fib({n});"
+            )
+        },
+    },
+    Program {
+        name: "nested_loops",
+        size: 300,
+        source: |n| {
+            format!(
+                "var sum = 0;
+                for (var i = 0; i < {n}; i = i + 1) {{
+                    for (var j = 0; j < {n}; j = j + 1) {{
+                        sum = sum + i * j;
+                    }}
+                }}
+                print sum;"
+            )
+        },
+    },
+    Program {
+        name: "string_building",
+        size: 20000,
+        source: |n| {
+            format!(
+                r#"var s = "";
+                for (var i = 0; i < {n}; i = i + 1) {{
+                    s = s + "x";
+                    if (s == "xxxxxxxxxx") s = "";
+                }}
+                print s;"#
+            )
+        },
+    },
+    Program {
+        name: "closure_churn",
+        size: 20000,
+        source: |n| {
+            format!(
+                "fun adder(n) {{
+                    fun add(x) {{ return x + n; }}
+                    return add;
+                }}
+                var sum = 0;
+                for (var i = 0; i < {n}; i = i + 1) {{
+                    sum = adder(i)(sum) - i + 1;
+                }}
+                print sum;"
+            )
+        },
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The tree-walking interpreter with its default configuration.
+    Tree,
+    /// The tree-walking interpreter in strict mode with limits, which add checks to every step.
+    TreeChecked,
+    /// The bytecode VM.
+    Vm,
+}
+
+pub const BACKENDS: [Backend; 3] = [Backend::Tree, Backend::TreeChecked, Backend::Vm];
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Tree => "tree",
+            Backend::TreeChecked => "tree-checked",
+            Backend::Vm => "vm",
+        }
+    }
+
+    /// Runs `source` on a fresh instance, returning what it printed.
+    pub fn run(self, source: &str) -> Result<String, LoxError> {
+        let mut printer = WritePrinter::new(Vec::new());
+        match self {
+            Backend::Tree => Lox::with_printer(&mut printer).run(source)?,
+            Backend::TreeChecked => {
+                let mut interpreter = Interpreter::builder()
+                    .printer(&mut printer)
+                    .strict(true)
+                    .limits(Limits {
+                        max_steps: Some(u64::MAX),
+                        max_call_depth: Some(1000),
+                    })
+                    .build();
+                let scope = Scope::new_globals_ptr();
+                let tokens = Scanner::new(source).scan_tokens()?;
+                let mut statements = Parser::new(tokens).parse()?;
+                for s in &mut statements {
+                    Resolver.resolve_statement(&scope, s)?;
+                }
+                optimize(&mut statements);
+                let globals = interpreter.globals().clone();
+                for s in &statements {
+                    interpreter.evaluate_stmt(&globals, s)?;
+                }
+            }
+            Backend::Vm => rlox_vm::Vm::new(&mut printer).run(source)?,
+        }
+        Ok(String::from_utf8_lossy(&printer.into_inner()).into_owned())
+    }
+}
+
+/// Durations of the runs of a benchmark.
+pub struct Samples(Vec<Duration>);
+
+impl Samples {
+    /// Runs `f` once to warm up, then repeatedly for about `budget`, at least 3 times.
+    pub fn measure(budget: Duration, mut f: impl FnMut()) -> Self {
+        f();
+        let start = Instant::now();
+        let mut samples = Vec::new();
+        while samples.len() < 3 || start.elapsed() < budget {
+            let run = Instant::now();
+            f();
+            samples.push(run.elapsed());
+        }
+        samples.sort();
+        Self(samples)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn min(&self) -> Duration {
+        self.0[0]
+    }
+
+    pub fn median(&self) -> Duration {
+        self.0[self.0.len() / 2]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends_agree() {
+        for program in &PROGRAMS {
+            let source = program.source(10);
+            let expected = Backend::Tree.run(&source).unwrap();
+            assert!(!expected.is_empty(), "{}", program.name);
+            for backend in BACKENDS {
+                assert_eq!(expected, backend.run(&source).unwrap(), "{}", program.name);
+            }
+        }
+    }
+}
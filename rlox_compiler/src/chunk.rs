@@ -0,0 +1,127 @@
+use rlox_syntax::Span;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// A single instruction. Operands are indices into the constants of the chunk, stack slots
+/// relative to the current call frame, upvalue indices or absolute jump targets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    Constant(usize),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(usize),
+    SetLocal(usize),
+    /// Operand is the constant holding the name of the variable, which the VM replaces with the
+    /// slot of the variable when it loads the script.
+    GetGlobal(usize),
+    DefineGlobal(usize),
+    SetGlobal(usize),
+    GetUpvalue(usize),
+    SetUpvalue(usize),
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// Replaces the top of the stack with its truthiness, which is what `!` evaluates to in the
+    /// tree-walking interpreter.
+    Truthy,
+    Negate,
+    /// Prints the given number of values on top of the stack on one line, the deepest first.
+    Print(usize),
+    Jump(usize),
+    /// Jumps if the top of the stack is falsy, leaving it on the stack.
+    JumpIfFalse(usize),
+    /// Calls the value below the given number of arguments.
+    Call(usize),
+    /// Operand is the constant holding the function to close over.
+    Closure(usize),
+    /// Moves the local on top of the stack to the heap, as a function captured it.
+    CloseUpvalue,
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<FunctionProto>),
+}
+
+/// Instructions with the location of the code each of them came from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Constant>,
+}
+
+impl Chunk {
+    /// Appends `op`, returning its index.
+    pub fn write(&mut self, op: OpCode, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    /// Lists the instructions with their lines and constants, followed by the chunks of the
+    /// functions defined in this one.
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut out = format!("== {name} ==\n");
+        for (index, (op, span)) in self.code.iter().zip(&self.spans).enumerate() {
+            write!(out, "{index:04} {:4} {op:?}", span.line).unwrap();
+            match op {
+                OpCode::Constant(i)
+                | OpCode::GetGlobal(i)
+                | OpCode::DefineGlobal(i)
+                | OpCode::SetGlobal(i) => match &self.constants[*i] {
+                    Constant::Number(n) => write!(out, " ; {n}").unwrap(),
+                    Constant::String(s) => write!(out, " ; {s:?}").unwrap(),
+                    Constant::Function(f) => write!(out, " ; <fn {}>", f.name).unwrap(),
+                },
+                OpCode::Closure(i) => {
+                    if let Constant::Function(f) = &self.constants[*i] {
+                        write!(out, " ; <fn {}>", f.name).unwrap();
+                    }
+                }
+                _ => {}
+            }
+            out.push('\n');
+        }
+        for constant in &self.constants {
+            if let Constant::Function(f) = constant {
+                out.push_str(&f.chunk.disassemble(&f.name));
+            }
+        }
+        out
+    }
+}
+
+/// Where a closure finds a captured variable when it's created: a local of the enclosing
+/// function, or an upvalue the enclosing function captured itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpvalueRef {
+    pub is_local: bool,
+    pub index: usize,
+}
+
+/// A compiled function, which becomes a closure when its declaration is executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub upvalues: Vec<UpvalueRef>,
+}
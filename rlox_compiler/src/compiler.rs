@@ -0,0 +1,527 @@
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode, UpvalueRef};
+use rlox_syntax::{codes, Diagnostic, Expr, Literal, LoxError, Span, Statement, TokenKind};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::RwLock;
+
+/// Name of the function wrapping the top level code of a script.
+pub const SCRIPT_NAME: &str = "<script>";
+
+struct Local {
+    name: String,
+    depth: usize,
+    // Whether a closure captured it, so that it must be moved to the heap when it goes out of
+    // scope.
+    captured: bool,
+}
+
+/// Constants which are stored once per chunk.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    // Bits, so that 0 and -0 stay apart
+    Number(u64),
+    String(Rc<str>),
+}
+
+struct FunctionState {
+    proto: FunctionProto,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    constants: HashMap<ConstantKey, usize>,
+}
+
+impl FunctionState {
+    fn new(name: &str, arity: usize) -> Self {
+        Self {
+            proto: FunctionProto {
+                name: name.to_owned(),
+                arity,
+                chunk: Chunk::default(),
+                upvalues: Vec::new(),
+            },
+            // The first slot holds the function being called.
+            locals: vec![Local {
+                name: String::new(),
+                depth: 0,
+                captured: false,
+            }],
+            scope_depth: 0,
+            constants: HashMap::new(),
+        }
+    }
+}
+
+/// Compiles statements which have been checked by the resolver. Variables declared outside of
+/// any block are globals looked up by name, and the others live on the stack.
+pub struct Compiler {
+    // Functions being compiled, the innermost last
+    functions: Vec<FunctionState>,
+    // Strings of every chunk, so that equal strings share an allocation
+    strings: HashSet<Rc<str>>,
+    // First construct found which the VM can't run
+    unsupported: Option<LoxError>,
+}
+
+impl Compiler {
+    /// Compiles top level statements into a function taking no arguments. Fails on the first
+    /// construct which only the tree-walking interpreter supports.
+    pub fn compile(statements: &[Statement]) -> Result<FunctionProto, LoxError> {
+        let mut compiler = Self {
+            functions: vec![FunctionState::new(SCRIPT_NAME, 0)],
+            strings: HashSet::new(),
+            unsupported: None,
+        };
+        for s in statements {
+            compiler.statement(s);
+        }
+        if let Some(error) = compiler.unsupported {
+            return Err(error);
+        }
+        let end = statements.last().map(Statement::span).unwrap_or_default();
+        compiler.emit(OpCode::Nil, end);
+        compiler.emit(OpCode::Return, end);
+        Ok(compiler.functions.pop().expect("script function").proto)
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression(s) => {
+                self.expression(&s.expr);
+                self.emit(OpCode::Pop, s.span);
+            }
+            Statement::Print(s) => {
+                for expr in &s.exprs {
+                    self.expression(expr);
+                }
+                self.emit(OpCode::Print(s.exprs.len()), s.span);
+            }
+            Statement::VariableDecl(s) => {
+                match &s.expr {
+                    Some(expr) => self.expression(expr),
+                    None => {
+                        self.emit(OpCode::Nil, s.span);
+                    }
+                }
+                self.define_variable(&s.name, s.span);
+            }
+            Statement::Block(s) => {
+                self.begin_scope();
+                for s in &s.statements {
+                    self.statement(s);
+                }
+                self.end_scope(s.span);
+            }
+            Statement::If(s) => {
+                self.expression(&s.condition);
+                let then_jump = self.emit(OpCode::JumpIfFalse(0), s.span);
+                self.emit(OpCode::Pop, s.span);
+                self.statement(&s.then_branch);
+                let else_jump = self.emit(OpCode::Jump(0), s.span);
+                self.patch_jump(then_jump);
+                self.emit(OpCode::Pop, s.span);
+                if let Some(else_branch) = &s.else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump);
+            }
+            Statement::While(s) => {
+                let loop_start = self.chunk().code.len();
+                // Left by the optimizer for loops which run until they return.
+                let forever = matches!(
+                    &s.condition,
+                    Expr::Literal(e) if e.literal == Literal::Boolean(true)
+                );
+                let exit_jump = (!forever).then(|| {
+                    self.expression(&s.condition);
+                    let exit_jump = self.emit(OpCode::JumpIfFalse(0), s.span);
+                    self.emit(OpCode::Pop, s.span);
+                    exit_jump
+                });
+                self.statement(&s.body);
+                self.emit(OpCode::Jump(loop_start), s.span);
+                if let Some(exit_jump) = exit_jump {
+                    self.patch_jump(exit_jump);
+                    self.emit(OpCode::Pop, s.span);
+                }
+            }
+            Statement::Function(s) => {
+                // Declared before the body, which may call the function recursively.
+                let global = self.current().scope_depth == 0;
+                if !global {
+                    self.add_local(&s.name);
+                }
+
+                self.function(&s.name, &s.params, s.variadic, &s.body, s.span);
+                if global {
+                    let name = self.string_constant(&s.name);
+                    self.emit(OpCode::DefineGlobal(name), s.span);
+                }
+            }
+            Statement::Return(s) => {
+                match &s.value {
+                    Some(value) => self.expression(value),
+                    None => {
+                        self.emit(OpCode::Nil, s.span);
+                    }
+                }
+                self.emit(OpCode::Return, s.span);
+            }
+            Statement::Class(s) => self.unsupported(s.span, "Classes"),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            // Only the right operand stays on the stack
+            Expr::Binary(e) if e.operator == TokenKind::Comma => {
+                self.expression(&e.left);
+                self.emit(OpCode::Pop, e.span);
+                self.expression(&e.right);
+            }
+            Expr::Binary(e) => {
+                self.expression(&e.left);
+                self.expression(&e.right);
+                let op = match e.operator {
+                    TokenKind::Plus => OpCode::Add,
+                    TokenKind::Minus => OpCode::Subtract,
+                    TokenKind::Star => OpCode::Multiply,
+                    TokenKind::Slash => OpCode::Divide,
+                    TokenKind::Greater => OpCode::Greater,
+                    TokenKind::GreaterEqual => OpCode::GreaterEqual,
+                    TokenKind::Less => OpCode::Less,
+                    TokenKind::LessEqual => OpCode::LessEqual,
+                    TokenKind::EqualEqual => OpCode::Equal,
+                    TokenKind::BangEqual => OpCode::NotEqual,
+                    other => unreachable!("binary operator {other:?} is never parsed"),
+                };
+                self.emit(op, e.span);
+            }
+            Expr::Grouping(e) => self.expression(&e.expr),
+            Expr::Literal(e) => {
+                let op = match &e.literal {
+                    Literal::Number(n) => OpCode::Constant(self.number_constant(*n)),
+                    Literal::String(s) => OpCode::Constant(self.string_constant(s)),
+                    Literal::Boolean(true) => OpCode::True,
+                    Literal::Boolean(false) => OpCode::False,
+                    Literal::Nil => OpCode::Nil,
+                };
+                self.emit(op, e.span);
+            }
+            Expr::Unary(e) => {
+                self.expression(&e.right);
+                let op = match e.operator {
+                    TokenKind::Minus => OpCode::Negate,
+                    TokenKind::Bang => OpCode::Truthy,
+                    other => unreachable!("unary operator {other:?} is never parsed"),
+                };
+                self.emit(op, e.span);
+            }
+            Expr::Variable(e) => {
+                let op = match self.resolve(&e.name) {
+                    Variable::Local(slot) => OpCode::GetLocal(slot),
+                    Variable::Upvalue(index) => OpCode::GetUpvalue(index),
+                    Variable::Global(name) => OpCode::GetGlobal(name),
+                };
+                self.emit(op, e.span);
+            }
+            Expr::Assign(e) => {
+                self.expression(&e.value);
+                let op = match self.resolve(&e.name) {
+                    Variable::Local(slot) => OpCode::SetLocal(slot),
+                    Variable::Upvalue(index) => OpCode::SetUpvalue(index),
+                    Variable::Global(name) => OpCode::SetGlobal(name),
+                };
+                self.emit(op, e.span);
+            }
+            Expr::Logical(e) => {
+                self.expression(&e.left);
+                if e.operator == TokenKind::Or {
+                    let else_jump = self.emit(OpCode::JumpIfFalse(0), e.span);
+                    let end_jump = self.emit(OpCode::Jump(0), e.span);
+                    self.patch_jump(else_jump);
+                    self.emit(OpCode::Pop, e.span);
+                    self.expression(&e.right);
+                    self.patch_jump(end_jump);
+                } else {
+                    let end_jump = self.emit(OpCode::JumpIfFalse(0), e.span);
+                    self.emit(OpCode::Pop, e.span);
+                    self.expression(&e.right);
+                    self.patch_jump(end_jump);
+                }
+            }
+            Expr::Call(e) => {
+                self.expression(&e.callee);
+                for argument in &e.arguments {
+                    self.expression(argument);
+                }
+                self.emit(OpCode::Call(e.arguments.len()), e.span);
+            }
+            Expr::Get(e) => self.unsupported(e.span, "Properties"),
+            Expr::Set(e) => self.unsupported(e.span, "Properties"),
+            Expr::This(e) => self.unsupported(e.span, "Methods"),
+            Expr::Super(e) => self.unsupported(e.span, "Methods"),
+            Expr::List(e) => self.unsupported(e.span, "Lists"),
+            Expr::Index(e) => self.unsupported(e.span, "Lists"),
+            Expr::IndexSet(e) => self.unsupported(e.span, "Lists"),
+            Expr::Slice(e) => self.unsupported(e.span, "Slices"),
+            Expr::Lambda(e) => self.function("lambda", &e.params, e.variadic, &e.body, e.span),
+        }
+    }
+
+    /// Compiles the parameters and body of a function, leaving a closure of it on the stack.
+    fn function(
+        &mut self,
+        name: &str,
+        params: &[String],
+        variadic: bool,
+        body: &RwLock<Statement>,
+        span: Span,
+    ) {
+        if variadic {
+            self.unsupported(span, "Rest parameters");
+        }
+        self.functions.push(FunctionState::new(name, params.len()));
+        self.begin_scope();
+        for param in params {
+            self.add_local(param);
+        }
+        self.statement(&body.read().unwrap_or_else(|e| e.into_inner()));
+        self.emit(OpCode::Nil, span);
+        self.emit(OpCode::Return, span);
+        let proto = self.functions.pop().expect("function being compiled").proto;
+
+        let constant = self
+            .chunk()
+            .add_constant(Constant::Function(Rc::new(proto)));
+        self.emit(OpCode::Closure(constant), span);
+    }
+
+    /// Records that the VM can't run `what`, e.g. `Classes`, unless something else was found
+    /// before.
+    fn unsupported(&mut self, span: Span, what: &str) {
+        self.unsupported.get_or_insert_with(|| {
+            let diagnostic = Diagnostic::new(span, format!("{what} are not supported by the VM."))
+                .with_code(codes::UNSUPPORTED_BY_VM)
+                .with_note("Run the script with the tree-walking interpreter instead.");
+            LoxError::Compile(Box::new(diagnostic))
+        });
+    }
+
+    /// Binds the value on top of the stack to `name`.
+    fn define_variable(&mut self, name: &str, span: Span) {
+        if self.current().scope_depth == 0 {
+            let name = self.string_constant(name);
+            self.emit(OpCode::DefineGlobal(name), span);
+        } else {
+            // The value stays where it is, as the slot of the local.
+            self.add_local(name);
+        }
+    }
+
+    fn add_local(&mut self, name: &str) {
+        let state = self.current();
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name: name.to_owned(),
+            depth,
+            captured: false,
+        });
+    }
+
+    fn resolve(&mut self, name: &str) -> Variable {
+        let level = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(level, name) {
+            Variable::Local(slot)
+        } else if let Some(index) = self.resolve_upvalue(level, name) {
+            Variable::Upvalue(index)
+        } else {
+            Variable::Global(self.string_constant(name))
+        }
+    }
+
+    fn resolve_local(&self, level: usize, name: &str) -> Option<usize> {
+        self.functions[level]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+    }
+
+    fn resolve_upvalue(&mut self, level: usize, name: &str) -> Option<usize> {
+        if level == 0 {
+            return None;
+        }
+        if let Some(slot) = self.resolve_local(level - 1, name) {
+            self.functions[level - 1].locals[slot].captured = true;
+            return Some(self.add_upvalue(level, slot, true));
+        }
+        let index = self.resolve_upvalue(level - 1, name)?;
+        Some(self.add_upvalue(level, index, false))
+    }
+
+    fn add_upvalue(&mut self, level: usize, index: usize, is_local: bool) -> usize {
+        let upvalues = &mut self.functions[level].proto.upvalues;
+        let upvalue = UpvalueRef { is_local, index };
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return existing;
+        }
+        upvalues.push(upvalue);
+        upvalues.len() - 1
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self.current().locals.pop_if(|local| local.depth > depth) {
+            let op = if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit(op, span);
+        }
+    }
+
+    fn number_constant(&mut self, n: f64) -> usize {
+        self.constant(ConstantKey::Number(n.to_bits()), Constant::Number(n))
+    }
+
+    fn string_constant(&mut self, s: &str) -> usize {
+        let s = match self.strings.get(s) {
+            Some(existing) => existing.clone(),
+            None => {
+                let s: Rc<str> = s.into();
+                self.strings.insert(s.clone());
+                s
+            }
+        };
+        self.constant(ConstantKey::String(s.clone()), Constant::String(s))
+    }
+
+    /// Index of the constant identified by `key`, adding `constant` if the chunk doesn't have it.
+    fn constant(&mut self, key: ConstantKey, constant: Constant) -> usize {
+        if let Some(&index) = self.current().constants.get(&key) {
+            return index;
+        }
+        let index = self.chunk().add_constant(constant);
+        self.current().constants.insert(key, index);
+        index
+    }
+
+    fn emit(&mut self, op: OpCode, span: Span) -> usize {
+        self.chunk().write(op, span)
+    }
+
+    /// Makes the jump at `index` land on the next instruction.
+    fn patch_jump(&mut self, index: usize) {
+        let target = self.chunk().code.len();
+        match &mut self.chunk().code[index] {
+            OpCode::Jump(to) | OpCode::JumpIfFalse(to) => *to = target,
+            op => unreachable!("{op:?} is not a jump"),
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("function being compiled")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().proto.chunk
+    }
+}
+
+enum Variable {
+    Local(usize),
+    Upvalue(usize),
+    Global(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_parser::{Parser, Scanner};
+
+    fn compile(source: &str) -> FunctionProto {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        Compiler::compile(&Parser::new(tokens).parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_locals_and_globals() {
+        let script = compile("var a = 1; { var b = a; print b; }");
+        let expected = [
+            OpCode::Constant(0),
+            OpCode::DefineGlobal(1),
+            OpCode::GetGlobal(1),
+            OpCode::GetLocal(1),
+            OpCode::Print(1),
+            OpCode::Pop,
+            OpCode::Nil,
+            OpCode::Return,
+        ];
+        assert_eq!(expected.to_vec(), script.chunk.code);
+    }
+
+    #[test]
+    fn test_comma() {
+        let script = compile("print (true, nil);");
+        let expected = [
+            OpCode::True,
+            OpCode::Pop,
+            OpCode::Nil,
+            OpCode::Print(1),
+            OpCode::Nil,
+            OpCode::Return,
+        ];
+        assert_eq!(expected.to_vec(), script.chunk.code);
+    }
+
+    #[test]
+    fn test_constant_deduplication() {
+        let script = compile(r#"var a = "a"; print "a" + a; print 1; print 1.0; print -0;"#);
+        let expected = [
+            Constant::String("a".into()),
+            Constant::Number(1.0),
+            Constant::Number(0.0),
+        ];
+        assert_eq!(expected.to_vec(), script.chunk.constants);
+
+        let script = compile(r#"fun f() { return "s"; } print "s";"#);
+        let Constant::Function(f) = &script.chunk.constants[0] else {
+            panic!("f is not compiled");
+        };
+        let (Constant::String(inner), Constant::String(outer)) =
+            (&f.chunk.constants[0], &script.chunk.constants[2])
+        else {
+            panic!("strings are not constants");
+        };
+        assert!(Rc::ptr_eq(inner, outer));
+    }
+
+    #[test]
+    fn test_upvalues() {
+        let script = compile("{ var a = 1; fun f() { fun g() { a = 2; } } }");
+        let Constant::Function(f) = &script.chunk.constants[1] else {
+            panic!("f is not compiled");
+        };
+        let Constant::Function(g) = &f.chunk.constants[0] else {
+            panic!("g is not compiled");
+        };
+        let captured = UpvalueRef {
+            is_local: true,
+            index: 1,
+        };
+        assert_eq!(vec![captured], f.upvalues);
+        let forwarded = UpvalueRef {
+            is_local: false,
+            index: 0,
+        };
+        assert_eq!(vec![forwarded], g.upvalues);
+        // f goes out of scope first, then the captured a.
+        assert_eq!([OpCode::Pop, OpCode::CloseUpvalue], script.chunk.code[2..4]);
+    }
+}
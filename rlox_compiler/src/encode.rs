@@ -0,0 +1,284 @@
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode, UpvalueRef};
+use rlox_syntax::Span;
+use std::rc::Rc;
+
+const MAGIC: &[u8; 4] = b"RLXC";
+/// Incremented whenever the encoding or the instruction set changes.
+const FORMAT_VERSION: u32 = 2;
+
+/// Stable hash of `bytes` (64-bit FNV-1a), e.g. to tell whether an encoded program was compiled
+/// from a given source.
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl FunctionProto {
+    /// Encodes the function, along with a `key` identifying what it was compiled from.
+    pub fn encode(&self, key: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&key.to_le_bytes());
+        encode_function(&mut out, self);
+        out
+    }
+
+    /// Decodes a function encoded by [`FunctionProto::encode`] with the same `key`. Returns None
+    /// if the data is malformed, from another version or for another key.
+    pub fn decode(bytes: &[u8], key: u64) -> Option<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MAGIC || reader.u32()? != FORMAT_VERSION || reader.u64()? != key {
+            return None;
+        }
+        let function = reader.function()?;
+        reader.bytes.is_empty().then_some(function)
+    }
+}
+
+fn encode_function(out: &mut Vec<u8>, function: &FunctionProto) {
+    encode_str(out, &function.name);
+    encode_usize(out, function.arity);
+    encode_usize(out, function.upvalues.len());
+    for upvalue in &function.upvalues {
+        out.push(upvalue.is_local as u8);
+        encode_usize(out, upvalue.index);
+    }
+    encode_chunk(out, &function.chunk);
+}
+
+fn encode_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    encode_usize(out, chunk.code.len());
+    for op in &chunk.code {
+        let (tag, operand) = op_tag(*op);
+        out.push(tag);
+        if let Some(operand) = operand {
+            encode_usize(out, operand);
+        }
+    }
+    for span in &chunk.spans {
+        encode_usize(out, span.line);
+        encode_usize(out, span.column);
+        encode_usize(out, span.len);
+    }
+    encode_usize(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(n) => {
+                out.push(0);
+                out.extend_from_slice(&n.to_bits().to_le_bytes());
+            }
+            Constant::String(s) => {
+                out.push(1);
+                encode_str(out, s);
+            }
+            Constant::Function(f) => {
+                out.push(2);
+                encode_function(out, f);
+            }
+        }
+    }
+}
+
+fn encode_usize(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u64).to_le_bytes());
+}
+
+fn encode_str(out: &mut Vec<u8>, s: &str) {
+    encode_usize(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Tag of `op` in the encoding, with its operand if it has one.
+fn op_tag(op: OpCode) -> (u8, Option<usize>) {
+    match op {
+        OpCode::Constant(i) => (0, Some(i)),
+        OpCode::Nil => (1, None),
+        OpCode::True => (2, None),
+        OpCode::False => (3, None),
+        OpCode::Pop => (4, None),
+        OpCode::GetLocal(i) => (5, Some(i)),
+        OpCode::SetLocal(i) => (6, Some(i)),
+        OpCode::GetGlobal(i) => (7, Some(i)),
+        OpCode::DefineGlobal(i) => (8, Some(i)),
+        OpCode::SetGlobal(i) => (9, Some(i)),
+        OpCode::GetUpvalue(i) => (10, Some(i)),
+        OpCode::SetUpvalue(i) => (11, Some(i)),
+        OpCode::Equal => (12, None),
+        OpCode::NotEqual => (13, None),
+        OpCode::Greater => (14, None),
+        OpCode::GreaterEqual => (15, None),
+        OpCode::Less => (16, None),
+        OpCode::LessEqual => (17, None),
+        OpCode::Add => (18, None),
+        OpCode::Subtract => (19, None),
+        OpCode::Multiply => (20, None),
+        OpCode::Divide => (21, None),
+        OpCode::Truthy => (22, None),
+        OpCode::Negate => (23, None),
+        OpCode::Print(n) => (24, Some(n)),
+        OpCode::Jump(i) => (25, Some(i)),
+        OpCode::JumpIfFalse(i) => (26, Some(i)),
+        OpCode::Call(i) => (27, Some(i)),
+        OpCode::Closure(i) => (28, Some(i)),
+        OpCode::CloseUpvalue => (29, None),
+        OpCode::Return => (30, None),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.u64()?.try_into().ok()
+    }
+
+    /// A length of items which take at least `item_size` bytes each, so that corrupted lengths
+    /// don't make huge allocations.
+    fn len(&mut self, item_size: usize) -> Option<usize> {
+        let len = self.usize()?;
+        (len.checked_mul(item_size)? <= self.bytes.len()).then_some(len)
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let len = self.len(1)?;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+
+    fn function(&mut self) -> Option<FunctionProto> {
+        let name = self.str()?.to_owned();
+        let arity = self.usize()?;
+        let upvalues = (0..self.len(9)?)
+            .map(|_| {
+                let is_local = self.u8()? != 0;
+                let index = self.usize()?;
+                Some(UpvalueRef { is_local, index })
+            })
+            .collect::<Option<_>>()?;
+        let chunk = self.chunk()?;
+        Some(FunctionProto {
+            name,
+            arity,
+            chunk,
+            upvalues,
+        })
+    }
+
+    fn chunk(&mut self) -> Option<Chunk> {
+        let code: Vec<_> = (0..self.len(1)?)
+            .map(|_| self.op())
+            .collect::<Option<_>>()?;
+        let spans = (0..code.len())
+            .map(|_| Some(Span::new(self.usize()?, self.usize()?, self.usize()?)))
+            .collect::<Option<_>>()?;
+        let constants = (0..self.len(1)?)
+            .map(|_| match self.u8()? {
+                0 => Some(Constant::Number(f64::from_bits(self.u64()?))),
+                1 => Some(Constant::String(Rc::from(self.str()?))),
+                2 => Some(Constant::Function(Rc::new(self.function()?))),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+        Some(Chunk {
+            code,
+            spans,
+            constants,
+        })
+    }
+
+    fn op(&mut self) -> Option<OpCode> {
+        let op = match self.u8()? {
+            0 => OpCode::Constant(self.usize()?),
+            1 => OpCode::Nil,
+            2 => OpCode::True,
+            3 => OpCode::False,
+            4 => OpCode::Pop,
+            5 => OpCode::GetLocal(self.usize()?),
+            6 => OpCode::SetLocal(self.usize()?),
+            7 => OpCode::GetGlobal(self.usize()?),
+            8 => OpCode::DefineGlobal(self.usize()?),
+            9 => OpCode::SetGlobal(self.usize()?),
+            10 => OpCode::GetUpvalue(self.usize()?),
+            11 => OpCode::SetUpvalue(self.usize()?),
+            12 => OpCode::Equal,
+            13 => OpCode::NotEqual,
+            14 => OpCode::Greater,
+            15 => OpCode::GreaterEqual,
+            16 => OpCode::Less,
+            17 => OpCode::LessEqual,
+            18 => OpCode::Add,
+            19 => OpCode::Subtract,
+            20 => OpCode::Multiply,
+            21 => OpCode::Divide,
+            22 => OpCode::Truthy,
+            23 => OpCode::Negate,
+            24 => OpCode::Print(self.usize()?),
+            25 => OpCode::Jump(self.usize()?),
+            26 => OpCode::JumpIfFalse(self.usize()?),
+            27 => OpCode::Call(self.usize()?),
+            28 => OpCode::Closure(self.usize()?),
+            29 => OpCode::CloseUpvalue,
+            30 => OpCode::Return,
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+    use rlox_parser::{Parser, Scanner};
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"
+var greeting = "hi";
+fun outer(n) {
+    var count = n;
+    fun inner() { count = count + 1.5; return greeting; }
+    return inner;
+}
+while (false) print outer(1)();
+"#;
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let script = Compiler::compile(&Parser::new(tokens).parse().unwrap()).unwrap();
+        let key = fingerprint(source.as_bytes());
+        let encoded = script.encode(key);
+
+        assert_eq!(Some(&script), FunctionProto::decode(&encoded, key).as_ref());
+        assert_eq!(None, FunctionProto::decode(&encoded, key + 1));
+        assert_eq!(
+            None,
+            FunctionProto::decode(&encoded[..encoded.len() - 1], key)
+        );
+        let mut corrupted = encoded.clone();
+        corrupted[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(None, FunctionProto::decode(&corrupted, key));
+    }
+}
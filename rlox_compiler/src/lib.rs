@@ -0,0 +1,9 @@
+//! Lowers resolved syntax trees to bytecode for the virtual machine of `rlox_vm`.
+
+mod chunk;
+mod compiler;
+mod encode;
+
+pub use chunk::*;
+pub use compiler::*;
+pub use encode::*;
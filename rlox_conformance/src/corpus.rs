@@ -0,0 +1,130 @@
+use crate::run_test;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Directories of the suite which don't hold whole programs, but test the intermediate
+/// interpreters of the book's early chapters, or measure performance.
+const SKIPPED_DIRECTORIES: &[&str] = &["benchmark", "expressions", "scanning"];
+
+/// Chapter of tests directly in the root of the suite.
+pub const ROOT_CHAPTER: &str = "(root)";
+
+/// A script of the suite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub path: PathBuf,
+    /// Directory of the test under the root, e.g. `closure`, which the suite groups tests by.
+    pub chapter: String,
+}
+
+/// Every test under `root`, sorted by path.
+pub fn collect_tests(root: &Path) -> std::io::Result<Vec<TestCase>> {
+    let mut tests = Vec::new();
+    collect(root, None, &mut tests)?;
+    Ok(tests)
+}
+
+fn collect(path: &Path, chapter: Option<&str>, tests: &mut Vec<TestCase>) -> std::io::Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            match chapter {
+                Some(chapter) => collect(&entry, Some(chapter), tests)?,
+                None if entry.is_dir() && SKIPPED_DIRECTORIES.contains(&name.as_ref()) => {}
+                None if entry.is_dir() => collect(&entry, Some(&name), tests)?,
+                None => collect(&entry, Some(ROOT_CHAPTER), tests)?,
+            }
+        }
+    } else if path.extension().is_some_and(|ext| ext == "lox") {
+        tests.push(TestCase {
+            path: path.to_owned(),
+            chapter: chapter.unwrap_or(ROOT_CHAPTER).to_owned(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub passed: usize,
+    pub total: usize,
+}
+
+impl Tally {
+    pub fn percentage(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.passed as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+/// Results of running a suite.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub chapters: BTreeMap<String, Tally>,
+    /// Failed tests with the reasons of failure, in the order they ran.
+    pub failures: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl Report {
+    pub fn total(&self) -> Tally {
+        self.chapters
+            .values()
+            .fold(Tally::default(), |sum, t| Tally {
+                passed: sum.passed + t.passed,
+                total: sum.total + t.total,
+            })
+    }
+}
+
+/// One line per chapter with its passing tests and conformance percentage, then the total.
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .chapters
+            .keys()
+            .map(String::len)
+            .max()
+            .unwrap_or(0)
+            .max(5);
+        let line = |f: &mut Formatter<'_>, name: &str, tally: &Tally| {
+            let count = format!("{}/{}", tally.passed, tally.total);
+            writeln!(
+                f,
+                "{name:<width$}  {count:>9}  {:>5.1}%",
+                tally.percentage()
+            )
+        };
+        for (chapter, tally) in &self.chapters {
+            line(f, chapter, tally)?;
+        }
+        line(f, "total", &self.total())
+    }
+}
+
+/// Runs `tests`, keeping only the ones of `chapters` unless it's empty.
+pub fn run_tests(tests: &[TestCase], chapters: &[String]) -> std::io::Result<Report> {
+    let mut report = Report::default();
+    for test in tests {
+        if !chapters.is_empty() && !chapters.contains(&test.chapter) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&test.path)?;
+        let failures = run_test(&source);
+        let tally = report.chapters.entry(test.chapter.clone()).or_default();
+        tally.total += 1;
+        if failures.is_empty() {
+            tally.passed += 1;
+        } else {
+            report.failures.push((test.path.clone(), failures));
+        }
+    }
+    Ok(report)
+}
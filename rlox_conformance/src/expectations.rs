@@ -0,0 +1,68 @@
+/// What a test expects, from the comments of its script.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Expectations {
+    /// Printed lines, in order.
+    pub output: Vec<String>,
+    /// Scan, parse or resolve errors by line, with their text starting at `Error`, e.g.
+    /// `Error at ';': Expect expression.`
+    pub static_errors: Vec<(usize, String)>,
+    /// Line and message of the runtime error ending the script.
+    pub runtime_error: Option<(usize, String)>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let Some((_, comment)) = line.split_once("//") else {
+                continue;
+            };
+            let comment = comment.trim();
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_owned());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some((line_number, message.to_owned()));
+            } else if let Some(rest) = comment.strip_prefix("[line ") {
+                let reported = rest
+                    .split_once(']')
+                    .and_then(|(number, rest)| Some((number.parse().ok()?, rest.trim_start())));
+                if let Some((number, rest)) = reported {
+                    if rest.starts_with("Error") {
+                        expectations.static_errors.push((number, rest.to_owned()));
+                    }
+                }
+            } else if comment.starts_with("Error") {
+                expectations
+                    .static_errors
+                    .push((line_number, comment.to_owned()));
+            }
+        }
+        expectations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let source = r#"
+print 1; // expect: 1
+var a = ; // Error at ';': Expect expression.
+// [line 7] Error at end: Expect '}' after block.
+// [java line 3] Error: Only in jlox.
+print a; // expect runtime error: Undefined variable 'a'.
+"#;
+        let expected = Expectations {
+            output: vec!["1".to_owned()],
+            static_errors: vec![
+                (3, "Error at ';': Expect expression.".to_owned()),
+                (7, "Error at end: Expect '}' after block.".to_owned()),
+            ],
+            runtime_error: Some((6, "Undefined variable 'a'.".to_owned())),
+        };
+        assert_eq!(expected, Expectations::parse(source));
+    }
+}
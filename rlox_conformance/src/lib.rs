@@ -0,0 +1,21 @@
+//! Harness for the test suite of Crafting Interpreters, whose `test/` directory holds one Lox
+//! script per test with its expectations written as comments:
+//!
+//! ```text
+//! print 1 + 2; // expect: 3
+//! print nil + 1; // expect runtime error: Operands must be numbers.
+//! var a = ; // Error at ';': Expect expression.
+//! // [line 5] Error at end: Expect '}' after block.
+//! ```
+//!
+//! Unlike `rlox test`, values are printed the way the book prints them and error messages must
+//! match word for word, so that the share of passing tests measures how closely the interpreter
+//! follows the book.
+
+mod corpus;
+mod expectations;
+mod runner;
+
+pub use corpus::*;
+pub use expectations::*;
+pub use runner::*;
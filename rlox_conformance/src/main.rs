@@ -0,0 +1,55 @@
+//! Runs the test suite of Crafting Interpreters and reports how much of it passes.
+//!
+//! ```text
+//! rlox_conformance [--chapter=<name>]... [--verbose] <suite>
+//! ```
+//!
+//! `<suite>` is the `test/` directory of the book's repository. Tests are grouped by their
+//! directory, which `--chapter` selects. Failures are listed with `--verbose`.
+
+use rlox_conformance::{collect_tests, run_tests};
+use std::path::Path;
+use std::process::exit;
+
+// As in rlox_repl
+const EX_USAGE: i32 = 64;
+
+fn main() -> std::io::Result<()> {
+    let mut chapters = Vec::new();
+    let mut verbose = false;
+    let mut root = None;
+    for arg in std::env::args().skip(1) {
+        if let Some(chapter) = arg.strip_prefix("--chapter=") {
+            chapters.push(chapter.to_owned());
+        } else if arg == "--verbose" {
+            verbose = true;
+        } else if root.is_none() && !arg.starts_with('-') {
+            root = Some(arg);
+        } else {
+            usage(&format!("Unexpected argument: {arg}"));
+        }
+    }
+    let Some(root) = root else {
+        usage("The test directory must be given");
+    };
+
+    let tests = collect_tests(Path::new(&root))?;
+    let report = run_tests(&tests, &chapters)?;
+    if verbose {
+        for (path, failures) in &report.failures {
+            println!("FAIL {}", path.display());
+            for failure in failures {
+                println!("    {failure}");
+            }
+        }
+        println!();
+    }
+    print!("{report}");
+    Ok(())
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{message}");
+    eprintln!("Usage: rlox_conformance [--chapter=<name>]... [--verbose] <suite>");
+    exit(EX_USAGE);
+}
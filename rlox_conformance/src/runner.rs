@@ -0,0 +1,160 @@
+use crate::Expectations;
+use rlox_interpreter::{Lox, LoxList, Object, Printer, Value};
+use rlox_syntax::LoxError;
+
+/// Prints values the way the book does, e.g. `3` rather than `Number(3.0)`.
+struct BookPrinter {
+    lines: Vec<String>,
+}
+
+impl Printer for BookPrinter {
+    fn print(&mut self, message: &str) {
+        self.lines.push(message.to_owned());
+    }
+
+    fn print_value(&mut self, value: &Value) {
+        self.lines.push(book_format(value));
+    }
+
+    fn print_values(&mut self, values: &[Value]) {
+        let line: Vec<_> = values.iter().map(book_format).collect();
+        self.lines.push(line.join(" "));
+    }
+}
+
+fn book_format(value: &Value) -> String {
+    format_value(value, &mut Vec::new())
+}
+
+/// Formats `value` inside the `lists` being formatted, which a list containing itself refers
+/// back to as `[...]`.
+fn format_value(value: &Value, lists: &mut Vec<Object<LoxList>>) -> String {
+    match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Nil => "nil".to_owned(),
+        Value::FunctionObject(f) => format!("<fn {}>", f.name),
+        Value::BoundMethod(m) => format!("<fn {}>", m.method.name),
+        Value::NativeFunction(_) | Value::HostFunction(_) => "<native fn>".to_owned(),
+        Value::Foreign(foreign) => format!("{foreign:?}"),
+        Value::Class(class) => class.name.clone(),
+        Value::Instance(instance) => format!("{} instance", instance.class.name),
+        Value::List(list) if lists.contains(list) => "[...]".to_owned(),
+        Value::List(list) => {
+            lists.push(list.clone());
+            let elements: Vec<_> = list
+                .to_vec()
+                .iter()
+                .map(|element| format_value(element, lists))
+                .collect();
+            lists.pop();
+            format!("[{}]", elements.join(", "))
+        }
+    }
+}
+
+/// Runs a single test on the tree-walking interpreter, returning the reasons of failure.
+pub fn run_test(source: &str) -> Vec<String> {
+    let expectations = Expectations::parse(source);
+    let mut printer = BookPrinter { lines: Vec::new() };
+    let result = Lox::with_printer(&mut printer).run(source);
+
+    let mut failures = Vec::new();
+    for (index, expected) in expectations.output.iter().enumerate() {
+        match printer.lines.get(index) {
+            Some(actual) if actual == expected => {}
+            Some(actual) => failures.push(format!("Expected output '{expected}', got '{actual}'")),
+            None => failures.push(format!("Missing expected output '{expected}'")),
+        }
+    }
+    for extra in printer.lines.iter().skip(expectations.output.len()) {
+        failures.push(format!("Unexpected output '{extra}'"));
+    }
+
+    match (&result, &expectations.runtime_error) {
+        (Err(e @ LoxError::Runtime(_)), Some((line, message))) => {
+            let actual = &e.diagnostic().message;
+            if e.span().line != *line || actual != message {
+                failures.push(format!(
+                    "Expected runtime error '{message}' on line {line}, got '{actual}' on line {}",
+                    e.span().line
+                ));
+            }
+        }
+        (Err(e), None) if !e.is_static() => {
+            failures.push(format!("Unexpected runtime error '{e}'"));
+        }
+        (Ok(()), Some((line, message))) => {
+            failures.push(format!("Expected runtime error '{message}' on line {line}"));
+        }
+        _ => {}
+    }
+
+    // The parser stops at the first error, so only that one can be compared.
+    let static_error = result.as_ref().err().filter(|e| e.is_static());
+    match (static_error, expectations.static_errors.first()) {
+        (Some(e), Some((line, expected))) => {
+            let actual = &e.diagnostic().message;
+            if e.span().line != *line || actual != error_message(expected) {
+                failures.push(format!(
+                    "Expected '{expected}' on line {line}, got '{actual}' on line {}",
+                    e.span().line
+                ));
+            }
+            for (line, expected) in &expectations.static_errors[1..] {
+                failures.push(format!("Missing '{expected}' on line {line}"));
+            }
+        }
+        (Some(e), None) => failures.push(format!("Unexpected error '{e}'")),
+        (None, Some((line, expected))) => {
+            failures.push(format!("Expected '{expected}' on line {line}"));
+        }
+        (None, None) => {}
+    }
+    failures
+}
+
+/// Message of an expected error, e.g. `Expect expression.` for
+/// `Error at ';': Expect expression.`
+fn error_message(expected: &str) -> &str {
+    let message = match expected.strip_prefix("Error at '") {
+        Some(rest) => rest.split_once("': ").map(|(_, message)| message),
+        None => expected.split_once(": ").map(|(_, message)| message),
+    };
+    message.unwrap_or(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book_output() {
+        let source = r#"
+fun f() {}
+print 1 + 2; // expect: 3
+print 0.5; // expect: 0.5
+print "a" + "b"; // expect: ab
+print f; // expect: <fn f>
+print clock; // expect: <native fn>
+print nil == false; // expect: false
+var l = [1, [2]];
+l[0] = l;
+print l; // expect: [[...], [2]]
+"#;
+        assert!(run_test(source).is_empty(), "{:?}", run_test(source));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(
+            "Expect expression.",
+            error_message("Error at ';': Expect expression.")
+        );
+        assert_eq!("Expect '}'.", error_message("Error at end: Expect '}'."));
+        let failures = run_test("print nil + 1; // expect runtime error: Wrong message.");
+        assert_eq!(1, failures.len());
+        assert!(failures[0].starts_with("Expected runtime error 'Wrong message.' on line 1"));
+    }
+}
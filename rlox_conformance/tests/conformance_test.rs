@@ -0,0 +1,56 @@
+use rlox_conformance::{collect_tests, run_tests, Tally, ROOT_CHAPTER};
+use std::path::Path;
+
+fn corpus() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus"))
+}
+
+#[test]
+fn test_collect_tests() {
+    let tests = collect_tests(corpus()).unwrap();
+    let chapters: Vec<_> = tests.iter().map(|t| t.chapter.as_str()).collect();
+    // Benchmarks are left out.
+    assert_eq!(
+        vec![
+            "closure",
+            "closure",
+            ROOT_CHAPTER,
+            "operator",
+            "operator",
+            "operator",
+            "variable",
+            "variable"
+        ],
+        chapters
+    );
+}
+
+#[test]
+fn test_run_chapters() {
+    let tests = collect_tests(corpus()).unwrap();
+    let report = run_tests(&tests, &["closure".to_owned()]).unwrap();
+    let closure = Tally {
+        passed: 2,
+        total: 2,
+    };
+    assert_eq!(
+        vec![("closure", closure)],
+        report
+            .chapters
+            .iter()
+            .map(|(c, t)| (c.as_str(), *t))
+            .collect::<Vec<_>>()
+    );
+    assert!(report.failures.is_empty());
+    assert_eq!(
+        "closure        2/2  100.0%\ntotal          2/2  100.0%\n",
+        report.to_string()
+    );
+
+    let report = run_tests(&tests, &[]).unwrap();
+    assert_eq!(8, report.total().total);
+    assert_eq!(
+        report.total().total - report.failures.len(),
+        report.total().passed
+    );
+}
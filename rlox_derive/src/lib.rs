@@ -4,3 +4,8 @@ use proc_macro::TokenStream;
 pub fn syntax_node(attr: TokenStream, input: TokenStream) -> TokenStream {
     rlox_derive_impl::syntax_node(attr.into(), input.into()).into()
 }
+
+#[proc_macro_attribute]
+pub fn syntax_enum(attr: TokenStream, input: TokenStream) -> TokenStream {
+    rlox_derive_impl::syntax_enum(attr.into(), input.into()).into()
+}
@@ -1,77 +1,356 @@
 use proc_macro2::TokenStream;
-use quote::{quote, ToTokens};
-use syn::{Field, Fields, ItemStruct, Path};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{
+    Error, Field, Fields, GenericArgument, Ident, ItemEnum, ItemStruct, Path, PathArguments,
+    PathSegment, Token, Type,
+};
 
 // refer to https://github.com/dtolnay/syn/blob/master/examples/heapsize/heapsize_derive/src/lib.rs
 
+/// The item as written followed by `error`, so that the error is the only one reported rather
+/// than ones about the item missing.
+fn with_error(input: TokenStream, error: Error) -> TokenStream {
+    let error = error.to_compile_error();
+    quote! {
+        #input
+        #error
+    }
+}
+
 pub fn syntax_node(attr: TokenStream, input: TokenStream) -> TokenStream {
-    let path = syn::parse2::<Path>(attr).unwrap();
+    expand_syntax_node(attr, input.clone()).unwrap_or_else(|e| with_error(input, e))
+}
+
+/// Arguments of `#[syntax_node(Enum::Variant, id = field)]`.
+struct NodeArgs {
+    path: Path,
+    // Name of the id field, `id` by default
+    id: Ident,
+}
 
-    if path.segments.len() != 2 {
-        panic!("Must be used with a form of 'Enum::Variant'");
+impl Parse for NodeArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input
+            .parse::<Path>()
+            .map_err(|e| Error::new(e.span(), "Expected a form of 'Enum::Variant'"))?;
+        if path.segments.len() != 2 {
+            return Err(Error::new(
+                path.span(),
+                "Must be used with a form of 'Enum::Variant'",
+            ));
+        }
+        let mut id = format_ident!("id");
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let key = input.parse::<Ident>()?;
+            if key != "id" {
+                return Err(Error::new(
+                    key.span(),
+                    "Unknown option, expected 'id = field'",
+                ));
+            }
+            input.parse::<Token![=]>()?;
+            id = input.parse()?;
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Self { path, id })
     }
+}
 
-    let enum_name = &path.segments[0].ident;
+fn expand_syntax_node(attr: TokenStream, input: TokenStream) -> syn::Result<TokenStream> {
+    let attr_span = attr.span();
+    if attr.is_empty() {
+        return Err(Error::new(
+            attr_span,
+            "Must be used with a form of 'Enum::Variant'",
+        ));
+    }
+    let NodeArgs { path, id } = syn::parse2(attr)?;
+    // With its generic arguments, if any
+    let enum_type = &path.segments[0];
+    let visitor = visitor_name(&enum_type.ident);
+    let visit = visit_method(&path.segments[1].ident);
 
-    let item = syn::parse2::<ItemStruct>(input.clone()).unwrap();
+    let item = syn::parse2::<ItemStruct>(input.clone())
+        .map_err(|e| Error::new(e.span(), "#[syntax_node] can only be used on structs"))?;
     let name = &item.ident;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
 
-    let fields = match item.fields {
-        Fields::Named(fields) => fields.named,
-        _ => panic!("SyntaxNode can only be derived for structs with named fields"),
+    let fields = match &item.fields {
+        Fields::Named(fields) => &fields.named,
+        fields => {
+            return Err(Error::new(
+                fields.span(),
+                "SyntaxNode can only be derived for structs with named fields",
+            ))
+        }
     };
 
-    let mut id_field: Option<Field> = None;
-    let mut arg_fields = Vec::new();
+    let (id_fields, arg_fields): (Vec<&Field>, Vec<&Field>) =
+        fields.iter().partition(|f| f.ident.as_ref() == Some(&id));
+    let Some(id_field) = id_fields.first() else {
+        return Err(Error::new(
+            name.span(),
+            format!("SyntaxNode must have a field named '{id}'"),
+        ));
+    };
+    let id_type = &id_field.ty;
 
-    for f in fields {
-        let ident_name = f.ident.to_token_stream().to_string();
-        let ty_name = f.ty.to_token_stream().to_string();
-        if ident_name == "id" && ty_name == "usize" {
-            id_field = Some(f);
-        } else {
-            arg_fields.push(f);
+    // A span is required first, whatever its place among the fields.
+    let (span_fields, arg_fields): (Vec<&Field>, Vec<&Field>) = arg_fields
+        .into_iter()
+        .partition(|f| f.ident.as_ref().is_some_and(|ident| ident == "span"));
+    let span_field = span_fields.first().map(|f| &f.ty);
+    let span_param = span_field.map(|ty| quote! { span: #ty, });
+    let span_arg = span_field.map(|_| quote! { span, });
+    let span_method = span_field.map(|_| {
+        quote! {
+            fn span(&self) -> Span {
+                self.span
+            }
         }
-    }
-
-    if id_field.is_none() {
-        panic!("SyntaxNode must have a field named 'id' of type 'usize'");
-    }
+    });
 
     let params = arg_fields.iter().map(|f| {
-        let ident = f.ident.clone();
-        let ty = f.ty.clone();
+        let ident = &f.ident;
+        let ty = &f.ty;
         quote! {
             #ident: #ty
         }
     });
 
     let args = arg_fields.iter().map(|f| {
-        let ident = f.ident.clone();
+        let ident = &f.ident;
         quote! {
             #ident
         }
     });
 
+    // In the order new_wrapped takes them
+    let builder_fields = span_fields
+        .iter()
+        .chain(&arg_fields)
+        .copied()
+        .collect::<Vec<_>>();
+    let builder =
+        has_optional_field(&arg_fields).then(|| expand_builder(&item, enum_type, &builder_fields));
+    let builder_method = builder.as_ref().map(|_| {
+        let builder_name = builder_name(name);
+        let idents = builder_fields.iter().map(|f| &f.ident);
+        quote! {
+            pub fn builder() -> #builder_name #ty_generics {
+                #builder_name {
+                    #(#idents: ::core::option::Option::None),*
+                }
+            }
+        }
+    });
+
     let output = quote! {
         #input
 
-        impl SyntaxNode for #name {
-            fn id(&self) -> usize {
-                self.id
+        impl #impl_generics SyntaxNode for #name #ty_generics #where_clause {
+            type Id = #id_type;
+
+            fn id(&self) -> Self::Id {
+                self.#id
             }
+
+            #span_method
         }
 
-        impl #name {
-            pub fn new_wrapped(#(#params),*) -> #enum_name {
+        impl #impl_generics #name #ty_generics #where_clause {
+            pub fn new_wrapped(#span_param #(#params),*) -> #enum_type {
                 #path(Ptr::new(Self {
-                    id: Self::generate_id(),
+                    #id: ::core::convert::From::from(Self::generate_id()),
+                    #span_arg
                     #(#args),*
                 }))
             }
+
+            pub fn accept<V: #visitor>(&self, visitor: &mut V) -> V::Output {
+                visitor.#visit(self)
+            }
+
+            #builder_method
+        }
+
+        #builder
+    };
+    Ok(output)
+}
+
+/// Builder of the node `item` with a setter per field of `fields`, which are in the order
+/// `new_wrapped` takes them. Fields of `Option` types may be left unset.
+fn expand_builder(item: &ItemStruct, enum_type: &PathSegment, fields: &[&Field]) -> TokenStream {
+    let name = &item.ident;
+    let builder = builder_name(name);
+    let vis = &item.vis;
+    let generics = &item.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let mut declarations = Vec::new();
+    let mut setters = Vec::new();
+    let mut args = Vec::new();
+    for f in fields {
+        let ident = &f.ident;
+        let ty = &f.ty;
+        if is_option(ty) {
+            declarations.push(quote! { #ident: #ty });
+            setters.push(quote! {
+                pub fn #ident(mut self, #ident: impl ::core::convert::Into<#ty>) -> Self {
+                    self.#ident = #ident.into();
+                    self
+                }
+            });
+            args.push(quote! { self.#ident });
+        } else {
+            let message = format!(
+                "'{}' must be set before building {name}",
+                ident.as_ref().unwrap()
+            );
+            declarations.push(quote! { #ident: ::core::option::Option<#ty> });
+            setters.push(quote! {
+                pub fn #ident(mut self, #ident: #ty) -> Self {
+                    self.#ident = ::core::option::Option::Some(#ident);
+                    self
+                }
+            });
+            args.push(quote! { self.#ident.expect(#message) });
+        }
+    }
+
+    let doc = format!(
+        "Builder of [`{name}`], created by [`{name}::builder`]. Fields other than `Option`s must be set."
+    );
+    quote! {
+        #[doc = #doc]
+        #vis struct #builder #generics #where_clause {
+            #(#declarations),*
+        }
+
+        impl #impl_generics #builder #ty_generics #where_clause {
+            #(#setters)*
+
+            pub fn build_wrapped(self) -> #enum_type {
+                <#name #ty_generics>::new_wrapped(#(#args),*)
+            }
+        }
+    }
+}
+
+/// Generates, for an enum whose variants each wrap a syntax node, e.g. `If(Ptr<statement::If>)`,
+/// a visitor trait named after the enum, e.g. `StatementVisitor`, with a `visit_if` method per
+/// variant, and an `accept` method on the enum dispatching to them.
+pub fn syntax_enum(attr: TokenStream, input: TokenStream) -> TokenStream {
+    expand_syntax_enum(attr, input.clone()).unwrap_or_else(|e| with_error(input, e))
+}
+
+fn expand_syntax_enum(attr: TokenStream, input: TokenStream) -> syn::Result<TokenStream> {
+    if !attr.is_empty() {
+        return Err(Error::new(attr.span(), "#[syntax_enum] takes no arguments"));
+    }
+    let item = syn::parse2::<ItemEnum>(input.clone())
+        .map_err(|e| Error::new(e.span(), "#[syntax_enum] can only be used on enums"))?;
+    let name = &item.ident;
+    let visitor = visitor_name(name);
+
+    let mut methods = Vec::new();
+    let mut arms = Vec::new();
+    for variant in &item.variants {
+        let node = match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                pointee(ty).ok_or_else(|| {
+                    Error::new(ty.span(), "Expected a pointer to a node, e.g. 'Ptr<Node>'")
+                })?
+            }
+            _ => {
+                return Err(Error::new(
+                    variant.span(),
+                    format!("Variant '{}' must have a single field", variant.ident),
+                ))
+            }
+        };
+        let variant = &variant.ident;
+        let visit = visit_method(variant);
+        methods.push(quote! {
+            fn #visit(&mut self, node: &#node) -> Self::Output;
+        });
+        arms.push(quote! {
+            #name::#variant(node) => visitor.#visit(node)
+        });
+    }
+
+    let doc = format!("Operation over every kind of [`{name}`], called by its `accept` methods.");
+    Ok(quote! {
+        #input
+
+        #[doc = #doc]
+        pub trait #visitor {
+            type Output;
+
+            #(#methods)*
         }
+
+        impl #name {
+            pub fn accept<V: #visitor>(&self, visitor: &mut V) -> V::Output {
+                match self {
+                    #(#arms),*
+                }
+            }
+        }
+    })
+}
+
+fn builder_name(node_name: &Ident) -> Ident {
+    format_ident!("{}Builder", node_name)
+}
+
+fn has_optional_field(fields: &[&Field]) -> bool {
+    fields.iter().any(|f| is_option(&f.ty))
+}
+
+/// Whether `ty` is written as an `Option<T>`.
+fn is_option(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
     };
-    output
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Option")
+}
+
+fn visitor_name(enum_name: &Ident) -> Ident {
+    format_ident!("{}Visitor", enum_name)
+}
+
+/// `visit_variable_decl` for `VariableDecl`.
+fn visit_method(variant: &Ident) -> Ident {
+    let mut name = String::from("visit");
+    for c in variant.to_string().chars() {
+        if c.is_uppercase() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    format_ident!("{}", name)
+}
+
+/// `T` of a `Ptr<T>` or `Box<T>`.
+fn pointee(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let PathArguments::AngleBracketed(arguments) = &path.path.segments.last()?.arguments else {
+        return None;
+    };
+    match arguments.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
@@ -101,7 +380,9 @@ mod tests {
             }
 
             impl SyntaxNode for Foo {
-                fn id(&self) -> usize {
+                type Id = usize;
+
+                fn id(&self) -> Self::Id {
                     self.id
                 }
             }
@@ -109,11 +390,302 @@ mod tests {
             impl Foo {
                 pub fn new_wrapped(bar: String, baz: i32) -> Enum {
                     Enum::Variant(Ptr::new(Self {
-                        id: Self::generate_id(),
+                        id: ::core::convert::From::from(Self::generate_id()),
                         bar,
                         baz // comma missing. unwanted, but it's ok
                     }))
                 }
+
+                pub fn accept<V: EnumVisitor>(&self, visitor: &mut V) -> V::Output {
+                    visitor.visit_variant(self)
+                }
+            }
+        }
+        .to_string();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_syntax_node_span() {
+        let attr = quote! { Enum::Variant };
+        let input = quote! {
+            struct Foo {
+                id: usize,
+                bar: String,
+                span: Span,
+            }
+        };
+        let output = syntax_node(attr, input).to_string();
+
+        let expected = quote! {
+            struct Foo {
+                id: usize,
+                bar: String,
+                span: Span,
+            }
+
+            impl SyntaxNode for Foo {
+                type Id = usize;
+
+                fn id(&self) -> Self::Id {
+                    self.id
+                }
+
+                fn span(&self) -> Span {
+                    self.span
+                }
+            }
+
+            impl Foo {
+                pub fn new_wrapped(span: Span, bar: String) -> Enum {
+                    Enum::Variant(Ptr::new(Self {
+                        id: ::core::convert::From::from(Self::generate_id()),
+                        span,
+                        bar
+                    }))
+                }
+
+                pub fn accept<V: EnumVisitor>(&self, visitor: &mut V) -> V::Output {
+                    visitor.visit_variant(self)
+                }
+            }
+        }
+        .to_string();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_syntax_node_shapes() {
+        let attr = quote! { Enum::<T>::Variant, id = node_id };
+        let input = quote! {
+            /// Documented.
+            struct Foo<T: Clone> where T: Default {
+                /// The id.
+                node_id: NodeId,
+                value: T,
+            }
+        };
+        let output = syntax_node(attr, input).to_string();
+
+        let expected = quote! {
+            /// Documented.
+            struct Foo<T: Clone> where T: Default {
+                /// The id.
+                node_id: NodeId,
+                value: T,
+            }
+
+            impl<T: Clone> SyntaxNode for Foo<T> where T: Default {
+                type Id = NodeId;
+
+                fn id(&self) -> Self::Id {
+                    self.node_id
+                }
+            }
+
+            impl<T: Clone> Foo<T> where T: Default {
+                pub fn new_wrapped(value: T) -> Enum::<T> {
+                    Enum::<T>::Variant(Ptr::new(Self {
+                        node_id: ::core::convert::From::from(Self::generate_id()),
+                        value
+                    }))
+                }
+
+                pub fn accept<V: EnumVisitor>(&self, visitor: &mut V) -> V::Output {
+                    visitor.visit_variant(self)
+                }
+            }
+        }
+        .to_string();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_syntax_node_builder() {
+        let attr = quote! { Enum::Variant };
+        let input = quote! {
+            pub struct Foo {
+                id: usize,
+                bar: String,
+                baz: Option<i32>,
+            }
+        };
+        let output = syntax_node(attr, input).to_string();
+
+        let doc = "Builder of [`Foo`], created by [`Foo::builder`]. Fields other than `Option`s must be set.";
+        let expected = quote! {
+            pub struct Foo {
+                id: usize,
+                bar: String,
+                baz: Option<i32>,
+            }
+
+            impl SyntaxNode for Foo {
+                type Id = usize;
+
+                fn id(&self) -> Self::Id {
+                    self.id
+                }
+            }
+
+            impl Foo {
+                pub fn new_wrapped(bar: String, baz: Option<i32>) -> Enum {
+                    Enum::Variant(Ptr::new(Self {
+                        id: ::core::convert::From::from(Self::generate_id()),
+                        bar,
+                        baz
+                    }))
+                }
+
+                pub fn accept<V: EnumVisitor>(&self, visitor: &mut V) -> V::Output {
+                    visitor.visit_variant(self)
+                }
+
+                pub fn builder() -> FooBuilder {
+                    FooBuilder {
+                        bar: ::core::option::Option::None,
+                        baz: ::core::option::Option::None
+                    }
+                }
+            }
+
+            #[doc = #doc]
+            pub struct FooBuilder {
+                bar: ::core::option::Option<String>,
+                baz: Option<i32>
+            }
+
+            impl FooBuilder {
+                pub fn bar(mut self, bar: String) -> Self {
+                    self.bar = ::core::option::Option::Some(bar);
+                    self
+                }
+
+                pub fn baz(mut self, baz: impl ::core::convert::Into<Option<i32> >) -> Self {
+                    self.baz = baz.into();
+                    self
+                }
+
+                pub fn build_wrapped(self) -> Enum {
+                    <Foo>::new_wrapped(
+                        self.bar.expect("'bar' must be set before building Foo"),
+                        self.baz
+                    )
+                }
+            }
+        }
+        .to_string();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_syntax_node_errors() {
+        let error = |attr: TokenStream, input: TokenStream| {
+            expand_syntax_node(attr, input).unwrap_err().to_string()
+        };
+        let node = quote! { struct Foo { id: usize } };
+        assert_eq!(
+            "Must be used with a form of 'Enum::Variant'",
+            error(quote! { Variant }, node.clone())
+        );
+        assert_eq!(
+            "Expected a form of 'Enum::Variant'",
+            error(quote! { 1 }, node)
+        );
+        assert_eq!(
+            "SyntaxNode must have a field named 'id'",
+            error(
+                quote! { Enum::Variant },
+                quote! { struct Foo { node_id: u32 } }
+            )
+        );
+        assert_eq!(
+            "SyntaxNode must have a field named 'node_id'",
+            error(
+                quote! { Enum::Variant, id = node_id },
+                quote! { struct Foo { id: u32 } }
+            )
+        );
+        assert_eq!(
+            "Unknown option, expected 'id = field'",
+            error(
+                quote! { Enum::Variant, name = id },
+                quote! { struct Foo { id: u32 } }
+            )
+        );
+        assert_eq!(
+            "Must be used with a form of 'Enum::Variant'",
+            error(TokenStream::new(), quote! { struct Foo { id: u32 } })
+        );
+        assert_eq!(
+            "SyntaxNode can only be derived for structs with named fields",
+            error(quote! { Enum::Variant }, quote! { struct Foo(usize); })
+        );
+
+        // The item is kept, followed by the error.
+        let output = syntax_node(quote! { Variant }, quote! { struct Foo; }).to_string();
+        assert!(output.starts_with("struct Foo ;"), "{output}");
+        assert!(output.contains("compile_error !"), "{output}");
+    }
+
+    #[test]
+    fn test_syntax_enum_errors() {
+        let error = |input: TokenStream| {
+            expand_syntax_enum(TokenStream::new(), input)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            "Expected a pointer to a node, e.g. 'Ptr<Node>'",
+            error(quote! { enum Expr { Unary(expr::Unary) } })
+        );
+        assert_eq!(
+            "Variant 'Nil' must have a single field",
+            error(quote! { enum Expr { Nil } })
+        );
+        assert_eq!(
+            "#[syntax_enum] takes no arguments",
+            expand_syntax_enum(quote! { Visitor }, quote! { enum Expr {} })
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_syntax_enum() {
+        let input = quote! {
+            pub enum Expr {
+                Unary(Ptr<expr::Unary>),
+                VariableDecl(Box<expr::VariableDecl>),
+            }
+        };
+        let output = syntax_enum(TokenStream::new(), input).to_string();
+
+        let expected = quote! {
+            pub enum Expr {
+                Unary(Ptr<expr::Unary>),
+                VariableDecl(Box<expr::VariableDecl>),
+            }
+
+            #[doc = "Operation over every kind of [`Expr`], called by its `accept` methods."]
+            pub trait ExprVisitor {
+                type Output;
+
+                fn visit_unary(&mut self, node: &expr::Unary) -> Self::Output;
+                fn visit_variable_decl(&mut self, node: &expr::VariableDecl) -> Self::Output;
+            }
+
+            impl Expr {
+                pub fn accept<V: ExprVisitor>(&self, visitor: &mut V) -> V::Output {
+                    match self {
+                        Expr::Unary(node) => visitor.visit_unary(node),
+                        Expr::VariableDecl(node) => visitor.visit_variable_decl(node)
+                    }
+                }
             }
         }
         .to_string();
@@ -0,0 +1,291 @@
+//! C interface for embedding the interpreter in non-Rust applications.
+//!
+//! ```c
+//! RloxHandle *lox = rlox_new();
+//! if (rlox_run(lox, "print 1 + 2;") != RLOX_OK) {
+//!     fprintf(stderr, "%s\n", rlox_last_error(lox));
+//! }
+//! rlox_free(lox);
+//! ```
+
+use rlox_interpreter::{Lox, LoxError, Value};
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+
+pub const RLOX_OK: c_int = 0;
+/// Scan, parse or resolve error, following the exit codes of the command line.
+pub const RLOX_COMPILE_ERROR: c_int = 65;
+pub const RLOX_RUNTIME_ERROR: c_int = 70;
+/// Invalid arguments, such as a null pointer or a string which isn't UTF-8.
+pub const RLOX_INVALID_ARGUMENT: c_int = 64;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RloxValueKind {
+    Nil,
+    Boolean,
+    Number,
+    String,
+    /// Any value which has no C representation, such as a function.
+    Other,
+}
+
+/// A value crossing the C boundary. Only the field matching `kind` is meaningful.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RloxValue {
+    pub kind: RloxValueKind,
+    pub boolean: bool,
+    pub number: f64,
+    /// NUL-terminated UTF-8, owned by whoever created the value.
+    pub string: *const c_char,
+}
+
+impl RloxValue {
+    fn new(kind: RloxValueKind) -> Self {
+        Self {
+            kind,
+            boolean: false,
+            number: 0.0,
+            string: std::ptr::null(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `string` must be null or a valid NUL-terminated string.
+    unsafe fn to_value(self) -> anyhow::Result<Value> {
+        Ok(match self.kind {
+            RloxValueKind::Nil => Value::Nil,
+            RloxValueKind::Boolean => Value::Boolean(self.boolean),
+            RloxValueKind::Number => Value::Number(self.number),
+            RloxValueKind::String if self.string.is_null() => {
+                anyhow::bail!("Native function returned a null string.")
+            }
+            RloxValueKind::String => Value::from(CStr::from_ptr(self.string).to_str()?),
+            RloxValueKind::Other => anyhow::bail!("Native function returned an unknown value."),
+        })
+    }
+}
+
+/// Callback of a native function. `args` holds `argc` values, which are valid only during the
+/// call. The result is written to `out`, whose string is copied before the callback returns to
+/// the interpreter. A non-zero return value raises a runtime error.
+pub type RloxNativeFn = extern "C" fn(
+    user_data: *mut c_void,
+    args: *const RloxValue,
+    argc: usize,
+    out: *mut RloxValue,
+) -> c_int;
+
+pub struct RloxHandle {
+    lox: Lox<'static>,
+    last_error: Option<CString>,
+}
+
+// The callback and its data are owned by the C side, which promises they can be used from the
+// thread running the interpreter.
+struct NativeCallback {
+    func: RloxNativeFn,
+    user_data: *mut c_void,
+}
+
+unsafe impl Send for NativeCallback {}
+unsafe impl Sync for NativeCallback {}
+
+impl NativeCallback {
+    fn call(&self, args: &[Value]) -> anyhow::Result<Value> {
+        // Keeps the strings of the arguments alive during the call.
+        let strings: Vec<Option<CString>> = args
+            .iter()
+            .map(|arg| match arg {
+                Value::String(s) => CString::new(s.as_bytes()).ok(),
+                _ => None,
+            })
+            .collect();
+        let c_args: Vec<RloxValue> = args
+            .iter()
+            .zip(&strings)
+            .map(|(arg, string)| match arg {
+                Value::Nil => RloxValue::new(RloxValueKind::Nil),
+                Value::Boolean(b) => RloxValue {
+                    boolean: *b,
+                    ..RloxValue::new(RloxValueKind::Boolean)
+                },
+                Value::Number(n) => RloxValue {
+                    number: *n,
+                    ..RloxValue::new(RloxValueKind::Number)
+                },
+                Value::String(_) => RloxValue {
+                    string: string.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+                    ..RloxValue::new(RloxValueKind::String)
+                },
+                _ => RloxValue::new(RloxValueKind::Other),
+            })
+            .collect();
+
+        let mut out = RloxValue::new(RloxValueKind::Nil);
+        let status = (self.func)(self.user_data, c_args.as_ptr(), c_args.len(), &mut out);
+        if status != RLOX_OK {
+            anyhow::bail!("Native function failed with status {status}.");
+        }
+        unsafe { out.to_value() }
+    }
+}
+
+impl RloxHandle {
+    fn fail(&mut self, code: c_int, message: &str) -> c_int {
+        // Interior NULs can't be represented, so the message is cut there.
+        let message = message.split('\0').next().unwrap_or_default();
+        self.last_error = CString::new(message).ok();
+        code
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or a valid NUL-terminated string.
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        None
+    } else {
+        CStr::from_ptr(ptr).to_str().ok()
+    }
+}
+
+/// Creates an interpreter printing to stdout. It must be freed with [`rlox_free`].
+#[no_mangle]
+pub extern "C" fn rlox_new() -> *mut RloxHandle {
+    Box::into_raw(Box::new(RloxHandle {
+        lox: Lox::new(),
+        last_error: None,
+    }))
+}
+
+/// # Safety
+///
+/// `handle` must be null or come from [`rlox_new`], and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rlox_free(handle: *mut RloxHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Runs `source`, returning [`RLOX_OK`] or an error code whose message is available from
+/// [`rlox_last_error`].
+///
+/// # Safety
+///
+/// `handle` must come from [`rlox_new`], and `source` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rlox_run(handle: *mut RloxHandle, source: *const c_char) -> c_int {
+    let Some(handle) = handle.as_mut() else {
+        return RLOX_INVALID_ARGUMENT;
+    };
+    handle.last_error = None;
+    let Some(source) = to_str(source) else {
+        return handle.fail(RLOX_INVALID_ARGUMENT, "Source must be a UTF-8 string.");
+    };
+    match handle.lox.run(source) {
+        Ok(()) => RLOX_OK,
+        Err(e @ (LoxError::Runtime(_) | LoxError::Internal(_))) => {
+            handle.fail(RLOX_RUNTIME_ERROR, &e.to_string())
+        }
+        Err(e) => handle.fail(RLOX_COMPILE_ERROR, &e.to_string()),
+    }
+}
+
+/// Defines a global function `name` taking `arity` arguments, which calls `func` with
+/// `user_data`.
+///
+/// # Safety
+///
+/// `handle` must come from [`rlox_new`], `name` must be a NUL-terminated string, and
+/// `user_data` must stay valid as long as the handle.
+#[no_mangle]
+pub unsafe extern "C" fn rlox_register_native(
+    handle: *mut RloxHandle,
+    name: *const c_char,
+    arity: usize,
+    func: RloxNativeFn,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(handle) = handle.as_mut() else {
+        return RLOX_INVALID_ARGUMENT;
+    };
+    let Some(name) = to_str(name) else {
+        return handle.fail(RLOX_INVALID_ARGUMENT, "Name must be a UTF-8 string.");
+    };
+    let callback = NativeCallback { func, user_data };
+    handle
+        .lox
+        .register_function(name, arity, move |_interpreter, args| callback.call(args));
+    RLOX_OK
+}
+
+/// Message of the error returned by the last call, or null if it succeeded. The string is valid
+/// until the next call with the same handle.
+///
+/// # Safety
+///
+/// `handle` must come from [`rlox_new`].
+#[no_mangle]
+pub unsafe extern "C" fn rlox_last_error(handle: *const RloxHandle) -> *const c_char {
+    match handle.as_ref().and_then(|h| h.last_error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn add(
+        user_data: *mut c_void,
+        args: *const RloxValue,
+        argc: usize,
+        out: *mut RloxValue,
+    ) -> c_int {
+        let args = unsafe { std::slice::from_raw_parts(args, argc) };
+        let offset = unsafe { *(user_data as *const f64) };
+        if args[0].kind != RloxValueKind::Number {
+            return 1;
+        }
+        unsafe {
+            *out = RloxValue {
+                number: args[0].number + offset,
+                ..RloxValue::new(RloxValueKind::Number)
+            };
+        }
+        RLOX_OK
+    }
+
+    #[test]
+    fn test_run() {
+        let mut offset = 10.0_f64;
+        unsafe {
+            let handle = rlox_new();
+            let status = rlox_register_native(
+                handle,
+                c"add".as_ptr(),
+                1,
+                add,
+                &mut offset as *mut f64 as *mut c_void,
+            );
+            assert_eq!(RLOX_OK, status);
+            assert_eq!(RLOX_OK, rlox_run(handle, c"var a = add(1);".as_ptr()));
+            assert!(rlox_last_error(handle).is_null());
+            assert_eq!(Some(Value::Number(11.0)), (*handle).lox.get_global("a"));
+
+            assert_eq!(RLOX_RUNTIME_ERROR, rlox_run(handle, c"add(nil);".as_ptr()));
+            let error = CStr::from_ptr(rlox_last_error(handle));
+            assert_eq!(
+                "Line 1, Native function failed with status 1.",
+                error.to_str().unwrap()
+            );
+            assert_eq!(RLOX_COMPILE_ERROR, rlox_run(handle, c"print".as_ptr()));
+            rlox_free(handle);
+        }
+    }
+}
@@ -0,0 +1,803 @@
+//! Source formatter behind `rlox fmt`, also meant to be called by editors.
+//!
+//! The formatter prints the parsed syntax tree with canonical spacing and indentation. Comments
+//! and blank lines are not part of the tree, so they are kept aside as trivia and put back in
+//! front of the first statement which follows them.
+
+mod minify;
+
+pub use minify::*;
+
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How the formatted code looks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatConfig {
+    /// Spaces per nesting level.
+    pub indent_width: usize,
+    /// Statements longer than this have the arguments of their calls, or the parameters of
+    /// their function, put one per line.
+    pub max_line_length: usize,
+    pub brace_style: BraceStyle,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_line_length: 100,
+            brace_style: BraceStyle::SameLine,
+        }
+    }
+}
+
+/// Where the opening brace of a block following `if`, `else`, `while`, `for` or `fun` goes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BraceStyle {
+    /// `if (a) {`
+    #[default]
+    SameLine,
+    /// `if (a)` then `{` on the next line, `else` starting its own line too.
+    NextLine,
+}
+
+impl BraceStyle {
+    /// The style named `same-line` or `next-line`, as in options.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "same-line" => Some(BraceStyle::SameLine),
+            "next-line" => Some(BraceStyle::NextLine),
+            _ => None,
+        }
+    }
+}
+
+/// Formats `source` according to `config`, keeping its comments.
+pub fn format_source(source: &str, config: &FormatConfig) -> Result<String, LoxError> {
+    let (tokens, comments) = Scanner::new(source).scan_tokens_with_comments()?;
+    let mut formatter = Formatter::new(source, &tokens, comments, config);
+    let statements = Parser::new(tokens).parse()?;
+    for s in &statements {
+        formatter.statement(s);
+    }
+    formatter.trivia_before(usize::MAX, false);
+    Ok(formatter.out)
+}
+
+enum Trivia {
+    BlankLine(usize),
+    Comment(Comment),
+}
+
+impl Trivia {
+    fn line(&self) -> usize {
+        match self {
+            Trivia::BlankLine(line) => *line,
+            Trivia::Comment(c) => c.span.line,
+        }
+    }
+
+    /// Whether the trivia goes before code starting on `line`, as a block comment starting
+    /// that line does.
+    fn is_before(&self, line: usize) -> bool {
+        match self {
+            Trivia::Comment(c) if !c.trailing => c.span.line <= line,
+            t => t.line() < line,
+        }
+    }
+}
+
+struct Formatter<'a> {
+    out: String,
+    depth: usize,
+    config: &'a FormatConfig,
+    // One level of indentation
+    indent: String,
+    // Blank lines and comments which are not printed yet, in source order
+    trivia: VecDeque<Trivia>,
+    // Line of the closing brace for the position of each opening brace, or of the `class`
+    // keyword for the body of a class
+    closing_lines: HashMap<(usize, usize), usize>,
+    // Positions of `for` keywords, whose loops were desugared by the parser
+    for_keywords: HashSet<(usize, usize)>,
+    // Positions of the names of methods, which aren't preceded by `fun`, or of their `static`
+    methods: HashSet<(usize, usize)>,
+    // Nothing has been printed in the current block yet
+    at_block_start: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(
+        source: &str,
+        tokens: &[Token],
+        comments: Vec<Comment>,
+        config: &'a FormatConfig,
+    ) -> Self {
+        // Lines after the first one of block comments, whose blank lines are in their text
+        let comment_lines: HashSet<usize> = comments
+            .iter()
+            .flat_map(|c| c.span.line + 1..c.span.line + c.text.lines().count())
+            .collect();
+        let mut trivia: Vec<Trivia> = source
+            .lines()
+            .enumerate()
+            .filter(|(index, text)| text.trim().is_empty() && !comment_lines.contains(&(index + 1)))
+            .map(|(index, _)| Trivia::BlankLine(index + 1))
+            .chain(comments.into_iter().map(Trivia::Comment))
+            .collect();
+        trivia.sort_by_key(Trivia::line);
+
+        let mut closing_lines = HashMap::new();
+        let mut for_keywords = HashSet::new();
+        let mut open_braces = Vec::new();
+        let mut class_keyword = None;
+        for token in tokens {
+            match token.kind {
+                TokenKind::Class => class_keyword = Some((token.line, token.column)),
+                TokenKind::LeftBrace => {
+                    open_braces.push(class_keyword.take().unwrap_or((token.line, token.column)))
+                }
+                TokenKind::RightBrace => {
+                    if let Some(position) = open_braces.pop() {
+                        closing_lines.insert(position, token.line);
+                    }
+                }
+                TokenKind::For => {
+                    for_keywords.insert((token.line, token.column));
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            out: String::new(),
+            depth: 0,
+            config,
+            indent: " ".repeat(config.indent_width),
+            trivia: trivia.into(),
+            closing_lines,
+            for_keywords,
+            methods: HashSet::new(),
+            at_block_start: true,
+        }
+    }
+
+    /// Prints the trivia found before `line`. Blank lines are collapsed into one, and dropped
+    /// at the start of a block or, unless `keep_blank_line`, at the end.
+    fn trivia_before(&mut self, line: usize, keep_blank_line: bool) {
+        let mut blank_line = false;
+        while self.trivia.front().is_some_and(|t| t.is_before(line)) {
+            match self.trivia.pop_front().unwrap() {
+                Trivia::BlankLine(_) => blank_line = true,
+                Trivia::Comment(comment) if comment.trailing && self.out.ends_with('\n') => {
+                    self.out.pop();
+                    self.out.push(' ');
+                    self.out.push_str(&comment.text);
+                    self.out.push('\n');
+                }
+                Trivia::Comment(comment) => {
+                    if blank_line && !self.at_block_start {
+                        self.out.push('\n');
+                    }
+                    blank_line = false;
+                    self.indent();
+                    self.out.push_str(&comment.text);
+                    self.out.push('\n');
+                    self.at_block_start = false;
+                }
+            }
+        }
+        if blank_line && keep_blank_line && !self.at_block_start {
+            self.out.push('\n');
+        }
+    }
+
+    fn indent(&mut self) {
+        self.out.push_str(&self.indent.repeat(self.depth));
+    }
+
+    /// Whether `text`, starting at the current indentation, fits in a line.
+    fn fits(&self, text: &str) -> bool {
+        let width = self.indent.len() * self.depth + text.chars().count();
+        width <= self.config.max_line_length
+    }
+
+    /// Formats `e` as part of a statement starting with `prefix` and ending with `suffix`,
+    /// putting the arguments of its calls one per line if the statement is too long.
+    fn expr(&self, prefix: &str, e: &Expr, suffix: &str) -> String {
+        let text = format!("{prefix}{}{suffix}", expr(e));
+        if self.fits(&text) {
+            return text;
+        }
+        let indent = self.indent.repeat(self.depth);
+        let wrapped = wrapped_expr(e, &indent, &self.indent);
+        format!("{prefix}{wrapped}{suffix}")
+    }
+
+    /// `print` with its values on one line if it fits, one per following line otherwise.
+    fn print_statement(&self, values: &[Expr]) -> String {
+        let [first, rest @ ..] = values else {
+            unreachable!("print has at least one value");
+        };
+        if rest.is_empty() {
+            return self.expr("print ", first, ";");
+        }
+        let values: Vec<_> = values.iter().map(expr).collect();
+        let text = format!("print {};", values.join(", "));
+        if self.fits(&text) {
+            return text;
+        }
+        let indent = self.indent.repeat(self.depth + 1);
+        format!("print\n{indent}{};", values.join(&format!(",\n{indent}")))
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        self.trivia_before(stmt.span().line, true);
+        self.indent();
+        self.statement_body(stmt);
+        self.out.push('\n');
+        self.at_block_start = false;
+    }
+
+    /// Prints a statement from the current position, without leading indentation nor the
+    /// trailing newline.
+    fn statement_body(&mut self, stmt: &Statement) {
+        let span = stmt.span();
+        if self.for_keywords.contains(&(span.line, span.column)) {
+            self.for_statement(stmt);
+            return;
+        }
+
+        match stmt {
+            Statement::Expression(s) => {
+                let text = self.expr("", &s.expr, ";");
+                self.out.push_str(&text);
+            }
+            Statement::Print(s) => {
+                let text = self.print_statement(&s.exprs);
+                self.out.push_str(&text);
+            }
+            Statement::VariableDecl(s) => {
+                let text = self.variable_decl(s);
+                self.out.push_str(&text);
+            }
+            Statement::Block(s) => self.block(s.span, &s.statements),
+            Statement::If(s) => {
+                let text = format!("if ({}) ", expr(&s.condition));
+                self.out.push_str(&text);
+                self.body(&s.then_branch);
+                if let Some(else_branch) = &s.else_branch {
+                    let then_block = matches!(s.then_branch, Statement::Block(_));
+                    if then_block && self.config.brace_style == BraceStyle::SameLine {
+                        self.out.push(' ');
+                    } else {
+                        self.out.push('\n');
+                        self.indent();
+                    }
+                    self.out.push_str("else ");
+                    self.body(else_branch);
+                }
+            }
+            Statement::While(s) => {
+                let text = format!("while ({}) ", expr(&s.condition));
+                self.out.push_str(&text);
+                self.body(&s.body);
+            }
+            Statement::Function(s) => {
+                let keyword = if s.is_static {
+                    "static "
+                } else if self.methods.contains(&(span.line, span.column)) {
+                    ""
+                } else {
+                    "fun "
+                };
+                let params = s.written_params();
+                let mut text = format!("{keyword}{}({}) ", s.name, params.join(", "));
+                if !self.fits(text.trim_end()) && !params.is_empty() {
+                    let indent = self.indent.repeat(self.depth);
+                    let params = wrapped_list(&params, &indent, &self.indent);
+                    text = format!("{keyword}{}({params}) ", s.name);
+                }
+                self.out.push_str(&text);
+                self.body(&s.body.read().unwrap());
+            }
+            Statement::Return(s) => match &s.value {
+                Some(value) => {
+                    let text = self.expr("return ", value, ";");
+                    self.out.push_str(&text);
+                }
+                None => self.out.push_str("return;"),
+            },
+            Statement::Class(s) => {
+                let text = match &s.superclass {
+                    Some(superclass) => format!("class {} < {} ", s.name, expr(superclass)),
+                    None => format!("class {} ", s.name),
+                };
+                self.out.push_str(&text);
+                self.methods.extend(s.methods.iter().map(|m| {
+                    let span = m.span();
+                    (span.line, span.column)
+                }));
+                self.block(s.span, &s.methods);
+            }
+        }
+    }
+
+    /// Prints the body of a statement, after its header and a space.
+    fn body(&mut self, stmt: &Statement) {
+        let opens_line = match stmt {
+            Statement::Block(s) => {
+                self.config.brace_style == BraceStyle::NextLine
+                    && !self.for_keywords.contains(&(s.span.line, s.span.column))
+                    && !self.is_empty_block(s.span, &s.statements)
+            }
+            _ => false,
+        };
+        if opens_line {
+            self.out.pop();
+            self.out.push('\n');
+            self.indent();
+        }
+        self.statement_body(stmt);
+    }
+
+    fn closing_line(&self, span: Span) -> usize {
+        self.closing_lines
+            .get(&(span.line, span.column))
+            .copied()
+            .unwrap_or(span.line)
+    }
+
+    /// Whether the block is printed as `{}`, having neither statements nor comments.
+    fn is_empty_block(&self, span: Span, statements: &[Statement]) -> bool {
+        let closing_line = self.closing_line(span);
+        statements.is_empty()
+            && !self.trivia.iter().any(
+                |t| matches!(t, Trivia::Comment(c) if c.span.line <= closing_line && !c.trailing),
+            )
+    }
+
+    fn block(&mut self, span: Span, statements: &[Statement]) {
+        let closing_line = self.closing_line(span);
+        if self.is_empty_block(span, statements) {
+            self.trivia
+                .retain(|t| !matches!(t, Trivia::BlankLine(line) if *line < closing_line));
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push_str("{\n");
+        self.depth += 1;
+        self.at_block_start = true;
+        for s in statements {
+            self.statement(s);
+        }
+        self.trivia_before(closing_line, false);
+        self.depth -= 1;
+        self.indent();
+        self.out.push('}');
+    }
+
+    /// Prints the original `for` loop, reversing the desugaring of the parser. Every statement
+    /// introduced by the desugaring has the span of the `for` keyword.
+    fn for_statement(&mut self, stmt: &Statement) {
+        let span = stmt.span();
+        // The initializer is a statement per variable it declares
+        let (initializers, while_statement) = match stmt {
+            Statement::Block(block) => {
+                let (last, initializers) = block.statements.split_last().unwrap_or_else(|| {
+                    unreachable!("for loop must be desugared into a while loop")
+                });
+                (initializers, last)
+            }
+            _ => (&[][..], stmt),
+        };
+        let Statement::While(while_statement) = while_statement else {
+            unreachable!("for loop must be desugared into a while loop");
+        };
+
+        let initializer = match initializers {
+            [Statement::Expression(s)] => format!("{};", expr(&s.expr)),
+            [] => ";".to_owned(),
+            declarations => {
+                let declarators: Vec<_> = declarations
+                    .iter()
+                    .map(|s| match s {
+                        Statement::VariableDecl(s) => match &s.expr {
+                            Some(value) => format!("{} = {}", s.name, expr(value)),
+                            None => s.name.clone(),
+                        },
+                        _ => unreachable!("initializer of for loop must be variable declarations"),
+                    })
+                    .collect();
+                format!("var {};", declarators.join(", "))
+            }
+        };
+        let condition = match &while_statement.condition {
+            Expr::Literal(literal) if literal.span == span => String::new(),
+            condition => format!(" {}", expr(condition)),
+        };
+        let (body, increment) = match &while_statement.body {
+            Statement::Block(block) if block.span == span => {
+                let Statement::Expression(increment) = &block.statements[1] else {
+                    unreachable!("increment of for loop must be an expression");
+                };
+                (&block.statements[0], format!(" {}", expr(&increment.expr)))
+            }
+            body => (body, String::new()),
+        };
+
+        let text = format!("for ({initializer}{condition};{increment}) ");
+        self.out.push_str(&text);
+        self.body(body);
+    }
+
+    fn variable_decl(&self, decl: &statement::VariableDecl) -> String {
+        match &decl.expr {
+            Some(value) => self.expr(&format!("{} {} = ", decl.keyword(), decl.name), value, ";"),
+            None => format!("var {};", decl.name),
+        }
+    }
+}
+
+fn expr(e: &Expr) -> String {
+    match e {
+        Expr::Binary(e) if e.operator == TokenKind::Comma => {
+            format!("{}, {}", expr(&e.left), expr(&e.right))
+        }
+        Expr::Binary(e) => format!(
+            "{} {} {}",
+            expr(&e.left),
+            operator(e.operator),
+            expr(&e.right)
+        ),
+        Expr::Logical(e) => format!(
+            "{} {} {}",
+            expr(&e.left),
+            operator(e.operator),
+            expr(&e.right)
+        ),
+        Expr::Unary(e) => format!("{}{}", operator(e.operator), expr(&e.right)),
+        Expr::Grouping(e) => format!("({})", expr(&e.expr)),
+        Expr::Literal(e) => match &e.literal {
+            Literal::Number(n) => n.to_string(),
+            Literal::String(s) => format!("\"{s}\""),
+            Literal::Boolean(b) => b.to_string(),
+            Literal::Nil => "nil".to_owned(),
+        },
+        Expr::Variable(e) => e.name.clone(),
+        Expr::Assign(e) => format!("{} = {}", e.name, expr(&e.value)),
+        Expr::Call(e) => {
+            let arguments: Vec<_> = e.arguments.iter().map(expr).collect();
+            format!("{}({})", expr(&e.callee), arguments.join(", "))
+        }
+        Expr::Get(e) => format!("{}{}{}", expr(&e.object), e.dot(), e.name),
+        Expr::Set(e) => format!("{}.{} = {}", expr(&e.object), e.name, expr(&e.value)),
+        Expr::This(_) => "this".to_owned(),
+        Expr::Super(e) => format!("super.{}", e.method),
+        Expr::List(e) => {
+            let elements: Vec<_> = e.elements.iter().map(expr).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Expr::Index(e) => format!("{}[{}]", expr(&e.object), expr(&e.index)),
+        Expr::Slice(e) => format!(
+            "{}[{}:{}]",
+            expr(&e.object),
+            e.start.as_ref().map(expr).unwrap_or_default(),
+            e.end.as_ref().map(expr).unwrap_or_default()
+        ),
+        Expr::IndexSet(e) => format!(
+            "{}[{}] = {}",
+            expr(&e.object),
+            expr(&e.index),
+            expr(&e.value)
+        ),
+        Expr::Lambda(e) => {
+            let body = e.body.read().unwrap();
+            let Some(value) = expr::Lambda::returned(&body) else {
+                unreachable!("body of lambda must be desugared from an expression");
+            };
+            format!("fun ({}) -> {}", e.written_params().join(", "), expr(value))
+        }
+    }
+}
+
+/// Same as [`expr`], but with the arguments of calls and the elements of lists put one per
+/// line, one level deeper than `indent`. Calls and lists nested in them are kept on one line.
+fn wrapped_expr(e: &Expr, indent: &str, level: &str) -> String {
+    let wrapped = |e| wrapped_expr(e, indent, level);
+    match e {
+        Expr::Binary(e) if e.operator == TokenKind::Comma => {
+            format!("{}, {}", wrapped(&e.left), wrapped(&e.right))
+        }
+        Expr::Binary(e) => format!(
+            "{} {} {}",
+            wrapped(&e.left),
+            operator(e.operator),
+            wrapped(&e.right)
+        ),
+        Expr::Logical(e) => format!(
+            "{} {} {}",
+            wrapped(&e.left),
+            operator(e.operator),
+            wrapped(&e.right)
+        ),
+        Expr::Unary(e) => format!("{}{}", operator(e.operator), wrapped(&e.right)),
+        Expr::Grouping(e) => format!("({})", wrapped(&e.expr)),
+        Expr::Assign(e) => format!("{} = {}", e.name, wrapped(&e.value)),
+        Expr::Get(e) => format!("{}{}{}", wrapped(&e.object), e.dot(), e.name),
+        Expr::Set(e) => format!("{}.{} = {}", wrapped(&e.object), e.name, wrapped(&e.value)),
+        Expr::Call(e) if !e.arguments.is_empty() => {
+            let arguments: Vec<_> = e.arguments.iter().map(expr).collect();
+            let arguments = wrapped_list(&arguments, indent, level);
+            format!("{}({arguments})", wrapped(&e.callee))
+        }
+        Expr::List(e) if !e.elements.is_empty() => {
+            let elements: Vec<_> = e.elements.iter().map(expr).collect();
+            format!("[{}]", wrapped_list(&elements, indent, level))
+        }
+        Expr::Index(e) => format!("{}[{}]", wrapped(&e.object), expr(&e.index)),
+        Expr::IndexSet(e) => format!(
+            "{}[{}] = {}",
+            wrapped(&e.object),
+            expr(&e.index),
+            wrapped(&e.value)
+        ),
+        _ => expr(e),
+    }
+}
+
+/// `items` one per line, between the parentheses or brackets of a line indented by `indent`.
+fn wrapped_list(items: &[String], indent: &str, level: &str) -> String {
+    let mut text = String::from("\n");
+    for (i, item) in items.iter().enumerate() {
+        let separator = if i + 1 < items.len() { "," } else { "" };
+        text.push_str(&format!("{indent}{level}{item}{separator}\n"));
+    }
+    text.push_str(indent);
+    text
+}
+
+fn operator(kind: TokenKind) -> &'static str {
+    kind.lexeme()
+        .unwrap_or_else(|| unreachable!("{kind:?} is not an operator"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let source = r#"
+// Counts to three.
+var   limit=3 ;  // inclusive
+
+
+fun count(n){for(var i=1;i<=n;i=i+1)print i;
+  // done
+}
+if(limit>0){count(limit);}else print"none";
+{}
+for(;;){ }
+"#;
+        let expected = r#"// Counts to three.
+var limit = 3; // inclusive
+
+fun count(n) {
+    for (var i = 1; i <= n; i = i + 1) print i;
+    // done
+}
+if (limit > 0) {
+    count(limit);
+} else print "none";
+{}
+for (;;) {}
+"#;
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_comma() {
+        let source = "var x=(1,2);for(;x<9;x=x+1,x=x*2)print(x,-x),x;";
+        let expected = "var x = (1, 2);\nfor (; x < 9; x = x + 1, x = x * 2) print (x, -x), x;\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_optional_chaining() {
+        let source = "print a ?. b.c?.d( 1 );";
+        let expected = "print a?.b.c?.d(1);\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_const() {
+        let source = "const a=1,b=a*2;";
+        let expected = "const a = 1;\nconst b = a * 2;\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_multiple_declarators() {
+        // Each variable gets its own declaration, except in `for` loops
+        let source = "var a=1,b;for(var i=0,j;i<a;)print i;";
+        let expected = "var a = 1;\nvar b;\nfor (var i = 0, j; i < a;) print i;\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let source = "var f=fun(a,b)->a+b;print f(1,2),(fun()->nil)();";
+        let expected = "var f = fun (a, b) -> a + b;\nprint f(1, 2), (fun () -> nil)();\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_rest_parameters() {
+        let source = "fun log(level,...args){print level,args;}var all=fun(...xs)->xs;";
+        let expected =
+            "fun log(level, ...args) {\n    print level, args;\n}\nvar all = fun (...xs) -> xs;\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_lists() {
+        let source = "var a=[1,[ ],a[0]];print a [1+1];a[0]=a[1]=2;print a[1 :-1],a[ : ];";
+        let expected =
+            "var a = [1, [], a[0]];\nprint a[1 + 1];\na[0] = a[1] = 2;\nprint a[1:-1], a[:];\n";
+        assert_formatted(source, expected, &FormatConfig::default());
+
+        let source = "var numbers = [100, 200, 300];";
+        let expected = "var numbers = [\n    100,\n    200,\n    300\n];\n";
+        let config = FormatConfig {
+            max_line_length: 24,
+            ..FormatConfig::default()
+        };
+        assert_formatted(source, expected, &config);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let source = r"/* Header
+
+   text. */
+print 1;   /* trailing */
+/* leading */ print 2;
+{
+/* inside */ }
+";
+        let expected = r"/* Header
+
+   text. */
+print 1; /* trailing */
+/* leading */
+print 2;
+{
+    /* inside */
+}
+";
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    #[test]
+    fn test_indent_width() {
+        let source = "fun f() { if (true) { print 1; } }";
+        let expected = "fun f() {\n  if (true) {\n    print 1;\n  }\n}\n";
+        let config = FormatConfig {
+            indent_width: 2,
+            ..FormatConfig::default()
+        };
+        assert_formatted(source, expected, &config);
+    }
+
+    #[test]
+    fn test_next_line_braces() {
+        let source = r#"
+fun f(a) { if (a) { print 1; } else { print 2; } }
+while (false) {}
+{ print 3; }
+"#;
+        let expected = r#"fun f(a)
+{
+    if (a)
+    {
+        print 1;
+    }
+    else
+    {
+        print 2;
+    }
+}
+while (false) {}
+{
+    print 3;
+}
+"#;
+        let config = FormatConfig {
+            brace_style: BraceStyle::NextLine,
+            ..FormatConfig::default()
+        };
+        assert_formatted(source, expected, &config);
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let source = r#"
+fun add(first, second, third) { return first + second + third; }
+print add(1, 2, 3);
+var sum = add(clock(), clock(), 3) + 1;
+print 1, sum;
+print "the sum is", sum, "!";
+"#;
+        let expected = r#"fun add(
+    first,
+    second,
+    third
+) {
+    return first + second + third;
+}
+print add(1, 2, 3);
+var sum = add(
+    clock(),
+    clock(),
+    3
+) + 1;
+print 1, sum;
+print
+    "the sum is",
+    sum,
+    "!";
+"#;
+        let config = FormatConfig {
+            max_line_length: 24,
+            ..FormatConfig::default()
+        };
+        assert_formatted(source, expected, &config);
+    }
+
+    #[test]
+    fn test_class() {
+        let source = r#"
+class Point{
+  // Distance to the origin.
+  length(){return sqrt(this.x*this.x+this.y*this.y);}
+  move(dx,dy){this.x=this.x+dx;}
+}
+class Point3<Point{length(){return super.length();}}
+class Empty{}
+class Factory{static  create(){return Factory();}}
+"#;
+        let expected = r#"class Point {
+    // Distance to the origin.
+    length() {
+        return sqrt(this.x * this.x + this.y * this.y);
+    }
+    move(dx, dy) {
+        this.x = this.x + dx;
+    }
+}
+class Point3 < Point {
+    length() {
+        return super.length();
+    }
+}
+class Empty {}
+class Factory {
+    static create() {
+        return Factory();
+    }
+}
+"#;
+        assert_formatted(source, expected, &FormatConfig::default());
+    }
+
+    /// Also checks that formatting again changes nothing.
+    fn assert_formatted(source: &str, expected: &str, config: &FormatConfig) {
+        let formatted = format_source(source, config).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(formatted, format_source(&formatted, config).unwrap());
+    }
+}
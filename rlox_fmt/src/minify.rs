@@ -0,0 +1,168 @@
+//! Minified output, for embedding scripts where every byte counts.
+
+use rlox_interpreter::{Resolver, Scope, SymbolKind, Symbols};
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::*;
+use std::collections::{HashMap, HashSet};
+
+/// Prints `source` on a single line, without comments and with only the spaces which separate
+/// tokens. With `rename_locals`, variables, parameters and functions which aren't global are
+/// also given the shortest names available, the most used ones first. Globals keep their names,
+/// as the host or other scripts may refer to them.
+pub fn minify_source(source: &str, rename_locals: bool) -> Result<String, LoxError> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let mut statements = Parser::new(tokens.clone()).parse()?;
+    let renames = if rename_locals {
+        let scope = Scope::new_globals_ptr();
+        for s in &mut statements {
+            Resolver.resolve_statement(&scope, s)?;
+        }
+        local_names(&tokens, &Symbols::collect(&tokens, &statements))
+    } else {
+        HashMap::new()
+    };
+
+    let mut out = String::new();
+    let mut previous: Option<&str> = None;
+    for token in &tokens {
+        if token.kind == TokenKind::Eof {
+            break;
+        }
+        let lexeme = renames
+            .get(&(token.line, token.column))
+            .map_or(token.lexeme(), String::as_str);
+        if previous.is_some_and(|previous| needs_space(previous, lexeme)) {
+            out.push(' ');
+        }
+        out.push_str(lexeme);
+        previous = Some(lexeme);
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+/// New names of the local symbols, by position of their declarations and references.
+fn local_names(tokens: &[Token], symbols: &Symbols) -> HashMap<(usize, usize), String> {
+    let mut locals: Vec<(usize, Vec<Span>)> = symbols
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| !s.global && s.kind != SymbolKind::Native)
+        .map(|(index, _)| (index, symbols.occurrences(index)))
+        .collect();
+    // Stable, so that equally used symbols are named in source order
+    locals.sort_by_key(|(_, occurrences)| std::cmp::Reverse(occurrences.len()));
+
+    // Names which stay, so that no renamed local shadows them
+    let renamed: HashSet<_> = locals
+        .iter()
+        .flat_map(|(_, occurrences)| occurrences)
+        .map(|span| (span.line, span.column))
+        .collect();
+    let taken: HashSet<&str> = tokens
+        .iter()
+        .filter(|t| t.kind == TokenKind::Identifier && !renamed.contains(&(t.line, t.column)))
+        .map(Token::lexeme)
+        .collect();
+
+    let mut names = ShortNames { next: 0 };
+    let mut renames = HashMap::new();
+    for (_, occurrences) in locals {
+        let name = loop {
+            let name = names.next();
+            if !taken.contains(name.as_str()) && is_identifier(&name) {
+                break name;
+            }
+        };
+        for span in occurrences {
+            renames.insert((span.line, span.column), name.clone());
+        }
+    }
+    renames
+}
+
+/// `a` to `z`, `A` to `Z`, then two letters and so on.
+struct ShortNames {
+    next: usize,
+}
+
+impl ShortNames {
+    const LETTERS: &'static [u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    fn next(&mut self) -> String {
+        let mut n = self.next;
+        self.next += 1;
+        let mut name = Vec::new();
+        loop {
+            name.push(Self::LETTERS[n % Self::LETTERS.len()]);
+            n /= Self::LETTERS.len();
+            if n == 0 {
+                break;
+            }
+            n -= 1;
+        }
+        name.reverse();
+        String::from_utf8(name).unwrap()
+    }
+}
+
+/// Whether `name` scans as an identifier rather than a keyword.
+fn is_identifier(name: &str) -> bool {
+    Scanner::new(name)
+        .scan_tokens()
+        .is_ok_and(|tokens| tokens[0].kind == TokenKind::Identifier)
+}
+
+/// Whether two tokens would scan differently without a space between them.
+fn needs_space(previous: &str, next: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let (Some(last), Some(first)) = (previous.chars().last(), next.chars().next()) else {
+        return false;
+    };
+    // Also keeps `1 .5` apart, and operators which would merge such as `/ /` and `< =`.
+    (is_word(last) || last == '.') && (is_word(first) || first == '.')
+        || matches!(last, '!' | '=' | '<' | '>') && first == '='
+        || last == '/' && first == '/'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minify() {
+        let source = r#"
+// Counts up
+fun makeCounter(step) {
+    var count = 0; // starts at zero
+    fun increment() {
+        count = count + step;
+        return count;
+    }
+    return increment;
+}
+var counter = makeCounter(2);
+print counter() >= -1 and !nil;
+"#;
+        let expected = "fun makeCounter(step){var count=0;fun increment(){count=count+step;return count;}return increment;}var counter=makeCounter(2);print counter()>=-1 and!nil;\n";
+        assert_eq!(expected, minify_source(source, false).unwrap());
+        let expected = "fun makeCounter(b){var a=0;fun c(){a=a+b;return a;}return c;}var counter=makeCounter(2);print counter()>=-1 and!nil;\n";
+        assert_eq!(expected, minify_source(source, true).unwrap());
+    }
+
+    #[test]
+    fn test_rename_avoids_kept_names() {
+        // `a` and `b` are globals, so neither can name the parameter.
+        let source = "var a = 1;\nvar b = 2;\nfun f(x) { return x + a + b; }\n";
+        let expected = "var a=1;var b=2;fun f(c){return c+a+b;}\n";
+        assert_eq!(expected, minify_source(source, true).unwrap());
+    }
+
+    #[test]
+    fn test_short_names() {
+        let mut names = ShortNames { next: 0 };
+        let names: Vec<_> = (0..54).map(|_| names.next()).collect();
+        assert_eq!(["a", "b"], names[..2]);
+        assert_eq!(["Z", "aa", "ab"], names[51..]);
+    }
+}
@@ -0,0 +1,227 @@
+//! Natives returning futures, for hosts which run scripts from an async runtime.
+//!
+//! The interpreter itself is synchronous. [`Lox::run_async`] runs it on a thread of its own, and
+//! each call of an async native sends its future back to the `run_async` future, which awaits it
+//! on the host's runtime while the interpreter thread waits for the result.
+
+use crate::sync::Unpoisoned;
+use crate::{Lox, Value};
+use rlox_syntax::LoxError;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+pub type ValueFuture = Pin<Box<dyn Future<Output = anyhow::Result<Value>> + Send>>;
+
+struct AsyncCall {
+    future: ValueFuture,
+    reply: mpsc::Sender<anyhow::Result<Value>>,
+}
+
+#[derive(Default)]
+struct State {
+    calls: VecDeque<AsyncCall>,
+    done: Option<(Lox<'static>, Result<(), LoxError>)>,
+    // Set once the `run_async` future is dropped
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+/// State of a single `run_async`, shared with the interpreter thread.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn notify(&self, update: impl FnOnce(&mut State)) {
+        let mut state = self.state.lock_unpoisoned();
+        update(&mut state);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Connects the async natives of an instance to its `run_async` in progress, if any.
+#[derive(Clone, Default)]
+pub(crate) struct AsyncBridge(Arc<Mutex<Option<Arc<Shared>>>>);
+
+impl AsyncBridge {
+    /// Waits for `future`, through the `run_async` in progress or by blocking the thread.
+    pub(crate) fn call(&self, future: ValueFuture) -> anyhow::Result<Value> {
+        let shared = self.0.lock_unpoisoned().clone();
+        let Some(shared) = shared else {
+            return block_on(future);
+        };
+        let (reply, receiver) = mpsc::channel();
+        let mut cancelled = false;
+        shared.notify(|state| {
+            cancelled = state.cancelled;
+            if !cancelled {
+                state.calls.push_back(AsyncCall { future, reply });
+            }
+        });
+        if cancelled {
+            anyhow::bail!("Script was cancelled.");
+        }
+        receiver
+            .recv()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Script was cancelled.")))
+    }
+}
+
+enum Event {
+    Call(AsyncCall),
+    Done(Box<(Lox<'static>, Result<(), LoxError>)>),
+}
+
+struct NextEvent<'a>(&'a Shared);
+
+impl Future for NextEvent<'_> {
+    type Output = Event;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Event> {
+        let mut state = self.0.state.lock_unpoisoned();
+        if let Some(call) = state.calls.pop_front() {
+            Poll::Ready(Event::Call(call))
+        } else if let Some(done) = state.done.take() {
+            Poll::Ready(Event::Done(Box::new(done)))
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+// Makes natives waiting for a dropped `run_async` fail instead of waiting forever.
+struct CancelOnDrop(Arc<Shared>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock_unpoisoned();
+        state.cancelled = true;
+        state.calls.clear();
+    }
+}
+
+impl Lox<'static> {
+    /// Defines a global function awaiting the future returned by `func`. Sync natives keep
+    /// working unchanged next to it.
+    ///
+    /// With [`Lox::run`], the future is awaited by blocking the thread.
+    pub fn register_async_function<F, Fut>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(Vec<Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<Value>> + Send + 'static,
+    {
+        let bridge = self.bridge.clone();
+        self.register_function(name, arity, move |_interpreter, args| {
+            bridge.call(Box::pin(func(args.to_vec())))
+        });
+    }
+
+    /// Same as [`Lox::run`], but awaits async natives on the caller's runtime instead of
+    /// blocking it. The interpreter runs on a thread of its own meanwhile.
+    ///
+    /// If the future is dropped before completion, the instance is reset to a fresh one and the
+    /// script fails at its next async call.
+    pub async fn run_async(&mut self, source: &str) -> Result<(), LoxError> {
+        let shared = Arc::new(Shared::default());
+        *self.bridge.0.lock_unpoisoned() = Some(shared.clone());
+        let _cancel = CancelOnDrop(shared.clone());
+
+        let mut lox = std::mem::take(self);
+        let source = source.to_owned();
+        let thread_shared = shared.clone();
+        std::thread::spawn(move || {
+            let result = lox.run(&source);
+            *lox.bridge.0.lock_unpoisoned() = None;
+            thread_shared.notify(|state| state.done = Some((lox, result)));
+        });
+
+        loop {
+            match NextEvent(&shared).await {
+                Event::Call(call) => {
+                    let result = call.future.await;
+                    // The interpreter thread is waiting for it.
+                    let _ = call.reply.send(result);
+                }
+                Event::Done(done) => {
+                    let (lox, result) = *done;
+                    *self = lox;
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WritePrinter;
+    use std::time::Duration;
+
+    /// Completes from another thread after a while, like a timer of a runtime would.
+    fn delayed(value: f64) -> impl Future<Output = anyhow::Result<Value>> {
+        let (sender, receiver) = mpsc::channel();
+        let mut receiver = Some(receiver);
+        std::future::poll_fn(move |cx| {
+            if let Some(r) = &receiver {
+                if let Ok(value) = r.try_recv() {
+                    receiver = None;
+                    return Poll::Ready(Ok(Value::Number(value)));
+                }
+            }
+            let waker = cx.waker().clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(5));
+                let _ = sender.send(value);
+                waker.wake();
+            });
+            Poll::Pending
+        })
+    }
+
+    #[test]
+    fn test_run_async() {
+        let mut lox = Lox::with_printer(WritePrinter::new(std::io::sink()));
+        lox.register_async_function("fetch", 1, |args| async move {
+            let n: f64 = args[0].clone().try_into()?;
+            delayed(n * 2.0).await
+        });
+        block_on(lox.run_async("var a = fetch(1) + fetch(2);")).unwrap();
+        assert_eq!(Some(Value::Number(6.0)), lox.get_global("a"));
+
+        // Blocking the thread without run_async
+        lox.run("var b = fetch(a);").unwrap();
+        assert_eq!(Some(Value::Number(12.0)), lox.get_global("b"));
+    }
+}
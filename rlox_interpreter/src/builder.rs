@@ -0,0 +1,133 @@
+use crate::{Environment, EnvironmentPtr, Interpreter, Limits, Printer, StdOutPrinter};
+use std::io::BufRead;
+
+/// Configuration of an [`Interpreter`], created by [`Interpreter::builder`]:
+///
+/// ```
+/// use rlox_interpreter::{Interpreter, Limits};
+///
+/// let interpreter = Interpreter::builder()
+///     .stdlib(false)
+///     .limits(Limits {
+///         max_steps: Some(1000),
+///         ..Limits::default()
+///     })
+///     .strict(true)
+///     .build();
+/// assert_eq!(0, interpreter.globals().lock().unwrap().locals().count());
+/// ```
+pub struct InterpreterBuilder<'p> {
+    printer: Option<&'p mut dyn Printer>,
+    input: Option<&'p mut dyn BufRead>,
+    arguments: Vec<String>,
+    globals: Option<EnvironmentPtr>,
+    stdlib: bool,
+    limits: Limits,
+    strict: bool,
+    deterministic: bool,
+    frozen_globals: bool,
+    trace: bool,
+}
+
+impl Default for InterpreterBuilder<'_> {
+    fn default() -> Self {
+        Self {
+            printer: None,
+            input: None,
+            arguments: Vec::new(),
+            globals: None,
+            stdlib: true,
+            limits: Limits::default(),
+            strict: false,
+            deterministic: false,
+            frozen_globals: false,
+            trace: false,
+        }
+    }
+}
+
+impl<'p> InterpreterBuilder<'p> {
+    /// Destination of `print` statements, stdout by default.
+    pub fn printer(mut self, printer: &'p mut dyn Printer) -> Self {
+        self.printer = Some(printer);
+        self
+    }
+
+    /// Source of the `input` native, stdin by default.
+    pub fn input(mut self, input: &'p mut dyn BufRead) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    /// Values returned by the `args` native.
+    pub fn arguments(mut self, arguments: Vec<String>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    /// Global environment shared with the host, e.g. by a session outliving the interpreter,
+    /// instead of a new one. [`InterpreterBuilder::stdlib`] doesn't apply to it.
+    pub fn globals(mut self, globals: EnvironmentPtr) -> Self {
+        self.globals = Some(globals);
+        self
+    }
+
+    /// Whether the globals hold the native functions, true by default.
+    pub fn stdlib(mut self, stdlib: bool) -> Self {
+        self.stdlib = stdlib;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reject conditions which aren't booleans, instead of using their truthiness.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Make natives which depend on the outside world, such as `clock`, return fixed values.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Forbid the scripts to define or assign global variables, see
+    /// [`Interpreter::set_frozen_globals`].
+    pub fn frozen_globals(mut self, frozen: bool) -> Self {
+        self.frozen_globals = frozen;
+        self
+    }
+
+    /// Log every executed statement to stderr.
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub fn build(self) -> Interpreter<'p> {
+        // Leaking a zero-sized printer doesn't allocate anything.
+        let printer = self
+            .printer
+            .unwrap_or_else(|| Box::leak(Box::new(StdOutPrinter)));
+        let mut interpreter = Interpreter::new(printer);
+        if let Some(globals) = self.globals {
+            interpreter.set_globals(globals);
+        } else if !self.stdlib {
+            interpreter.set_globals(Environment::new_empty_globals_ptr());
+        }
+        if let Some(input) = self.input {
+            interpreter.set_input(input);
+        }
+        interpreter.set_arguments(self.arguments);
+        interpreter.set_limits(self.limits);
+        interpreter.set_strict(self.strict);
+        interpreter.set_deterministic(self.deterministic);
+        interpreter.set_frozen_globals(self.frozen_globals);
+        interpreter.set_trace(self.trace);
+        interpreter
+    }
+}
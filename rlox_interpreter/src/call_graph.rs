@@ -0,0 +1,34 @@
+use crate::{SymbolKind, Symbols};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Graphviz DOT graph of which functions call which, including the natives which are called.
+/// Calls from outside of any function come from a `<script>` node.
+pub fn call_graph_dot(symbols: &Symbols) -> String {
+    let node = |symbol: Option<usize>| match symbol {
+        Some(symbol) => format!("f{symbol}"),
+        None => "script".to_owned(),
+    };
+    let edges: BTreeSet<_> = symbols
+        .calls
+        .iter()
+        .map(|call| (call.caller, call.callee))
+        .collect();
+
+    let mut out =
+        String::from("digraph calls {\n    script [label=\"<script>\", shape=plaintext];\n");
+    for (index, symbol) in symbols.symbols.iter().enumerate() {
+        let shape = match symbol.kind {
+            SymbolKind::Function => "",
+            SymbolKind::Native if edges.iter().any(|&(_, callee)| callee == index) => ", shape=box",
+            _ => continue,
+        };
+        let name = symbol.name.replace('"', "\\\"");
+        writeln!(out, "    {} [label=\"{name}\"{shape}];", node(Some(index))).unwrap();
+    }
+    for (caller, callee) in edges {
+        writeln!(out, "    {} -> {};", node(caller), node(Some(callee))).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
@@ -0,0 +1,159 @@
+use crate::func::{check_arity, Callable, FunctionObject};
+use crate::interpreter::{Environment, Interpreter};
+use crate::sync::Unpoisoned;
+use crate::value::{Object, Value};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Mutex;
+
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Object<LoxClass>>,
+    pub methods: HashMap<String, Object<FunctionObject>>,
+    /// Methods declared `static`, called on the class itself.
+    pub static_methods: HashMap<String, Object<FunctionObject>>,
+}
+
+impl LoxClass {
+    /// The method `name` of the class, or else the one it inherits.
+    pub fn find_method(&self, name: &str) -> Option<&Object<FunctionObject>> {
+        self.methods.get(name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+
+    /// The static method `name` of the class, or else the one it inherits.
+    pub fn find_static_method(&self, name: &str) -> Option<&Object<FunctionObject>> {
+        self.static_methods.get(name).or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_static_method(name))
+        })
+    }
+}
+
+impl Debug for LoxClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LoxClass({:?})", self.name)
+    }
+}
+
+/// Calling a class creates an instance of it, so the instance can refer to the class shared by
+/// the value which was called. The arguments are given to the `init` method, if any.
+impl Callable for Object<LoxClass> {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    fn variadic(&self) -> bool {
+        self.find_method("init").is_some_and(|init| init.variadic)
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> anyhow::Result<Value> {
+        check_arity(self, args.len())?;
+        interpreter.record_allocation();
+        let instance = Object::new(LoxInstance::new(self.clone()));
+        if let Some(init) = self.find_method("init") {
+            let init = BoundMethod {
+                receiver: instance.clone(),
+                method: init.clone(),
+            };
+            init.call(interpreter, args)?;
+        }
+        Ok(Value::Instance(instance))
+    }
+}
+
+pub struct LoxInstance {
+    pub class: Object<LoxClass>,
+    fields: Mutex<HashMap<String, Value>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Object<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Value of the field `name`, `None` if it was never set.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.fields.lock_unpoisoned().get(name).cloned()
+    }
+
+    /// Value of the property `name` of `instance`: the field if there is one, which shadows a
+    /// method of the same name, or else the method bound to the instance.
+    pub fn property(instance: &Object<LoxInstance>, name: &str) -> Option<Value> {
+        if let Some(value) = instance.get(name) {
+            return Some(value);
+        }
+        let method = instance.class.find_method(name)?;
+        Some(Value::BoundMethod(Object::new(BoundMethod {
+            receiver: instance.clone(),
+            method: method.clone(),
+        })))
+    }
+
+    /// Copy of the values of the fields, in no particular order.
+    pub(crate) fn field_values(&self) -> Vec<Value> {
+        self.fields.lock_unpoisoned().values().cloned().collect()
+    }
+
+    /// Sets the field `name`, creating it if the instance doesn't have it yet.
+    pub fn set(&self, name: &str, value: Value) {
+        self.fields.lock_unpoisoned().insert(name.to_owned(), value);
+    }
+}
+
+impl Debug for LoxInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LoxInstance({:?})", self.class.name)
+    }
+}
+
+/// A method read from an instance, which remembers the instance to be `this` when called.
+pub struct BoundMethod {
+    pub receiver: Object<LoxInstance>,
+    pub method: Object<FunctionObject>,
+}
+
+impl Debug for BoundMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "BoundMethod({:?}, {:?})",
+            self.receiver.class.name, self.method.name
+        )
+    }
+}
+
+/// Calls the method in an environment defining `this`, between its closure and its parameters,
+/// where the resolver expects it.
+impl Callable for BoundMethod {
+    fn arity(&self) -> usize {
+        self.method.arity()
+    }
+
+    fn variadic(&self) -> bool {
+        self.method.variadic
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> anyhow::Result<Value> {
+        let environment = Environment::new_ptr(self.method.closure.clone());
+        environment
+            .lock_unpoisoned()
+            .define_variable("this", Value::Instance(self.receiver.clone()))?;
+        let method = FunctionObject {
+            name: self.method.name.clone(),
+            parameters: self.method.parameters.clone(),
+            variadic: self.method.variadic,
+            body: self.method.body.clone(),
+            closure: environment,
+            initializer: self.method.initializer,
+        };
+        method.call(interpreter, args)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{Resolver, Scope, SymbolKind, Symbols};
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Keyword,
+    /// Name of a function or native, where it's declared or referred to.
+    Function,
+    Parameter,
+    VariableRead,
+    /// A declaration or an assignment.
+    VariableWrite,
+    String,
+    Number,
+    Comment,
+    /// Name of a class, where it's declared or referred to.
+    Class,
+}
+
+impl TokenClass {
+    pub const ALL: [TokenClass; 9] = [
+        TokenClass::Keyword,
+        TokenClass::Function,
+        TokenClass::Parameter,
+        TokenClass::VariableRead,
+        TokenClass::VariableWrite,
+        TokenClass::String,
+        TokenClass::Number,
+        TokenClass::Comment,
+        TokenClass::Class,
+    ];
+
+    /// Short name, usable as a CSS class.
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Function => "function",
+            TokenClass::Parameter => "parameter",
+            TokenClass::VariableRead => "variable",
+            TokenClass::VariableWrite => "variable-write",
+            TokenClass::String => "string",
+            TokenClass::Number => "number",
+            TokenClass::Comment => "comment",
+            TokenClass::Class => "class",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassifiedRange {
+    pub span: Span,
+    pub class: TokenClass,
+}
+
+/// Classifies the tokens and comments of `source`, in source order. Identifiers are classified
+/// with the resolver's findings, so they are left out if the source doesn't parse or resolve;
+/// only scanning errors are errors.
+pub fn classify(source: &str) -> Result<Vec<ClassifiedRange>, LoxError> {
+    let (tokens, comments) = Scanner::new(source).scan_tokens_with_comments()?;
+    let symbols = resolved_symbols(&tokens).unwrap_or_default();
+    let identifier_class = |span: Span| {
+        let (symbol, write) = match symbols.references.iter().find(|r| r.span == span) {
+            Some(reference) => (reference.symbol, reference.write),
+            // Declarations write their variable.
+            None => (
+                symbols.symbols.iter().position(|s| s.span == Some(span))?,
+                true,
+            ),
+        };
+        Some(match symbols.symbols[symbol].kind {
+            SymbolKind::Function | SymbolKind::Native => TokenClass::Function,
+            SymbolKind::Parameter => TokenClass::Parameter,
+            SymbolKind::Variable if write => TokenClass::VariableWrite,
+            SymbolKind::Variable => TokenClass::VariableRead,
+            SymbolKind::Class => TokenClass::Class,
+        })
+    };
+
+    let mut ranges: Vec<_> = tokens
+        .iter()
+        .filter_map(|token| {
+            let class = match token.kind {
+                TokenKind::String => TokenClass::String,
+                TokenKind::Number => TokenClass::Number,
+                TokenKind::Identifier => identifier_class(token.span())?,
+                kind if kind.is_keyword() => TokenClass::Keyword,
+                _ => return None,
+            };
+            Some(ClassifiedRange {
+                span: token.span(),
+                class,
+            })
+        })
+        .chain(comments.iter().map(|comment| ClassifiedRange {
+            span: comment.span,
+            class: TokenClass::Comment,
+        }))
+        .collect();
+    ranges.sort_by_key(|r| (r.span.line, r.span.column));
+    Ok(ranges)
+}
+
+fn resolved_symbols(tokens: &[Token]) -> Option<Symbols> {
+    let mut statements = Parser::new(tokens.to_vec()).parse().ok()?;
+    let scope = Scope::new_globals_ptr();
+    for s in &mut statements {
+        Resolver.resolve_statement(&scope, s).ok()?;
+    }
+    Some(Symbols::collect(tokens, &statements))
+}
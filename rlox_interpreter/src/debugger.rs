@@ -0,0 +1,23 @@
+use crate::interpreter::{EnvironmentPtr, Interpreter};
+use rlox_syntax::{LoxError, Statement};
+
+/// Hook called by the interpreter while running a program, which can pause the execution to
+/// inspect it.
+pub trait Debugger {
+    /// Called before every statement is executed, with the environment it runs in. Returning an
+    /// error aborts the program with it.
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        environment: &EnvironmentPtr,
+        stmt: &Statement,
+    ) -> Result<(), LoxError>;
+}
+
+/// An active function call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallFrame {
+    pub name: String,
+    /// Line of the call expression.
+    pub line: usize,
+}
@@ -1,22 +1,48 @@
-use crate::interpreter::{Environment, Interpreter};
-use crate::value::Value;
+use crate::interpreter::{Environment, Interpreter, ReturnError};
+use crate::list::LoxList;
+use crate::sync::{Unpoisoned, UnpoisonedRw};
+use crate::value::{FromLoxArgs, Object, Value};
 use anyhow::bail;
 use rlox_syntax::Statement;
-use std::cmp::Ordering;
 use std::fmt::{Debug, Formatter};
 use std::sync::{Arc, Mutex, RwLock};
 
 pub trait Callable {
+    /// Number of arguments taken, or the least number of them if [`Callable::variadic`].
     fn arity(&self) -> usize;
+
+    /// Whether any number of arguments after the first [`Callable::arity`] ones may be given.
+    fn variadic(&self) -> bool {
+        false
+    }
+
     fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> anyhow::Result<Value>;
 }
 
+/// Fails unless `count` arguments can be given to `callable`.
+pub fn check_arity(callable: &impl Callable, count: usize) -> anyhow::Result<()> {
+    let arity = callable.arity();
+    if callable.variadic() {
+        if count < arity {
+            bail!("Expected at least {arity} arguments but got {count}.");
+        }
+    } else if count != arity {
+        bail!("Expected {arity} arguments but got {count}.");
+    }
+    Ok(())
+}
+
 pub struct FunctionObject {
     pub name: String,
     pub parameters: Vec<String>,
+    /// Whether the last of `parameters` is a rest parameter, bound to a list of the remaining
+    /// arguments.
+    pub variadic: bool,
     // RwLock needed to support recursive call
     pub body: Arc<RwLock<Statement>>,
     pub closure: Arc<Mutex<Environment>>,
+    /// Whether this is the `init` method of a class, which returns `this` whatever it returns.
+    pub initializer: bool,
 }
 
 impl Debug for FunctionObject {
@@ -27,26 +53,43 @@ impl Debug for FunctionObject {
 
 impl Callable for FunctionObject {
     fn arity(&self) -> usize {
-        self.parameters.len()
+        self.parameters.len() - usize::from(self.variadic)
+    }
+
+    fn variadic(&self) -> bool {
+        self.variadic
     }
 
     // TODO: leaky interpreter
     fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> anyhow::Result<Value> {
-        match args.len().cmp(&self.arity()) {
-            Ordering::Less => bail!("More args must be given"),
-            Ordering::Greater => bail!("Less args must be given"),
-            _ => {}
-        }
+        check_arity(self, args.len())?;
 
-        let environment = Environment::new_ptr(self.closure.clone());
+        let environment = interpreter.push_environment(&self.closure);
         {
-            let mut env = environment.lock().unwrap();
+            let mut env = environment.lock_unpoisoned();
+            let (args, rest) = args.split_at(self.arity());
             for (param, arg) in self.parameters.iter().zip(args.iter()) {
                 // TODO: do not clone
                 env.define_variable(param, arg.clone())?;
             }
+            if let (true, Some(param)) = (self.variadic, self.parameters.last()) {
+                interpreter.record_allocation();
+                let rest = Value::List(Object::new(LoxList::new(rest.to_vec())));
+                env.define_variable(param, rest)?;
+            }
         }
-        interpreter.evaluate_stmt(&environment, &self.body.read().unwrap())?;
+        let result = interpreter.execute(&environment, &self.body.read_unpoisoned());
+        interpreter.pop_environment(environment);
+        if self.initializer {
+            // The resolver made sure that `return` has no value in initializers
+            if let Err(e) = result {
+                if !e.is::<ReturnError>() {
+                    return Err(e);
+                }
+            }
+            return self.closure.lock_unpoisoned().get_variable("this", 0);
+        }
+        result?;
 
         Ok(Value::Nil)
     }
@@ -78,6 +121,52 @@ impl Callable for NativeFunction {
     }
 
     fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> anyhow::Result<Value> {
+        if args.len() != self.arity {
+            bail!("Expected {} arguments but got {}.", self.arity, args.len());
+        }
+        (self.func)(interpreter, args)
+    }
+}
+
+type HostFuncBox = Box<dyn Fn(&mut Interpreter, &[Value]) -> anyhow::Result<Value> + Send + Sync>;
+
+/// Native function backed by a closure, which unlike [`NativeFunction`] can capture state of the
+/// host program such as handles or channels.
+pub struct HostFunction {
+    pub name: String,
+    pub arity: usize,
+    func: HostFuncBox,
+}
+
+impl HostFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        func: impl Fn(&mut Interpreter, &[Value]) -> anyhow::Result<Value> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            arity,
+            func: Box::new(func),
+        }
+    }
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HostFunction({:?})", self.name)
+    }
+}
+
+impl Callable for HostFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> anyhow::Result<Value> {
+        if args.len() != self.arity {
+            bail!("Expected {} arguments but got {}.", self.arity, args.len());
+        }
         (self.func)(interpreter, args)
     }
 }
@@ -88,8 +177,15 @@ pub mod impls {
 
     pub static CLOCK: NativeFunction = NativeFunction {
         name: "clock",
-        arity: 2,
-        func: |_interpreter, _args| {
+        arity: 0,
+        func: |interpreter, _args| {
+            if interpreter.is_deterministic() {
+                return Ok(Value::Number(0.0));
+            }
+            // There is no system clock without a host to provide it.
+            if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+                bail!("clock is not available on this platform.");
+            }
             Ok(Value::Number(
                 SystemTime::now()
                     .duration_since(UNIX_EPOCH)
@@ -99,7 +195,36 @@ pub mod impls {
         },
     };
 
-    pub static ALL_FUNCS: &[&NativeFunction] = &[&CLOCK];
+    /// `args(i)` returns the i-th argument given to the script, or nil if there is none.
+    pub static ARGS: NativeFunction = NativeFunction {
+        name: "args",
+        arity: 1,
+        func: |interpreter, args| {
+            let (index,): (f64,) = FromLoxArgs::from_lox_args(args)?;
+            let argument = if index >= 0.0 && index.fract() == 0.0 {
+                interpreter.arguments().get(index as usize)
+            } else {
+                None
+            };
+            Ok(argument.cloned().into())
+        },
+    };
+
+    /// `input()` returns the next line of input without its line ending, or nil at the end.
+    pub static INPUT: NativeFunction = NativeFunction {
+        name: "input",
+        arity: 0,
+        func: |interpreter, _args| Ok(interpreter.read_line()?.into()),
+    };
+
+    /// `memoryStats()` describes what the session retains, see [`crate::HeapStats`].
+    pub static MEMORY_STATS: NativeFunction = NativeFunction {
+        name: "memoryStats",
+        arity: 0,
+        func: |interpreter, _args| Ok(interpreter.heap_stats().to_string().into()),
+    };
+
+    pub static ALL_FUNCS: &[&NativeFunction] = &[&CLOCK, &ARGS, &INPUT, &MEMORY_STATS];
 }
 
 #[cfg(test)]
@@ -120,10 +245,37 @@ mod tests {
         HELLO.call(&mut interpreter, &[]).unwrap();
     }
 
+    #[test]
+    fn test_args() {
+        let mut printer = StdOutPrinter;
+        let mut interpreter = Interpreter::new(&mut printer);
+        interpreter.set_arguments(vec!["foo".to_owned()]);
+        let first = impls::ARGS.call(&mut interpreter, &[Value::Number(0.0)]);
+        assert_eq!(Value::from("foo"), first.unwrap());
+        let second = impls::ARGS.call(&mut interpreter, &[Value::Number(1.0)]);
+        assert_eq!(Value::Nil, second.unwrap());
+        assert!(impls::ARGS.call(&mut interpreter, &[]).is_err());
+        let error = impls::ARGS
+            .call(&mut interpreter, &[Value::from("0")])
+            .unwrap_err();
+        assert_eq!("Expected a number but got string.", error.to_string());
+    }
+
     #[test]
     fn test_equal() {
         let f1 = Value::NativeFunction(&impls::CLOCK);
         let f2 = Value::NativeFunction(&impls::CLOCK);
         assert_eq!(f1, f2);
     }
+
+    #[test]
+    fn test_native_arity() {
+        let mut printer = StdOutPrinter;
+        let mut interpreter = Interpreter::new(&mut printer);
+        assert!(impls::CLOCK.call(&mut interpreter, &[]).is_ok());
+        let error = impls::CLOCK
+            .call(&mut interpreter, &[Value::Nil, Value::Nil])
+            .unwrap_err();
+        assert_eq!("Expected 0 arguments but got 2.", error.to_string());
+    }
 }
@@ -0,0 +1,202 @@
+use crate::sync::UnpoisonedRw;
+use crate::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Slots of the global variable names seen so far by a global environment, shared with the
+/// resolver of the code it runs so that the resolver numbers the names the same way.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GlobalSlots(Arc<RwLock<HashMap<Arc<str>, usize>>>);
+
+impl GlobalSlots {
+    /// Slot of `name`, `None` if no variable of that name was ever defined.
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.0.read_unpoisoned().get(name).copied()
+    }
+
+    /// Slot of `name`, which is allocated the first time the name is seen.
+    pub fn get_or_insert(&self, name: &str) -> usize {
+        if let Some(slot) = self.get(name) {
+            return slot;
+        }
+        let mut slots = self.0.write_unpoisoned();
+        let next = slots.len();
+        *slots.entry(name.into()).or_insert(next)
+    }
+}
+
+/// Variables of an environment. Global ones are stored by slot, so that the variables whose slot
+/// was found by the resolver are read without hashing their name; the others, such as the ones
+/// of the debugger's expressions, look their slot up by name. A slot found by a resolver with
+/// other slots than the environment's holds another name, and the variable is looked up by name
+/// too.
+#[derive(Debug)]
+pub(crate) enum Variables {
+    /// Few enough for a search to be faster than hashing.
+    Local(Vec<(String, Value)>),
+    Global {
+        slots: GlobalSlots,
+        values: Vec<Option<(Arc<str>, Value)>>,
+    },
+}
+
+impl Variables {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Variables::Local(variables) => variables
+                .iter()
+                .rfind(|(n, _)| n == name)
+                .map(|(_, value)| value),
+            Variables::Global { slots, values } => {
+                let slot = slots.get(name)?;
+                values.get(slot)?.as_ref().map(|(_, value)| value)
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self {
+            Variables::Local(variables) => variables
+                .iter_mut()
+                .rfind(|(n, _)| n == name)
+                .map(|(_, value)| value),
+            Variables::Global { slots, values } => {
+                let slot = slots.get(name)?;
+                values.get_mut(slot)?.as_mut().map(|(_, value)| value)
+            }
+        }
+    }
+
+    /// Index and value of the local variable `name`, checking at `hint` first.
+    pub fn get_local(&self, name: &str, hint: Option<usize>) -> Option<(usize, &Value)> {
+        let Variables::Local(variables) = self else {
+            return None;
+        };
+        let index = hint
+            .filter(|&i| variables.get(i).is_some_and(|(n, _)| n == name))
+            .or_else(|| variables.iter().rposition(|(n, _)| n == name))?;
+        Some((index, &variables[index].1))
+    }
+
+    /// Same as [`Variables::get_local`] for changing the variable.
+    pub fn get_local_mut(
+        &mut self,
+        name: &str,
+        hint: Option<usize>,
+    ) -> Option<(usize, &mut Value)> {
+        let Variables::Local(variables) = self else {
+            return None;
+        };
+        let index = hint
+            .filter(|&i| variables.get(i).is_some_and(|(n, _)| n == name))
+            .or_else(|| variables.iter().rposition(|(n, _)| n == name))?;
+        Some((index, &mut variables[index].1))
+    }
+
+    /// Global variable `name`, found at `slot` by the resolver.
+    pub fn get_global(&self, name: &str, slot: usize) -> Option<&Value> {
+        match self {
+            Variables::Local(_) => None,
+            Variables::Global { slots, values } => {
+                let slot = Self::checked_slot(slots, values, name, slot)?;
+                values.get(slot)?.as_ref().map(|(_, value)| value)
+            }
+        }
+    }
+
+    /// Same as [`Variables::get_global`] for changing the variable.
+    pub fn get_global_mut(&mut self, name: &str, slot: usize) -> Option<&mut Value> {
+        match self {
+            Variables::Local(_) => None,
+            Variables::Global { slots, values } => {
+                let slot = Self::checked_slot(slots, values, name, slot)?;
+                values.get_mut(slot)?.as_mut().map(|(_, value)| value)
+            }
+        }
+    }
+
+    /// `slot` if it holds `name`, or else the slot of `name` in `slots`.
+    fn checked_slot(
+        slots: &GlobalSlots,
+        values: &[Option<(Arc<str>, Value)>],
+        name: &str,
+        slot: usize,
+    ) -> Option<usize> {
+        match values.get(slot) {
+            Some(Some((n, _))) if **n == *name => Some(slot),
+            _ => slots.get(name),
+        }
+    }
+
+    /// Slots of the global variables, `None` for local ones.
+    pub fn global_slots(&self) -> Option<&GlobalSlots> {
+        match self {
+            Variables::Local(_) => None,
+            Variables::Global { slots, .. } => Some(slots),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, value: Value) {
+        match self {
+            Variables::Local(variables) => match variables.iter_mut().find(|(n, _)| n == name) {
+                Some((_, variable)) => *variable = value,
+                None => variables.push((name.to_owned(), value)),
+            },
+            Variables::Global { slots, values } => {
+                let slot = slots.get_or_insert(name);
+                if values.len() <= slot {
+                    values.resize(slot + 1, None);
+                }
+                values[slot] = Some((name.into(), value));
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&str, &Value)> + '_> {
+        match self {
+            Variables::Local(variables) => {
+                Box::new(variables.iter().map(|(name, value)| (name.as_str(), value)))
+            }
+            Variables::Global { values, .. } => Box::new(
+                values
+                    .iter()
+                    .flatten()
+                    .map(|(name, value)| (&**name, value)),
+            ),
+        }
+    }
+
+    /// Removes the variables, keeping the allocated memory for the next ones.
+    pub fn clear(&mut self) {
+        match self {
+            Variables::Local(variables) => variables.clear(),
+            Variables::Global { values, .. } => values.clear(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_slots() {
+        let mut variables = Variables::Global {
+            slots: GlobalSlots::default(),
+            values: Vec::new(),
+        };
+        variables.insert("a", Value::Nil);
+        assert_eq!(None, variables.get("typo"));
+        assert_eq!(None, variables.get_mut("typo"));
+        let Variables::Global { slots, .. } = &variables else {
+            unreachable!();
+        };
+        assert_eq!(None, slots.get("typo"));
+        assert_eq!(Some(0), slots.get("a"));
+
+        // Each global environment numbers its own variables
+        let other = GlobalSlots::default();
+        assert_eq!(0, other.get_or_insert("b"));
+        assert_eq!(None, slots.get("b"));
+    }
+}
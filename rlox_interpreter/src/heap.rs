@@ -0,0 +1,79 @@
+use crate::sync::Unpoisoned;
+use crate::{EnvironmentPtr, LoxInstance, LoxList, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// What a session retains, as returned by
+/// [`Interpreter::heap_stats`](crate::Interpreter::heap_stats).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Live environments: the global one and the ones captured by closures.
+    pub environments: usize,
+    /// Values held by variables, list elements and fields of instances, by type name.
+    pub values: BTreeMap<&'static str, usize>,
+    /// Distinct strings, values sharing a string counting it once.
+    pub strings: usize,
+    /// Total length of the distinct strings in bytes.
+    pub string_bytes: usize,
+}
+
+impl HeapStats {
+    /// Counts what is reachable from `globals`, following the closures of functions, the
+    /// elements of lists and the fields of instances.
+    pub fn collect(globals: &EnvironmentPtr) -> Self {
+        let mut stats = Self::default();
+        let mut environments = HashSet::new();
+        // Lists and instances by address, as they may contain themselves
+        let mut lists: HashSet<*const LoxList> = HashSet::new();
+        let mut instances: HashSet<*const LoxInstance> = HashSet::new();
+        // Lengths by address, to count shared strings once
+        let mut strings = HashMap::new();
+        let mut pending = vec![globals.clone()];
+        let mut values = Vec::new();
+        while let Some(environment) = pending.pop() {
+            if !environments.insert(Arc::as_ptr(&environment)) {
+                continue;
+            }
+            // Copying the values out, as a closure among them may refer to this environment.
+            let environment = environment.lock_unpoisoned();
+            pending.extend(environment.parent().cloned());
+            values.extend(environment.locals().map(|(_, value)| value.clone()));
+            drop(environment);
+            while let Some(value) = values.pop() {
+                *stats.values.entry(value.type_name()).or_default() += 1;
+                match value {
+                    Value::String(s) => {
+                        strings.insert(Arc::as_ptr(&s), s.len());
+                    }
+                    Value::FunctionObject(f) => pending.push(f.closure.clone()),
+                    Value::List(list) if lists.insert(&*list) => values.extend(list.to_vec()),
+                    Value::Instance(instance) if instances.insert(&*instance) => {
+                        values.extend(instance.field_values());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        stats.environments = environments.len();
+        stats.strings = strings.len();
+        stats.string_bytes = strings.values().sum();
+        stats
+    }
+}
+
+/// e.g. `environments: 2, strings: 1 (5 bytes), values: function 3, string 1`
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "environments: {}, strings: {} ({} bytes), values:",
+            self.environments, self.strings, self.string_bytes
+        )?;
+        for (i, (type_name, count)) in self.values.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{type_name} {count}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use crate::classify;
+use rlox_syntax::LoxError;
+
+/// Default colors for the classes of [`highlight_html`], as a stylesheet.
+pub const HIGHLIGHT_CSS: &str = "\
+pre.rlox { background: #fafafa; color: #383a42; padding: 1em; }
+pre.rlox .keyword { color: #a626a4; }
+pre.rlox .function { color: #4078f2; }
+pre.rlox .parameter { color: #986801; }
+pre.rlox .variable-write { color: #e45649; }
+pre.rlox .string { color: #50a14f; }
+pre.rlox .number { color: #986801; }
+pre.rlox .comment { color: #a0a1a7; font-style: italic; }
+pre.rlox .class { color: #c18401; }
+";
+
+/// `source` as an HTML `<pre class="rlox">` element, where every classified token is a `<span>`
+/// with the [name](crate::TokenClass::name) of its class as CSS class, such as
+/// `<span class="keyword">var</span>`. Fails only if the source doesn't scan.
+pub fn highlight_html(source: &str) -> Result<String, LoxError> {
+    let ranges = classify(source)?;
+    let chars: Vec<char> = source.chars().collect();
+    // Index in `chars` of the start of each line
+    let mut line_starts = vec![0];
+    line_starts.extend(
+        chars
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == '\n')
+            .map(|(i, _)| i + 1),
+    );
+
+    let mut out = String::from("<pre class=\"rlox\"><code>");
+    let mut position = 0;
+    for range in ranges {
+        let start = line_starts[range.span.line - 1] + range.span.column - 1;
+        let end = (start + range.span.len).min(chars.len());
+        escape_into(&mut out, &chars[position..start]);
+        out.push_str("<span class=\"");
+        out.push_str(range.class.name());
+        out.push_str("\">");
+        escape_into(&mut out, &chars[start..end]);
+        out.push_str("</span>");
+        position = end;
+    }
+    escape_into(&mut out, &chars[position..]);
+    out.push_str("</code></pre>\n");
+    Ok(out)
+}
+
+fn escape_into(out: &mut String, chars: &[char]) {
+    for c in chars {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(*c),
+        }
+    }
+}
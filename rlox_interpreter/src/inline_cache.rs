@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Positions at which variables were found in their environments, by the id of the expression
+/// which looked them up, so that running the expression again checks there first.
+#[derive(Default)]
+pub(crate) struct InlineCache(HashMap<usize, usize, BuildHasherDefault<IdHasher>>);
+
+impl InlineCache {
+    pub fn get(&self, id: usize) -> Option<usize> {
+        self.0.get(&id).copied()
+    }
+
+    pub fn set(&mut self, id: usize, index: usize) {
+        self.0.insert(id, index);
+    }
+}
+
+/// Hashes node ids, which are unique integers, with a single multiplication.
+#[derive(Default)]
+struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(self.0 << 8 | byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+}
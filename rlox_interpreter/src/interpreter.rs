@@ -1,20 +1,37 @@
+use crate::builder::InterpreterBuilder;
+use crate::class::{BoundMethod, LoxClass, LoxInstance};
+use crate::debugger::{CallFrame, Debugger};
 use crate::func;
 use crate::func::{Callable, FunctionObject};
+use crate::globals::{GlobalSlots, Variables};
+use crate::heap::HeapStats;
+use crate::inline_cache::InlineCache;
+use crate::limits::{LimitAction, LimitHandler, LimitKind, Limits};
+use crate::list::LoxList;
+use crate::metrics::InterpreterMetrics;
+use crate::sync::Unpoisoned;
 use crate::value::{Object, Value};
-use anyhow::bail;
-use rlox_syntax::{Expr, Statement, TokenKind};
+use rlox_syntax::{
+    codes, expr, AstPrinter, Diagnostic, Expr, Literal, LoxError, Span, Statement, TokenKind,
+    TraceFrame,
+};
 use std::collections::HashMap;
 use std::fmt::Formatter;
+use std::io::{BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub struct Environment {
     parent: Option<Arc<Mutex<Environment>>>,
-    variables: HashMap<String, Value>,
+    variables: Variables,
 }
 
 pub type EnvironmentPtr = Arc<Mutex<Environment>>;
 
+/// Number of released environments an interpreter keeps for reuse.
+const MAX_FREE_ENVIRONMENTS: usize = 64;
+
 impl Environment {
     pub fn new_ptr(parent: EnvironmentPtr) -> EnvironmentPtr {
         Arc::new(Mutex::new(Self::new(Some(parent), false)))
@@ -24,16 +41,24 @@ impl Environment {
         Arc::new(Mutex::new(Self::new(None, true)))
     }
 
+    /// Global environment without any native function, for running untrusted code.
+    pub fn new_empty_globals_ptr() -> EnvironmentPtr {
+        Arc::new(Mutex::new(Self::new(None, false)))
+    }
+
     fn new(parent: Option<EnvironmentPtr>, fill_global: bool) -> Environment {
-        let mut zelf = Self {
-            parent,
-            variables: HashMap::new(),
+        let variables = match parent {
+            Some(_) => Variables::Local(Vec::new()),
+            None => Variables::Global {
+                slots: GlobalSlots::default(),
+                values: Vec::new(),
+            },
         };
+        let mut zelf = Self { parent, variables };
 
         if fill_global {
             for f in func::impls::ALL_FUNCS {
-                zelf.variables
-                    .insert(f.name.to_owned(), Value::NativeFunction(f));
+                zelf.variables.insert(f.name, Value::NativeFunction(f));
             }
         }
 
@@ -45,28 +70,172 @@ impl Environment {
             if let Some(value) = self.variables.get(name) {
                 Ok(value.clone())
             } else {
-                panic!("Failed to resolve variable: {}", name);
+                Err(InternalError(format!("Failed to resolve variable: {name}")).into())
+            }
+        } else if let Some(parent) = &self.parent {
+            Ok(parent
+                .lock_unpoisoned()
+                .get_variable(name, resolution - 1)?)
+        } else {
+            Err(InternalError(format!("Failed to resolve variable: {name}")).into())
+        }
+    }
+
+    /// Same as [`Environment::get_variable`] for a local variable, returning the position at
+    /// which it was found, to be given as `hint` next time.
+    fn get_local_variable(
+        &self,
+        name: &str,
+        resolution: usize,
+        hint: Option<usize>,
+    ) -> anyhow::Result<(Value, usize)> {
+        if resolution == 0 {
+            match self.variables.get_local(name, hint) {
+                Some((index, value)) => Ok((value.clone(), index)),
+                // A global looked up by name, e.g. by the debugger
+                None => Ok((self.get_variable(name, 0)?, 0)),
+            }
+        } else if let Some(parent) = &self.parent {
+            parent
+                .lock_unpoisoned()
+                .get_local_variable(name, resolution - 1, hint)
+        } else {
+            Err(InternalError(format!("Failed to resolve variable: {name}")).into())
+        }
+    }
+
+    /// Same as [`Environment::get_variable`] for a global variable whose slot was found by the
+    /// resolver.
+    fn get_global(&self, name: &str, slot: usize, resolution: usize) -> anyhow::Result<Value> {
+        if resolution == 0 {
+            if let Some(value) = self.variables.get_global(name, slot) {
+                Ok(value.clone())
+            } else {
+                Err(InternalError(format!("Failed to resolve variable: {name}")).into())
             }
         } else if let Some(parent) = &self.parent {
-            Ok(parent.lock().unwrap().get_variable(name, resolution - 1)?)
+            parent
+                .lock_unpoisoned()
+                .get_global(name, slot, resolution - 1)
         } else {
-            panic!("Failed to resolve variable: {}", name);
+            Err(InternalError(format!("Failed to resolve variable: {name}")).into())
         }
     }
 
+    /// Slots of the variables of a global environment, `None` for other environments.
+    pub(crate) fn global_slots(&self) -> Option<&GlobalSlots> {
+        self.variables.global_slots()
+    }
+
+    pub(crate) fn parent(&self) -> Option<&EnvironmentPtr> {
+        self.parent.as_ref()
+    }
+
+    /// Names of the variables of this environment and of its parents, for suggestions.
+    fn visible_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.locals().map(|(name, _)| name.to_owned()).collect();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.lock_unpoisoned().visible_names());
+        }
+        names
+    }
+
+    /// Number of environments above this one.
+    pub fn depth(&self) -> usize {
+        match &self.parent {
+            Some(parent) => parent.lock_unpoisoned().depth() + 1,
+            None => 0,
+        }
+    }
+
+    /// How many levels should be escalated to find `name`, looking it up by name at runtime
+    /// instead of relying on the resolver.
+    pub fn depth_of(&self, name: &str) -> Option<usize> {
+        if self.variables.get(name).is_some() {
+            Some(0)
+        } else if let Some(parent) = &self.parent {
+            parent
+                .lock_unpoisoned()
+                .depth_of(name)
+                .map(|depth| depth + 1)
+        } else {
+            None
+        }
+    }
+
+    /// Looks up `name` in this environment only, without escalating to the parents.
+    pub fn get_local(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    /// Variables defined in this environment only, in no particular order.
+    pub fn locals(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.variables.iter()
+    }
+
     pub fn define_variable(&mut self, name: &str, value: Value) -> anyhow::Result<()> {
-        self.variables.insert(name.to_string(), value);
+        self.variables.insert(name, value);
         Ok(())
     }
 
     pub fn assign_variable(&mut self, name: &str, value: &Value) -> anyhow::Result<()> {
         // TODO: fun counter() { var c = 1; fun inc() { c = c + 1; return c; } return inc; }
-        if self.variables.contains_key(name) {
-            self.variables.insert(name.to_string(), value.clone());
+        if let Some(variable) = self.variables.get_mut(name) {
+            *variable = value.clone();
         } else if let Some(parent) = &self.parent {
-            parent.lock().unwrap().assign_variable(name, value)?;
+            parent.lock_unpoisoned().assign_variable(name, value)?;
         } else {
-            bail!("Undefined variable '{name}'.");
+            return Err(UndefinedVariable(name.to_owned()).into());
+        }
+        Ok(())
+    }
+
+    /// Same as [`Environment::assign_variable`] for a local variable found `resolution` levels
+    /// above, returning the position at which it was found, to be given as `hint` next time.
+    fn assign_local_variable(
+        &mut self,
+        name: &str,
+        resolution: usize,
+        hint: Option<usize>,
+        value: &Value,
+    ) -> anyhow::Result<usize> {
+        if resolution == 0 {
+            match self.variables.get_local_mut(name, hint) {
+                Some((index, variable)) => {
+                    *variable = value.clone();
+                    Ok(index)
+                }
+                None => self.assign_variable(name, value).map(|_| 0),
+            }
+        } else if let Some(parent) = &self.parent {
+            parent
+                .lock_unpoisoned()
+                .assign_local_variable(name, resolution - 1, hint, value)
+        } else {
+            Err(UndefinedVariable(name.to_owned()).into())
+        }
+    }
+
+    /// Same as [`Environment::assign_variable`] for a global variable whose slot was found by the
+    /// resolver.
+    fn assign_global(
+        &mut self,
+        name: &str,
+        slot: usize,
+        resolution: usize,
+        value: &Value,
+    ) -> anyhow::Result<()> {
+        if resolution == 0 {
+            match self.variables.get_global_mut(name, slot) {
+                Some(variable) => *variable = value.clone(),
+                None => return Err(UndefinedVariable(name.to_owned()).into()),
+            }
+        } else if let Some(parent) = &self.parent {
+            parent
+                .lock_unpoisoned()
+                .assign_global(name, slot, resolution - 1, value)?;
+        } else {
+            return Err(UndefinedVariable(name.to_owned()).into());
         }
         Ok(())
     }
@@ -74,104 +243,376 @@ impl Environment {
 
 pub struct Interpreter<'p> {
     printer: &'p mut dyn Printer,
+    // Command line arguments given to the script, exposed by the `args` native
+    arguments: Vec<String>,
+    // Log every executed statement to stderr
+    trace: bool,
+    debugger: Option<&'p mut dyn Debugger>,
+    metrics: Option<&'p mut dyn InterpreterMetrics>,
+    limits: Limits,
+    limit_handler: Option<&'p mut dyn LimitHandler>,
+    // Statements executed so far, counted against `limits.max_steps`
+    steps: u64,
+    call_stack: Vec<CallFrame>,
+    globals: EnvironmentPtr,
+    // Read by the `input` native, stdin if unset
+    input: Option<&'p mut dyn BufRead>,
+    // Only booleans are accepted as conditions
+    strict: bool,
+    // Natives depending on the outside world, such as `clock`, return fixed values
+    deterministic: bool,
+    // Scripts can't define nor assign global variables
+    frozen_globals: bool,
+    // Environments of finished blocks and calls which nothing refers to anymore, reused so that
+    // running them doesn't allocate
+    free_environments: Vec<EnvironmentPtr>,
+    // Where the local variables read or assigned by each expression were found last time
+    inline_cache: InlineCache,
 }
 
 impl<'p> Interpreter<'p> {
     pub fn new(printer: &'p mut dyn Printer) -> Self {
-        Self { printer }
+        Self {
+            printer,
+            arguments: Vec::new(),
+            trace: false,
+            debugger: None,
+            metrics: None,
+            limits: Limits::default(),
+            limit_handler: None,
+            steps: 0,
+            call_stack: Vec::new(),
+            globals: Environment::new_globals_ptr(),
+            input: None,
+            strict: false,
+            deterministic: false,
+            frozen_globals: false,
+            free_environments: Vec::new(),
+            inline_cache: InlineCache::default(),
+        }
+    }
+
+    /// Starts configuring an interpreter, as an alternative to calling the setters one by one.
+    pub fn builder() -> InterpreterBuilder<'p> {
+        InterpreterBuilder::default()
+    }
+
+    /// Global environment of the interpreter, holding the natives unless disabled by
+    /// [`InterpreterBuilder::stdlib`].
+    pub fn globals(&self) -> &EnvironmentPtr {
+        &self.globals
+    }
+
+    pub(crate) fn set_globals(&mut self, globals: EnvironmentPtr) {
+        self.globals = globals;
+    }
+
+    /// What the global environment retains, e.g. to find what a long-running session leaks.
+    pub fn heap_stats(&self) -> HeapStats {
+        HeapStats::collect(&self.globals)
     }
 
+    pub fn set_input(&mut self, input: &'p mut dyn BufRead) {
+        self.input = Some(input);
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Makes the global environment read-only for the scripts, which can still declare variables
+    /// and functions in blocks and functions. The host keeps changing it through
+    /// [`Interpreter::globals`], e.g. to provide natives and configuration values the scripts
+    /// must not overwrite.
+    pub fn set_frozen_globals(&mut self, frozen: bool) {
+        self.frozen_globals = frozen;
+    }
+
+    /// Reads a line of input without its line ending, or `None` at the end.
+    pub fn read_line(&mut self) -> anyhow::Result<Option<String>> {
+        let mut line = String::new();
+        let read = match self.input.as_deref_mut() {
+            Some(input) => input.read_line(&mut line)?,
+            None => std::io::stdin().lock().read_line(&mut line)?,
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(trimmed);
+        Ok(Some(line))
+    }
+
+    pub fn set_debugger(&mut self, debugger: &'p mut dyn Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    pub fn set_metrics(&mut self, metrics: &'p mut dyn InterpreterMetrics) {
+        self.metrics = Some(metrics);
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_limit_handler(&mut self, handler: &'p mut dyn LimitHandler) {
+        self.limit_handler = Some(handler);
+    }
+
+    /// Function calls in progress, the innermost last.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn set_arguments(&mut self, arguments: Vec<String>) {
+        self.arguments = arguments;
+    }
+
+    pub fn arguments(&self) -> &[String] {
+        &self.arguments
+    }
+
+    /// Executes a statement, such as a top-level one of a program.
     pub fn evaluate_stmt(
         &mut self,
-        environment: &Arc<Mutex<Environment>>,
+        environment: &EnvironmentPtr,
+        stmt: &Statement,
+    ) -> Result<(), LoxError> {
+        self.guard(stmt.span(), |zelf| zelf.execute(environment, stmt))
+    }
+
+    pub fn evaluate_expr(
+        &mut self,
+        environment: &EnvironmentPtr,
+        expr: &Expr,
+    ) -> Result<Value, LoxError> {
+        self.guard(expr.span(), |zelf| zelf.evaluate(environment, expr))
+    }
+
+    /// Runs `f` for the public entry points, reporting its panics as internal errors instead of
+    /// unwinding into the host.
+    fn guard<T>(
+        &mut self,
+        span: Span,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> Result<T, LoxError> {
+        match panic::catch_unwind(AssertUnwindSafe(|| f(self))) {
+            Ok(result) => result.map_err(|e| Self::runtime_error(span, e)),
+            Err(payload) => {
+                self.call_stack.clear();
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                let message = format!("Internal error: {message}");
+                Err(LoxError::Internal(Box::new(Self::internal_diagnostic(
+                    span, message,
+                ))))
+            }
+        }
+    }
+
+    pub(crate) fn execute(
+        &mut self,
+        environment: &EnvironmentPtr,
         stmt: &Statement,
     ) -> anyhow::Result<()> {
+        if self.trace {
+            Self::trace_statement(stmt);
+        }
+        if let Some(metrics) = self.metrics.as_deref_mut() {
+            metrics.statement_executed();
+        }
+        self.steps += 1;
+        if let Some(max_steps) = self.limits.max_steps {
+            if self.steps > max_steps {
+                let extra = self.exceed(LimitKind::Steps, max_steps, stmt.span())?;
+                self.limits.max_steps = Some(max_steps + extra);
+            }
+        }
+        // Taken out while it runs, as it receives the interpreter itself.
+        if let Some(debugger) = self.debugger.take() {
+            let result = debugger.before_statement(self, environment, stmt);
+            self.debugger = Some(debugger);
+            result?;
+        }
         match stmt {
             Statement::Expression(expr) => {
-                self.evaluate_expr(environment, &expr.expr)?;
+                self.evaluate(environment, &expr.expr)?;
             }
-            Statement::Print(expr) => {
-                let value = self.evaluate_expr(environment, &expr.expr)?;
-                self.printer.print(&format!("{:?}", value));
+            Statement::Print(print) => {
+                let mut values = Vec::with_capacity(print.exprs.len());
+                for expr in &print.exprs {
+                    values.push(self.evaluate(environment, expr)?);
+                }
+                self.printer.print_values(&values);
             }
             Statement::VariableDecl(var) => {
+                self.check_global_definition(environment, &var.name, var.span)?;
                 let value = if let Some(expr) = &var.expr {
-                    self.evaluate_expr(environment, &expr)?
+                    self.evaluate(environment, expr)?
                 } else {
                     Value::Nil
                 };
                 environment
-                    .lock()
-                    .unwrap()
+                    .lock_unpoisoned()
                     .define_variable(&var.name, value)?;
             }
             Statement::Block(block) => {
-                let environment = Environment::new_ptr(environment.clone());
-
-                for s in &block.statements {
-                    self.evaluate_stmt(&environment, s)?;
-                }
+                let environment = self.push_environment(environment);
+                let result = block
+                    .statements
+                    .iter()
+                    .try_for_each(|s| self.execute(&environment, s));
+                self.pop_environment(environment);
+                result?;
             }
             Statement::If(s) => {
-                let condition = self.evaluate_expr(environment, &s.condition)?;
-                if Self::is_truthy(&condition) {
-                    self.evaluate_stmt(environment, &s.then_branch)?;
+                let condition = self.evaluate(environment, &s.condition)?;
+                if self.is_truthy(&condition, s.condition.span())? {
+                    self.execute(environment, &s.then_branch)?;
                 } else if let Some(else_branch) = &s.else_branch {
-                    self.evaluate_stmt(environment, else_branch)?;
+                    self.execute(environment, else_branch)?;
                 }
             }
             Statement::While(s) => {
-                while Self::is_truthy(&self.evaluate_expr(environment, &s.condition)?) {
-                    self.evaluate_stmt(environment, &s.body)?;
+                // Left by the optimizer for loops which run until they return.
+                let forever = matches!(
+                    &s.condition,
+                    Expr::Literal(e) if e.literal == Literal::Boolean(true)
+                );
+                while forever || {
+                    let condition = self.evaluate(environment, &s.condition)?;
+                    self.is_truthy(&condition, s.condition.span())?
+                } {
+                    self.execute(environment, &s.body)?;
                 }
             }
             Statement::Function(s) => {
                 // identifier resolution 을 별도 pass 없이 여기에서 해도 되지 않나
+                self.check_global_definition(environment, &s.name, s.span)?;
                 let closure = environment.clone();
-                environment.lock().unwrap().define_variable(
+                self.record_allocation();
+                environment.lock_unpoisoned().define_variable(
                     &s.name,
                     Value::FunctionObject(Object::new(FunctionObject {
                         name: s.name.to_owned(),
                         parameters: s.params.to_owned(),
+                        variadic: s.variadic,
                         body: s.body.clone(),
                         closure,
+                        initializer: false,
                     })),
                 )?;
             }
             Statement::Return(expr) => {
                 let value = if let Some(expr) = &expr.value {
-                    self.evaluate_expr(environment, expr)?
+                    self.evaluate(environment, expr)?
                 } else {
                     Value::Nil
                 };
                 // Rewind stack until call statement, using this dirty way!
                 return Err(ReturnError(value).into());
             }
+            Statement::Class(s) => {
+                self.check_global_definition(environment, &s.name, s.span)?;
+                let superclass = match &s.superclass {
+                    Some(superclass) => match self.evaluate(environment, superclass)? {
+                        Value::Class(class) => Some(class),
+                        _ => {
+                            return Self::error(
+                                superclass.span(),
+                                codes::SUPERCLASS_NOT_A_CLASS,
+                                "Superclass must be a class.",
+                            )
+                        }
+                    },
+                    None => None,
+                };
+                // Methods of a subclass find the superclass as `super`, defined around them
+                let closure = match &superclass {
+                    Some(superclass) => {
+                        let closure = Environment::new_ptr(environment.clone());
+                        closure
+                            .lock_unpoisoned()
+                            .define_variable("super", Value::Class(superclass.clone()))?;
+                        closure
+                    }
+                    None => environment.clone(),
+                };
+                let mut methods = HashMap::new();
+                let mut static_methods = HashMap::new();
+                for method in &s.methods {
+                    let Statement::Function(method) = method else {
+                        continue;
+                    };
+                    let methods = if method.is_static {
+                        &mut static_methods
+                    } else {
+                        &mut methods
+                    };
+                    methods.insert(
+                        method.name.to_owned(),
+                        Object::new(FunctionObject {
+                            name: method.name.to_owned(),
+                            parameters: method.params.to_owned(),
+                            variadic: method.variadic,
+                            body: method.body.clone(),
+                            closure: closure.clone(),
+                            initializer: !method.is_static && method.name == "init",
+                        }),
+                    );
+                }
+                self.record_allocation();
+                environment.lock_unpoisoned().define_variable(
+                    &s.name,
+                    Value::Class(Object::new(LoxClass {
+                        name: s.name.to_owned(),
+                        superclass,
+                        methods,
+                        static_methods,
+                    })),
+                )?;
+            }
         }
         Ok(())
     }
 
-    pub fn evaluate_expr(
-        &mut self,
-        environment: &EnvironmentPtr,
-        expr: &Expr,
-    ) -> anyhow::Result<Value> {
+    fn evaluate(&mut self, environment: &EnvironmentPtr, expr: &Expr) -> anyhow::Result<Value> {
         let result = match expr {
             Expr::Binary(expr) => {
-                let lval = self.evaluate_expr(environment, &expr.left)?;
-                let rval = self.evaluate_expr(environment, &expr.right)?;
+                let lval = self.evaluate(environment, &expr.left)?;
+                let rval = self.evaluate(environment, &expr.right)?;
 
                 match (lval, expr.operator, rval) {
+                    (_, TokenKind::Comma, rval) => rval,
                     (Value::Number(l), TokenKind::Plus, Value::Number(r)) => Value::Number(l + r),
-                    (Value::String(mut l), TokenKind::Plus, Value::String(r)) => {
-                        l.push_str(&r);
-                        Value::String(l)
+                    (Value::String(l), TokenKind::Plus, Value::String(r)) => {
+                        self.record_allocation();
+                        Value::String([&*l, &*r].concat().into())
                     }
                     (Value::Number(l), TokenKind::Minus, Value::Number(r)) => Value::Number(l - r),
                     (Value::Number(l), TokenKind::Star, Value::Number(r)) => Value::Number(l * r),
                     (Value::Number(l), TokenKind::Slash, Value::Number(r)) => {
                         if r == 0.0 {
-                            bail!("Divided by zero");
+                            return Self::error(
+                                expr.span,
+                                codes::DIVISION_BY_ZERO,
+                                "Divided by zero",
+                            );
                         }
                         Value::Number(l / r)
                     }
@@ -186,86 +627,614 @@ impl<'p> Interpreter<'p> {
                     (Value::Number(l), TokenKind::LessEqual, Value::Number(r)) => {
                         Value::Boolean(l <= r)
                     }
+                    // Strings are ordered by their characters, so `"Z" < "a"`
+                    (Value::String(l), TokenKind::Greater, Value::String(r)) => {
+                        Value::Boolean(l > r)
+                    }
+                    (Value::String(l), TokenKind::GreaterEqual, Value::String(r)) => {
+                        Value::Boolean(l >= r)
+                    }
+                    (Value::String(l), TokenKind::Less, Value::String(r)) => Value::Boolean(l < r),
+                    (Value::String(l), TokenKind::LessEqual, Value::String(r)) => {
+                        Value::Boolean(l <= r)
+                    }
                     (lval, TokenKind::EqualEqual, rval) => Value::Boolean(lval == rval),
                     (lval, TokenKind::BangEqual, rval) => Value::Boolean(lval != rval),
                     (l, op, r) => {
-                        bail!("Unsupported binary operator: {:?} {:?} {:?}", l, op, r);
+                        return Self::error(
+                            expr.span,
+                            codes::INVALID_OPERANDS,
+                            format!("Unsupported binary operator: {:?} {:?} {:?}", l, op, r),
+                        );
                     }
                 }
             }
-            Expr::Grouping(expr) => self.evaluate_expr(environment, &expr.expr)?,
+            Expr::Grouping(expr) => self.evaluate(environment, &expr.expr)?,
             Expr::Literal(expr) => expr.literal.clone().into(),
             Expr::Unary(expr) => {
-                let rval = self.evaluate_expr(environment, &expr.right)?;
+                let rval = self.evaluate(environment, &expr.right)?;
                 match (expr.operator, rval) {
                     (TokenKind::Minus, Value::Number(n)) => Value::Number(-n),
-                    (TokenKind::Bang, rval) => Value::Boolean(Self::is_truthy(&rval)),
+                    (TokenKind::Bang, rval) => Value::Boolean(self.is_truthy(&rval, expr.span)?),
                     (op, r) => {
-                        bail!("Unsupported unary operator: {:?}{:?}", op, r);
+                        return Self::error(
+                            expr.span,
+                            codes::INVALID_OPERANDS,
+                            format!("Unsupported unary operator: {:?}{:?}", op, r),
+                        );
+                    }
+                }
+            }
+            Expr::Variable(expr) => {
+                let environment = environment.lock_unpoisoned();
+                match expr.slot {
+                    Some(slot) => environment.get_global(&expr.name, slot, expr.resolution),
+                    None => {
+                        let hint = self.inline_cache.get(expr.id);
+                        environment
+                            .get_local_variable(&expr.name, expr.resolution, hint)
+                            .map(|(value, index)| {
+                                if hint != Some(index) {
+                                    self.inline_cache.set(expr.id, index);
+                                }
+                                value
+                            })
                     }
                 }
+                .map_err(|e| Self::locate_in(&environment, expr.span, e))?
             }
-            Expr::Variable(expr) => environment
-                .lock()
-                .unwrap()
-                .get_variable(&expr.name, expr.resolution)?,
             Expr::Assign(expr) => {
-                let value = self.evaluate_expr(environment, &expr.value)?;
-                environment
-                    .lock()
-                    .unwrap()
-                    .assign_variable(&expr.name, &value)?;
+                let value = self.evaluate(environment, &expr.value)?;
+                // Only globals have a slot
+                if self.frozen_globals && expr.slot.is_some() {
+                    return Self::error(
+                        expr.span,
+                        codes::FROZEN_GLOBAL,
+                        format!(
+                            "Can't assign global variable '{}', globals are frozen.",
+                            expr.name
+                        ),
+                    );
+                }
+                let mut environment = environment.lock_unpoisoned();
+                match expr.slot {
+                    Some(slot) => {
+                        environment.assign_global(&expr.name, slot, expr.resolution, &value)
+                    }
+                    None => {
+                        let hint = self.inline_cache.get(expr.id);
+                        environment
+                            .assign_local_variable(&expr.name, expr.resolution, hint, &value)
+                            .map(|index| {
+                                if hint != Some(index) {
+                                    self.inline_cache.set(expr.id, index);
+                                }
+                            })
+                    }
+                }
+                .map_err(|e| Self::locate_in(&environment, expr.span, e))?;
                 value
             }
             Expr::Logical(expr) => {
-                let left = self.evaluate_expr(environment, &expr.left)?;
+                let left = self.evaluate(environment, &expr.left)?;
+                let truthy = self.is_truthy(&left, expr.span)?;
                 match expr.operator {
-                    TokenKind::Or if Self::is_truthy(&left) => left,
-                    TokenKind::And if !Self::is_truthy(&left) => left,
-                    _ => self.evaluate_expr(environment, &expr.right)?,
+                    TokenKind::Or if truthy => left,
+                    TokenKind::And if !truthy => left,
+                    _ => self.evaluate(environment, &expr.right)?,
                 }
             }
             Expr::Call(expr) => {
-                let callable = self.evaluate_expr(environment, &expr.callee)?;
+                let callable = match &expr.callee {
+                    // Nor are the arguments evaluated when the call is skipped
+                    Expr::Get(get) if get.optional => {
+                        match self.evaluate(environment, &get.object)? {
+                            Value::Nil => return Ok(Value::Nil),
+                            object => Self::property(object, get)?,
+                        }
+                    }
+                    callee => self.evaluate(environment, callee)?,
+                };
                 let mut arg_values = Vec::new();
                 for arg in &expr.arguments {
-                    arg_values.push(self.evaluate_expr(environment, arg)?);
+                    arg_values.push(self.evaluate(environment, arg)?);
                 }
 
-                let result = if let Value::NativeFunction(f) = callable {
-                    f.call(self, &arg_values)
-                } else if let Value::FunctionObject(f) = callable {
-                    f.call(self, &arg_values)
-                } else {
-                    bail!("Only function types can be called.");
+                let frame = |name: &str| CallFrame {
+                    name: name.to_owned(),
+                    line: expr.span.line,
                 };
+                if let Some(max_depth) = self.limits.max_call_depth {
+                    if self.call_stack.len() >= max_depth {
+                        let extra =
+                            self.exceed(LimitKind::CallDepth, max_depth as u64, expr.span)?;
+                        self.limits.max_call_depth = Some(max_depth + extra as usize);
+                    }
+                }
+                let result = match callable {
+                    Value::NativeFunction(f) => {
+                        self.enter(frame(f.name));
+                        f.call(self, &arg_values)
+                    }
+                    Value::FunctionObject(f) => {
+                        self.enter(frame(&f.name));
+                        f.call(self, &arg_values)
+                    }
+                    Value::HostFunction(f) => {
+                        self.enter(frame(&f.name));
+                        f.call(self, &arg_values)
+                    }
+                    Value::Class(class) => {
+                        self.enter(frame(&class.name));
+                        class.call(self, &arg_values)
+                    }
+                    Value::BoundMethod(method) => {
+                        self.enter(frame(&method.method.name));
+                        method.call(self, &arg_values)
+                    }
+                    _ => {
+                        return Self::error(
+                            expr.span,
+                            codes::NOT_CALLABLE,
+                            "Can only call functions and classes.",
+                        )
+                    }
+                };
+                let callee = self.call_stack.pop().expect("frame pushed by the call");
 
                 match result {
                     Ok(value) => value,
                     Err(e) => match e.downcast::<ReturnError>() {
                         Ok(re) => re.0,
                         Err(e) => {
-                            return Err(e);
+                            return Err(self.unwind(&callee, expr.span, e));
                         }
                     },
                 }
             }
+            Expr::Get(expr) => match self.evaluate(environment, &expr.object)? {
+                Value::Nil if expr.optional => Value::Nil,
+                object => Self::property(object, expr)?,
+            },
+            Expr::Set(expr) => {
+                let Value::Instance(instance) = self.evaluate(environment, &expr.object)? else {
+                    return Self::error(
+                        expr.span,
+                        codes::NOT_AN_INSTANCE,
+                        "Only instances have fields.",
+                    );
+                };
+                let value = self.evaluate(environment, &expr.value)?;
+                instance.set(&expr.name, value.clone());
+                value
+            }
+            Expr::This(expr) => environment
+                .lock_unpoisoned()
+                .get_variable("this", expr.resolution)
+                .map_err(|e| Self::locate(expr.span, e))?,
+            Expr::Super(expr) => {
+                let (superclass, receiver) = {
+                    let environment = environment.lock_unpoisoned();
+                    // `this` is defined right inside of the environment of `super`
+                    (
+                        environment.get_variable("super", expr.resolution),
+                        environment.get_variable("this", expr.resolution - 1),
+                    )
+                };
+                let (Value::Class(superclass), Value::Instance(receiver)) = (
+                    superclass.map_err(|e| Self::locate(expr.span, e))?,
+                    receiver.map_err(|e| Self::locate(expr.span, e))?,
+                ) else {
+                    let error = InternalError("Failed to resolve super".to_owned());
+                    return Err(Self::locate(expr.span, error.into()));
+                };
+                let Some(method) = superclass.find_method(&expr.method) else {
+                    return Self::error(
+                        expr.span,
+                        codes::UNDEFINED_PROPERTY,
+                        format!("Undefined property '{}'.", expr.method),
+                    );
+                };
+                Value::BoundMethod(Object::new(BoundMethod {
+                    receiver,
+                    method: method.clone(),
+                }))
+            }
+            Expr::List(expr) => {
+                let mut elements = Vec::with_capacity(expr.elements.len());
+                for element in &expr.elements {
+                    elements.push(self.evaluate(environment, element)?);
+                }
+                self.record_allocation();
+                Value::List(Object::new(LoxList::new(elements)))
+            }
+            Expr::Index(expr) => {
+                let object = self.evaluate(environment, &expr.object)?;
+                let index = self.evaluate(environment, &expr.index)?;
+                let Value::List(list) = object else {
+                    return Self::error(
+                        expr.span,
+                        codes::NOT_A_LIST,
+                        format!("Can only index lists, not {}.", object.type_name()),
+                    );
+                };
+                let index = Self::list_index(&list, &index, expr.span)?;
+                list.get(index).expect("index checked against the length")
+            }
+            Expr::Slice(expr) => {
+                let object = self.evaluate(environment, &expr.object)?;
+                let mut bound = |e: &Option<Expr>| {
+                    e.as_ref()
+                        .map(|e| self.evaluate(environment, e))
+                        .transpose()
+                };
+                let start = bound(&expr.start)?;
+                let end = bound(&expr.end)?;
+                match object {
+                    Value::List(list) => {
+                        let elements = list.to_vec();
+                        let len = elements.len();
+                        let start = Self::slice_bound(start, 0, len, expr.span)?;
+                        let end = Self::slice_bound(end, len, len, expr.span)?.max(start);
+                        self.record_allocation();
+                        Value::List(Object::new(LoxList::new(elements[start..end].to_vec())))
+                    }
+                    Value::String(s) => {
+                        let len = s.chars().count();
+                        let start = Self::slice_bound(start, 0, len, expr.span)?;
+                        let end = Self::slice_bound(end, len, len, expr.span)?.max(start);
+                        self.record_allocation();
+                        let slice: String = s.chars().skip(start).take(end - start).collect();
+                        Value::String(slice.into())
+                    }
+                    object => {
+                        return Self::error(
+                            expr.span,
+                            codes::NOT_A_LIST,
+                            format!(
+                                "Can only slice lists and strings, not {}.",
+                                object.type_name()
+                            ),
+                        );
+                    }
+                }
+            }
+            Expr::IndexSet(expr) => {
+                let object = self.evaluate(environment, &expr.object)?;
+                let Value::List(list) = object else {
+                    return Self::error(
+                        expr.span,
+                        codes::NOT_A_LIST,
+                        format!("Can only index lists, not {}.", object.type_name()),
+                    );
+                };
+                let index = self.evaluate(environment, &expr.index)?;
+                let value = self.evaluate(environment, &expr.value)?;
+                let index = Self::list_index(&list, &index, expr.span)?;
+                list.set(index, value.clone());
+                value
+            }
+            Expr::Lambda(expr) => {
+                self.record_allocation();
+                Value::FunctionObject(Object::new(FunctionObject {
+                    name: "lambda".to_owned(),
+                    parameters: expr.params.to_owned(),
+                    variadic: expr.variadic,
+                    body: expr.body.clone(),
+                    closure: environment.clone(),
+                    initializer: false,
+                }))
+            }
         };
 
         Ok(result)
     }
 
-    fn is_truthy(value: &Value) -> bool {
+    /// Fails if defining `name` in `environment` would change frozen globals.
+    fn check_global_definition(
+        &self,
+        environment: &EnvironmentPtr,
+        name: &str,
+        span: Span,
+    ) -> anyhow::Result<()> {
+        if self.frozen_globals && Arc::ptr_eq(environment, &self.globals) {
+            return Self::error(
+                span,
+                codes::FROZEN_GLOBAL,
+                format!("Can't define global variable '{name}', globals are frozen."),
+            );
+        }
+        Ok(())
+    }
+
+    /// Asks the handler what to do about a reached limit, returning how much it is raised by.
+    fn exceed(&mut self, kind: LimitKind, limit: u64, span: Span) -> anyhow::Result<u64> {
+        let action = match self.limit_handler.as_deref_mut() {
+            Some(handler) => handler.limit_reached(kind, limit),
+            None => LimitAction::Abort,
+        };
+        match action {
+            LimitAction::Grant(extra) if extra > 0 => Ok(extra),
+            _ => match kind {
+                LimitKind::Steps => Self::error(
+                    span,
+                    codes::LIMIT_REACHED,
+                    format!("Step limit of {limit} reached."),
+                ),
+                LimitKind::CallDepth => Self::error(
+                    span,
+                    codes::LIMIT_REACHED,
+                    format!("Call depth limit of {limit} reached."),
+                ),
+            },
+        }
+    }
+
+    fn enter(&mut self, frame: CallFrame) {
+        if let Some(metrics) = self.metrics.as_deref_mut() {
+            metrics.function_called(&frame.name);
+        }
+        self.call_stack.push(frame);
+    }
+
+    pub(crate) fn record_allocation(&mut self) {
+        if let Some(metrics) = self.metrics.as_deref_mut() {
+            metrics.value_allocated();
+        }
+    }
+
+    /// Creates an environment enclosed by `parent`, to be given back to
+    /// [`Interpreter::pop_environment`] once the block or call it was created for is finished.
+    pub(crate) fn push_environment(&mut self, parent: &EnvironmentPtr) -> EnvironmentPtr {
+        let environment = match self.free_environments.pop() {
+            Some(environment) => {
+                environment.lock_unpoisoned().parent = Some(parent.clone());
+                environment
+            }
+            None => Environment::new_ptr(parent.clone()),
+        };
+        if let Some(metrics) = self.metrics.as_deref_mut() {
+            metrics.environment_created(environment.lock_unpoisoned().depth());
+        }
+        environment
+    }
+
+    /// Keeps `environment` for reuse, unless a closure or the host still refers to it.
+    pub(crate) fn pop_environment(&mut self, environment: EnvironmentPtr) {
+        if Arc::strong_count(&environment) > 1
+            || self.free_environments.len() >= MAX_FREE_ENVIRONMENTS
+        {
+            return;
+        }
+        {
+            let mut env = environment.lock_unpoisoned();
+            env.parent = None;
+            env.variables.clear();
+        }
+        self.free_environments.push(environment);
+    }
+
+    fn error<T>(span: Span, code: &'static str, message: impl Into<String>) -> anyhow::Result<T> {
+        Err(Diagnostic::new(span, message).with_code(code).into())
+    }
+
+    fn internal_diagnostic(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(span, message).with_code(codes::INTERNAL_ERROR)
+    }
+
+    fn runtime_error(span: Span, error: anyhow::Error) -> LoxError {
+        let error = match error.downcast::<LoxError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<InternalError>() {
+            Ok(InternalError(message)) => {
+                return LoxError::Internal(Box::new(Self::internal_diagnostic(span, message)))
+            }
+            Err(error) => error,
+        };
+        match error.downcast::<Diagnostic>() {
+            Ok(diagnostic) => LoxError::Runtime(Box::new(diagnostic)),
+            Err(error) => {
+                let diagnostic =
+                    Diagnostic::new(span, error.to_string()).with_code(codes::RUNTIME_ERROR);
+                LoxError::Runtime(Box::new(diagnostic))
+            }
+        }
+    }
+
+    /// Attaches `span` to an error which doesn't know where it happened yet.
+    fn locate(span: Span, error: anyhow::Error) -> anyhow::Error {
+        if let Some(InternalError(message)) = error.downcast_ref::<InternalError>() {
+            LoxError::Internal(Box::new(Self::internal_diagnostic(span, message.clone()))).into()
+        } else if error.is::<Diagnostic>() || error.is::<LoxError>() || error.is::<ReturnError>() {
+            error
+        } else {
+            Diagnostic::new(span, error.to_string())
+                .with_code(codes::RUNTIME_ERROR)
+                .into()
+        }
+    }
+
+    /// Same as [`Interpreter::locate`], suggesting the variables of `environment` for an
+    /// undefined one.
+    fn locate_in(environment: &Environment, span: Span, error: anyhow::Error) -> anyhow::Error {
+        match error.downcast::<UndefinedVariable>() {
+            Ok(UndefinedVariable(name)) => {
+                let names = environment.visible_names();
+                Diagnostic::new(span, format!("Undefined variable '{name}'."))
+                    .with_code(codes::UNDEFINED_VARIABLE)
+                    .with_name_suggestion(&name, names.iter().map(String::as_str))
+                    .into()
+            }
+            Err(error) => Self::locate(span, error),
+        }
+    }
+
+    /// Same as [`Interpreter::locate`] for an error of the call at `span`, adding to the
+    /// backtrace of a runtime error the frame of `callee`, which it is leaving, and the line of
+    /// the call in the function it gets back to.
+    fn unwind(&self, callee: &CallFrame, span: Span, error: anyhow::Error) -> anyhow::Error {
+        let mut error = Self::locate(span, error);
+        let diagnostic = if let Some(diagnostic) = error.downcast_mut::<Diagnostic>() {
+            diagnostic
+        } else if let Some(LoxError::Runtime(diagnostic)) = error.downcast_mut::<LoxError>() {
+            diagnostic
+        } else {
+            return error;
+        };
+        if diagnostic.backtrace.is_empty() {
+            diagnostic.backtrace.push(TraceFrame {
+                function: callee.name.clone(),
+                line: diagnostic.span.line,
+            });
+        }
+        if let Some(caller) = self.call_stack.last() {
+            diagnostic.backtrace.push(TraceFrame {
+                function: caller.name.clone(),
+                line: span.line,
+            });
+        }
+        error
+    }
+
+    fn trace_statement(stmt: &Statement) {
+        // Only the head of compound statements, as their bodies are traced on their own.
+        let printed = AstPrinter::default().print_statement(stmt);
+        let head = printed.lines().next().unwrap_or_default();
+        eprintln!("[line {}] {}", stmt.span().line, head);
+    }
+
+    /// Truthiness of a condition at `span`. In strict mode, only booleans are accepted.
+    fn is_truthy(&self, value: &Value, span: Span) -> anyhow::Result<bool> {
         match value {
-            Value::Nil => false,
-            Value::Boolean(b) => *b,
-            _ => true,
+            Value::Boolean(b) => Ok(*b),
+            _ if self.strict => Self::error(
+                span,
+                codes::EXPECTED_BOOLEAN,
+                format!("Expected a boolean but got {}.", value.type_name()),
+            ),
+            Value::Nil => Ok(false),
+            _ => Ok(true),
         }
     }
+
+    /// Value of the property read by `expr` from `object`.
+    fn property(object: Value, expr: &expr::Get) -> anyhow::Result<Value> {
+        let property = match object {
+            Value::Instance(instance) => LoxInstance::property(&instance, &expr.name),
+            // Static methods are called on their class, which isn't `this` in them
+            Value::Class(class) => class
+                .find_static_method(&expr.name)
+                .map(|method| Value::FunctionObject(method.clone())),
+            _ => {
+                return Self::error(
+                    expr.span,
+                    codes::NOT_AN_INSTANCE,
+                    "Only instances and classes have properties.",
+                )
+            }
+        };
+        match property {
+            Some(value) => Ok(value),
+            None => Self::error(
+                expr.span,
+                codes::UNDEFINED_PROPERTY,
+                format!("Undefined property '{}'.", expr.name),
+            ),
+        }
+    }
+
+    /// Position in `list` of the element `index` at `span` refers to, which must be a whole
+    /// number below the length of the list.
+    fn list_index(list: &LoxList, index: &Value, span: Span) -> anyhow::Result<usize> {
+        let Value::Number(n) = *index else {
+            return Self::error(
+                span,
+                codes::INVALID_OPERANDS,
+                format!("Index must be a number but got {}.", index.type_name()),
+            );
+        };
+        let len = list.len();
+        if n.fract() != 0.0 || n < 0.0 || n >= len as f64 {
+            return Self::error(
+                span,
+                codes::INDEX_OUT_OF_RANGE,
+                format!("Index {n} is out of range for a list of length {len}."),
+            );
+        }
+        Ok(n as usize)
+    }
+
+    /// Position in a value of length `len` which a bound of a slice at `span` refers to, or
+    /// `default` if it's omitted. Negative bounds count from the end, and bounds beyond either
+    /// end are clamped to it, so that slicing never fails out of range.
+    fn slice_bound(
+        bound: Option<Value>,
+        default: usize,
+        len: usize,
+        span: Span,
+    ) -> anyhow::Result<usize> {
+        let Some(bound) = bound else {
+            return Ok(default);
+        };
+        let Value::Number(n) = bound else {
+            return Self::error(
+                span,
+                codes::INVALID_OPERANDS,
+                format!(
+                    "Slice bound must be a number but got {}.",
+                    bound.type_name()
+                ),
+            );
+        };
+        if n.fract() != 0.0 {
+            return Self::error(
+                span,
+                codes::INDEX_OUT_OF_RANGE,
+                format!("Slice bound {n} is not a whole number."),
+            );
+        }
+        let n = if n < 0.0 { n + len as f64 } else { n };
+        Ok(n.clamp(0.0, len as f64) as usize)
+    }
 }
 
+/// Destination of `print` statements.
 pub trait Printer {
     fn print(&mut self, message: &str);
+
+    /// Called with the value of a `print` statement. Override it to handle values in a structured
+    /// way; by default it prints the debug representation of the value.
+    fn print_value(&mut self, value: &Value) {
+        self.print(&format!("{:?}", value));
+    }
+
+    /// Called with the values of a `print` statement, printed on a single line separated by
+    /// spaces. A single value goes to [`Printer::print_value`].
+    fn print_values(&mut self, values: &[Value]) {
+        match values {
+            [value] => self.print_value(value),
+            _ => {
+                let line: Vec<_> = values.iter().map(|v| format!("{v:?}")).collect();
+                self.print(&line.join(" "));
+            }
+        }
+    }
+}
+
+impl<P: Printer + ?Sized> Printer for &mut P {
+    fn print(&mut self, message: &str) {
+        (**self).print(message);
+    }
+
+    fn print_value(&mut self, value: &Value) {
+        (**self).print_value(value);
+    }
+
+    fn print_values(&mut self, values: &[Value]) {
+        (**self).print_values(values);
+    }
 }
 
 pub struct StdOutPrinter;
@@ -276,8 +1245,52 @@ impl Printer for StdOutPrinter {
     }
 }
 
+/// Writes every printed line to `W`, such as a file, a socket or a `Vec<u8>`.
+///
+/// Printing can't fail, so the first write error is kept and returned by [`WritePrinter::flush`],
+/// and nothing more is written after it.
+pub struct WritePrinter<W: Write> {
+    writer: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> WritePrinter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flushes the writer, or returns the error which stopped printing.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Printer for WritePrinter<W> {
+    fn print(&mut self, message: &str) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", message) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
-struct ReturnError(Value);
+pub(crate) struct ReturnError(Value);
 
 impl std::fmt::Display for ReturnError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -286,3 +1299,29 @@ impl std::fmt::Display for ReturnError {
 }
 
 impl std::error::Error for ReturnError {}
+
+/// Assignment to a variable which isn't defined, turned into a diagnostic by
+/// [`Interpreter::locate_in`].
+#[derive(Debug)]
+struct UndefinedVariable(String);
+
+impl std::fmt::Display for UndefinedVariable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Undefined variable '{}'.", self.0)
+    }
+}
+
+impl std::error::Error for UndefinedVariable {}
+
+/// A broken invariant of the interpreter, such as an unresolved variable, reported as
+/// [`LoxError::Internal`].
+#[derive(Debug)]
+struct InternalError(String);
+
+impl std::fmt::Display for InternalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InternalError {}
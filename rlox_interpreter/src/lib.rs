@@ -1,9 +1,40 @@
+mod async_native;
+mod builder;
+mod call_graph;
+mod class;
+mod classify;
+mod debugger;
 mod func;
+mod globals;
+mod heap;
+mod highlight;
+mod inline_cache;
 mod interpreter;
+mod limits;
+mod list;
+mod lox;
+mod metrics;
+mod pool;
 mod resolver;
+mod symbols;
+mod sync;
 mod value;
 
+pub use async_native::*;
+pub use builder::*;
+pub use call_graph::*;
+pub use class::*;
+pub use classify::*;
+pub use debugger::*;
 pub use func::*;
+pub use heap::*;
+pub use highlight::*;
 pub use interpreter::*;
+pub use limits::*;
+pub use list::*;
+pub use lox::*;
+pub use metrics::*;
+pub use pool::*;
 pub use resolver::*;
+pub use symbols::*;
 pub use value::*;
@@ -0,0 +1,30 @@
+/// Bounds on the work a program can do, `None` meaning unbounded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Number of statements which can be executed.
+    pub max_steps: Option<u64>,
+    /// Number of nested function calls.
+    pub max_call_depth: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Steps,
+    CallDepth,
+}
+
+/// What to do once a limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitAction {
+    /// Raise the limit by the given amount and go on.
+    Grant(u64),
+    /// Stop the program with a runtime error.
+    Abort,
+}
+
+/// Decides what happens when a limit is reached, e.g. to grant more budget to trusted scripts or
+/// to log violations. Without a handler, the program is aborted.
+pub trait LimitHandler {
+    /// `limit` is the value which has been reached.
+    fn limit_reached(&mut self, kind: LimitKind, limit: u64) -> LimitAction;
+}
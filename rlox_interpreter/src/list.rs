@@ -0,0 +1,62 @@
+use crate::sync::Unpoisoned;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::sync::Mutex;
+
+thread_local! {
+    // Lists being formatted on this thread, from the outermost one
+    static FORMATTING: RefCell<Vec<*const LoxList>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A list of values, shared by the values referring to it, e.g. a list literal or the rest
+/// parameter of a variadic function.
+pub struct LoxList {
+    elements: Mutex<Vec<Value>>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<Value>) -> Self {
+        Self {
+            elements: Mutex::new(elements),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.lock_unpoisoned().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Element at `index`, `None` if the list is shorter.
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.elements.lock_unpoisoned().get(index).cloned()
+    }
+
+    /// Replaces the element at `index`, which must be within the list.
+    pub fn set(&self, index: usize, value: Value) {
+        self.elements.lock_unpoisoned()[index] = value;
+    }
+
+    /// Copy of the elements, which the list doesn't share.
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.elements.lock_unpoisoned().clone()
+    }
+}
+
+/// The elements are copied out to be formatted, as they may contain the list itself, which is
+/// then written `[...]`.
+impl Debug for LoxList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let list = self as *const LoxList;
+        if FORMATTING.with(|lists| lists.borrow().contains(&list)) {
+            return f.write_str("[...]");
+        }
+        FORMATTING.with(|lists| lists.borrow_mut().push(list));
+        let result = write!(f, "{:?}", self.to_vec());
+        FORMATTING.with(|lists| lists.borrow_mut().pop());
+        result
+    }
+}
@@ -0,0 +1,252 @@
+use crate::async_native::AsyncBridge;
+use crate::sync::Unpoisoned;
+use crate::{Environment, EnvironmentPtr, Interpreter, Printer, Resolver, Scope, ScopePtr};
+use crate::{HostFunction, Object, StdOutPrinter, Value};
+use rlox_parser::{optimize, Parser, Scanner};
+use rlox_syntax::Statement;
+pub use rlox_syntax::{LoxError, SourceOrigin};
+
+/// Scans, parses and resolves the source of `scanner` against `scope`, reporting every error
+/// found rather than the first. Scanning and parsing go on past their errors, while resolving is
+/// skipped after any, which would otherwise be followed by bogus errors, e.g. about variables
+/// whose declaration couldn't be parsed.
+pub fn analyze(scanner: Scanner, scope: &ScopePtr) -> Result<Vec<Statement>, Vec<LoxError>> {
+    let (tokens, mut errors) = scanner.scan_tokens_recovering();
+    let (mut statements, parse_errors) = Parser::new(tokens).parse_recovering();
+    errors.extend(parse_errors);
+    if errors.is_empty() {
+        errors = Resolver.resolve_statements(scope, &mut statements);
+    }
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Runs Lox source in a single call, wiring the scanner, parser, resolver and interpreter
+/// together. Definitions are kept from one call to the next.
+///
+/// An instance can be moved to another thread; see [`InterpreterPool`](crate::InterpreterPool) to
+/// run many scripts concurrently.
+///
+/// ```
+/// use rlox_interpreter::{Lox, Value};
+///
+/// let mut lox = Lox::new();
+/// lox.run("var a = 1;").unwrap();
+/// assert_eq!(Value::Number(3.0), lox.eval("a + 2").unwrap());
+/// ```
+pub struct Lox<'p> {
+    printer: Box<dyn Printer + Send + 'p>,
+    environment: EnvironmentPtr,
+    scope: ScopePtr,
+    arguments: Vec<String>,
+    frozen_globals: bool,
+    pub(crate) bridge: AsyncBridge,
+}
+
+impl Lox<'static> {
+    /// Creates an instance which prints to stdout.
+    pub fn new() -> Self {
+        Self::with_printer(StdOutPrinter)
+    }
+}
+
+impl Default for Lox<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'p> Lox<'p> {
+    /// Creates an instance which sends the output of `print` statements to `printer`.
+    pub fn with_printer(printer: impl Printer + Send + 'p) -> Self {
+        let environment = Environment::new_globals_ptr();
+        let scope = Scope::new_globals_ptr();
+        scope
+            .lock_unpoisoned()
+            .use_slots_of(&environment.lock_unpoisoned());
+        Self {
+            printer: Box::new(printer),
+            environment,
+            scope,
+            arguments: Vec::new(),
+            frozen_globals: false,
+            bridge: AsyncBridge::default(),
+        }
+    }
+
+    /// Sets the values returned by the `args` native function.
+    pub fn set_arguments(&mut self, arguments: Vec<String>) {
+        self.arguments = arguments;
+    }
+
+    /// Makes the globals defined so far, such as the natives, the host's and the ones of a
+    /// prelude script, read-only for the scripts run afterwards. They can still declare variables
+    /// and functions in blocks and functions, while defining or assigning a global is a runtime
+    /// error. [`Lox::set_global`] keeps working.
+    ///
+    /// ```
+    /// use rlox_interpreter::Lox;
+    ///
+    /// let mut lox = Lox::new();
+    /// lox.set_global("limit", 3.0);
+    /// lox.run("fun double(n) { return n * 2; }").unwrap();
+    /// lox.freeze_globals();
+    /// assert!(lox.run("{ var limit = double(limit); print limit; }").is_ok());
+    /// assert!(lox.run("limit = 10;").is_err());
+    /// assert!(lox.run("fun double(n) { return n; }").is_err());
+    /// ```
+    pub fn freeze_globals(&mut self) {
+        self.frozen_globals = true;
+    }
+
+    /// Value of the global variable `name`, e.g. to read back a result after [`Lox::run`].
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.environment.lock_unpoisoned().get_local(name).cloned()
+    }
+
+    /// Defines or overwrites the global variable `name`, visible to the scripts run afterwards.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.scope.lock_unpoisoned().declare_initialized(name);
+        // Defining a variable can't fail.
+        let _ = self
+            .environment
+            .lock_unpoisoned()
+            .define_variable(name, value.into());
+    }
+
+    /// Defines a global function calling `func`, which can capture state of the host program:
+    ///
+    /// ```
+    /// use rlox_interpreter::{FromLoxArgs, Lox, Value};
+    ///
+    /// let offset = 10.0;
+    /// let mut lox = Lox::new();
+    /// lox.register_function("shift", 1, move |_interpreter, args| {
+    ///     let (n,): (f64,) = FromLoxArgs::from_lox_args(args)?;
+    ///     Ok(Value::from(n + offset))
+    /// });
+    /// assert_eq!(Value::Number(11.0), lox.eval("shift(1)").unwrap());
+    /// ```
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&mut Interpreter, &[Value]) -> anyhow::Result<Value> + Send + Sync + 'static,
+    ) {
+        let function = HostFunction::new(name, arity, func);
+        self.set_global(name, Value::HostFunction(Object::new(function)));
+    }
+
+    /// Global variables sorted by name, including native functions.
+    pub fn globals(&self) -> impl Iterator<Item = (String, Value)> {
+        let environment = self.environment.lock_unpoisoned();
+        let mut globals: Vec<_> = environment
+            .locals()
+            .map(|(name, value)| (name.to_owned(), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals.into_iter()
+    }
+
+    /// Runs the statements of `source`.
+    pub fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        self.run_tokens(Scanner::new(source)).map(|_| ())
+    }
+
+    /// Same as [`Lox::run`], returning the value of the last statement if it's an expression
+    /// statement, for interactive front ends to show:
+    ///
+    /// ```
+    /// use rlox_interpreter::{Lox, Value};
+    ///
+    /// let mut lox = Lox::new();
+    /// assert_eq!(Some(Value::Number(2.0)), lox.run_with_result("var a = 1; a + 1;").unwrap());
+    /// assert_eq!(None, lox.run_with_result("var b = a;").unwrap());
+    /// ```
+    pub fn run_with_result(&mut self, source: &str) -> Result<Option<Value>, LoxError> {
+        self.run_tokens(Scanner::new(source))
+    }
+
+    /// Same as [`Lox::run`] for a source which is part of something larger, reporting errors at
+    /// their location within `origin`.
+    pub fn run_from(&mut self, origin: &SourceOrigin, source: &str) -> Result<(), LoxError> {
+        self.run_tokens(Scanner::new(source).with_origin(origin))
+            .map(|_| ())
+            .map_err(|e| e.with_source_name(&origin.name))
+    }
+
+    /// Same as [`Lox::run`], but reports every error found before running rather than the first,
+    /// nothing being run if there is any. See [`analyze`].
+    ///
+    /// ```
+    /// use rlox_interpreter::Lox;
+    ///
+    /// let mut lox = Lox::new();
+    /// let errors = lox.run_checked("print 1 +; print a;\nvar = 2;").unwrap_err();
+    /// assert_eq!(2, errors.len());
+    /// ```
+    pub fn run_checked(&mut self, source: &str) -> Result<(), Vec<LoxError>> {
+        self.analyze_and_execute(Scanner::new(source)).map(|_| ())
+    }
+
+    fn run_tokens(&mut self, scanner: Scanner) -> Result<Option<Value>, LoxError> {
+        self.analyze_and_execute(scanner).map_err(|errors| {
+            errors
+                .into_iter()
+                .next()
+                .expect("a failed run has an error")
+        })
+    }
+
+    /// Analyzes and runs the source of `scanner`, forgetting the globals it declared without
+    /// defining them if it fails, so that a later run can declare them again.
+    fn analyze_and_execute(&mut self, scanner: Scanner) -> Result<Option<Value>, Vec<LoxError>> {
+        let result = analyze(scanner, &self.scope)
+            .and_then(|statements| self.execute(statements).map_err(|e| vec![e]));
+        if result.is_err() {
+            self.scope
+                .lock_unpoisoned()
+                .forget_undefined(&self.environment.lock_unpoisoned());
+        }
+        result
+    }
+
+    fn execute(&mut self, mut statements: Vec<Statement>) -> Result<Option<Value>, LoxError> {
+        optimize(&mut statements);
+        let environment = self.environment.clone();
+        let last = match statements.pop() {
+            Some(Statement::Expression(last)) => Some(last),
+            Some(s) => {
+                statements.push(s);
+                None
+            }
+            None => None,
+        };
+        let mut interpreter = self.interpreter();
+        for s in &statements {
+            interpreter.evaluate_stmt(&environment, s)?;
+        }
+        last.map(|last| interpreter.evaluate_expr(&environment, &last.expr))
+            .transpose()
+    }
+
+    /// Evaluates a single expression, such as `a + 1`, against the global environment.
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let mut expr = Parser::new(tokens).parse_single_expression()?;
+        Resolver.resolve_expression(&self.scope, &mut expr)?;
+        let environment = self.environment.clone();
+        self.interpreter().evaluate_expr(&environment, &expr)
+    }
+
+    fn interpreter(&mut self) -> Interpreter<'_> {
+        let mut interpreter = Interpreter::new(self.printer.as_mut());
+        interpreter.set_globals(self.environment.clone());
+        interpreter.set_arguments(self.arguments.clone());
+        interpreter.set_frozen_globals(self.frozen_globals);
+        interpreter
+    }
+}
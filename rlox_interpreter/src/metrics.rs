@@ -0,0 +1,45 @@
+/// Receives events of the interpreter, e.g. to feed a monitoring system. Every method does
+/// nothing by default, so implementations only pick the events they need.
+pub trait InterpreterMetrics {
+    fn statement_executed(&mut self) {}
+
+    /// Called when any function is called, native or not.
+    fn function_called(&mut self, _name: &str) {}
+
+    /// Called when a function object or a string is created at runtime.
+    fn value_allocated(&mut self) {}
+
+    /// Called when an environment is created for a block or a call, `depth` levels below the
+    /// globals.
+    fn environment_created(&mut self, _depth: usize) {}
+}
+
+/// Metrics which count events, for hosts which only need totals.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MetricCounters {
+    pub statements: usize,
+    pub calls: usize,
+    pub allocations: usize,
+    pub environments: usize,
+    /// Deepest environment created.
+    pub peak_depth: usize,
+}
+
+impl InterpreterMetrics for MetricCounters {
+    fn statement_executed(&mut self) {
+        self.statements += 1;
+    }
+
+    fn function_called(&mut self, _name: &str) {
+        self.calls += 1;
+    }
+
+    fn value_allocated(&mut self) {
+        self.allocations += 1;
+    }
+
+    fn environment_created(&mut self, depth: usize) {
+        self.environments += 1;
+        self.peak_depth = self.peak_depth.max(depth);
+    }
+}
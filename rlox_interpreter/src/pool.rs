@@ -0,0 +1,116 @@
+use crate::sync::Unpoisoned;
+use crate::{Lox, WritePrinter};
+use rlox_syntax::LoxError;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// What a script run by [`InterpreterPool`] printed, and how it ended.
+#[derive(Debug)]
+pub struct ScriptOutput {
+    pub output: String,
+    pub result: Result<(), LoxError>,
+}
+
+type Job = (String, Sender<ScriptOutput>);
+
+/// Fixed set of worker threads running independent scripts concurrently. Every script runs on a
+/// fresh [`Lox`], so scripts never see each other's globals.
+///
+/// ```
+/// use rlox_interpreter::InterpreterPool;
+///
+/// let pool = InterpreterPool::new(2);
+/// let pending: Vec<_> = (0..4).map(|i| pool.execute(format!("print {i};"))).collect();
+/// for (i, receiver) in pending.into_iter().enumerate() {
+///     assert_eq!(format!("Number({i}.0)\n"), receiver.recv().unwrap().output);
+/// }
+/// ```
+pub struct InterpreterPool {
+    // None once dropped, which makes the workers stop
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl InterpreterPool {
+    pub fn new(threads: usize) -> Self {
+        assert!(threads > 0, "a pool needs at least one thread");
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads)
+            .map(|_| {
+                let receiver = receiver.clone();
+                std::thread::spawn(move || Self::work(&receiver))
+            })
+            .collect();
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queues `source`, returning a receiver of its output once it has run.
+    pub fn execute(&self, source: impl Into<String>) -> Receiver<ScriptOutput> {
+        let (sender, receiver) = mpsc::channel();
+        self.sender
+            .as_ref()
+            .expect("pool is running")
+            .send((source.into(), sender))
+            .expect("workers are alive");
+        receiver
+    }
+
+    fn work(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            let job = receiver.lock_unpoisoned().recv();
+            let Ok((source, sender)) = job else {
+                return;
+            };
+            let mut printer = WritePrinter::new(Vec::new());
+            let result = Lox::with_printer(&mut printer).run(&source);
+            let output = String::from_utf8_lossy(printer.get_ref()).into_owned();
+            // The caller may have stopped waiting.
+            let _ = sender.send(ScriptOutput { output, result });
+        }
+    }
+}
+
+impl Drop for InterpreterPool {
+    fn drop(&mut self) {
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EnvironmentPtr, Value};
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_send() {
+        assert_send::<Value>();
+        assert_send::<EnvironmentPtr>();
+        assert_send::<Lox>();
+        assert_send::<LoxError>();
+    }
+
+    #[test]
+    fn test_isolation() {
+        let pool = InterpreterPool::new(3);
+        let pending: Vec<_> = (0..6)
+            .map(|i| pool.execute(format!("var a = {i}; print a * 2;")))
+            .collect();
+        for (i, receiver) in pending.into_iter().enumerate() {
+            let script = receiver.recv().unwrap();
+            assert!(script.result.is_ok());
+            assert_eq!(format!("Number({}.0)\n", i * 2), script.output);
+        }
+        let failed = pool.execute("print undefined;").recv().unwrap();
+        assert!(matches!(failed.result, Err(LoxError::Resolve(_))));
+    }
+}
@@ -1,42 +1,130 @@
-use anyhow::bail;
-use rlox_syntax::{Expr, Statement};
-use std::cell::RefCell;
+use crate::func;
+use crate::globals::GlobalSlots;
+use crate::interpreter::Environment;
+use crate::sync::{Unpoisoned, UnpoisonedRw};
+use rlox_syntax::{codes, statement, Diagnostic, Expr, LoxError, Node, Span, Statement};
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
 
 enum VariableState {
     Declared,
     Initialized,
 }
 
-pub type ScopePtr = Rc<RefCell<Scope>>;
+pub type ScopePtr = Arc<Mutex<Scope>>;
 
 pub struct Scope {
     parent: Option<ScopePtr>,
     variables: HashMap<String, VariableState>,
+    // Where the variables declared by `var` are
+    declarations: HashMap<String, Span>,
+    // Where the variables declared by `const` are, which can't be assigned
+    constants: HashMap<String, Span>,
+    // Parameters of a static method, which hide the `this` of any method around its class
+    static_method: bool,
+    // Slots of the globals, for the global scope
+    slots: GlobalSlots,
 }
 
 impl Scope {
     pub fn new_ptr(parent: Option<ScopePtr>) -> ScopePtr {
-        Rc::new(RefCell::new(Self::new(parent)))
+        Arc::new(Mutex::new(Self::new(parent)))
+    }
+
+    pub fn new_globals_ptr() -> ScopePtr {
+        let scope = Self::new_ptr(None);
+        for f in func::impls::ALL_FUNCS {
+            scope
+                .lock_unpoisoned()
+                .variables
+                .insert(f.name.to_owned(), VariableState::Initialized);
+        }
+        scope
+    }
+
+    /// Declares a variable defined outside of the resolved code, e.g. by a host program.
+    pub fn declare_initialized(&mut self, name: &str) {
+        self.variables
+            .insert(name.to_owned(), VariableState::Initialized);
+    }
+
+    /// Numbers the globals with the slots of `environment`, the global environment running the
+    /// resolved code, so that it reads them by slot rather than by name.
+    pub fn use_slots_of(&mut self, environment: &Environment) {
+        if let Some(slots) = environment.global_slots() {
+            self.slots = slots.clone();
+        }
+    }
+
+    /// Forgets the variables which `environment`, the global environment running the resolved
+    /// code, doesn't define, e.g. the ones declared by a REPL line which failed before defining
+    /// them.
+    pub fn forget_undefined(&mut self, environment: &Environment) {
+        let defined = |name: &String| environment.get_variable(name, 0).is_ok();
+        self.variables.retain(|name, _| defined(name));
+        self.declarations.retain(|name, _| defined(name));
+        self.constants.retain(|name, _| defined(name));
+    }
+
+    /// Names of the variables of this scope and of its parents, for suggestions.
+    fn visible_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.variables.keys().cloned().collect();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.lock_unpoisoned().visible_names());
+        }
+        names
     }
 
     fn new(parent: Option<ScopePtr>) -> Self {
         Self {
             parent,
             variables: HashMap::new(),
+            declarations: HashMap::new(),
+            constants: HashMap::new(),
+            static_method: false,
+            slots: GlobalSlots::default(),
         }
     }
 
-    fn resolve(&self, name: &str) -> Option<usize> {
+    /// How many levels should be escalated to find `name`, with its slot if it's a global.
+    fn resolve(&self, name: &str) -> Option<(usize, Option<usize>)> {
         if let Some(&VariableState::Initialized) = self.variables.get(name) {
-            Some(0)
+            let slot = self
+                .parent
+                .is_none()
+                .then(|| self.slots.get_or_insert(name));
+            Some((0, slot))
         } else if let Some(parent) = &self.parent {
-            parent.borrow_mut().resolve(name).map(|n| n + 1)
+            let (resolution, slot) = parent.lock_unpoisoned().resolve(name)?;
+            Some((resolution + 1, slot))
         } else {
             None
         }
     }
+
+    /// Where `name`, found `resolution` levels up, was declared if it's a constant.
+    fn constant(&self, name: &str, resolution: usize) -> Option<Span> {
+        if resolution == 0 {
+            self.constants.get(name).copied()
+        } else {
+            let parent = self.parent.as_ref()?;
+            parent.lock_unpoisoned().constant(name, resolution - 1)
+        }
+    }
+
+    /// How many levels should be escalated to find `this`, or else where it's used: "in a static
+    /// method" or "outside of a class".
+    fn resolve_this(&self) -> Result<usize, &'static str> {
+        if self.variables.contains_key("this") {
+            Ok(0)
+        } else if self.static_method {
+            Err("in a static method")
+        } else if let Some(parent) = &self.parent {
+            parent.lock_unpoisoned().resolve_this().map(|r| r + 1)
+        } else {
+            Err("outside of a class")
+        }
+    }
 }
 
 pub struct ResolvedStatement(pub Statement);
@@ -44,41 +132,74 @@ pub struct ResolvedStatement(pub Statement);
 pub struct Resolver;
 
 impl Resolver {
+    /// Resolves each of `statements`, going on after an error so that all of the statements
+    /// in error are reported rather than the first one.
+    pub fn resolve_statements(
+        &mut self,
+        scope: &ScopePtr,
+        statements: &mut [Statement],
+    ) -> Vec<LoxError> {
+        statements
+            .iter_mut()
+            .filter_map(|s| self.resolve_statement(scope, s).err())
+            .collect()
+    }
+
     pub fn resolve_statement(
         &mut self,
         scope: &ScopePtr,
         statement: &mut Statement,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), LoxError> {
         match statement {
             Statement::Expression(stmt) => {
                 self.resolve_expression(scope, &mut stmt.expr)?;
             }
             Statement::Print(stmt) => {
-                self.resolve_expression(scope, &mut stmt.expr)?;
+                for expr in &mut stmt.exprs {
+                    self.resolve_expression(scope, expr)?;
+                }
             }
             Statement::VariableDecl(stmt) => {
-                if scope.borrow().variables.contains_key(&stmt.name) {
-                    bail!(
-                        "Already a variable with this name in this scope: {}",
-                        stmt.name
-                    )
+                if scope.lock_unpoisoned().variables.contains_key(&stmt.name) {
+                    let mut diagnostic = Diagnostic::new(
+                        stmt.span,
+                        format!(
+                            "Already a variable with this name in this scope: {}",
+                            stmt.name
+                        ),
+                    );
+                    if let Some(&previous) = scope.lock_unpoisoned().declarations.get(&stmt.name) {
+                        diagnostic = diagnostic.with_label(previous, "previously declared here");
+                    }
+                    let diagnostic = diagnostic
+                        .with_code(codes::REDECLARED_VARIABLE)
+                        .with_note("Use assignment to change the value.");
+                    return Err(LoxError::Resolve(Box::new(diagnostic)));
+                }
+                {
+                    let mut scope = scope.lock_unpoisoned();
+                    scope
+                        .variables
+                        .insert(stmt.name.clone(), VariableState::Declared);
+                    scope.declarations.insert(stmt.name.clone(), stmt.span);
+                    if stmt.constant {
+                        scope.constants.insert(stmt.name.clone(), stmt.span);
+                    } else {
+                        scope.constants.remove(&stmt.name);
+                    }
                 }
-                scope
-                    .borrow_mut()
-                    .variables
-                    .insert(stmt.name.clone(), VariableState::Declared);
                 if let Some(expr) = &mut stmt.expr {
                     self.resolve_expression(scope, expr)?;
                 }
                 scope
-                    .borrow_mut()
+                    .lock_unpoisoned()
                     .variables
                     .insert(stmt.name.clone(), VariableState::Initialized);
             }
             Statement::Block(stmt) => {
-                let mut scope = Scope::new_ptr(Some(scope.clone()));
+                let scope = Scope::new_ptr(Some(scope.clone()));
                 for s in &mut stmt.statements {
-                    self.resolve_statement(&mut scope, s)?;
+                    self.resolve_statement(&scope, s)?;
                 }
             }
             Statement::If(stmt) => {
@@ -95,29 +216,62 @@ impl Resolver {
             Statement::Function(stmt) => {
                 // TODO: scope 관련 처리가 interpreter 에서 중복되는데, error-prone
                 // interpreter 에서 여기 scope 를 가져다 environment 를 생성하게 만들기
+                Self::check_constant_redeclared(scope, stmt.span, &stmt.name)?;
                 scope
-                    .borrow_mut()
+                    .lock_unpoisoned()
                     .variables
                     .insert(stmt.name.clone(), VariableState::Initialized);
-                let params_scope = Scope::new_ptr(Some(scope.clone()));
-                for p in &stmt.params {
-                    params_scope
-                        .borrow_mut()
-                        .variables
-                        .insert(p.into(), VariableState::Initialized);
-                }
-                self.resolve_statement(&params_scope, &mut stmt.body.write().unwrap())?;
+                self.resolve_function(scope, &stmt.params, &stmt.body, false)?;
             }
             Statement::Return(stmt) => {
                 if let Some(expr) = &mut stmt.value {
                     self.resolve_expression(scope, expr)?;
                 }
             }
+            Statement::Class(stmt) => {
+                Self::check_constant_redeclared(scope, stmt.span, &stmt.name)?;
+                scope.lock_unpoisoned().declare_initialized(&stmt.name);
+                let mut scope = scope.clone();
+                if let Some(superclass) = &mut stmt.superclass {
+                    if matches!(superclass, Expr::Variable(v) if v.name == stmt.name) {
+                        let diagnostic = Diagnostic::new(
+                            superclass.span(),
+                            "A class can't inherit from itself.",
+                        )
+                        .with_code(codes::INHERITS_FROM_ITSELF);
+                        return Err(LoxError::Resolve(Box::new(diagnostic)));
+                    }
+                    self.resolve_expression(&scope, superclass)?;
+                    // The interpreter defines `super` in an environment around the methods
+                    scope = Scope::new_ptr(Some(scope));
+                    scope.lock_unpoisoned().declare_initialized("super");
+                }
+                // Bound methods define `this` in an environment around the parameters
+                let this_scope = Scope::new_ptr(Some(scope.clone()));
+                this_scope.lock_unpoisoned().declare_initialized("this");
+                for method in &mut stmt.methods {
+                    if let Statement::Function(method) = method {
+                        if method.is_static {
+                            // Static methods are called on the class, without `this`
+                            self.resolve_function(&scope, &method.params, &method.body, true)?;
+                            continue;
+                        }
+                        if method.name == "init" {
+                            Self::check_initializer(method)?;
+                        }
+                        self.resolve_function(&this_scope, &method.params, &method.body, false)?;
+                    }
+                }
+            }
         }
         Ok(())
     }
 
-    fn resolve_expression(&mut self, scope: &ScopePtr, expr: &mut Expr) -> anyhow::Result<()> {
+    pub fn resolve_expression(
+        &mut self,
+        scope: &ScopePtr,
+        expr: &mut Expr,
+    ) -> Result<(), LoxError> {
         match expr {
             Expr::Binary(expr) => {
                 self.resolve_expression(scope, &mut expr.left)?;
@@ -131,18 +285,30 @@ impl Resolver {
                 self.resolve_expression(scope, &mut expr.right)?;
             }
             Expr::Variable(expr) => {
-                if let Some(resolution) = scope.borrow().resolve(&expr.name) {
-                    expr.resolution = resolution;
-                } else {
-                    bail!("Referenced undefined varable: {}", expr.name);
-                }
+                let resolved = scope.lock_unpoisoned().resolve(&expr.name);
+                let Some((resolution, slot)) = resolved else {
+                    return Self::undefined_variable(scope, expr.span, &expr.name);
+                };
+                expr.resolution = resolution;
+                expr.slot = slot;
             }
             Expr::Assign(expr) => {
-                if let Some(resolution) = scope.borrow().resolve(&expr.name) {
-                    expr.resolution = resolution;
-                } else {
-                    bail!("Referenced undefined varable: {}", expr.name);
+                let resolved = scope.lock_unpoisoned().resolve(&expr.name);
+                let Some((resolution, slot)) = resolved else {
+                    return Self::undefined_variable(scope, expr.span, &expr.name);
+                };
+                let constant = scope.lock_unpoisoned().constant(&expr.name, resolution);
+                if let Some(declaration) = constant {
+                    let diagnostic = Diagnostic::new(
+                        expr.span,
+                        format!("Can't assign to constant '{}'.", expr.name),
+                    )
+                    .with_label(declaration, "declared constant here")
+                    .with_code(codes::ASSIGN_TO_CONSTANT);
+                    return Err(LoxError::Resolve(Box::new(diagnostic)));
                 }
+                expr.resolution = resolution;
+                expr.slot = slot;
                 self.resolve_expression(scope, &mut expr.value)?;
             }
             Expr::Logical(expr) => {
@@ -155,9 +321,130 @@ impl Resolver {
                     self.resolve_expression(scope, arg)?;
                 }
             }
+            // Properties are looked up dynamically, only the object is resolved.
+            Expr::Get(expr) => {
+                self.resolve_expression(scope, &mut expr.object)?;
+            }
+            Expr::Set(expr) => {
+                self.resolve_expression(scope, &mut expr.object)?;
+                self.resolve_expression(scope, &mut expr.value)?;
+            }
+            Expr::List(expr) => {
+                for element in &mut expr.elements {
+                    self.resolve_expression(scope, element)?;
+                }
+            }
+            Expr::Index(expr) => {
+                self.resolve_expression(scope, &mut expr.object)?;
+                self.resolve_expression(scope, &mut expr.index)?;
+            }
+            Expr::Slice(expr) => {
+                self.resolve_expression(scope, &mut expr.object)?;
+                for bound in expr.start.iter_mut().chain(&mut expr.end) {
+                    self.resolve_expression(scope, bound)?;
+                }
+            }
+            Expr::IndexSet(expr) => {
+                self.resolve_expression(scope, &mut expr.object)?;
+                self.resolve_expression(scope, &mut expr.index)?;
+                self.resolve_expression(scope, &mut expr.value)?;
+            }
+            // `this` can't be a variable name, so it's only found around methods.
+            Expr::This(expr) => match scope.lock_unpoisoned().resolve_this() {
+                Ok(resolution) => expr.resolution = resolution,
+                Err(context) => {
+                    let diagnostic =
+                        Diagnostic::new(expr.span, format!("Can't use 'this' {context}."))
+                            .with_code(codes::THIS_OUTSIDE_CLASS);
+                    return Err(LoxError::Resolve(Box::new(diagnostic)));
+                }
+            },
+            // `super` belongs to the innermost class only if its `this` is right inside of it.
+            Expr::Super(expr) => {
+                let (superclass, this) = {
+                    let scope = scope.lock_unpoisoned();
+                    (scope.resolve("super"), scope.resolve_this())
+                };
+                let message = match (superclass, this) {
+                    (Some((resolution, _)), Ok(this)) if this + 1 == resolution => {
+                        expr.resolution = resolution;
+                        return Ok(());
+                    }
+                    (_, Ok(_)) => "Can't use 'super' in a class with no superclass.".to_owned(),
+                    (_, Err(context)) => format!("Can't use 'super' {context}."),
+                };
+                let diagnostic =
+                    Diagnostic::new(expr.span, message).with_code(codes::SUPER_OUTSIDE_SUBCLASS);
+                return Err(LoxError::Resolve(Box::new(diagnostic)));
+            }
+            Expr::Lambda(expr) => {
+                self.resolve_function(scope, &expr.params, &expr.body, false)?;
+            }
         }
         Ok(())
     }
+
+    /// Resolves the parameters and body of a function or lambda declared in `scope`.
+    fn resolve_function(
+        &mut self,
+        scope: &ScopePtr,
+        params: &[String],
+        body: &RwLock<Statement>,
+        static_method: bool,
+    ) -> Result<(), LoxError> {
+        let params_scope = Scope::new_ptr(Some(scope.clone()));
+        params_scope.lock_unpoisoned().static_method = static_method;
+        for p in params {
+            params_scope
+                .lock_unpoisoned()
+                .variables
+                .insert(p.into(), VariableState::Initialized);
+        }
+        self.resolve_statement(&params_scope, &mut body.write_unpoisoned())
+    }
+
+    /// Fails if the body of the initializer, outside of the functions declared in it, returns a
+    /// value.
+    fn check_initializer(init: &statement::Function) -> Result<(), LoxError> {
+        fn return_with_value(node: Node) -> Option<Span> {
+            match node {
+                Node::Statement(Statement::Return(s)) if s.value.is_some() => Some(s.span),
+                // Function bodies aren't among the children
+                node => node.children().find_map(return_with_value),
+            }
+        }
+        let body = init.body.read_unpoisoned();
+        match return_with_value(Node::Statement(&body)) {
+            Some(span) => {
+                let diagnostic = Diagnostic::new(span, "Can't return a value from an initializer.")
+                    .with_code(codes::RETURN_FROM_INITIALIZER);
+                Err(LoxError::Resolve(Box::new(diagnostic)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn undefined_variable(scope: &ScopePtr, span: Span, name: &str) -> Result<(), LoxError> {
+        let names = scope.lock_unpoisoned().visible_names();
+        let diagnostic = Diagnostic::new(span, format!("Referenced undefined variable: {name}"))
+            .with_code(codes::UNDEFINED_VARIABLE)
+            .with_name_suggestion(name, names.iter().map(String::as_str));
+        Err(LoxError::Resolve(Box::new(diagnostic)))
+    }
+
+    /// Rejects a function or class declaring `name` over a constant of the same scope.
+    fn check_constant_redeclared(scope: &ScopePtr, span: Span, name: &str) -> Result<(), LoxError> {
+        let Some(declaration) = scope.lock_unpoisoned().constants.get(name).copied() else {
+            return Ok(());
+        };
+        let diagnostic = Diagnostic::new(
+            span,
+            format!("Already a constant with this name in this scope: {name}"),
+        )
+        .with_label(declaration, "declared constant here")
+        .with_code(codes::REDECLARED_VARIABLE);
+        Err(LoxError::Resolve(Box::new(diagnostic)))
+    }
 }
 
 #[cfg(test)]
@@ -202,7 +489,7 @@ var a = "global";
                 Statement::Expression(_stmt) => {}
                 Statement::Print(stmt) => {
                     *print_count += 1;
-                    let Expr::Variable(expr ) = &stmt.expr else {
+                    let [Expr::Variable(expr)] = stmt.exprs.as_slice() else {
                         panic!("print statement has expr other than variable;")
                     };
                     if expr.name == "a" {
@@ -232,6 +519,7 @@ var a = "global";
                     visit_statement(&stmt.body.read().unwrap(), print_count);
                 }
                 Statement::Return(_stmt) => {}
+                Statement::Class(_stmt) => {}
             }
         }
 
@@ -258,7 +546,9 @@ noop();
         let mut stmts = parse(source)?;
         let before = format!("{:?}", stmts);
         resolve(&mut stmts)?;
-        let after = format!("{:?}", stmts);
+        // Only the global slot is found, the resolution staying 0.
+        let slot = "slot: Some(0)";
+        let after = format!("{:?}", stmts).replace(slot, "slot: None");
         assert_eq!(before, after);
         Ok(())
     }
@@ -293,6 +583,62 @@ var a = 2;
         Ok(())
     }
 
+    #[test]
+    fn test_assign_to_constant() -> anyhow::Result<()> {
+        let source = r#"
+const a = 1;
+fun f() {
+    a = 2;
+}
+        "#;
+        let mut stmts = parse(source)?;
+        let error = resolve(&mut stmts).unwrap_err();
+        let error = error.downcast::<LoxError>()?;
+        let diagnostic = error.diagnostic();
+        assert_eq!("Can't assign to constant 'a'.", diagnostic.message);
+        assert_eq!(Some(codes::ASSIGN_TO_CONSTANT), diagnostic.code);
+        assert_eq!(Span::new(4, 5, 1), diagnostic.span);
+        assert_eq!(Span::new(2, 1, 5), diagnostic.labels[0].span);
+
+        // A variable of the same name in an inner scope can be assigned
+        let mut stmts = parse("const a = 1; { var a = 2; a = 3; }")?;
+        resolve(&mut stmts)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_function_redeclaring_constant() -> anyhow::Result<()> {
+        let mut stmts = parse("const c = 1;\nfun c() {}")?;
+        let error = resolve(&mut stmts).unwrap_err();
+        let error = error.downcast::<LoxError>()?;
+        let diagnostic = error.diagnostic();
+        assert_eq!(
+            "Already a constant with this name in this scope: c",
+            diagnostic.message
+        );
+        assert_eq!(Some(codes::REDECLARED_VARIABLE), diagnostic.code);
+        assert_eq!(Span::new(1, 1, 5), diagnostic.labels[0].span);
+
+        // A function of the same name in an inner scope shadows it
+        let mut stmts = parse("const c = 1; { fun c() {} }")?;
+        resolve(&mut stmts)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_class_redeclaring_constant() -> anyhow::Result<()> {
+        let mut stmts = parse("const c = 1;\nclass c {}")?;
+        let error = resolve(&mut stmts).unwrap_err();
+        let error = error.downcast::<LoxError>()?;
+        let diagnostic = error.diagnostic();
+        assert_eq!(
+            "Already a constant with this name in this scope: c",
+            diagnostic.message
+        );
+        assert_eq!(Some(codes::REDECLARED_VARIABLE), diagnostic.code);
+        Ok(())
+    }
+
     #[test]
     fn test_try_to_initialize_self() -> anyhow::Result<()> {
         let source = "var a = a;";
@@ -303,6 +649,21 @@ var a = 2;
         Ok(())
     }
 
+    #[test]
+    fn test_undefined_variable_suggestion() -> anyhow::Result<()> {
+        let mut stmts = parse("var count = 0; cout = count + 1;")?;
+        let scope = Scope::new_ptr(None);
+        let errors = Resolver.resolve_statements(&scope, &mut stmts);
+        assert_eq!(vec!["did you mean 'count'?"], errors[0].diagnostic().notes);
+        let mut stmts = parse("fun f(step) { print stpe; }")?;
+        let errors = Resolver.resolve_statements(&scope, &mut stmts);
+        assert_eq!(vec!["did you mean 'step'?"], errors[0].diagnostic().notes);
+        let mut stmts = parse("print total;")?;
+        let errors = Resolver.resolve_statements(&scope, &mut stmts);
+        assert!(errors[0].diagnostic().notes.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_try_to_initialize_with_same_name() -> anyhow::Result<()> {
         let source = r#"
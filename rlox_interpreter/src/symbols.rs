@@ -0,0 +1,350 @@
+use crate::func;
+use crate::sync::UnpoisonedRw;
+use rlox_syntax::*;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Parameter,
+    Native,
+    Class,
+}
+
+/// Something declared with a name, which references refer to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the name is declared, `None` for natives.
+    pub span: Option<Span>,
+    /// Number of parameters of functions and natives.
+    pub arity: Option<usize>,
+    /// Whether the symbol is declared in the global scope.
+    pub global: bool,
+}
+
+/// A use of a symbol by a variable expression or an assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    /// Index in [`Symbols::symbols`].
+    pub symbol: usize,
+    /// Whether the reference assigns the symbol rather than reading it.
+    pub write: bool,
+}
+
+/// Declarations of a program and what refers to them, found by following the distances
+/// computed by the [`Resolver`](crate::Resolver).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Symbols {
+    pub symbols: Vec<Symbol>,
+    /// In source order.
+    pub references: Vec<Reference>,
+    /// Calls of functions or natives by name, in source order.
+    pub calls: Vec<Call>,
+}
+
+/// A call of the function or native `callee` by the function `caller`, `None` meaning the top
+/// level of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    pub caller: Option<usize>,
+    pub callee: usize,
+}
+
+impl Symbols {
+    /// Collects the symbols of `statements`, which must have been resolved in a global scope
+    /// created by [`Scope::new_globals_ptr`](crate::Scope::new_globals_ptr). `tokens` are the
+    /// ones the statements were parsed from, which locate the declared names.
+    pub fn collect(tokens: &[Token], statements: &[Statement]) -> Self {
+        let mut collector = Collector {
+            tokens,
+            token_indices: tokens
+                .iter()
+                .enumerate()
+                .map(|(i, t)| ((t.line, t.column), i))
+                .collect(),
+            scopes: vec![HashMap::new()],
+            functions: Vec::new(),
+            symbols: Symbols::default(),
+        };
+        for f in func::impls::ALL_FUNCS {
+            collector.declare(f.name, SymbolKind::Native, None, Some(f.arity));
+        }
+        for s in statements {
+            collector.statement(s);
+        }
+        let mut symbols = collector.symbols;
+        symbols
+            .references
+            .sort_by_key(|r| (r.span.line, r.span.column));
+        symbols
+    }
+
+    /// Symbol whose declaration or reference covers the 1-based `line` and `column`.
+    pub fn at(&self, line: usize, column: usize) -> Option<usize> {
+        let covers = |span: &Span| {
+            span.line == line && (span.column..=span.column + span.len).contains(&column)
+        };
+        self.references
+            .iter()
+            .find(|r| covers(&r.span))
+            .map(|r| r.symbol)
+            .or_else(|| {
+                self.symbols
+                    .iter()
+                    .position(|s| s.span.as_ref().is_some_and(covers))
+            })
+    }
+
+    /// Spans of the declaration of `symbol`, if any, then of its references.
+    pub fn occurrences(&self, symbol: usize) -> Vec<Span> {
+        self.symbols[symbol]
+            .span
+            .into_iter()
+            .chain(
+                self.references
+                    .iter()
+                    .filter(|r| r.symbol == symbol)
+                    .map(|r| r.span),
+            )
+            .collect()
+    }
+}
+
+struct Collector<'a> {
+    tokens: &'a [Token],
+    // Index of the token at each position
+    token_indices: HashMap<(usize, usize), usize>,
+    // Symbols by name, for the same scopes as the resolver's
+    scopes: Vec<HashMap<String, usize>>,
+    // Functions whose body is being walked, the innermost last
+    functions: Vec<usize>,
+    symbols: Symbols,
+}
+
+impl Collector<'_> {
+    fn declare(&mut self, name: &str, kind: SymbolKind, span: Option<Span>, arity: Option<usize>) {
+        let index = self.symbols.symbols.len();
+        self.symbols.symbols.push(Symbol {
+            name: name.to_owned(),
+            kind,
+            span,
+            arity,
+            global: self.scopes.len() == 1,
+        });
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_owned(), index);
+    }
+
+    /// Tokens following the keyword at `span`.
+    fn tokens_after(&self, span: Span) -> impl Iterator<Item = &Token> {
+        let index = self.token_indices.get(&(span.line, span.column));
+        let rest = index.map_or(&[][..], |&i| &self.tokens[i + 1..]);
+        rest.iter()
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression(s) => self.expr(&s.expr),
+            Statement::Print(s) => {
+                for expr in &s.exprs {
+                    self.expr(expr);
+                }
+            }
+            Statement::VariableDecl(s) => {
+                if let Some(expr) = &s.expr {
+                    self.expr(expr);
+                }
+                let span = self.tokens_after(s.span).next().map(Token::span);
+                self.declare(&s.name, SymbolKind::Variable, span, None);
+            }
+            Statement::Block(s) => self.block(&s.statements),
+            Statement::If(s) => {
+                self.expr(&s.condition);
+                self.statement(&s.then_branch);
+                if let Some(else_branch) = &s.else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While(s) => {
+                self.expr(&s.condition);
+                self.statement(&s.body);
+            }
+            Statement::Function(s) => {
+                // The name, then the parameters between the parentheses
+                let mut names = self
+                    .tokens_after(s.span)
+                    .take_while(|t| t.kind != TokenKind::RightParen)
+                    .filter(|t| t.kind == TokenKind::Identifier)
+                    .map(Token::span)
+                    .collect::<Vec<_>>()
+                    .into_iter();
+                let arity = Some(s.params.len());
+                self.declare(&s.name, SymbolKind::Function, names.next(), arity);
+                self.functions.push(self.symbols.symbols.len() - 1);
+                self.function_body(&s.params, &s.body, names);
+                self.functions.pop();
+            }
+            Statement::Return(s) => {
+                if let Some(value) = &s.value {
+                    self.expr(value);
+                }
+            }
+            Statement::Class(s) => {
+                let span = self.tokens_after(s.span).next().map(Token::span);
+                self.declare(&s.name, SymbolKind::Class, span, None);
+                if let Some(superclass) = &s.superclass {
+                    self.expr(superclass);
+                    // The scope of `super`
+                    self.scopes.push(HashMap::new());
+                }
+                // Methods are properties rather than symbols, only `this` is around them, except
+                // for static methods
+                for method in &s.methods {
+                    let Statement::Function(method) = method else {
+                        continue;
+                    };
+                    // The span of a method is its name, or its `static` keyword before the name,
+                    // followed by the parameters
+                    let names = self
+                        .tokens_after(method.span)
+                        .skip(usize::from(method.is_static))
+                        .take_while(|t| t.kind != TokenKind::RightParen)
+                        .filter(|t| t.kind == TokenKind::Identifier)
+                        .map(Token::span)
+                        .collect::<Vec<_>>();
+                    if !method.is_static {
+                        self.scopes.push(HashMap::new());
+                    }
+                    self.function_body(&method.params, &method.body, names.into_iter());
+                    if !method.is_static {
+                        self.scopes.pop();
+                    }
+                }
+                if s.superclass.is_some() {
+                    self.scopes.pop();
+                }
+            }
+        }
+    }
+
+    /// Declares the parameters of a function or lambda, located by `names`, and walks its body.
+    fn function_body(
+        &mut self,
+        params: &[String],
+        body: &RwLock<Statement>,
+        mut names: impl Iterator<Item = Span>,
+    ) {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(param, SymbolKind::Parameter, names.next(), None);
+        }
+        self.statement(&body.read_unpoisoned());
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.scopes.push(HashMap::new());
+        for s in statements {
+            self.statement(s);
+        }
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Binary(e) => {
+                self.expr(&e.left);
+                self.expr(&e.right);
+            }
+            Expr::Logical(e) => {
+                self.expr(&e.left);
+                self.expr(&e.right);
+            }
+            Expr::Grouping(e) => self.expr(&e.expr),
+            Expr::Unary(e) => self.expr(&e.right),
+            Expr::Get(e) => self.expr(&e.object),
+            Expr::Set(e) => {
+                self.expr(&e.object);
+                self.expr(&e.value);
+            }
+            Expr::List(e) => {
+                for element in &e.elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Index(e) => {
+                self.expr(&e.object);
+                self.expr(&e.index);
+            }
+            Expr::Slice(e) => {
+                self.expr(&e.object);
+                for bound in e.start.iter().chain(&e.end) {
+                    self.expr(bound);
+                }
+            }
+            Expr::IndexSet(e) => {
+                self.expr(&e.object);
+                self.expr(&e.index);
+                self.expr(&e.value);
+            }
+            Expr::Literal(_) | Expr::This(_) | Expr::Super(_) => {}
+            Expr::Variable(e) => self.reference(&e.name, e.resolution, e.span, false),
+            Expr::Assign(e) => {
+                self.expr(&e.value);
+                self.reference(&e.name, e.resolution, e.span, true);
+            }
+            Expr::Lambda(e) => {
+                // The parameters between the parentheses after `fun`
+                let names = self
+                    .tokens_after(e.span)
+                    .take_while(|t| t.kind != TokenKind::RightParen)
+                    .filter(|t| t.kind == TokenKind::Identifier)
+                    .map(Token::span)
+                    .collect::<Vec<_>>();
+                self.function_body(&e.params, &e.body, names.into_iter());
+            }
+            Expr::Call(e) => {
+                self.expr(&e.callee);
+                if let Expr::Variable(callee) = &e.callee {
+                    let callee = self.lookup(&callee.name, callee.resolution);
+                    let is_function = callee.is_some_and(|callee| {
+                        let kind = self.symbols.symbols[callee].kind;
+                        matches!(kind, SymbolKind::Function | SymbolKind::Native)
+                    });
+                    if let (Some(callee), true) = (callee, is_function) {
+                        self.symbols.calls.push(Call {
+                            caller: self.functions.last().copied(),
+                            callee,
+                        });
+                    }
+                }
+                for argument in &e.arguments {
+                    self.expr(argument);
+                }
+            }
+        }
+    }
+
+    /// Symbol of `name` in the scope `resolution` levels above the current one.
+    fn lookup(&self, name: &str, resolution: usize) -> Option<usize> {
+        let depth = self.scopes.len().checked_sub(resolution + 1)?;
+        self.scopes[depth].get(name).copied()
+    }
+
+    fn reference(&mut self, name: &str, resolution: usize, span: Span, write: bool) {
+        if let Some(symbol) = self.lookup(name, resolution) {
+            self.symbols.references.push(Reference {
+                span,
+                symbol,
+                write,
+            });
+        }
+    }
+}
@@ -1,18 +1,31 @@
-use crate::func::{FunctionObject, NativeFunction};
+use crate::class::{BoundMethod, LoxClass, LoxInstance};
+use crate::func::{FunctionObject, HostFunction, NativeFunction};
+use crate::list::LoxList;
+use anyhow::bail;
 use rlox_syntax::Literal;
-use std::fmt::Debug;
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
 use std::sync::Arc;
 
+/// A runtime value. Strings and objects are reference-counted, so that cloning a value, e.g. to
+/// read a variable, doesn't copy its contents.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Number(f64),
-    String(String),
+    /// Shared, so that copying a string value doesn't copy its characters.
+    String(Arc<str>),
     Boolean(bool),
     Nil,
     NativeFunction(&'static NativeFunction),
     // TODO: object - garbage collection, etc.
     FunctionObject(Object<FunctionObject>),
+    HostFunction(Object<HostFunction>),
+    Foreign(Foreign),
+    Class(Object<LoxClass>),
+    Instance(Object<LoxInstance>),
+    BoundMethod(Object<BoundMethod>),
+    List(Object<LoxList>),
 }
 
 impl From<Literal> for Value {
@@ -26,6 +39,186 @@ impl From<Literal> for Value {
     }
 }
 
+impl Value {
+    /// Name of the type for messages, e.g. `number`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::NativeFunction(_)
+            | Value::FunctionObject(_)
+            | Value::HostFunction(_)
+            | Value::BoundMethod(_) => "function",
+            Value::Foreign(_) => "foreign object",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<Arc<str>> for Value {
+    fn from(value: Arc<str>) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Self::Nil
+    }
+}
+
+/// `None` becomes nil.
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Nil, Into::into)
+    }
+}
+
+macro_rules! impl_try_from_value {
+    ($type:ty, $variant:ident, $name:literal) => {
+        impl TryFrom<Value> for $type {
+            type Error = anyhow::Error;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::$variant(v) => Ok(v),
+                    other => bail!("Expected a {} but got {}.", $name, other.type_name()),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_value!(f64, Number, "number");
+impl_try_from_value!(bool, Boolean, "boolean");
+impl_try_from_value!(Arc<str>, String, "string");
+impl_try_from_value!(Foreign, Foreign, "foreign object");
+
+impl TryFrom<Value> for String {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Arc::<str>::try_from(value).map(|s| s.to_string())
+    }
+}
+
+/// Accepts any value, including nil.
+impl TryFrom<Value> for Option<f64> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Nil => Ok(None),
+            value => value.try_into().map(Some),
+        }
+    }
+}
+
+/// Converts the arguments of a native function into Rust values at once:
+///
+/// ```
+/// use rlox_interpreter::{FromLoxArgs, Value};
+///
+/// let args = [Value::Number(1.0), Value::from("a")];
+/// let (n, s): (f64, String) = FromLoxArgs::from_lox_args(&args).unwrap();
+/// assert_eq!((1.0, "a".to_owned()), (n, s));
+/// ```
+pub trait FromLoxArgs: Sized {
+    fn from_lox_args(args: &[Value]) -> anyhow::Result<Self>;
+}
+
+macro_rules! impl_from_lox_args {
+    ($count:literal; $($type:ident),*) => {
+        impl<$($type),*> FromLoxArgs for ($($type,)*)
+        where
+            $($type: TryFrom<Value, Error = anyhow::Error>),*
+        {
+            #[allow(unused_variables, unused_mut, non_snake_case)]
+            fn from_lox_args(args: &[Value]) -> anyhow::Result<Self> {
+                if args.len() != $count {
+                    bail!("Expected {} arguments but got {}.", $count, args.len());
+                }
+                let mut args = args.iter().cloned();
+                $(let $type = $type::try_from(args.next().unwrap())?;)*
+                Ok(($($type,)*))
+            }
+        }
+    };
+}
+
+impl_from_lox_args!(0;);
+impl_from_lox_args!(1; A);
+impl_from_lox_args!(2; A, B);
+impl_from_lox_args!(3; A, B, C);
+impl_from_lox_args!(4; A, B, C, D);
+
+/// Opaque object of the host program, which scripts can only pass around, e.g. back to native
+/// functions which downcast it.
+#[derive(Clone)]
+pub struct Foreign {
+    type_name: &'static str,
+    object: Arc<dyn Any + Send + Sync>,
+}
+
+impl Foreign {
+    pub fn new<T: Any + Send + Sync>(object: T) -> Self {
+        Self {
+            type_name: std::any::type_name::<T>(),
+            object: Arc::new(object),
+        }
+    }
+
+    /// Name of the Rust type of the object.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.object.downcast_ref()
+    }
+}
+
+impl Debug for Foreign {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Foreign({})", self.type_name)
+    }
+}
+
+/// Foreign objects are equal only to themselves.
+impl PartialEq for Foreign {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Arc::as_ptr(&self.object), Arc::as_ptr(&other.object))
+    }
+}
+
 // Arc is necessary due to the current implementation of return statement using anyhow::Error.
 #[derive(Debug)]
 pub struct Object<T: Debug>(Arc<T>);
@@ -1,5 +1,11 @@
-use rlox_interpreter::{Environment, Interpreter, Printer, Resolver, Scope};
+use rlox_interpreter::{
+    call_graph_dot, classify, highlight_html, Environment, Foreign, Interpreter, LimitAction,
+    LimitHandler, LimitKind, Limits, Lox, LoxError, MetricCounters, Printer, Resolver, Scope,
+    SourceOrigin, SymbolKind, Symbols, TokenClass, Value, WritePrinter,
+};
 use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{codes, Span, Statement};
+use std::sync::{Arc, Mutex};
 
 struct TestPrinter {
     messages: Vec<String>,
@@ -19,20 +25,21 @@ impl Printer for TestPrinter {
     }
 }
 
+/// Parses and resolves `source` for tests driving the interpreter directly.
+fn compile(source: &str) -> Vec<Statement> {
+    let mut statements = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+        .parse()
+        .unwrap();
+    let scope = Scope::new_globals_ptr();
+    for s in &mut statements {
+        Resolver.resolve_statement(&scope, s).unwrap();
+    }
+    statements
+}
+
 fn print_from(source: &str) -> anyhow::Result<Vec<String>> {
     let mut printer = TestPrinter::new();
-    let tokens = Scanner::new(source).scan_tokens()?;
-    let mut parser = Parser::new(tokens);
-    let mut statements = parser.parse()?;
-    let environment = Environment::new_globals_ptr();
-    let global_scope = Scope::new_ptr(None);
-    for stmt in &mut statements {
-        Resolver.resolve_statement(&global_scope, stmt)?;
-    }
-    let mut interpreter = Interpreter::new(&mut printer);
-    for s in statements {
-        interpreter.evaluate_stmt(&environment, &s)?;
-    }
+    Lox::with_printer(&mut printer).run(source)?;
     Ok(printer.messages)
 }
 
@@ -51,6 +58,568 @@ var b = 2;
     assert_eq!(vec!["Number(7.0)"], print_from(source).unwrap());
 }
 
+#[test]
+fn test_print_multiple_values() {
+    assert_eq!(
+        vec![r#"String("sum:") Number(3.0) Boolean(true)"#],
+        print_from(r#"print "sum:", 1 + 2, true;"#).unwrap()
+    );
+}
+
+#[test]
+fn test_string_comparison() {
+    let source = r#"
+print "apple" < "banana", "apple" <= "apple", "b" > "abc", "Z" >= "a";
+print "" < "a", "ab" < "a", "é" > "z";
+"#;
+    assert_eq!(
+        vec![
+            "Boolean(true) Boolean(true) Boolean(true) Boolean(false)",
+            "Boolean(true) Boolean(false) Boolean(true)",
+        ],
+        print_from(source).unwrap()
+    );
+
+    let error = Lox::with_printer(TestPrinter::new())
+        .run(r#"print "a" < 1;"#)
+        .unwrap_err();
+    assert_eq!(Some(codes::INVALID_OPERANDS), error.diagnostic().code);
+}
+
+#[test]
+fn test_misplaced_digit_separator() {
+    let errors = Lox::with_printer(TestPrinter::new())
+        .run_checked("print 1__0 + 2;")
+        .unwrap_err();
+    assert_eq!(1, errors.len());
+    assert_eq!(
+        Some(codes::MISPLACED_SEPARATOR),
+        errors[0].diagnostic().code
+    );
+}
+
+#[test]
+fn test_comma() {
+    let source = r"
+var a = 0;
+var b = 0;
+for (var i = 0; i < 3; i = i + 1, a = a + i) b = b + 1, b = b * 2;
+print (a, b), (a = 10, a + 1);
+";
+    assert_eq!(
+        vec!["Number(14.0) Number(11.0)"],
+        print_from(source).unwrap()
+    );
+}
+
+#[test]
+fn test_multiple_declarators() {
+    let source = r"
+var a = 1, b = a + 1, c;
+{
+    var a = b, d = a * 10;
+    print a, d;
+}
+for (var i = 0, j = 3; i < j; i = i + 1, j = j - 1) print i, j;
+print a, b, c;
+";
+    assert_eq!(
+        vec![
+            "Number(2.0) Number(20.0)",
+            "Number(0.0) Number(3.0)",
+            "Number(1.0) Number(2.0)",
+            "Number(1.0) Number(2.0) Nil",
+        ],
+        print_from(source).unwrap()
+    );
+
+    let error = print_from("{ var a = 1, a = 2; }").unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Already a variable with this name in this scope: a"));
+}
+
+#[test]
+fn test_const() {
+    let source = r"
+const limit = 3;
+fun below(n) {
+    const half = limit / 2;
+    return n < half;
+}
+{
+    var limit = 0;
+    limit = limit + 1;
+    print limit;
+}
+print limit, below(1);
+";
+    assert_eq!(
+        vec!["Number(1.0)", "Number(3.0) Boolean(true)"],
+        print_from(source).unwrap()
+    );
+
+    let error = Lox::with_printer(TestPrinter::new())
+        .run("const a = 1;\nprint a;\na = 2;")
+        .unwrap_err();
+    assert_eq!("Can't assign to constant 'a'.", error.diagnostic().message);
+    assert_eq!(Some(codes::ASSIGN_TO_CONSTANT), error.diagnostic().code);
+}
+
+#[test]
+fn test_lambdas() {
+    let source = r"
+fun map2(f, a, b) {
+    return f(a), f(b);
+}
+var offset = 10;
+var shift = fun (x) -> x + offset;
+offset = 20;
+print map2(shift, 1, 2), (fun (x, y) -> x * y)(3, 4);
+fun (x) -> x;
+";
+    assert_eq!(
+        vec!["Number(22.0) Number(12.0)"],
+        print_from(source).unwrap()
+    );
+}
+
+#[test]
+fn test_rest_parameters() {
+    let source = r#"
+fun log(level, ...args) {
+    print level, args;
+}
+log("debug");
+log("info", 1, "two");
+class List {
+    init(...items) {
+        this.items = items;
+    }
+}
+print (fun (...all) -> all)(nil), List(1, 2).items;
+"#;
+    assert_eq!(
+        vec![
+            r#"String("debug") List(Object([]))"#,
+            r#"String("info") List(Object([Number(1.0), String("two")]))"#,
+            "List(Object([Nil])) List(Object([Number(1.0), Number(2.0)]))",
+        ],
+        print_from(source).unwrap()
+    );
+
+    let error = print_from("fun log(level, ...args) {} log();").unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Expected at least 1 arguments but got 0."));
+}
+
+#[test]
+fn test_lists() {
+    let source = r#"
+var a = [1, "two", [3]];
+print a, [];
+print a[0], a[2][0], a[1 + 1];
+print a == a, [1] == [1];
+var b = a;
+print a[1] = a[2][0] = 4;
+print b;
+"#;
+    assert_eq!(
+        vec![
+            r#"List(Object([Number(1.0), String("two"), List(Object([Number(3.0)]))])) List(Object([]))"#,
+            r#"Number(1.0) Number(3.0) List(Object([Number(3.0)]))"#,
+            "Boolean(true) Boolean(false)",
+            "Number(4.0)",
+            "List(Object([Number(1.0), Number(4.0), List(Object([Number(4.0)]))]))",
+        ],
+        print_from(source).unwrap()
+    );
+
+    for (source, message, code) in [
+        (
+            "print [1, 2][2];",
+            "Index 2 is out of range for a list of length 2.",
+            codes::INDEX_OUT_OF_RANGE,
+        ),
+        (
+            "print [1, 2][-1];",
+            "Index -1 is out of range for a list of length 2.",
+            codes::INDEX_OUT_OF_RANGE,
+        ),
+        (
+            "print [1, 2][0.5];",
+            "Index 0.5 is out of range for a list of length 2.",
+            codes::INDEX_OUT_OF_RANGE,
+        ),
+        (
+            "print [1][nil];",
+            "Index must be a number but got nil.",
+            codes::INVALID_OPERANDS,
+        ),
+        (
+            "print nil[0];",
+            "Can only index lists, not nil.",
+            codes::NOT_A_LIST,
+        ),
+        (
+            "var a = []; a[0] = 1;",
+            "Index 0 is out of range for a list of length 0.",
+            codes::INDEX_OUT_OF_RANGE,
+        ),
+        (
+            "var s = \"s\"; s[0] = 1;",
+            "Can only index lists, not string.",
+            codes::NOT_A_LIST,
+        ),
+    ] {
+        let error = Lox::with_printer(TestPrinter::new())
+            .run(source)
+            .unwrap_err();
+        assert_eq!(message, error.diagnostic().message);
+        assert_eq!(Some(code), error.diagnostic().code);
+    }
+}
+
+#[test]
+fn test_self_containing_list() {
+    let source = r"
+var l = [1];
+l[0] = l;
+print l;
+var m = [l, [2]];
+print m;
+";
+    assert_eq!(
+        vec![
+            "List(Object([List(Object([...]))]))",
+            "List(Object([List(Object([List(Object([...]))])), List(Object([Number(2.0)]))]))",
+        ],
+        print_from(source).unwrap()
+    );
+}
+
+#[test]
+fn test_slices() {
+    let source = r#"
+var a = [1, 2, 3, 4];
+print a[1:3], a[2:], a[:1], a[:];
+print a[-1:], a[:-3], a[-3:-1];
+print a[3:1], a[-10:10];
+var b = a[:];
+b[0] = 0;
+print a[0], b[0];
+var s = "héllo";
+print s[1:3], s[-3:], s[:-4], s[5:];
+"#;
+    assert_eq!(
+        vec![
+            "List(Object([Number(2.0), Number(3.0)])) List(Object([Number(3.0), Number(4.0)])) \
+             List(Object([Number(1.0)])) \
+             List(Object([Number(1.0), Number(2.0), Number(3.0), Number(4.0)]))",
+            "List(Object([Number(4.0)])) List(Object([Number(1.0)])) \
+             List(Object([Number(2.0), Number(3.0)]))",
+            "List(Object([])) List(Object([Number(1.0), Number(2.0), Number(3.0), Number(4.0)]))",
+            "Number(1.0) Number(0.0)",
+            r#"String("él") String("llo") String("h") String("")"#,
+        ],
+        print_from(source).unwrap()
+    );
+
+    for (source, message, code) in [
+        (
+            "print [1][0.5:];",
+            "Slice bound 0.5 is not a whole number.",
+            codes::INDEX_OUT_OF_RANGE,
+        ),
+        (
+            "print \"s\"[:nil];",
+            "Slice bound must be a number but got nil.",
+            codes::INVALID_OPERANDS,
+        ),
+        (
+            "print 1[1:];",
+            "Can only slice lists and strings, not number.",
+            codes::NOT_A_LIST,
+        ),
+    ] {
+        let error = Lox::with_printer(TestPrinter::new())
+            .run(source)
+            .unwrap_err();
+        assert_eq!(message, error.diagnostic().message);
+        assert_eq!(Some(code), error.diagnostic().code);
+    }
+}
+
+#[test]
+fn test_class() {
+    let source = r"
+class Point {}
+var a = Point();
+var b = Point();
+print Point, a;
+print a == a, a == b;
+";
+    assert_eq!(
+        vec![
+            r#"Class(Object(LoxClass("Point"))) Instance(Object(LoxInstance("Point")))"#,
+            "Boolean(true) Boolean(false)",
+        ],
+        print_from(source).unwrap()
+    );
+
+    let error = print_from("class Point {} Point(1);").unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Expected 0 arguments but got 1."));
+}
+
+#[test]
+fn test_fields() {
+    let source = r"
+class Point {}
+var p = Point();
+p.x = 1;
+p.y = p.x = p.x + 1;
+var q = p;
+q.x = 3;
+print p.x, p.y;
+";
+    assert_eq!(vec!["Number(3.0) Number(2.0)"], print_from(source).unwrap());
+
+    let run_error = |source| {
+        Lox::with_printer(TestPrinter::new())
+            .run(source)
+            .unwrap_err()
+    };
+    let error = run_error("class Point {}\nprint Point().x;");
+    assert_eq!("Undefined property 'x'.", error.diagnostic().message);
+    assert_eq!(Some(codes::UNDEFINED_PROPERTY), error.diagnostic().code);
+    assert_eq!(2, error.span().line);
+    let error = run_error("var a = 1; a.x = 2;");
+    assert_eq!("Only instances have fields.", error.diagnostic().message);
+    let error = run_error("print nil.x;");
+    assert_eq!(Some(codes::NOT_AN_INSTANCE), error.diagnostic().code);
+}
+
+#[test]
+fn test_methods() {
+    let source = r#"
+class Counter {
+    add(n) {
+        this.count = this.count + n;
+        return this;
+    }
+    adder() {
+        fun add(n) { return this.add(n); }
+        return add;
+    }
+}
+var c = Counter();
+c.count = 0;
+c.add(1).add(2);
+var add = c.adder();
+add(3);
+var bound = c.add;
+bound(4);
+print c.count, bound;
+c.add = "field";
+print c.add;
+"#;
+    assert_eq!(
+        vec![
+            r#"Number(10.0) BoundMethod(Object(BoundMethod("Counter", "add")))"#,
+            r#"String("field")"#
+        ],
+        print_from(source).unwrap()
+    );
+
+    let error = Lox::with_printer(TestPrinter::new())
+        .run("fun f() { return this; }")
+        .unwrap_err();
+    assert_eq!(
+        "Can't use 'this' outside of a class.",
+        error.diagnostic().message
+    );
+    assert_eq!(Some(codes::THIS_OUTSIDE_CLASS), error.diagnostic().code);
+}
+
+#[test]
+fn test_optional_chaining() {
+    let source = r#"
+class Point {
+    init(x) { this.x = x; }
+    scaled(n) { return Point(this.x * n); }
+}
+fun loud(n) {
+    print "evaluated";
+    return n;
+}
+var p = Point(2);
+var q = nil;
+print p?.x, p?.scaled(3)?.x;
+print q?.x, q?.scaled(loud(3)), q?.scaled(1)?.x;
+"#;
+    assert_eq!(
+        vec!["Number(2.0) Number(6.0)", "Nil Nil Nil"],
+        print_from(source).unwrap()
+    );
+
+    let error = Lox::with_printer(TestPrinter::new())
+        .run("var q = nil;\nprint q?.x.y;")
+        .unwrap_err();
+    assert_eq!(Some(codes::NOT_AN_INSTANCE), error.diagnostic().code);
+    let error = Lox::with_printer(TestPrinter::new())
+        .run("print 1?.x;")
+        .unwrap_err();
+    assert_eq!(
+        "Only instances and classes have properties.",
+        error.diagnostic().message
+    );
+}
+
+#[test]
+fn test_init() {
+    let source = r#"
+class Point {
+    init(x, y) {
+        this.x = x;
+        this.y = y;
+        if (x == 0) return;
+        this.y = y * 2;
+    }
+}
+class Point3 < Point {
+    init(x, y, z) {
+        super.init(x, y);
+        this.z = z;
+    }
+}
+var p = Point(0, 1);
+var q = Point3(1, 2, 3);
+print p.x, p.y, q.y, q.z;
+print p.init(2, 1) == p, p.x;
+"#;
+    assert_eq!(
+        vec![
+            "Number(0.0) Number(1.0) Number(4.0) Number(3.0)",
+            "Boolean(true) Number(2.0)"
+        ],
+        print_from(source).unwrap()
+    );
+
+    let run_error = |source| {
+        Lox::with_printer(TestPrinter::new())
+            .run(source)
+            .unwrap_err()
+    };
+    let error = run_error("class Point { init(x) {} }\nPoint();");
+    assert_eq!(
+        "Expected 1 arguments but got 0.",
+        error.diagnostic().message
+    );
+    let error = run_error("class A { init() { return 1; } }");
+    assert_eq!(
+        "Can't return a value from an initializer.",
+        error.diagnostic().message
+    );
+    assert_eq!(
+        Some(codes::RETURN_FROM_INITIALIZER),
+        error.diagnostic().code
+    );
+    // Functions declared in the initializer return what they like.
+    assert!(print_from("class A { init() { fun f() { return 1; } } } A();").is_ok());
+}
+
+#[test]
+fn test_inheritance() {
+    let source = r#"
+class A {
+    name() { return "A"; }
+    greet() { return "hello " + this.name(); }
+}
+class B < A {
+    name() { return "B of " + super.name(); }
+}
+class C < B {
+    greet() { return super.greet() + "!"; }
+}
+print C().greet();
+"#;
+    assert_eq!(
+        vec![r#"String("hello B of A!")"#],
+        print_from(source).unwrap()
+    );
+
+    let run_error = |source| {
+        Lox::with_printer(TestPrinter::new())
+            .run(source)
+            .unwrap_err()
+    };
+    let error = run_error("class A < A {}");
+    assert_eq!(Some(codes::INHERITS_FROM_ITSELF), error.diagnostic().code);
+    let error = run_error("var A = 1; class B < A {}");
+    assert_eq!("Superclass must be a class.", error.diagnostic().message);
+    let error = run_error("class A { f() { super.f(); } }");
+    assert_eq!(
+        "Can't use 'super' in a class with no superclass.",
+        error.diagnostic().message
+    );
+    let error = run_error("class A {} class B < A { f() { class C { g() { super.g(); } } } }");
+    assert_eq!(Some(codes::SUPER_OUTSIDE_SUBCLASS), error.diagnostic().code);
+    let error = run_error("super.f();");
+    assert_eq!(
+        "Can't use 'super' outside of a class.",
+        error.diagnostic().message
+    );
+    let error = run_error("class A {} class B < A { f() { return super.f; } } B().f();");
+    assert_eq!("Undefined property 'f'.", error.diagnostic().message);
+}
+
+#[test]
+fn test_static_methods() {
+    let source = r#"
+class Math {
+    static square(n) { return n * n; }
+    static(n) { return Math.square(n) + 1; }
+}
+class Geometry < Math {
+    static area(side) { return Geometry.square(side); }
+}
+print Math.square(3), Math().static(3), Geometry.area(4);
+var static = Math.square;
+print static(5);
+"#;
+    assert_eq!(
+        vec!["Number(9.0) Number(10.0) Number(16.0)", "Number(25.0)"],
+        print_from(source).unwrap()
+    );
+
+    let run_error = |source| {
+        Lox::with_printer(TestPrinter::new())
+            .run(source)
+            .unwrap_err()
+    };
+    let error = run_error("class A { static f() { return this; } }");
+    assert_eq!(
+        "Can't use 'this' in a static method.",
+        error.diagnostic().message
+    );
+    assert_eq!(Some(codes::THIS_OUTSIDE_CLASS), error.diagnostic().code);
+    // The `this` of a method around the class isn't the one of the static method either
+    let error = run_error("class A { f() { class B { static g() { return this; } } } }");
+    assert_eq!(Some(codes::THIS_OUTSIDE_CLASS), error.diagnostic().code);
+    let error = run_error("class A { static f() {} } class B < A { static f() { super.f(); } }");
+    assert_eq!(
+        "Can't use 'super' in a static method.",
+        error.diagnostic().message
+    );
+    let error = run_error("class A { static f() {} } A().f();");
+    assert_eq!("Undefined property 'f'.", error.diagnostic().message);
+    let error = run_error("class A { f() {} } A.f();");
+    assert_eq!("Undefined property 'f'.", error.diagnostic().message);
+}
+
 #[test]
 fn test_closure() {
     let source = r"
@@ -69,6 +638,48 @@ print c();
     assert_eq!(vec!["Number(2.0)"], print_from(source).unwrap());
 }
 
+#[test]
+fn test_reused_environments() {
+    // Environments of finished calls are reused, except the ones captured by closures.
+    let source = r"
+fun constant(n) {
+    fun get() { return n; }
+    return get;
+}
+fun identity(n) { return n; }
+var one = constant(1);
+identity(2);
+var three = constant(3);
+identity(4);
+{ var n = 5; }
+print one() + three();
+";
+    assert_eq!(vec!["Number(4.0)"], print_from(source).unwrap());
+}
+
+#[test]
+fn test_shadowed_locals() {
+    // The same name is at different positions of different environments.
+    let source = r"
+fun f(x) {
+    var a = x;
+    {
+        var b = 0;
+        var a = x + 1;
+        a = a + b;
+        print a;
+    }
+    print a;
+}
+f(1);
+f(10);
+";
+    assert_eq!(
+        vec!["Number(2.0)", "Number(1.0)", "Number(11.0)", "Number(10.0)"],
+        print_from(source).unwrap()
+    );
+}
+
 #[test]
 fn test_recursion() {
     let source = r"
@@ -102,3 +713,595 @@ var a = "global";
         print_from(source).unwrap()
     );
 }
+
+#[test]
+fn test_eval() {
+    let mut printer = TestPrinter::new();
+    let mut lox = Lox::with_printer(&mut printer);
+    lox.run("var a = 1; fun double(x) { return x * 2; }")
+        .unwrap();
+    assert_eq!(Value::Number(6.0), lox.eval("double(a + 2)").unwrap());
+    assert!(matches!(lox.eval("a +"), Err(LoxError::Parse(_))));
+    assert!(matches!(lox.eval("a + nil"), Err(LoxError::Runtime(_))));
+}
+
+#[test]
+fn test_error_kinds() {
+    let run = |source: &str| Lox::with_printer(TestPrinter::new()).run(source);
+    assert!(matches!(run("print @;"), Err(LoxError::Scan(_))));
+    assert!(matches!(run("print 1"), Err(LoxError::Parse(_))));
+    assert!(matches!(run("{ var a = a; }"), Err(LoxError::Resolve(_))));
+    let error = run("var a = 1;\nprint a + nil;").unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+    assert_eq!(2, error.span().line);
+}
+
+#[test]
+fn test_backtrace() {
+    let source = "\
+fun inc(n) {
+    return n + nil;
+}
+fun counter() {
+    return inc(1);
+}
+print counter();";
+    let error = Lox::with_printer(TestPrinter::new())
+        .run(source)
+        .unwrap_err();
+    let frames: Vec<_> = error
+        .diagnostic()
+        .backtrace
+        .iter()
+        .map(|frame| (frame.function.as_str(), frame.line))
+        .collect();
+    assert_eq!(vec![("inc", 2), ("counter", 5)], frames);
+    let error = Lox::with_printer(TestPrinter::new())
+        .run("print nil + 1;")
+        .unwrap_err();
+    assert!(error.diagnostic().backtrace.is_empty());
+}
+
+#[test]
+fn test_print_value() {
+    struct ValuePrinter(Vec<Value>);
+
+    impl Printer for ValuePrinter {
+        fn print(&mut self, _message: &str) {
+            unreachable!("values are handled by print_value");
+        }
+
+        fn print_value(&mut self, value: &Value) {
+            self.0.push(value.clone());
+        }
+    }
+
+    let mut printer = ValuePrinter(Vec::new());
+    Lox::with_printer(&mut printer)
+        .run(r#"print 1; print "a" + "b";"#)
+        .unwrap();
+    assert_eq!(vec![Value::Number(1.0), Value::from("ab")], printer.0);
+}
+
+#[test]
+fn test_write_printer() {
+    let mut printer = WritePrinter::new(Vec::new());
+    Lox::with_printer(&mut printer)
+        .run("print 1; print true;")
+        .unwrap();
+    printer.flush().unwrap();
+    assert_eq!(
+        "Number(1.0)\nBoolean(true)\n",
+        String::from_utf8(printer.into_inner()).unwrap()
+    );
+}
+
+#[test]
+fn test_globals() {
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.set_global("limit", 3.0);
+    lox.run("var doubled = limit * 2;").unwrap();
+    assert_eq!(Some(Value::Number(6.0)), lox.get_global("doubled"));
+    assert_eq!(None, lox.get_global("missing"));
+    let names: Vec<_> = lox.globals().map(|(name, _)| name).collect();
+    assert_eq!(
+        vec!["args", "clock", "doubled", "input", "limit", "memoryStats"],
+        names
+    );
+}
+
+#[test]
+fn test_frozen_globals() {
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.set_global("config", "prod");
+    lox.run("var retries = 3;").unwrap();
+    lox.freeze_globals();
+    lox.run("{ fun twice(n) { var m = n * 2; return m; } print twice(retries); }")
+        .unwrap();
+    for source in [
+        "config = nil;",
+        "var total = 0;",
+        "fun clock() { return 0; }",
+    ] {
+        let error = lox.run(source).unwrap_err();
+        assert_eq!(Some(codes::FROZEN_GLOBAL), error.diagnostic().code);
+    }
+    assert_eq!(Some(Value::Number(3.0)), lox.get_global("retries"));
+    lox.set_global("retries", 5.0);
+    assert_eq!(Value::Number(5.0), lox.eval("retries").unwrap());
+}
+
+#[test]
+fn test_shared_strings() {
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.run(
+        r#"fun greeting() { return "hello"; } var a = greeting(); var b = a; var c = greeting();"#,
+    )
+    .unwrap();
+    let [Some(Value::String(a)), Some(Value::String(b)), Some(Value::String(c))] =
+        ["a", "b", "c"].map(|name| lox.get_global(name))
+    else {
+        panic!("expected strings");
+    };
+    // Neither reading a variable nor evaluating a literal copies the string.
+    assert!(Arc::ptr_eq(&a, &b));
+    assert!(Arc::ptr_eq(&a, &c));
+}
+
+#[test]
+fn test_heap_stats() {
+    let source = r#"
+var a = "hello";
+var b = a;
+fun counter() {
+    var count = 0;
+    fun inc() { count = count + 1; return count; }
+    return inc;
+}
+var c = counter();
+"#;
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.run(source).unwrap();
+    // The call and the body of `counter` are kept by `inc`.
+    assert_eq!(
+        Value::from(
+            "environments: 3, strings: 1 (5 bytes), values: function 7, number 1, string 2"
+        ),
+        lox.eval("memoryStats()").unwrap()
+    );
+
+    let mut printer = TestPrinter::new();
+    let interpreter = Interpreter::builder()
+        .printer(&mut printer)
+        .stdlib(false)
+        .build();
+    let stats = interpreter.heap_stats();
+    assert_eq!((1, 0), (stats.environments, stats.values.len()));
+
+    // Values only reachable through lists and fields are counted, once per list or instance
+    let source = r#"
+class Box {}
+var box = Box();
+box.items = [["hello", 1], nil];
+box.items[1] = box;
+"#;
+    let mut printer = TestPrinter::new();
+    let mut interpreter = Interpreter::builder()
+        .printer(&mut printer)
+        .stdlib(false)
+        .build();
+    let globals = interpreter.globals().clone();
+    for s in &compile(source) {
+        interpreter.evaluate_stmt(&globals, s).unwrap();
+    }
+    assert_eq!(
+        "environments: 1, strings: 1 (5 bytes), \
+         values: class 1, instance 2, list 2, number 1, string 1",
+        interpreter.heap_stats().to_string()
+    );
+}
+
+#[test]
+fn test_foreign() {
+    let mut printer = TestPrinter::new();
+    let mut lox = Lox::with_printer(&mut printer);
+    lox.set_global("handle", Value::Foreign(Foreign::new(42_u32)));
+    lox.run("var copy = handle; print copy == handle; print copy;")
+        .unwrap();
+    let Some(Value::Foreign(copy)) = lox.get_global("copy") else {
+        panic!("expected a foreign object");
+    };
+    assert_eq!(Some(&42), copy.downcast_ref::<u32>());
+    assert_eq!(None, copy.downcast_ref::<i32>());
+    drop(lox);
+    assert_eq!(
+        vec!["Boolean(true)", "Foreign(Foreign(u32))"],
+        printer.messages
+    );
+}
+
+#[test]
+fn test_register_function() {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    let sink = sent.clone();
+    lox.register_function("send", 1, move |_interpreter, args| {
+        sink.lock().unwrap().push(args[0].clone());
+        Ok(Value::Nil)
+    });
+    lox.run("for (var i = 0; i < 3; i = i + 1) send(i);")
+        .unwrap();
+    assert_eq!(
+        vec![Value::Number(0.0), Value::Number(1.0), Value::Number(2.0)],
+        *sent.lock().unwrap()
+    );
+    assert!(matches!(lox.run("send();"), Err(LoxError::Runtime(_))));
+}
+
+#[test]
+fn test_run_from() {
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    let origin = SourceOrigin::new("README.md").at(12, 1);
+    let error = lox.run_from(&origin, "var a;\nprint a + 1;").unwrap_err();
+    let diagnostic = error.diagnostic();
+    assert_eq!(13, diagnostic.span.line);
+    assert_eq!(Some("README.md"), diagnostic.source_name.as_deref());
+    assert!(diagnostic
+        .render("ignored", "")
+        .contains(" --> README.md:13:"));
+}
+
+#[test]
+fn test_metrics() {
+    let source = r#"
+fun greet(name) {
+    return "hi " + name;
+}
+{
+    greet("a");
+    greet("b");
+}
+"#;
+    let statements = compile(source);
+    let mut printer = TestPrinter::new();
+    let mut metrics = MetricCounters::default();
+    let mut interpreter = Interpreter::new(&mut printer);
+    interpreter.set_metrics(&mut metrics);
+    let environment = Environment::new_globals_ptr();
+    for s in &statements {
+        interpreter.evaluate_stmt(&environment, s).unwrap();
+    }
+    drop(interpreter);
+    assert_eq!(
+        MetricCounters {
+            // Declaration, block, two calls and their bodies and returns
+            statements: 8,
+            calls: 2,
+            // Function object and two strings
+            allocations: 3,
+            // Block, two calls and their bodies
+            environments: 5,
+            peak_depth: 2,
+        },
+        metrics
+    );
+}
+
+#[test]
+fn test_limits() {
+    struct Granter {
+        reached: Vec<(LimitKind, u64)>,
+    }
+
+    impl LimitHandler for Granter {
+        fn limit_reached(&mut self, kind: LimitKind, limit: u64) -> LimitAction {
+            self.reached.push((kind, limit));
+            match kind {
+                LimitKind::Steps if self.reached.len() < 3 => LimitAction::Grant(10),
+                _ => LimitAction::Abort,
+            }
+        }
+    }
+
+    let run = |source: &str, limits: Limits, handler: &mut Granter| {
+        let mut printer = TestPrinter::new();
+        let mut interpreter = Interpreter::new(&mut printer);
+        interpreter.set_limits(limits);
+        interpreter.set_limit_handler(handler);
+        let environment = Environment::new_globals_ptr();
+        compile(source)
+            .iter()
+            .try_for_each(|s| interpreter.evaluate_stmt(&environment, s))
+    };
+
+    let mut handler = Granter { reached: vec![] };
+    let limits = Limits {
+        max_steps: Some(10),
+        ..Limits::default()
+    };
+    let error = run("while (true) {}", limits, &mut handler).unwrap_err();
+    assert_eq!("Step limit of 30 reached.", error.diagnostic().message);
+    let expected = [
+        (LimitKind::Steps, 10),
+        (LimitKind::Steps, 20),
+        (LimitKind::Steps, 30),
+    ];
+    assert_eq!(expected.to_vec(), handler.reached);
+
+    let mut handler = Granter { reached: vec![] };
+    let limits = Limits {
+        max_call_depth: Some(5),
+        ..Limits::default()
+    };
+    let source = "fun f(n) { return f(n + 1); }\nf(0);";
+    let error = run(source, limits, &mut handler).unwrap_err();
+    assert_eq!("Call depth limit of 5 reached.", error.diagnostic().message);
+    assert_eq!(1, error.span().line);
+}
+
+#[test]
+fn test_builder() {
+    let run = |source: &str, interpreter: &mut Interpreter| {
+        let environment = interpreter.globals().clone();
+        compile(source)
+            .iter()
+            .try_for_each(|s| interpreter.evaluate_stmt(&environment, s))
+    };
+
+    let mut printer = TestPrinter::new();
+    let mut input = "first\nsecond".as_bytes();
+    let mut interpreter = Interpreter::builder()
+        .printer(&mut printer)
+        .input(&mut input)
+        .deterministic(true)
+        .build();
+    run(
+        "print input(); print input(); print input(); print clock();",
+        &mut interpreter,
+    )
+    .unwrap();
+    drop(interpreter);
+    let expected = [
+        "String(\"first\")",
+        "String(\"second\")",
+        "Nil",
+        "Number(0.0)",
+    ];
+    assert_eq!(expected.to_vec(), printer.messages);
+
+    let mut printer = TestPrinter::new();
+    let mut interpreter = Interpreter::builder()
+        .printer(&mut printer)
+        .strict(true)
+        .build();
+    run("if (true) print 1;", &mut interpreter).unwrap();
+    let error = run("if (nil) print 2;", &mut interpreter).unwrap_err();
+    assert_eq!(
+        "Expected a boolean but got nil.",
+        error.diagnostic().message
+    );
+
+    let interpreter = Interpreter::builder().stdlib(false).build();
+    assert_eq!(0, interpreter.globals().lock().unwrap().locals().count());
+}
+
+#[test]
+fn test_internal_errors() {
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.register_function("broken", 0, |_interpreter, _args| panic!("host bug"));
+    let error = lox.run("var a = 1; broken();").unwrap_err();
+    assert!(matches!(error, LoxError::Internal(_)));
+    assert_eq!("Internal error: host bug", error.diagnostic().message);
+    assert!(!error.is_static());
+    assert_eq!(Value::Number(2.0), lox.eval("a + 1").unwrap());
+
+    // Statements which skipped the resolver refer to variables which can't be found.
+    let statements = Parser::new(Scanner::new("print a;").scan_tokens().unwrap())
+        .parse()
+        .unwrap();
+    let mut printer = TestPrinter::new();
+    let mut interpreter = Interpreter::new(&mut printer);
+    let environment = Environment::new_ptr(Environment::new_globals_ptr());
+    let error = interpreter
+        .evaluate_stmt(&environment, &statements[0])
+        .unwrap_err();
+    assert!(matches!(error, LoxError::Internal(_)));
+    assert_eq!(7, error.span().column);
+}
+
+#[test]
+fn test_globals_of_failed_runs() {
+    // As in the REPL, the names declared by a line are forgotten if it fails before defining them
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.run("var x = 1; print y;").unwrap_err();
+    let error = lox.run("print x;").unwrap_err();
+    assert!(error.is_static());
+    assert_eq!(Some(codes::UNDEFINED_VARIABLE), error.diagnostic().code);
+    lox.run("var x = 2;").unwrap();
+    assert_eq!(Value::Number(2.0), lox.eval("x").unwrap());
+
+    lox.run("var a = 1; var z = nil();").unwrap_err();
+    let error = lox.run("print z;").unwrap_err();
+    assert_eq!(Some(codes::UNDEFINED_VARIABLE), error.diagnostic().code);
+    lox.run("const z = 3;").unwrap();
+    assert_eq!(Value::Number(3.0), lox.eval("z").unwrap());
+
+    // The ones it did define are kept
+    assert_eq!(Value::Number(1.0), lox.eval("a").unwrap());
+    let error = lox.run_checked("var a = 2;").unwrap_err();
+    assert_eq!(Some(codes::REDECLARED_VARIABLE), error[0].diagnostic().code);
+}
+
+#[test]
+fn test_global_slots() {
+    let statements = compile("var a = 1; print a; { var b = 2; print b; print a; }");
+    let slot = |stmt: &Statement| match stmt {
+        Statement::Print(print) => match &print.exprs[0] {
+            rlox_syntax::Expr::Variable(variable) => variable.slot,
+            _ => panic!("expected a variable"),
+        },
+        _ => panic!("expected a print statement"),
+    };
+    let Statement::Block(block) = &statements[2] else {
+        panic!("expected a block");
+    };
+    assert!(slot(&statements[1]).is_some());
+    assert_eq!(None, slot(&block.statements[1]));
+    assert_eq!(slot(&statements[1]), slot(&block.statements[2]));
+
+    // Globals are still found by name, e.g. by the host.
+    let mut lox = Lox::with_printer(TestPrinter::new());
+    lox.run("var a = 1; fun bump() { a = a + 1; } bump();")
+        .unwrap();
+    assert_eq!(Some(Value::Number(2.0)), lox.get_global("a"));
+    assert_eq!(None, lox.get_global("typo"));
+
+    // Code resolved with other slots than the environment's still finds its globals
+    let mut printer = TestPrinter::new();
+    let mut interpreter = Interpreter::new(&mut printer);
+    let environment = Environment::new_globals_ptr();
+    environment
+        .lock()
+        .unwrap()
+        .define_variable("unrelated", Value::Nil)
+        .unwrap();
+    for s in &compile("var a = 1; var b = 2; a = a + b; print a, b;") {
+        interpreter.evaluate_stmt(&environment, s).unwrap();
+    }
+    drop(interpreter);
+    assert_eq!(vec!["Number(3.0) Number(2.0)"], printer.messages);
+}
+
+#[test]
+fn test_symbols() {
+    let source = "var a = 1;\nfun f(a) { a = a + 1; return clock(); }\nprint a;";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let mut statements = Parser::new(tokens.clone()).parse().unwrap();
+    let scope = Scope::new_globals_ptr();
+    for s in &mut statements {
+        Resolver.resolve_statement(&scope, s).unwrap();
+    }
+    let symbols = Symbols::collect(&tokens, &statements);
+
+    let global = symbols.at(3, 7).unwrap();
+    assert_eq!(SymbolKind::Variable, symbols.symbols[global].kind);
+    assert_eq!(
+        vec![Span::new(1, 5, 1), Span::new(3, 7, 1)],
+        symbols.occurrences(global)
+    );
+    let param = symbols.at(2, 7).unwrap();
+    assert_eq!(SymbolKind::Parameter, symbols.symbols[param].kind);
+    let writes: Vec<_> = symbols
+        .references
+        .iter()
+        .filter(|r| r.symbol == param)
+        .map(|r| (r.span.column, r.write))
+        .collect();
+    assert_eq!(vec![(12, true), (16, false)], writes);
+
+    let function = symbols.at(2, 5).unwrap();
+    assert_eq!(Some(1), symbols.symbols[function].arity);
+    let clock = symbols.at(2, 31).unwrap();
+    assert_eq!(SymbolKind::Native, symbols.symbols[clock].kind);
+    assert_eq!(None, symbols.symbols[clock].span);
+
+    // Variables declared by the same `var` are each found at their name
+    let source = "var a = 1, b;\nprint b;";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let mut statements = Parser::new(tokens.clone()).parse().unwrap();
+    let scope = Scope::new_globals_ptr();
+    for s in &mut statements {
+        Resolver.resolve_statement(&scope, s).unwrap();
+    }
+    let symbols = Symbols::collect(&tokens, &statements);
+    let b = symbols.at(2, 7).unwrap();
+    assert_eq!(
+        vec![Span::new(1, 12, 1), Span::new(2, 7, 1)],
+        symbols.occurrences(b)
+    );
+}
+
+#[test]
+fn test_classify() {
+    let source = "// count\nfun f(n) { var i = n; i = i + 1; return clock(); }\nprint \"a\";";
+    let classes: Vec<_> = classify(source)
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.span.line, r.span.column, r.class))
+        .collect();
+    use TokenClass::*;
+    let expected = vec![
+        (1, 1, Comment),
+        (2, 1, Keyword),
+        (2, 5, Function),
+        (2, 7, Parameter),
+        (2, 12, Keyword),
+        (2, 16, VariableWrite),
+        (2, 20, Parameter),
+        (2, 23, VariableWrite),
+        (2, 27, VariableRead),
+        (2, 31, Number),
+        (2, 34, Keyword),
+        (2, 41, Function),
+        (3, 1, Keyword),
+        (3, 7, String),
+    ];
+    assert_eq!(expected, classes);
+
+    // Identifiers are left out when the source doesn't resolve.
+    let classes: Vec<_> = classify("print x;").unwrap();
+    assert_eq!(1, classes.len());
+    assert!(classify("print @;").is_err());
+}
+
+#[test]
+fn test_call_graph() {
+    let source = r#"
+fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+fun main() {
+    fun log(x) { print x; }
+    var start = clock();
+    log(fib(10));
+    var f = log;
+    f(1);
+}
+main();
+"#;
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let mut statements = Parser::new(tokens.clone()).parse().unwrap();
+    let scope = Scope::new_globals_ptr();
+    for s in &mut statements {
+        Resolver.resolve_statement(&scope, s).unwrap();
+    }
+    let symbols = Symbols::collect(&tokens, &statements);
+    let dot = call_graph_dot(&symbols);
+    let expected = r#"digraph calls {
+    script [label="<script>", shape=plaintext];
+    f0 [label="clock", shape=box];
+    f4 [label="fib"];
+    f6 [label="main"];
+    f7 [label="log"];
+    script -> f6;
+    f4 -> f4;
+    f6 -> f0;
+    f6 -> f4;
+    f6 -> f7;
+}
+"#;
+    assert_eq!(expected, dot);
+}
+
+#[test]
+fn test_highlight_html() {
+    let source =
+        "// <greeting>\nfun greet(name) {\n    var s = \"hi \" + name;\n    print s < 1;\n}\n";
+    let expected = r#"<pre class="rlox"><code><span class="comment">// &lt;greeting&gt;</span>
+<span class="keyword">fun</span> <span class="function">greet</span>(<span class="parameter">name</span>) {
+    <span class="keyword">var</span> <span class="variable-write">s</span> = <span class="string">&quot;hi &quot;</span> + <span class="parameter">name</span>;
+    <span class="keyword">print</span> <span class="variable">s</span> &lt; <span class="number">1</span>;
+}
+</code></pre>
+"#;
+    assert_eq!(expected, highlight_html(source).unwrap());
+    assert!(highlight_html("var a = \"unterminated;").is_err());
+}
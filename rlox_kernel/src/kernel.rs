@@ -0,0 +1,425 @@
+use crate::wire::{Message, Session, PROTOCOL_VERSION};
+use rlox_interpreter::{Lox, Printer, Value};
+use rlox_json::Json;
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::LoxError;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Sends a message to the clients subscribed to IOPub.
+pub type Publish = Arc<dyn Fn(Message) + Send + Sync>;
+
+/// State shared with the printer, which publishes the output of a cell as it runs.
+struct Shared {
+    session: Session,
+    publish: Publish,
+    // Request being executed, the parent of the output
+    current: Mutex<Option<Message>>,
+}
+
+impl Shared {
+    fn publish(&self, msg_type: &str, parent: &Message, content: Json) {
+        (self.publish)(self.session.broadcast(msg_type, parent, content));
+    }
+}
+
+struct StreamPrinter(Arc<Shared>);
+
+impl Printer for StreamPrinter {
+    fn print(&mut self, message: &str) {
+        let current = self
+            .0
+            .current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(parent) = &*current {
+            let content = Json::object([
+                ("name", "stdout".into()),
+                ("text", format!("{message}\n").into()),
+            ]);
+            self.0.publish("stream", parent, content);
+        }
+    }
+}
+
+/// Answers the requests of the shell and control channels, running cells in a single
+/// persistent interpreter, so that definitions carry over from one cell to the next.
+pub struct Kernel {
+    lox: Lox<'static>,
+    shared: Arc<Shared>,
+    execution_count: usize,
+}
+
+impl Kernel {
+    pub fn new(publish: Publish) -> Self {
+        let shared = Arc::new(Shared {
+            session: Session::default(),
+            publish,
+            current: Mutex::new(None),
+        });
+        Self {
+            lox: Lox::with_printer(StreamPrinter(shared.clone())),
+            shared,
+            execution_count: 0,
+        }
+    }
+
+    /// The reply to `request`, if it has one, and whether the kernel should shut down. The
+    /// status of the kernel is published around the handling.
+    pub fn handle(&mut self, request: &Message) -> (Option<Message>, bool) {
+        let status = |state: &str| Json::object([("execution_state", state.into())]);
+        self.shared.publish("status", request, status("busy"));
+        let mut shutdown = false;
+        let reply = match request.msg_type() {
+            "kernel_info_request" => Some(("kernel_info_reply", kernel_info())),
+            "execute_request" => Some(("execute_reply", self.execute(request))),
+            "is_complete_request" => Some((
+                "is_complete_reply",
+                is_complete(request.content.get("code").as_str().unwrap_or_default()),
+            )),
+            "complete_request" => Some(("complete_reply", self.complete(&request.content))),
+            "comm_info_request" => Some((
+                "comm_info_reply",
+                Json::object([("status", "ok".into()), ("comms", Json::object([]))]),
+            )),
+            "history_request" => Some((
+                "history_reply",
+                Json::object([
+                    ("status", "ok".into()),
+                    ("history", Json::Array(Vec::new())),
+                ]),
+            )),
+            // A running cell can't be stopped, as requests are handled one at a time.
+            "interrupt_request" => {
+                Some(("interrupt_reply", Json::object([("status", "ok".into())])))
+            }
+            "shutdown_request" => {
+                shutdown = true;
+                let restart = request.content.get("restart").clone();
+                Some((
+                    "shutdown_reply",
+                    Json::object([("status", "ok".into()), ("restart", restart)]),
+                ))
+            }
+            msg_type => {
+                eprintln!("Ignoring unsupported message {msg_type}");
+                None
+            }
+        };
+        self.shared.publish("status", request, status("idle"));
+        let reply =
+            reply.map(|(msg_type, content)| self.shared.session.reply(msg_type, request, content));
+        (reply, shutdown)
+    }
+
+    fn execute(&mut self, request: &Message) -> Json {
+        let content = &request.content;
+        let code = content.get("code").as_str().unwrap_or_default();
+        let silent = content.get("silent") == &Json::Bool(true);
+        let store_history = !silent && content.get("store_history") != &Json::Bool(false);
+        if store_history {
+            self.execution_count += 1;
+        }
+        let count = self.execution_count;
+        if !silent {
+            let input = Json::object([("code", code.into()), ("execution_count", count.into())]);
+            self.shared.publish("execute_input", request, input);
+        }
+
+        *self.current() = Some(request.clone());
+        let result = self.lox.run_with_result(code);
+        *self.current() = None;
+
+        match result {
+            Ok(value) => {
+                if let Some(value) = value.filter(|v| *v != Value::Nil && !silent) {
+                    let result = Json::object([
+                        ("execution_count", count.into()),
+                        ("data", mime_bundle(&value)),
+                        ("metadata", Json::object([])),
+                    ]);
+                    self.shared.publish("execute_result", request, result);
+                }
+                Json::object([
+                    ("status", "ok".into()),
+                    ("execution_count", count.into()),
+                    ("payload", Json::Array(Vec::new())),
+                    ("user_expressions", Json::object([])),
+                ])
+            }
+            Err(e) => {
+                let source_name = format!("<cell {count}>");
+                let error = Json::object([
+                    ("ename", error_name(&e).into()),
+                    ("evalue", e.diagnostic().message.as_str().into()),
+                    (
+                        "traceback",
+                        vec![e
+                            .diagnostic()
+                            .render_with_color(&source_name, code, true)
+                            .into()]
+                        .into(),
+                    ),
+                ]);
+                if !silent {
+                    self.shared.publish("error", request, error.clone());
+                }
+                let Json::Object(mut members) = error else {
+                    unreachable!("built as an object")
+                };
+                members.insert(0, ("status".to_owned(), "error".into()));
+                members.push(("execution_count".to_owned(), count.into()));
+                Json::Object(members)
+            }
+        }
+    }
+
+    fn current(&self) -> std::sync::MutexGuard<'_, Option<Message>> {
+        self.shared
+            .current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Global names completing the identifier before the cursor.
+    fn complete(&self, content: &Json) -> Json {
+        let code: Vec<char> = content
+            .get("code")
+            .as_str()
+            .unwrap_or_default()
+            .chars()
+            .collect();
+        let end = content
+            .get("cursor_pos")
+            .as_usize()
+            .unwrap_or(code.len())
+            .min(code.len());
+        let mut start = end;
+        while start > 0 && (code[start - 1].is_alphanumeric() || code[start - 1] == '_') {
+            start -= 1;
+        }
+        let prefix: String = code[start..end].iter().collect();
+        let matches: Vec<Json> = self
+            .lox
+            .globals()
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .map(|(name, _)| name.into())
+            .collect();
+        Json::object([
+            ("status", "ok".into()),
+            ("matches", matches.into()),
+            ("cursor_start", start.into()),
+            ("cursor_end", end.into()),
+            ("metadata", Json::object([])),
+        ])
+    }
+}
+
+fn kernel_info() -> Json {
+    Json::object([
+        ("status", "ok".into()),
+        ("protocol_version", PROTOCOL_VERSION.into()),
+        ("implementation", "rlox".into()),
+        ("implementation_version", env!("CARGO_PKG_VERSION").into()),
+        (
+            "language_info",
+            Json::object([
+                ("name", "lox".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+                ("mimetype", "text/x-lox".into()),
+                ("file_extension", ".lox".into()),
+            ]),
+        ),
+        ("banner", "rlox: a Lox interpreter".into()),
+        ("help_links", Json::Array(Vec::new())),
+    ])
+}
+
+fn error_name(error: &LoxError) -> &'static str {
+    match error {
+        LoxError::Scan(_) => "ScanError",
+        LoxError::Parse(_) => "ParseError",
+        LoxError::Resolve(_) => "ResolveError",
+        LoxError::Compile(_) => "CompileError",
+        LoxError::Runtime(_) => "RuntimeError",
+        LoxError::Internal(_) => "InternalError",
+    }
+}
+
+/// Whether `code` can run as is, or is missing its end, such as a closing brace.
+fn is_complete(code: &str) -> Json {
+    let status = match Scanner::new(code)
+        .scan_tokens()
+        .and_then(|tokens| Parser::new(tokens).parse())
+    {
+        Ok(_) => "complete",
+        Err(LoxError::Parse(d)) if d.message.starts_with("at end,") => "incomplete",
+        Err(LoxError::Scan(d)) if d.message.starts_with("Unterminated") => "incomplete",
+        Err(_) => "invalid",
+    };
+    let mut members = vec![("status", status.into())];
+    if status == "incomplete" {
+        members.push(("indent", "    ".into()));
+    }
+    Json::object(members)
+}
+
+/// Representations of a value for notebooks to display: the text which `print` shows, and HTML.
+fn mime_bundle(value: &Value) -> Json {
+    let html = match value {
+        Value::String(s) => format!("<pre>{}</pre>", html_escape(&format!("{s:?}"))),
+        Value::FunctionObject(f) => format!(
+            "<code><b>fun</b> {}({})</code>",
+            html_escape(&f.name),
+            html_escape(&f.parameters.join(", "))
+        ),
+        Value::BoundMethod(m) => format!(
+            "<code>{}.{}({})</code>",
+            html_escape(&m.receiver.class.name),
+            html_escape(&m.method.name),
+            html_escape(&m.method.parameters.join(", "))
+        ),
+        Value::NativeFunction(f) => {
+            format!("<code>&lt;native fn {}&gt;</code>", html_escape(f.name))
+        }
+        Value::Class(class) => format!("<code><b>class</b> {}</code>", html_escape(&class.name)),
+        Value::Instance(instance) => {
+            format!(
+                "<code>{} instance</code>",
+                html_escape(&instance.class.name)
+            )
+        }
+        Value::HostFunction(_) | Value::Foreign(_) => {
+            format!("<code>&lt;{}&gt;</code>", value.type_name())
+        }
+        Value::Number(_) | Value::Boolean(_) | Value::Nil | Value::List(_) => {
+            format!("<code>{}</code>", html_escape(&format!("{value:?}")))
+        }
+    };
+    Json::object([
+        ("text/plain", format!("{value:?}").into()),
+        ("text/html", html.into()),
+    ])
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(msg_type: &str, content: Json) -> Message {
+        Message {
+            identities: vec![b"client".to_vec()],
+            header: Json::object([("msg_id", "1".into()), ("msg_type", msg_type.into())]),
+            parent_header: Json::object([]),
+            metadata: Json::object([]),
+            content,
+        }
+    }
+
+    /// A kernel with the messages it publishes.
+    fn kernel() -> (Kernel, Arc<Mutex<Vec<Message>>>) {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let sink = published.clone();
+        let kernel = Kernel::new(Arc::new(move |message| sink.lock().unwrap().push(message)));
+        (kernel, published)
+    }
+
+    fn execute(kernel: &mut Kernel, code: &str) -> Message {
+        let (reply, shutdown) = kernel.handle(&request(
+            "execute_request",
+            Json::object([("code", code.into())]),
+        ));
+        assert!(!shutdown);
+        reply.unwrap()
+    }
+
+    #[test]
+    fn test_execute() {
+        let (mut kernel, published) = kernel();
+        let reply = execute(&mut kernel, "var a = 1;\nprint a;\na + 1;");
+        assert_eq!(Some("ok"), reply.content.get("status").as_str());
+        assert_eq!(Some(1), reply.content.get("execution_count").as_usize());
+        assert_eq!(vec![b"client".to_vec()], reply.identities);
+        {
+            let published = published.lock().unwrap();
+            let types: Vec<_> = published.iter().map(Message::msg_type).collect();
+            assert_eq!(
+                vec![
+                    "status",
+                    "execute_input",
+                    "stream",
+                    "execute_result",
+                    "status"
+                ],
+                types
+            );
+            assert_eq!(
+                Some("Number(1.0)\n"),
+                published[2].content.get("text").as_str()
+            );
+            assert_eq!(
+                Some("Number(2.0)"),
+                published[3].content.get("data").get("text/plain").as_str()
+            );
+            assert!(published
+                .iter()
+                .all(|m| m.parent_header.get("msg_id").as_str() == Some("1")));
+        }
+
+        // Definitions persist, and errors are published.
+        published.lock().unwrap().clear();
+        let reply = execute(&mut kernel, "a + nil;");
+        assert_eq!(Some("error"), reply.content.get("status").as_str());
+        assert_eq!(Some("RuntimeError"), reply.content.get("ename").as_str());
+        assert_eq!(Some(2), reply.content.get("execution_count").as_usize());
+        let published = published.lock().unwrap();
+        assert_eq!("error", published[2].msg_type());
+    }
+
+    #[test]
+    fn test_other_requests() {
+        let (mut kernel, _) = kernel();
+        let (reply, _) = kernel.handle(&request("kernel_info_request", Json::object([])));
+        let reply = reply.unwrap();
+        assert_eq!("kernel_info_reply", reply.msg_type());
+        assert_eq!(
+            Some("lox"),
+            reply.content.get("language_info").get("name").as_str()
+        );
+
+        let status = |code: &str| is_complete(code).get("status").as_str().unwrap().to_owned();
+        assert_eq!("complete", status("print 1;"));
+        assert_eq!("incomplete", status("fun f() {"));
+        assert_eq!("incomplete", status("print \"a"));
+        assert_eq!("invalid", status("print );"));
+
+        execute(&mut kernel, "var counter = 0;");
+        let content = Json::object([("code", "print cou".into()), ("cursor_pos", 9usize.into())]);
+        let (reply, _) = kernel.handle(&request("complete_request", content));
+        let reply = reply.unwrap();
+        assert_eq!(
+            &Json::from(vec!["counter".into()]),
+            reply.content.get("matches")
+        );
+        assert_eq!(Some(6), reply.content.get("cursor_start").as_usize());
+
+        let (reply, shutdown) = kernel.handle(&request("shutdown_request", Json::object([])));
+        assert!(shutdown);
+        assert_eq!("shutdown_reply", reply.unwrap().msg_type());
+    }
+
+    #[test]
+    fn test_mime_bundle() {
+        let bundle = mime_bundle(&Value::from("<b>"));
+        assert_eq!(Some("String(\"<b>\")"), bundle.get("text/plain").as_str());
+        assert_eq!(
+            Some("<pre>\"&lt;b&gt;\"</pre>"),
+            bundle.get("text/html").as_str()
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Jupyter kernel, to use Lox in notebooks.
+//!
+//! ```text
+//! rlox_kernel install <directory>    # writes the kernel spec, e.g. to ~/.local/share/jupyter/kernels/lox
+//! rlox_kernel <connection-file>      # what Jupyter runs
+//! ```
+//!
+//! The kernel speaks ZMTP over TCP itself rather than linking to ZeroMQ, and signs messages
+//! with HMAC-SHA256, the only scheme Jupyter uses.
+
+mod hmac;
+mod kernel;
+mod wire;
+mod zmtp;
+
+use kernel::Kernel;
+use rlox_json::Json;
+use std::io::{self, BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::exit;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use wire::Message;
+use zmtp::SocketType;
+
+const EX_USAGE: i32 = 64;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [command, directory] if command == "install" => install(Path::new(directory)),
+        [connection_file] if !connection_file.starts_with('-') => run(connection_file),
+        _ => {
+            eprintln!("Usage: rlox_kernel install <directory>");
+            eprintln!("       rlox_kernel <connection-file>");
+            exit(EX_USAGE);
+        }
+    }
+}
+
+/// Writes the kernel spec telling Jupyter how to start this executable.
+fn install(directory: &Path) -> io::Result<()> {
+    let executable = std::env::current_exe()?;
+    let spec = Json::object([
+        (
+            "argv",
+            vec![
+                executable.to_string_lossy().into_owned().into(),
+                "{connection_file}".into(),
+            ]
+            .into(),
+        ),
+        ("display_name", "Lox".into()),
+        ("language", "lox".into()),
+    ]);
+    std::fs::create_dir_all(directory)?;
+    let path = directory.join("kernel.json");
+    std::fs::write(&path, format!("{spec}\n"))?;
+    println!("Installed {}", path.display());
+    Ok(())
+}
+
+/// Where the sockets listen and how messages are signed, as written by Jupyter.
+struct ConnectionInfo {
+    ip: String,
+    key: Vec<u8>,
+    shell_port: usize,
+    control_port: usize,
+    iopub_port: usize,
+    stdin_port: usize,
+    hb_port: usize,
+}
+
+impl ConnectionInfo {
+    fn read(path: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let json = Json::parse(&std::fs::read_to_string(path)?).map_err(invalid)?;
+        if json.get("transport").as_str().unwrap_or("tcp") != "tcp" {
+            return Err(invalid("Only the tcp transport is supported".to_owned()));
+        }
+        let scheme = json
+            .get("signature_scheme")
+            .as_str()
+            .unwrap_or("hmac-sha256");
+        if scheme != "hmac-sha256" {
+            return Err(invalid(format!("Unsupported signature scheme {scheme}")));
+        }
+        let port = |name: &str| {
+            json.get(name)
+                .as_usize()
+                .ok_or_else(|| invalid(format!("Missing {name}")))
+        };
+        Ok(Self {
+            ip: json.get("ip").as_str().unwrap_or("127.0.0.1").to_owned(),
+            key: json
+                .get("key")
+                .as_str()
+                .unwrap_or_default()
+                .as_bytes()
+                .to_vec(),
+            shell_port: port("shell_port")?,
+            control_port: port("control_port")?,
+            iopub_port: port("iopub_port")?,
+            stdin_port: port("stdin_port")?,
+            hb_port: port("hb_port")?,
+        })
+    }
+}
+
+/// A request, with where to write its reply.
+struct Request {
+    frames: Vec<Vec<u8>>,
+    reply_to: Arc<Mutex<BufWriter<TcpStream>>>,
+}
+
+fn run(connection_file: &str) -> io::Result<()> {
+    let info = ConnectionInfo::read(connection_file)?;
+    let listen = |port: usize| TcpListener::bind((info.ip.as_str(), port as u16));
+    let shell = listen(info.shell_port)?;
+    let control = listen(info.control_port)?;
+    let iopub = listen(info.iopub_port)?;
+    let stdin = listen(info.stdin_port)?;
+    let heartbeat = listen(info.hb_port)?;
+
+    let (requests, received) = channel();
+    for listener in [shell, control, stdin] {
+        let requests = requests.clone();
+        std::thread::spawn(move || {
+            accept(listener, SocketType::Router, move |stream| {
+                serve(stream, &requests)
+            })
+        });
+    }
+    std::thread::spawn(move || accept(heartbeat, SocketType::Rep, echo));
+    let subscribers = Arc::new(Mutex::new(Vec::new()));
+    let accepted = subscribers.clone();
+    std::thread::spawn(move || {
+        accept(iopub, SocketType::Pub, move |stream| {
+            // Subscriptions are ignored, as every client wants all messages.
+            let writer = BufWriter::new(stream.try_clone()?);
+            accepted
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(writer);
+            let mut reader = BufReader::new(stream);
+            while zmtp::read_message(&mut reader)?.is_some() {}
+            Ok(())
+        })
+    });
+
+    let key = info.key.clone();
+    let mut kernel = Kernel::new(Arc::new(move |message: Message| {
+        let frames = message.encode(&key);
+        let mut subscribers = subscribers.lock().unwrap_or_else(PoisonError::into_inner);
+        // Subscribers which can't be written to have disconnected.
+        subscribers.retain_mut(|subscriber| zmtp::write_message(subscriber, &frames).is_ok());
+    }));
+    for request in received {
+        let message = match Message::decode(&request.frames, &info.key) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Ignoring message: {e}");
+                continue;
+            }
+        };
+        let (reply, shutdown) = kernel.handle(&message);
+        if let Some(reply) = reply {
+            let mut writer = request
+                .reply_to
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = zmtp::write_message(&mut *writer, &reply.encode(&info.key)) {
+                eprintln!("Failed to reply: {e}");
+            }
+        }
+        if shutdown {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Handles every connection to `listener` on its own thread after the handshake.
+fn accept(
+    listener: TcpListener,
+    socket_type: SocketType,
+    handle: impl Fn(TcpStream) -> io::Result<()> + Clone + Send + 'static,
+) {
+    for stream in listener.incoming() {
+        let handle = handle.clone();
+        std::thread::spawn(move || {
+            let result = stream.and_then(|mut stream| {
+                zmtp::handshake(&mut stream, socket_type)?;
+                handle(stream)
+            });
+            if let Err(e) = result {
+                eprintln!("Connection failed: {e}");
+            }
+        });
+    }
+}
+
+/// Forwards the messages of a shell, control or stdin connection to the kernel.
+fn serve(stream: TcpStream, requests: &Sender<Request>) -> io::Result<()> {
+    let reply_to = Arc::new(Mutex::new(BufWriter::new(stream.try_clone()?)));
+    let mut reader = BufReader::new(stream);
+    while let Some(frames) = zmtp::read_message(&mut reader)? {
+        let request = Request {
+            frames,
+            reply_to: reply_to.clone(),
+        };
+        if requests.send(request).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Sends heartbeats back as they are.
+fn echo(stream: TcpStream) -> io::Result<()> {
+    let mut writer = BufWriter::new(stream.try_clone()?);
+    let mut reader = BufReader::new(stream);
+    while let Some(frames) = zmtp::read_message(&mut reader)? {
+        zmtp::write_message(&mut writer, &frames)?;
+    }
+    Ok(())
+}
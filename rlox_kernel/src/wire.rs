@@ -0,0 +1,206 @@
+//! Messages of the Jupyter protocol, and how they are split into signed frames.
+
+use crate::hmac::hmac_sha256_hex;
+use rlox_json::Json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Separates the routing identities of a message from its signed parts.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Frames before the delimiter, which are the topic for messages on IOPub.
+    pub identities: Vec<Vec<u8>>,
+    pub header: Json,
+    pub parent_header: Json,
+    pub metadata: Json,
+    pub content: Json,
+}
+
+impl Message {
+    pub fn msg_type(&self) -> &str {
+        self.header.get("msg_type").as_str().unwrap_or_default()
+    }
+
+    /// Parses the frames of a message, checking its signature if there is a `key`.
+    pub fn decode(frames: &[Vec<u8>], key: &[u8]) -> Result<Self, String> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or("Message without delimiter")?;
+        let [signature, header, parent_header, metadata, content] = frames
+            .get(delimiter + 1..delimiter + 6)
+            .and_then(|parts| <&[Vec<u8>; 5]>::try_from(parts).ok())
+            .ok_or("Message with missing parts")?;
+        if !key.is_empty() {
+            let expected = hmac_sha256_hex(key, &[header, parent_header, metadata, content]);
+            if !constant_time_eq(expected.as_bytes(), signature) {
+                return Err("Message with an invalid signature".to_owned());
+            }
+        }
+        let parse = |part: &[u8]| {
+            std::str::from_utf8(part)
+                .map_err(|e| e.to_string())
+                .and_then(Json::parse)
+        };
+        Ok(Self {
+            identities: frames[..delimiter].to_vec(),
+            header: parse(header)?,
+            parent_header: parse(parent_header)?,
+            metadata: parse(metadata)?,
+            content: parse(content)?,
+        })
+    }
+
+    /// Frames of the message, signed with `key` unless it's empty.
+    pub fn encode(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let parts = [
+            &self.header,
+            &self.parent_header,
+            &self.metadata,
+            &self.content,
+        ]
+        .map(|part| part.to_string().into_bytes());
+        let signature = if key.is_empty() {
+            String::new()
+        } else {
+            let [header, parent_header, metadata, content] = &parts;
+            hmac_sha256_hex(key, &[header, parent_header, metadata, content])
+        };
+        let mut frames = self.identities.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signature.into_bytes());
+        frames.extend(parts);
+        frames
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Creates the messages sent by the kernel, with unique ids.
+pub struct Session {
+    id: String,
+    counter: AtomicU64,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        Self {
+            id: format!("{:x}-{:x}", nanos, std::process::id()),
+            counter: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Session {
+    /// A message of type `msg_type` replying to, or caused by, `parent`.
+    pub fn message(&self, msg_type: &str, parent: &Message, content: Json) -> Message {
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        Message {
+            identities: Vec::new(),
+            header: Json::object([
+                ("msg_id", format!("{}-{count}", self.id).into()),
+                ("session", self.id.as_str().into()),
+                ("username", "rlox".into()),
+                ("date", now_iso8601().into()),
+                ("msg_type", msg_type.into()),
+                ("version", PROTOCOL_VERSION.into()),
+            ]),
+            parent_header: parent.header.clone(),
+            metadata: Json::object([]),
+            content,
+        }
+    }
+
+    /// Same as [`Session::message`] for a reply on the channel `parent` came from, routed back
+    /// to its sender.
+    pub fn reply(&self, msg_type: &str, parent: &Message, content: Json) -> Message {
+        Message {
+            identities: parent.identities.clone(),
+            ..self.message(msg_type, parent, content)
+        }
+    }
+
+    /// Same as [`Session::message`] for IOPub, where the identity is the topic.
+    pub fn broadcast(&self, msg_type: &str, parent: &Message, content: Json) -> Message {
+        Message {
+            identities: vec![format!("kernel.{}.{msg_type}", self.id).into_bytes()],
+            ..self.message(msg_type, parent, content)
+        }
+    }
+}
+
+/// The current time in UTC, e.g. `2024-01-31T12:00:00.000000Z`.
+fn now_iso8601() -> String {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        elapsed.subsec_micros()
+    )
+}
+
+/// Year, month and day of a number of days since 1970-01-01, by Howard Hinnant's algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let session = Session::default();
+        let request = Message {
+            identities: vec![b"client".to_vec()],
+            header: Json::object([("msg_type", "execute_request".into())]),
+            parent_header: Json::object([]),
+            metadata: Json::object([]),
+            content: Json::object([("code", "print 1;".into())]),
+        };
+        let reply = session.reply("execute_reply", &request, Json::object([]));
+        assert_eq!(vec![b"client".to_vec()], reply.identities);
+        assert_eq!("execute_reply", reply.msg_type());
+        assert_eq!(request.header, reply.parent_header);
+
+        let frames = reply.encode(b"secret");
+        assert_eq!(reply, Message::decode(&frames, b"secret").unwrap());
+        assert!(Message::decode(&frames, b"other").is_err());
+        assert!(Message::decode(&frames[1..], b"").is_ok());
+        assert!(Message::decode(&frames[..4], b"").is_err());
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!((1970, 1, 1), civil_from_days(0));
+        assert_eq!((2000, 2, 29), civil_from_days(11_016));
+        assert_eq!((2024, 12, 31), civil_from_days(20_088));
+    }
+}
@@ -0,0 +1,196 @@
+//! Just enough of ZMTP 3, the wire protocol of ZeroMQ, to accept the connections of Jupyter
+//! clients: the NULL security mechanism, and multipart messages of frames.
+//!
+//! Routing is left to the kernel, which answers a request on the connection it came from, so
+//! the socket type is only announced to the peer during the handshake.
+
+use std::io::{self, Read, Write};
+
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketType {
+    Router,
+    Pub,
+    Rep,
+}
+
+impl SocketType {
+    fn name(self) -> &'static str {
+        match self {
+            SocketType::Router => "ROUTER",
+            SocketType::Pub => "PUB",
+            SocketType::Rep => "REP",
+        }
+    }
+}
+
+fn greeting() -> [u8; 64] {
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    // Version 3.0
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Exchanges greetings and `READY` commands with a peer which just connected.
+pub fn handshake(stream: &mut (impl Read + Write), socket_type: SocketType) -> io::Result<()> {
+    stream.write_all(&greeting())?;
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xff || peer[9] & 1 == 0 || peer[10] < 3 {
+        return Err(invalid("Peer doesn't speak ZMTP 3"));
+    }
+    if &peer[12..16] != b"NULL" || peer[16..32].iter().any(|b| *b != 0) {
+        return Err(invalid("Peer uses a security mechanism other than NULL"));
+    }
+
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    let name = b"Socket-Type";
+    ready.push(name.len() as u8);
+    ready.extend_from_slice(name);
+    let value = socket_type.name().as_bytes();
+    ready.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    ready.extend_from_slice(value);
+    write_frame(stream, FLAG_COMMAND, &ready)?;
+    stream.flush()?;
+
+    match read_frame(stream)? {
+        Some((flags, body)) if flags & FLAG_COMMAND != 0 && body.starts_with(b"\x05READY") => {
+            Ok(())
+        }
+        _ => Err(invalid("Expected READY from peer")),
+    }
+}
+
+fn read_frame(stream: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut flags = [0u8];
+    match stream.read_exact(&mut flags) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let flags = flags[0];
+    let size = if flags & FLAG_LONG != 0 {
+        let mut size = [0u8; 8];
+        stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size) as usize
+    } else {
+        let mut size = [0u8];
+        stream.read_exact(&mut size)?;
+        size[0] as usize
+    };
+    let mut body = vec![0; size];
+    stream.read_exact(&mut body)?;
+    Ok(Some((flags, body)))
+}
+
+fn write_frame(stream: &mut impl Write, flags: u8, body: &[u8]) -> io::Result<()> {
+    if body.len() > u8::MAX as usize {
+        stream.write_all(&[flags | FLAG_LONG])?;
+        stream.write_all(&(body.len() as u64).to_be_bytes())?;
+    } else {
+        stream.write_all(&[flags, body.len() as u8])?;
+    }
+    stream.write_all(body)
+}
+
+/// Reads the frames of the next message, skipping commands such as subscriptions. Returns
+/// `None` once the peer disconnects.
+pub fn read_message(stream: &mut impl Read) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let mut frames = Vec::new();
+    loop {
+        let Some((flags, body)) = read_frame(stream)? else {
+            return Ok(None);
+        };
+        if flags & FLAG_COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & FLAG_MORE == 0 {
+            return Ok(Some(frames));
+        }
+    }
+}
+
+pub fn write_message(stream: &mut impl Write, frames: &[Vec<u8>]) -> io::Result<()> {
+    for (i, frame) in frames.iter().enumerate() {
+        let flags = if i + 1 < frames.len() { FLAG_MORE } else { 0 };
+        write_frame(stream, flags, frame)?;
+    }
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stream reading `input` and collecting what is written.
+    struct Duplex {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_handshake_and_messages() {
+        // What a DEALER peer sends: its greeting, READY, a subscription-like command, then a
+        // message with a long frame.
+        let mut input = greeting().to_vec();
+        input.extend_from_slice(b"\x04\x1c\x05READY\x0bSocket-Type\x00\x00\x00\x06DEALER");
+        input.extend_from_slice(b"\x04\x0a\x09SUBSCRIBE");
+        let long = vec![b'x'; 300];
+        write_message(&mut input, &[b"a".to_vec(), long.clone()]).unwrap();
+        let mut stream = Duplex {
+            input: io::Cursor::new(input),
+            output: Vec::new(),
+        };
+
+        handshake(&mut stream, SocketType::Router).unwrap();
+        assert_eq!(greeting(), stream.output[..64]);
+        assert_eq!(
+            b"\x04\x1c\x05READY\x0bSocket-Type\x00\x00\x00\x06ROUTER",
+            &stream.output[64..]
+        );
+        assert_eq!(
+            Some(vec![b"a".to_vec(), long]),
+            read_message(&mut stream).unwrap()
+        );
+        assert_eq!(None, read_message(&mut stream).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_security() {
+        let mut input = greeting().to_vec();
+        input[12..17].copy_from_slice(b"PLAIN");
+        let mut stream = Duplex {
+            input: io::Cursor::new(input),
+            output: Vec::new(),
+        };
+        assert!(handshake(&mut stream, SocketType::Pub).is_err());
+    }
+}
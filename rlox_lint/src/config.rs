@@ -0,0 +1,159 @@
+use rlox_syntax::{codes, Diagnostic, Severity, Span};
+use std::collections::HashMap;
+
+/// Name of the configuration file looked up in the current directory by `rlox lint`.
+pub const CONFIG_FILE_NAME: &str = ".rloxlint";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// A block without statements, other than the body of a function.
+    EmptyBlock,
+    /// An `if` or `while` whose condition doesn't depend on anything, except `while (true)`.
+    ConstantCondition,
+    /// A declaration hiding a variable of an enclosing scope.
+    Shadowing,
+    /// `==` or `!=` between values which are known to be of different types.
+    MixedEquality,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 4] = [
+        Rule::EmptyBlock,
+        Rule::ConstantCondition,
+        Rule::Shadowing,
+        Rule::MixedEquality,
+    ];
+
+    /// Name used by configuration files and shown with the diagnostics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::EmptyBlock => "empty-block",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::Shadowing => "shadowing",
+            Rule::MixedEquality => "mixed-equality",
+        }
+    }
+
+    /// Stable code of the diagnostics reported by the rule.
+    pub fn code(self) -> &'static str {
+        match self {
+            Rule::EmptyBlock => codes::EMPTY_BLOCK,
+            Rule::ConstantCondition => codes::CONSTANT_CONDITION,
+            Rule::Shadowing => codes::SHADOWING,
+            Rule::MixedEquality => codes::MIXED_EQUALITY,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+
+    /// Severity unless configured otherwise, `None` meaning that the rule is disabled.
+    pub fn default_severity(self) -> Option<Severity> {
+        match self {
+            Rule::EmptyBlock | Rule::ConstantCondition | Rule::MixedEquality => {
+                Some(Severity::Warning)
+            }
+            // Shadowing is often deliberate, e.g. `var a = a;` in a block.
+            Rule::Shadowing => None,
+        }
+    }
+}
+
+/// Which rules are enabled, and with which severity.
+///
+/// The configuration file has a `rule = level` line per rule to change, the level being one of
+/// `off`, `note`, `warning` and `error`. Empty lines and lines starting with `#` are ignored:
+///
+/// ```text
+/// # Blocks may be left empty on purpose.
+/// empty-block = off
+/// shadowing = warning
+/// mixed-equality = error
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintConfig {
+    severities: HashMap<Rule, Option<Severity>>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        let severities = Rule::ALL
+            .into_iter()
+            .map(|rule| (rule, rule.default_severity()))
+            .collect();
+        Self { severities }
+    }
+}
+
+impl LintConfig {
+    /// Reads a configuration file, the rules it doesn't mention keeping their default severity.
+    pub fn parse(text: &str) -> Result<Self, Box<Diagnostic>> {
+        let mut config = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let column = line.len() - line.trim_start().len() + 1;
+            let span = Span::new(index + 1, column, trimmed.chars().count());
+            let Some((name, level)) = trimmed.split_once('=') else {
+                return Err(Box::new(Diagnostic::new(span, "Expected `rule = level`.")));
+            };
+            config.configure(name.trim(), level.trim(), span)?;
+        }
+        Ok(config)
+    }
+
+    /// Sets the rule named `rule` to the severity named `level`, where `span` is the setting
+    /// in the configuration, to report unknown names at.
+    pub fn configure(
+        &mut self,
+        rule: &str,
+        level: &str,
+        span: Span,
+    ) -> Result<(), Box<Diagnostic>> {
+        let Some(rule) = Rule::from_name(rule) else {
+            let names: Vec<_> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+            let diagnostic = Diagnostic::new(span, format!("Unknown rule: {rule}"))
+                .with_note(format!("Rules are {}.", names.join(", ")));
+            return Err(Box::new(diagnostic));
+        };
+        let severity = match level {
+            "off" => None,
+            "note" => Some(Severity::Note),
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            level => {
+                let diagnostic = Diagnostic::new(span, format!("Unknown level: {level}"))
+                    .with_note("Levels are off, note, warning, error.");
+                return Err(Box::new(diagnostic));
+            }
+        };
+        self.set(rule, severity);
+        Ok(())
+    }
+
+    /// Severity of the diagnostics of `rule`, `None` if it's disabled.
+    pub fn severity(&self, rule: Rule) -> Option<Severity> {
+        self.severities[&rule]
+    }
+
+    pub fn set(&mut self, rule: Rule, severity: Option<Severity>) {
+        self.severities.insert(rule, severity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_errors() {
+        let error = LintConfig::parse("shadowing = loud").unwrap_err();
+        assert_eq!("Unknown level: loud", error.message);
+        let error = LintConfig::parse("\n  unused = off").unwrap_err();
+        assert_eq!(Span::new(2, 3, 12), error.span);
+        assert!(LintConfig::parse("shadowing").is_err());
+    }
+}
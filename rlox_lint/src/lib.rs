@@ -0,0 +1,10 @@
+//! Checks which point at code that is valid but likely wrong, beyond the errors of the resolver.
+//!
+//! Every check is a [`Rule`] which reports standard [`Diagnostic`](rlox_syntax::Diagnostic)s
+//! with the severity given by a [`LintConfig`], usually loaded from a `.rloxlint` file.
+
+mod config;
+mod linter;
+
+pub use config::*;
+pub use linter::*;
@@ -0,0 +1,344 @@
+use crate::{LintConfig, Rule};
+use rlox_syntax::*;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Checks `statements` against the rules enabled by `config`, returning the diagnostics in
+/// source order.
+pub fn lint(statements: &[Statement], config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        scopes: vec![HashMap::new()],
+        diagnostics: Vec::new(),
+    };
+    for s in statements {
+        linter.statement(s);
+    }
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|d| (d.span.line, d.span.column));
+    diagnostics
+}
+
+/// Type of the values an expression may evaluate to, when known without running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StaticType {
+    Number,
+    String,
+    Boolean,
+    Nil,
+}
+
+impl StaticType {
+    fn name(self) -> &'static str {
+        match self {
+            StaticType::Number => "number",
+            StaticType::String => "string",
+            StaticType::Boolean => "boolean",
+            StaticType::Nil => "nil",
+        }
+    }
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    // Where the variables of each enclosing scope are declared, the globals first
+    scopes: Vec<HashMap<String, Span>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, diagnostic: Diagnostic) {
+        if let Some(severity) = self.config.severity(rule) {
+            let mut diagnostic = diagnostic
+                .with_code(rule.code())
+                .with_note(format!("from the `{}` rule", rule.name()));
+            diagnostic.severity = severity;
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression(s) => self.expr(&s.expr),
+            Statement::Print(s) => {
+                for expr in &s.exprs {
+                    self.expr(expr);
+                }
+            }
+            Statement::VariableDecl(s) => {
+                if let Some(expr) = &s.expr {
+                    self.expr(expr);
+                }
+                self.declare(&s.name, s.span);
+            }
+            Statement::Block(s) => {
+                if s.statements.is_empty() {
+                    self.report(Rule::EmptyBlock, Diagnostic::warning(s.span, "Empty block"));
+                }
+                self.block(&s.statements);
+            }
+            Statement::If(s) => {
+                self.condition(&s.condition, false);
+                self.statement(&s.then_branch);
+                if let Some(else_branch) = &s.else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While(s) => {
+                self.condition(&s.condition, true);
+                self.statement(&s.body);
+            }
+            Statement::Function(s) => {
+                self.declare(&s.name, s.span);
+                self.function(s.span, &s.params, &s.body);
+            }
+            Statement::Return(s) => {
+                if let Some(value) = &s.value {
+                    self.expr(value);
+                }
+            }
+            Statement::Class(s) => {
+                self.declare(&s.name, s.span);
+                if let Some(superclass) = &s.superclass {
+                    self.expr(superclass);
+                }
+                // The scope of `this`
+                self.scopes.push(HashMap::new());
+                for method in &s.methods {
+                    if let Statement::Function(method) = method {
+                        self.function(method.span, &method.params, &method.body);
+                    }
+                }
+                self.scopes.pop();
+            }
+        }
+    }
+
+    /// Checks the parameters and body of a function, method or lambda, spanning `span`.
+    fn function(&mut self, span: Span, params: &[String], body: &RwLock<Statement>) {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(param, span);
+        }
+        // An empty function is fine, e.g. as a callback doing nothing.
+        if let Statement::Block(body) = &*body.read().unwrap() {
+            self.block(&body.statements);
+        }
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.scopes.push(HashMap::new());
+        for s in statements {
+            self.statement(s);
+        }
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, span: Span) {
+        let (current, enclosing) = self.scopes.split_last_mut().unwrap();
+        let shadowed = enclosing
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied();
+        current.insert(name.to_owned(), span);
+        if let Some(previous) = shadowed {
+            let diagnostic = Diagnostic::warning(span, format!("Shadows variable: {name}"))
+                .with_label(previous, "shadowed declaration");
+            self.report(Rule::Shadowing, diagnostic);
+        }
+    }
+
+    /// Checks the condition of an `if`, or of a `while` if `is_loop`, which runs forever with a
+    /// `true` condition on purpose.
+    fn condition(&mut self, condition: &Expr, is_loop: bool) {
+        self.expr(condition);
+        let forever = matches!(condition, Expr::Literal(e) if e.literal == Literal::Boolean(true));
+        if is_constant(condition) && !(is_loop && forever) {
+            let diagnostic = Diagnostic::warning(condition.span(), "Condition is always the same");
+            self.report(Rule::ConstantCondition, diagnostic);
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Binary(e) => {
+                self.expr(&e.left);
+                self.expr(&e.right);
+                if matches!(e.operator, TokenKind::EqualEqual | TokenKind::BangEqual) {
+                    self.equality(e);
+                }
+            }
+            Expr::Logical(e) => {
+                self.expr(&e.left);
+                self.expr(&e.right);
+            }
+            Expr::Grouping(e) => self.expr(&e.expr),
+            Expr::Unary(e) => self.expr(&e.right),
+            Expr::Assign(e) => self.expr(&e.value),
+            Expr::Call(e) => {
+                self.expr(&e.callee);
+                for argument in &e.arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get(e) => self.expr(&e.object),
+            Expr::Set(e) => {
+                self.expr(&e.object);
+                self.expr(&e.value);
+            }
+            Expr::List(e) => {
+                for element in &e.elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Index(e) => {
+                self.expr(&e.object);
+                self.expr(&e.index);
+            }
+            Expr::Slice(e) => {
+                self.expr(&e.object);
+                for bound in e.start.iter().chain(&e.end) {
+                    self.expr(bound);
+                }
+            }
+            Expr::IndexSet(e) => {
+                self.expr(&e.object);
+                self.expr(&e.index);
+                self.expr(&e.value);
+            }
+            Expr::Lambda(e) => self.function(e.span, &e.params, &e.body),
+            Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super(_) => {}
+        }
+    }
+
+    fn equality(&mut self, e: &expr::Binary) {
+        let (Some(left), Some(right)) = (static_type(&e.left), static_type(&e.right)) else {
+            return;
+        };
+        if left != right {
+            let always = if e.operator == TokenKind::EqualEqual {
+                "false"
+            } else {
+                "true"
+            };
+            let diagnostic = Diagnostic::warning(
+                e.span,
+                format!(
+                    "Comparing a {} with a {} is always {always}",
+                    left.name(),
+                    right.name()
+                ),
+            );
+            self.report(Rule::MixedEquality, diagnostic);
+        }
+    }
+}
+
+/// Whether `e` evaluates to the same value whenever it runs.
+fn is_constant(e: &Expr) -> bool {
+    match e {
+        Expr::Literal(_) => true,
+        Expr::Binary(e) => is_constant(&e.left) && is_constant(&e.right),
+        Expr::Logical(e) => is_constant(&e.left) && is_constant(&e.right),
+        Expr::Grouping(e) => is_constant(&e.expr),
+        Expr::Unary(e) => is_constant(&e.right),
+        Expr::Variable(_)
+        | Expr::Assign(_)
+        | Expr::Call(_)
+        | Expr::Get(_)
+        | Expr::Set(_)
+        | Expr::This(_)
+        | Expr::Super(_)
+        | Expr::List(_)
+        | Expr::Index(_)
+        | Expr::IndexSet(_)
+        | Expr::Slice(_)
+        | Expr::Lambda(_) => false,
+    }
+}
+
+fn static_type(e: &Expr) -> Option<StaticType> {
+    match e {
+        Expr::Literal(e) => Some(match e.literal {
+            Literal::Number(_) => StaticType::Number,
+            Literal::String(_) => StaticType::String,
+            Literal::Boolean(_) => StaticType::Boolean,
+            Literal::Nil => StaticType::Nil,
+        }),
+        Expr::Grouping(e) => static_type(&e.expr),
+        Expr::Unary(e) => match e.operator {
+            TokenKind::Minus => Some(StaticType::Number),
+            _ => Some(StaticType::Boolean),
+        },
+        Expr::Binary(e) => match e.operator {
+            TokenKind::Comma => static_type(&e.right),
+            TokenKind::Minus | TokenKind::Star | TokenKind::Slash => Some(StaticType::Number),
+            // Adds numbers or concatenates strings
+            TokenKind::Plus => static_type(&e.left).or_else(|| static_type(&e.right)),
+            _ => Some(StaticType::Boolean),
+        },
+        Expr::Variable(_)
+        | Expr::Assign(_)
+        | Expr::Logical(_)
+        | Expr::Call(_)
+        | Expr::Get(_)
+        | Expr::Set(_)
+        | Expr::This(_)
+        | Expr::Super(_)
+        | Expr::List(_)
+        | Expr::Index(_)
+        | Expr::IndexSet(_)
+        | Expr::Slice(_)
+        | Expr::Lambda(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_parser::{Parser, Scanner};
+
+    fn messages(source: &str, config: &LintConfig) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        lint(&statements, config)
+            .into_iter()
+            .map(|d| format!("{:?} {}: {}", d.severity, d.span.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let source = r#"
+fun noop() {}
+if (1 < 2) {}
+while (true) print 1 == "1";
+for (;;) print (nil != false);
+var a = 1;
+{ var a = 2; print a + 1 == 3; }
+"#;
+        let expected = vec![
+            "Warning 3: Condition is always the same",
+            "Warning 3: Empty block",
+            "Warning 4: Comparing a number with a string is always false",
+            "Warning 5: Comparing a nil with a boolean is always true",
+        ];
+        assert_eq!(expected, messages(source, &LintConfig::default()));
+    }
+
+    #[test]
+    fn test_configured_rules() {
+        let config = LintConfig::parse(
+            "# Deliberately empty blocks\nempty-block = off\n\nshadowing = error\n",
+        )
+        .unwrap();
+        let source = r#"
+fun f(a) { var a = a; {} }
+var f = 1;
+"#;
+        let expected = vec!["Error 2: Shadows variable: a"];
+        assert_eq!(expected, messages(source, &config));
+    }
+}
@@ -0,0 +1,266 @@
+use rlox_fmt::FormatConfig;
+use rlox_interpreter::{classify, Resolver, Scope, SymbolKind, Symbols, TokenClass};
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{Diagnostic, LoxError, Span, TokenKind};
+
+/// Position as counted by the protocol: 0-based line, and UTF-16 code units in the line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+/// Token types of the semantic tokens, by index.
+pub const SEMANTIC_TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "function",
+    "parameter",
+    "variable",
+    "string",
+    "number",
+    "comment",
+    "class",
+];
+
+/// Token modifiers of the semantic tokens, by bit.
+pub const SEMANTIC_TOKEN_MODIFIERS: &[&str] = &["modification"];
+
+/// An open source file, analyzed whenever it changes.
+pub struct Document {
+    pub text: String,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Empty unless the whole document resolves.
+    pub symbols: Symbols,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let (diagnostics, symbols) = match Self::analyze(&text) {
+            Ok(symbols) => (Vec::new(), symbols),
+            Err(diagnostics) => (diagnostics, Symbols::default()),
+        };
+        Self {
+            text,
+            diagnostics,
+            symbols,
+        }
+    }
+
+    /// Resolves every statement, reporting the errors of all of them.
+    fn analyze(text: &str) -> Result<Symbols, Vec<Diagnostic>> {
+        let diagnostic = |e: LoxError| vec![e.diagnostic().clone()];
+        let tokens = Scanner::new(text).scan_tokens().map_err(diagnostic)?;
+        let mut statements = Parser::new(tokens.clone()).parse().map_err(diagnostic)?;
+        let scope = Scope::new_globals_ptr();
+        let errors: Vec<_> = statements
+            .iter_mut()
+            .filter_map(|s| Resolver.resolve_statement(&scope, s).err())
+            .map(|e| e.diagnostic().clone())
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(Symbols::collect(&tokens, &statements))
+    }
+
+    /// Where the symbol at `position` is declared.
+    pub fn definition(&self, position: Position) -> Option<Span> {
+        let symbol = self.symbol_at(position)?;
+        self.symbols.symbols[symbol].span
+    }
+
+    /// Spans to replace to rename the symbol at `position` to `new_name`.
+    pub fn rename(&self, position: Position, new_name: &str) -> Result<Vec<Span>, String> {
+        let tokens = Scanner::new(new_name).scan_tokens().unwrap_or_default();
+        if tokens.len() != 2 || tokens[0].kind != TokenKind::Identifier {
+            return Err(format!("Not a valid name: {new_name}"));
+        }
+        let symbol = self.symbol_at(position).ok_or("Nothing to rename here")?;
+        if self.symbols.symbols[symbol].kind == SymbolKind::Native {
+            return Err("Native functions can't be renamed".to_owned());
+        }
+        Ok(self.symbols.occurrences(symbol))
+    }
+
+    /// Markdown describing the symbol at `position`.
+    pub fn hover(&self, position: Position) -> Option<String> {
+        let symbol = &self.symbols.symbols[self.symbol_at(position)?];
+        let scope = if symbol.global { "global" } else { "local" };
+        let arity = symbol.arity.unwrap_or_default();
+        let parameters = if arity == 1 {
+            "parameter"
+        } else {
+            "parameters"
+        };
+        Some(match symbol.kind {
+            SymbolKind::Variable => format!("{scope} variable `{}`", symbol.name),
+            SymbolKind::Parameter => format!("parameter `{}`", symbol.name),
+            SymbolKind::Function => {
+                format!("{scope} function `{}`, {arity} {parameters}", symbol.name)
+            }
+            SymbolKind::Native => {
+                format!("native function `{}`, {arity} {parameters}", symbol.name)
+            }
+            SymbolKind::Class => format!("{scope} class `{}`", symbol.name),
+        })
+    }
+
+    pub fn format(&self, config: &FormatConfig) -> Result<String, LoxError> {
+        rlox_fmt::format_source(&self.text, config)
+    }
+
+    /// Semantic tokens encoded as the protocol expects: five numbers per token, which are the
+    /// line and start relative to the previous token, the length, the type and the modifiers.
+    pub fn semantic_tokens(&self) -> Vec<usize> {
+        let ranges = classify(&self.text).unwrap_or_default();
+        let mut data = Vec::new();
+        let mut previous = Position::default();
+        for range in ranges {
+            let (token_type, modifiers) = match range.class {
+                TokenClass::Keyword => (0, 0),
+                TokenClass::Function => (1, 0),
+                TokenClass::Parameter => (2, 0),
+                TokenClass::VariableRead => (3, 0),
+                TokenClass::VariableWrite => (3, 1),
+                TokenClass::String => (4, 0),
+                TokenClass::Number => (5, 0),
+                TokenClass::Comment => (6, 0),
+                TokenClass::Class => (7, 0),
+            };
+            for span in self.line_spans(range.span) {
+                let (start, end) = self.range(span);
+                let delta_line = start.line - previous.line;
+                let delta_start = if delta_line == 0 {
+                    start.character - previous.character
+                } else {
+                    start.character
+                };
+                let length = end.character - start.character;
+                data.extend([delta_line, delta_start, length, token_type, modifiers]);
+                previous = start;
+            }
+        }
+        data
+    }
+
+    /// `span` split at line ends, as tokens may not span several lines, e.g. strings.
+    fn line_spans(&self, span: Span) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut remaining = span.len;
+        let mut column = span.column;
+        for (index, text) in self.text.lines().enumerate().skip(span.line - 1) {
+            let available = text.chars().count().saturating_sub(column - 1);
+            let len = remaining.min(available);
+            if len > 0 {
+                spans.push(Span::new(index + 1, column, len));
+            }
+            // The line break counts as a character
+            remaining = remaining.saturating_sub(len + 1);
+            if remaining == 0 {
+                break;
+            }
+            column = 1;
+        }
+        spans
+    }
+
+    fn symbol_at(&self, position: Position) -> Option<usize> {
+        let (line, column) = self.line_column(position);
+        self.symbols.at(line, column)
+    }
+
+    /// 1-based line and column in characters of `position`.
+    pub fn line_column(&self, position: Position) -> (usize, usize) {
+        let text = self.text.lines().nth(position.line).unwrap_or_default();
+        let mut units = 0;
+        let column = text
+            .chars()
+            .take_while(|c| {
+                units += c.len_utf16();
+                units <= position.character
+            })
+            .count();
+        (position.line + 1, column + 1)
+    }
+
+    /// Start and end of `span`.
+    pub fn range(&self, span: Span) -> (Position, Position) {
+        let text = self.text.lines().nth(span.line - 1).unwrap_or_default();
+        let character = |column: usize| -> usize {
+            text.chars()
+                .take(column.saturating_sub(1))
+                .map(char::len_utf16)
+                .sum()
+        };
+        let line = span.line - 1;
+        let start = Position {
+            line,
+            character: character(span.column),
+        };
+        let end = Position {
+            line,
+            character: character(span.column + span.len),
+        };
+        (start, end)
+    }
+
+    /// Range covering the whole document.
+    pub fn full_range(&self) -> (Position, Position) {
+        let end = Position {
+            line: self.text.lines().count() + 1,
+            character: 0,
+        };
+        (Position::default(), end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, character: usize) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn test_navigation() {
+        let document = Document::new("var é = 1;\nfun f(a) { return a + é; }\nf(é);".to_owned());
+        assert!(document.diagnostics.is_empty());
+        assert_eq!(Some(Span::new(1, 5, 1)), document.definition(at(1, 22)));
+        assert_eq!(Some(Span::new(2, 7, 1)), document.definition(at(1, 18)));
+        assert_eq!(None, document.definition(at(0, 0)));
+        assert_eq!(
+            vec![Span::new(1, 5, 1), Span::new(2, 23, 1), Span::new(3, 3, 1)],
+            document.rename(at(1, 22), "count").unwrap()
+        );
+        assert!(document.rename(at(1, 22), "while").is_err());
+        assert!(document.rename(at(1, 22), "a b").is_err());
+        assert_eq!(
+            Some("global function `f`, 1 parameter".to_owned()),
+            document.hover(at(1, 4))
+        );
+        assert_eq!((at(0, 4), at(0, 5)), document.range(Span::new(1, 5, 1)));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let document = Document::new("var s = \"a\nb\";\nprint s;".to_owned());
+        let expected = vec![
+            0, 0, 3, 0, 0, // var
+            0, 4, 1, 3, 1, // s
+            0, 4, 2, 4, 0, // "a
+            1, 0, 2, 4, 0, // b"
+            1, 0, 5, 0, 0, // print
+            0, 6, 1, 3, 0, // s
+        ];
+        assert_eq!(expected, document.semantic_tokens());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let document = Document::new("print a;\nprint b;".to_owned());
+        let messages: Vec<_> = document.diagnostics.iter().map(|d| d.span.line).collect();
+        assert_eq!(vec![1, 2], messages);
+        assert_eq!(None, document.hover(at(0, 6)));
+    }
+}
@@ -0,0 +1,18 @@
+//! Language server for editors, over stdin and stdout.
+//!
+//! Supports diagnostics of the scanner, parser and resolver as documents change, go to
+//! definition, rename, hover, document formatting and semantic tokens.
+
+mod document;
+mod server;
+
+use server::Server;
+use std::process::exit;
+
+fn main() -> std::io::Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let shutdown = Server::default().serve(stdin.lock(), stdout.lock())?;
+    // Exiting without a shutdown request is an error for the protocol.
+    exit(if shutdown { 0 } else { 1 });
+}
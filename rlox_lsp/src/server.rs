@@ -0,0 +1,388 @@
+use crate::document::{Document, Position, SEMANTIC_TOKEN_MODIFIERS, SEMANTIC_TOKEN_TYPES};
+use rlox_fmt::FormatConfig;
+use rlox_json::Json;
+use rlox_syntax::{Diagnostic, Severity, Span};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+// Error codes of JSON-RPC and of the protocol
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const REQUEST_FAILED: i32 = -32803;
+
+type HandlerResult = Result<Json, (i32, String)>;
+
+/// Language server speaking JSON-RPC with `Content-Length` framed messages, one at a time.
+#[derive(Default)]
+pub struct Server {
+    // Open documents by URI
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl Server {
+    /// Answers the messages of `input` until the client asks to exit, returning whether it asked
+    /// for a shutdown first.
+    pub fn serve(
+        &mut self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> std::io::Result<bool> {
+        while let Some(content) = read_message(&mut input)? {
+            let replies = match Json::parse(&content) {
+                Ok(message) if message.get("method").as_str() == Some("exit") => {
+                    return Ok(self.shutdown);
+                }
+                Ok(message) => self.handle(&message),
+                Err(e) => vec![error_response(Json::Null, PARSE_ERROR, e)],
+            };
+            for reply in replies {
+                write_message(&mut output, &reply)?;
+            }
+        }
+        Ok(self.shutdown)
+    }
+
+    /// Messages to send in reply to `message`: a response for requests, and notifications.
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+        let id = message.get("id");
+        if *id == Json::Null {
+            return self.notification(method, params);
+        }
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/rename" => self.rename(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/formatting" => self.formatting(params),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method: {method}"))),
+        };
+        let response = match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            Err((code, message)) => error_response(id.clone(), code, message),
+        };
+        vec![response]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.get("uri").as_str() else {
+            return Vec::new();
+        };
+        let text = match method {
+            "textDocument/didOpen" => document.get("text").as_str(),
+            // Only full changes are asked for by the capabilities.
+            "textDocument/didChange" => match params.get("contentChanges") {
+                Json::Array(changes) => changes.last().and_then(|c| c.get("text").as_str()),
+                _ => None,
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Json::Array(Vec::new()))];
+            }
+            _ => None,
+        };
+        let Some(text) = text else {
+            return Vec::new();
+        };
+        let document = Document::new(text.to_owned());
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|d| diagnostic(&document, d))
+            .collect();
+        self.documents.insert(uri.to_owned(), document);
+        vec![publish_diagnostics(uri, Json::Array(diagnostics))]
+    }
+
+    /// Document and position of a request about a position.
+    fn position<'a>(
+        &'a self,
+        params: &'a Json,
+    ) -> Result<(&'a str, &'a Document, Position), (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let document = uri.and_then(|uri| self.documents.get(uri));
+        let position = params.get("position");
+        let line = position.get("line").as_usize();
+        let character = position.get("character").as_usize();
+        match (uri, document, line, character) {
+            (Some(uri), Some(document), Some(line), Some(character)) => {
+                Ok((uri, document, Position { line, character }))
+            }
+            _ => Err((INVALID_PARAMS, "Unknown document or position".to_owned())),
+        }
+    }
+
+    fn definition(&self, params: &Json) -> HandlerResult {
+        let (uri, document, position) = self.position(params)?;
+        Ok(match document.definition(position) {
+            Some(span) => {
+                Json::object([("uri", uri.into()), ("range", span_range(document, span))])
+            }
+            None => Json::Null,
+        })
+    }
+
+    fn rename(&self, params: &Json) -> HandlerResult {
+        let (uri, document, position) = self.position(params)?;
+        let new_name = params.get("newName").as_str().unwrap_or_default();
+        let spans = document
+            .rename(position, new_name)
+            .map_err(|message| (REQUEST_FAILED, message))?;
+        let edits = spans
+            .into_iter()
+            .map(|span| text_edit(span_range(document, span), new_name))
+            .collect();
+        Ok(Json::object([(
+            "changes",
+            Json::Object(vec![(uri.to_owned(), Json::Array(edits))]),
+        )]))
+    }
+
+    fn hover(&self, params: &Json) -> HandlerResult {
+        let (_, document, position) = self.position(params)?;
+        Ok(match document.hover(position) {
+            Some(text) => Json::object([(
+                "contents",
+                Json::object([("kind", "markdown".into()), ("value", text.into())]),
+            )]),
+            None => Json::Null,
+        })
+    }
+
+    fn semantic_tokens(&self, params: &Json) -> HandlerResult {
+        let document = self.document(params)?;
+        let data = document.semantic_tokens().into_iter().map(Json::from);
+        Ok(Json::object([("data", Json::Array(data.collect()))]))
+    }
+
+    /// Document of a request about a whole document.
+    fn document(&self, params: &Json) -> Result<&Document, (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        uri.and_then(|uri| self.documents.get(uri))
+            .ok_or((INVALID_PARAMS, "Unknown document".to_owned()))
+    }
+
+    fn formatting(&self, params: &Json) -> HandlerResult {
+        let document = self.document(params)?;
+        let mut config = FormatConfig::default();
+        if let Some(tab_size) = params.get("options").get("tabSize").as_usize() {
+            config.indent_width = tab_size;
+        }
+        let formatted = document
+            .format(&config)
+            .map_err(|e| (REQUEST_FAILED, e.diagnostic().message.clone()))?;
+        let (start, end) = document.full_range();
+        Ok(Json::Array(vec![text_edit(range(start, end), &formatted)]))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([(
+        "capabilities",
+        Json::object([
+            // Full document on every change
+            ("textDocumentSync", 1.into()),
+            ("definitionProvider", true.into()),
+            ("renameProvider", true.into()),
+            ("hoverProvider", true.into()),
+            ("documentFormattingProvider", true.into()),
+            (
+                "semanticTokensProvider",
+                Json::object([
+                    (
+                        "legend",
+                        Json::object([
+                            ("tokenTypes", strings(SEMANTIC_TOKEN_TYPES)),
+                            ("tokenModifiers", strings(SEMANTIC_TOKEN_MODIFIERS)),
+                        ]),
+                    ),
+                    ("full", true.into()),
+                ]),
+            ),
+        ]),
+    )])
+}
+
+fn strings(items: &[&str]) -> Json {
+    Json::Array(items.iter().map(|&s| s.into()).collect())
+}
+
+fn error_response(id: Json, code: i32, message: String) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Number(code.into())),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Json) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics)]),
+        ),
+    ])
+}
+
+fn diagnostic(document: &Document, diagnostic: &Diagnostic) -> Json {
+    let severity: usize = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let message = std::iter::once(&diagnostic.message)
+        .chain(&diagnostic.notes)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut members = vec![
+        ("range", span_range(document, diagnostic.span)),
+        ("severity", severity.into()),
+        ("source", "rlox".into()),
+        ("message", message.into()),
+    ];
+    if let Some(code) = diagnostic.code {
+        members.push(("code", code.into()));
+    }
+    Json::object(members)
+}
+
+fn span_range(document: &Document, span: Span) -> Json {
+    let (start, end) = document.range(span);
+    range(start, end)
+}
+
+fn range(start: Position, end: Position) -> Json {
+    let position =
+        |p: Position| Json::object([("line", p.line.into()), ("character", p.character.into())]);
+    Json::object([("start", position(start)), ("end", position(end))])
+}
+
+fn text_edit(range: Json, new_text: &str) -> Json {
+    Json::object([("range", range), ("newText", new_text.into())])
+}
+
+/// Content of the next message, `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Missing Content-Length header",
+        ));
+    };
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> std::io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the server on `messages`, returning what it wrote.
+    fn serve(messages: &[&str]) -> (bool, Vec<Json>) {
+        let input: String = messages
+            .iter()
+            .map(|m| format!("Content-Length: {}\r\n\r\n{m}", m.len()))
+            .collect();
+        let mut output = Vec::new();
+        let shutdown = Server::default()
+            .serve(input.as_bytes(), &mut output)
+            .unwrap();
+        let mut output = &output[..];
+        let mut replies = Vec::new();
+        while let Some(content) = read_message(&mut output).unwrap() {
+            replies.push(Json::parse(&content).unwrap());
+        }
+        (shutdown, replies)
+    }
+
+    #[test]
+    fn test_session() {
+        let (shutdown, replies) = serve(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.lox","text":"print b;"}}}"#,
+            r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.lox"},"contentChanges":[{"text":"var  b=1;\nprint b;"}]}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///a.lox"},"position":{"line":1,"character":6}}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/formatting","params":{"textDocument":{"uri":"file:///a.lox"},"options":{"tabSize":2,"insertSpaces":true}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"textDocument/codeLens","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ]);
+        assert!(shutdown);
+        assert_eq!(
+            &Json::Bool(true),
+            replies[0]
+                .get("result")
+                .get("capabilities")
+                .get("hoverProvider")
+        );
+
+        let diagnostics = |reply: &Json| match reply.get("params").get("diagnostics") {
+            Json::Array(diagnostics) => diagnostics.len(),
+            _ => panic!("expected diagnostics"),
+        };
+        assert_eq!(1, diagnostics(&replies[1]));
+        assert_eq!(0, diagnostics(&replies[2]));
+
+        let definition = replies[3].get("result").get("range").get("start");
+        assert_eq!(Some(0), definition.get("line").as_usize());
+        assert_eq!(Some(5), definition.get("character").as_usize());
+        let Json::Array(edits) = replies[4].get("result") else {
+            panic!("expected edits");
+        };
+        assert_eq!(
+            Some("var b = 1;\nprint b;\n"),
+            edits[0].get("newText").as_str()
+        );
+        assert_eq!(
+            &Json::Number(METHOD_NOT_FOUND.into()),
+            replies[5].get("error").get("code")
+        );
+        assert_eq!(&Json::Null, replies[6].get("result"));
+    }
+}
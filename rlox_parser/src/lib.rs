@@ -1,5 +1,7 @@
+mod optimizer;
 mod parser;
 mod scanner;
 
+pub use optimizer::*;
 pub use parser::*;
 pub use scanner::*;
@@ -0,0 +1,185 @@
+use rlox_syntax::*;
+
+/// Simplifies resolved statements without changing what they do, so that backends execute fewer
+/// nodes:
+///
+/// - `if` with a `true` or `false` condition becomes the branch which runs,
+/// - `while (false)` loops are dropped, and `while (true)` conditions are unwrapped from their
+///   parentheses,
+/// - `and` and `or` with a `true` or `false` left operand become the operand which is the result.
+///
+/// Only boolean literals are folded, so that strict mode still rejects other conditions. Scopes
+/// are left as they are, which keeps the resolved distances of variables valid.
+pub fn optimize(statements: &mut Vec<Statement>) {
+    *statements = std::mem::take(statements)
+        .into_iter()
+        .filter_map(optimize_statement)
+        .collect();
+}
+
+/// Returns the simplified statement, or None if it does nothing.
+fn optimize_statement(stmt: Statement) -> Option<Statement> {
+    let stmt = match stmt {
+        Statement::Expression(mut s) => {
+            s.expr = optimize_expr(s.expr);
+            Statement::Expression(s)
+        }
+        Statement::Print(mut s) => {
+            s.exprs = s.exprs.into_iter().map(optimize_expr).collect();
+            Statement::Print(s)
+        }
+        Statement::VariableDecl(mut s) => {
+            s.expr = s.expr.map(optimize_expr);
+            Statement::VariableDecl(s)
+        }
+        Statement::Block(mut s) => {
+            optimize(&mut s.statements);
+            Statement::Block(s)
+        }
+        Statement::If(s) => {
+            let statement::If {
+                id,
+                span,
+                condition,
+                then_branch,
+                else_branch,
+            } = *s;
+            let condition = optimize_expr(condition);
+            return match constant_bool(&condition) {
+                Some(true) => optimize_statement(then_branch),
+                Some(false) => else_branch.and_then(optimize_statement),
+                None => Some(Statement::If(Box::new(statement::If {
+                    id,
+                    span,
+                    condition,
+                    then_branch: optimize_statement(then_branch)
+                        .unwrap_or_else(|| statement::Block::new_wrapped(span, vec![])),
+                    else_branch: else_branch.and_then(optimize_statement),
+                }))),
+            };
+        }
+        Statement::While(s) => {
+            let statement::While {
+                id,
+                span,
+                condition,
+                body,
+            } = *s;
+            let mut condition = optimize_expr(condition);
+            match constant_bool(&condition) {
+                Some(false) => return None,
+                Some(true) => condition = expr::Literal::new_wrapped(span, Literal::Boolean(true)),
+                None => {}
+            }
+            Statement::While(Box::new(statement::While {
+                id,
+                span,
+                condition,
+                body: optimize_statement(body)
+                    .unwrap_or_else(|| statement::Block::new_wrapped(span, vec![])),
+            }))
+        }
+        Statement::Function(s) => {
+            if let Statement::Block(body) = &mut *s.body.write().unwrap_or_else(|e| e.into_inner())
+            {
+                optimize(&mut body.statements);
+            }
+            Statement::Function(s)
+        }
+        Statement::Return(mut s) => {
+            s.value = s.value.map(optimize_expr);
+            Statement::Return(s)
+        }
+        Statement::Class(mut s) => {
+            optimize(&mut s.methods);
+            Statement::Class(s)
+        }
+    };
+    Some(stmt)
+}
+
+fn optimize_expr(expr: Expr) -> Expr {
+    match expr {
+        Expr::Binary(mut e) => {
+            e.left = optimize_expr(e.left);
+            e.right = optimize_expr(e.right);
+            Expr::Binary(e)
+        }
+        Expr::Grouping(mut e) => {
+            e.expr = optimize_expr(e.expr);
+            Expr::Grouping(e)
+        }
+        Expr::Unary(mut e) => {
+            e.right = optimize_expr(e.right);
+            Expr::Unary(e)
+        }
+        Expr::Assign(mut e) => {
+            e.value = optimize_expr(e.value);
+            Expr::Assign(e)
+        }
+        Expr::Logical(mut e) => {
+            e.left = optimize_expr(e.left);
+            e.right = optimize_expr(e.right);
+            match (constant_bool(&e.left), e.operator) {
+                (Some(true), TokenKind::Or) | (Some(false), TokenKind::And) => e.left,
+                (Some(_), _) => e.right,
+                (None, _) => Expr::Logical(e),
+            }
+        }
+        Expr::Call(mut e) => {
+            e.callee = optimize_expr(e.callee);
+            e.arguments = e.arguments.into_iter().map(optimize_expr).collect();
+            Expr::Call(e)
+        }
+        Expr::Get(mut e) => {
+            e.object = optimize_expr(e.object);
+            Expr::Get(e)
+        }
+        Expr::Set(mut e) => {
+            e.object = optimize_expr(e.object);
+            e.value = optimize_expr(e.value);
+            Expr::Set(e)
+        }
+        Expr::List(mut e) => {
+            e.elements = e.elements.into_iter().map(optimize_expr).collect();
+            Expr::List(e)
+        }
+        Expr::Index(mut e) => {
+            e.object = optimize_expr(e.object);
+            e.index = optimize_expr(e.index);
+            Expr::Index(e)
+        }
+        Expr::Slice(mut e) => {
+            e.object = optimize_expr(e.object);
+            e.start = e.start.map(optimize_expr);
+            e.end = e.end.map(optimize_expr);
+            Expr::Slice(e)
+        }
+        Expr::IndexSet(mut e) => {
+            e.object = optimize_expr(e.object);
+            e.index = optimize_expr(e.index);
+            e.value = optimize_expr(e.value);
+            Expr::IndexSet(e)
+        }
+        Expr::Lambda(e) => {
+            if let Statement::Block(body) = &mut *e.body.write().unwrap_or_else(|e| e.into_inner())
+            {
+                optimize(&mut body.statements);
+            }
+            Expr::Lambda(e)
+        }
+        Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super(_) => expr,
+    }
+}
+
+/// Value of `expr` if it's a boolean literal, possibly in parentheses.
+fn constant_bool(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Literal(e) => match e.literal {
+            Literal::Boolean(b) => Some(b),
+            _ => None,
+        },
+        Expr::Grouping(e) => constant_bool(&e.expr),
+        _ => None,
+    }
+}
@@ -1,88 +1,273 @@
-use anyhow::bail;
 use rlox_syntax::*;
 use std::sync::{Arc, RwLock};
 
-type ParseExprResult = anyhow::Result<Expr>;
-type ParseStmtResult = anyhow::Result<Statement>;
+type ParseExprResult = Result<Expr, LoxError>;
+type ParseStmtResult = Result<Statement, LoxError>;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // `///` comments, in source order
+    doc_comments: Vec<Comment>,
+    // Syntax errors recovered from
+    errors: Vec<LoxError>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    /// Creates a parser of `tokens`, which are terminated by an end of file token if they aren't
+    /// already.
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        if tokens
+            .last()
+            .map(|t| t.kind != TokenKind::Eof)
+            .unwrap_or(true)
+        {
+            let (line, column) = tokens
+                .last()
+                .map(|t| (t.line, t.column + t.lexeme().chars().count()))
+                .unwrap_or((1, 1));
+            tokens.push(Token::new(TokenKind::Eof, "", None, line, column));
+        }
+        Self {
+            tokens,
+            current: 0,
+            doc_comments: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Attaches the `///` comments among `comments` to the function declarations they precede.
+    pub fn with_comments(mut self, comments: &[Comment]) -> Self {
+        self.doc_comments = comments
+            .iter()
+            .filter(|c| !c.trailing && c.text.starts_with("///") && !c.text.starts_with("////"))
+            .cloned()
+            .collect();
+        self
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Statement>, LoxError> {
+        let (statements, errors) = self.parse_recovering();
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(statements),
+        }
     }
 
-    pub fn parse(&mut self) -> anyhow::Result<Vec<Statement>> {
+    /// Same as [`Parser::parse`], but goes on after a syntax error from the start of the next
+    /// statement, returning every error along with the statements which could be parsed.
+    pub fn parse_recovering(&mut self) -> (Vec<Statement>, Vec<LoxError>) {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            statements.push(self.parse_declaration()?);
+            statements.extend(self.parse_declaration_recovering());
         }
-        Ok(statements)
+        (statements, std::mem::take(&mut self.errors))
     }
 
-    /// declaration    → funDecl
-    //                 | varDecl
-    //                 | statement ;
-    fn parse_declaration(&mut self) -> ParseStmtResult {
-        if self.match_(&[TokenKind::Var]) {
-            self.parse_variable_decl()
-        } else if self.match_(&[TokenKind::Fun]) {
-            self.parse_function_decl()
-        } else {
-            self.parse_statement()
+    /// Parses tokens holding a single expression, e.g. `a + 1`, without a trailing `;`.
+    pub fn parse_single_expression(&mut self) -> ParseExprResult {
+        let expr = self.parse_expression()?;
+        if !self.is_at_end() {
+            return Self::error(
+                self.peek(),
+                codes::EXPECT_END_OF_EXPRESSION,
+                "Expect end of expression.",
+            );
         }
+        Ok(expr)
     }
 
-    fn parse_variable_decl(&mut self) -> ParseStmtResult {
-        let name = self
-            .consume(&TokenKind::Identifier, "Expect variable name.")?
-            .lexeme
-            .to_owned();
-        let expr = if self.match_(&[TokenKind::Equal]) {
-            Some(self.parse_expression()?)
+    /// Parses a declaration, or records the error and skips to the next statement.
+    fn parse_declaration_recovering(&mut self) -> Vec<Statement> {
+        match self.parse_declaration() {
+            Ok(statements) => statements,
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                Vec::new()
+            }
+        }
+    }
+
+    /// declaration    → classDecl
+    //                 | funDecl
+    //                 | varDecl
+    //                 | constDecl
+    //                 | statement ;
+    ///
+    /// `fun` followed by `(` starts a lambda, in an expression statement. A `var` declaring
+    /// several variables is a statement per variable.
+    fn parse_declaration(&mut self) -> Result<Vec<Statement>, LoxError> {
+        if self.match_(&[TokenKind::Var, TokenKind::Const]) {
+            return self.parse_variable_decl();
+        }
+        let statement = if self.check(&TokenKind::Fun)
+            && self.tokens[self.current + 1].kind != TokenKind::LeftParen
+        {
+            self.advance();
+            self.parse_function_decl()?
+        } else if self.match_(&[TokenKind::Class]) {
+            self.parse_class_decl()?
         } else {
-            None
+            self.parse_statement()?
         };
+        Ok(vec![statement])
+    }
+
+    /// Text of the doc comments on the lines right above `line`.
+    fn doc_comment(&self, line: usize) -> Option<String> {
+        let end = self.doc_comments.partition_point(|c| c.span.line < line);
+        let mut start = end;
+        while start > 0 && self.doc_comments[start - 1].span.line + (end - start) + 1 == line {
+            start -= 1;
+        }
+        if start == end {
+            return None;
+        }
+        let lines: Vec<_> = self.doc_comments[start..end]
+            .iter()
+            .map(|c| {
+                let text = &c.text["///".len()..];
+                text.strip_prefix(' ').unwrap_or(text)
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    /// varDecl        → "var" declarator ( "," declarator )* ";" ;
+    /// declarator     → IDENTIFIER ( "=" assignment )? ;
+    /// constDecl      → "const" IDENTIFIER "=" assignment ( "," IDENTIFIER "=" assignment )* ";" ;
+    ///
+    /// Declares the variables one after the other, each spanning the `var` or `const` keyword or
+    /// the comma before its name.
+    fn parse_variable_decl(&mut self) -> Result<Vec<Statement>, LoxError> {
+        let constant = self.previous().kind == TokenKind::Const;
+        let mut declarations = Vec::new();
+        loop {
+            let span = self.previous().span();
+            let name = self
+                .consume(&TokenKind::Identifier, "Expect variable name.")?
+                .lexeme()
+                .to_owned();
+            let expr = if constant {
+                self.consume(&TokenKind::Equal, "Expect '=' after constant name.")?;
+                Some(self.parse_assignment()?)
+            } else if self.match_(&[TokenKind::Equal]) {
+                Some(self.parse_assignment()?)
+            } else {
+                None
+            };
+            declarations.push(statement::VariableDecl::new_wrapped(
+                span, name, expr, constant,
+            ));
+            if !self.match_(&[TokenKind::Comma]) {
+                break;
+            }
+        }
 
         self.consume(&TokenKind::Semicolon, "Expect ';' after value.")?;
 
-        Ok(statement::VariableDecl::new_wrapped(name, expr))
+        Ok(declarations)
     }
 
     fn parse_function_decl(&mut self) -> ParseStmtResult {
-        // TODO: method
+        let span = self.previous().span();
+        self.parse_function(span, false)
+    }
+
+    /// function       → IDENTIFIER "(" parameters? ")" block ;
+    ///
+    /// Shared by function declarations, spanning their `fun` keyword, and methods, spanning their
+    /// `static` keyword or else their name.
+    fn parse_function(&mut self, span: Span, is_static: bool) -> ParseStmtResult {
         let name = self
             .consume(&TokenKind::Identifier, "Expect function name.")?
-            .lexeme
+            .lexeme()
             .to_owned();
         self.consume(&TokenKind::LeftParen, "Expect '(' after function name.")?;
+        let (params, variadic) = self.parse_parameters()?;
+
+        self.consume(&TokenKind::LeftBrace, "Expect '{' before function body.")?;
+
+        let body = Arc::new(RwLock::new(self.parse_block_statement()?));
+        let doc = self.doc_comment(span.line);
+        Ok(statement::Function::new_wrapped(
+            span, name, params, variadic, body, is_static, doc,
+        ))
+    }
+
+    /// parameters     → IDENTIFIER ( "," IDENTIFIER )* ( "," "..." IDENTIFIER )?
+    //                 | "..." IDENTIFIER ;
+    ///
+    /// Parses the parameters after `(`, up to and including `)`, and whether the last one is a
+    /// rest parameter.
+    fn parse_parameters(&mut self) -> Result<(Vec<String>, bool), LoxError> {
         let mut params = Vec::new();
+        let mut variadic = false;
         if !self.check(&TokenKind::RightParen) {
             loop {
                 if params.len() >= 255 {
-                    Self::error(self.peek(), "Can't have more than 255 parameters")?;
+                    Self::error(
+                        self.peek(),
+                        codes::TOO_MANY_PARAMETERS,
+                        "Can't have more than 255 parameters",
+                    )?;
                 }
 
+                variadic = self.match_(&[TokenKind::Ellipsis]);
                 params.push(
                     self.consume(&TokenKind::Identifier, "Expect parameter name.")?
-                        .lexeme
+                        .lexeme()
                         .to_owned(),
                 );
 
-                if !self.match_(&[TokenKind::Comma]) {
+                if variadic || !self.match_(&[TokenKind::Comma]) {
                     break;
                 }
             }
         }
-        self.consume(&TokenKind::RightParen, "Expect ')' after parameters.")?;
-
-        self.consume(&TokenKind::LeftBrace, "Expect '{' before function body.")?;
+        let message = if variadic {
+            "Expect ')' after rest parameter."
+        } else {
+            "Expect ')' after parameters."
+        };
+        self.consume(&TokenKind::RightParen, message)?;
+        Ok((params, variadic))
+    }
 
-        let body = Arc::new(RwLock::new(self.parse_block_statement()?));
-        Ok(statement::Function::new_wrapped(name, params, body))
+    /// classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" ( "static"? function )* "}" ;
+    ///
+    /// `static` is only a keyword right before the name of a method, so it remains a valid name
+    /// elsewhere, including for a method.
+    fn parse_class_decl(&mut self) -> ParseStmtResult {
+        let span = self.previous().span();
+        let name = self
+            .consume(&TokenKind::Identifier, "Expect class name.")?
+            .lexeme()
+            .to_owned();
+        let superclass = if self.match_(&[TokenKind::Less]) {
+            let token = self.consume(&TokenKind::Identifier, "Expect superclass name.")?;
+            let (span, name) = (token.span(), token.lexeme().to_owned());
+            Some(expr::Variable::new_wrapped(span, name, 0, None))
+        } else {
+            None
+        };
+        self.consume(&TokenKind::LeftBrace, "Expect '{' before class body.")?;
+        let mut methods = Vec::new();
+        while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
+            let span = self.peek().span();
+            let is_static = self.check(&TokenKind::Identifier)
+                && self.peek().lexeme() == "static"
+                && self.tokens[self.current + 1].kind == TokenKind::Identifier;
+            if is_static {
+                self.advance();
+            }
+            methods.push(self.parse_function(span, is_static)?);
+        }
+        self.consume(&TokenKind::RightBrace, "Expect '}' after class body.")?;
+        Ok(statement::Class::new_wrapped(
+            span, name, superclass, methods,
+        ))
     }
 
     fn parse_statement(&mut self) -> ParseStmtResult {
@@ -103,62 +288,70 @@ impl Parser {
         }
     }
 
+    /// printStmt      → "print" assignment ( "," assignment )* ";" ;
     fn parse_print_statement(&mut self) -> ParseStmtResult {
-        let value = self.parse_expression()?;
+        let span = self.previous().span();
+        let mut values = vec![self.parse_assignment()?];
+        while self.match_(&[TokenKind::Comma]) {
+            values.push(self.parse_assignment()?);
+        }
         self.consume(&TokenKind::Semicolon, "Expect ';' after value.")?;
-        Ok(statement::Print::new_wrapped(value))
+        Ok(statement::Print::new_wrapped(span, values))
     }
 
     fn parse_expression_statement(&mut self) -> ParseStmtResult {
+        let span = self.peek().span();
         let value = self.parse_expression()?;
         self.consume(&TokenKind::Semicolon, "Expect ';' after value.")?;
-        Ok(statement::Expression::new_wrapped(value))
+        Ok(statement::Expression::new_wrapped(span, value))
     }
 
     fn parse_block_statement(&mut self) -> ParseStmtResult {
+        let span = self.previous().span();
         let mut statements = Vec::new();
         while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
-            statements.push(self.parse_declaration()?);
+            statements.extend(self.parse_declaration_recovering());
         }
         self.consume(&TokenKind::RightBrace, "Expect '}' after block.")?;
-        Ok(statement::Block::new_wrapped(statements))
+        Ok(statement::Block::new_wrapped(span, statements))
     }
 
     fn parse_if_statement(&mut self) -> ParseStmtResult {
+        let span = self.previous().span();
         self.consume(&TokenKind::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.parse_expression()?;
         self.consume(&TokenKind::RightParen, "Expect ')' after if condition.")?;
         let then_branch = self.parse_statement()?;
-        let else_branch = if self.match_(&[TokenKind::Else]) {
-            Some(self.parse_statement()?)
-        } else {
-            None
-        };
-        Ok(statement::If::new_wrapped(
-            condition,
-            then_branch,
-            else_branch,
-        ))
+        let mut builder = statement::If::builder()
+            .span(span)
+            .condition(condition)
+            .then_branch(then_branch);
+        if self.match_(&[TokenKind::Else]) {
+            builder = builder.else_branch(self.parse_statement()?);
+        }
+        Ok(builder.build_wrapped())
     }
 
     fn parse_while_statement(&mut self) -> ParseStmtResult {
+        let span = self.previous().span();
         self.consume(&TokenKind::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.parse_expression()?;
         self.consume(&TokenKind::RightParen, "Expect ')' after condition.")?;
         let body = self.parse_statement()?;
 
-        Ok(statement::While::new_wrapped(condition, body))
+        Ok(statement::While::new_wrapped(span, condition, body))
     }
 
     fn parse_for_statement(&mut self) -> ParseStmtResult {
+        let span = self.previous().span();
         self.consume(&TokenKind::LeftParen, "Expect '(' after 'for'.")?;
 
         let initializer = if self.match_(&[TokenKind::Semicolon]) {
-            None
+            Vec::new()
         } else if self.match_(&[TokenKind::Var]) {
-            Some(self.parse_variable_decl()?)
+            self.parse_variable_decl()?
         } else {
-            Some(self.parse_expression_statement()?)
+            vec![self.parse_expression_statement()?]
         };
 
         let condition = if self.check(&TokenKind::Semicolon) {
@@ -168,6 +361,7 @@ impl Parser {
         };
         self.consume(&TokenKind::Semicolon, "Expect ';' after loop condition.")?;
 
+        let increment_span = self.peek().span();
         let increment = if self.check(&TokenKind::RightParen) {
             None
         } else {
@@ -179,29 +373,36 @@ impl Parser {
 
         // Desugaring
         if let Some(increment) = increment {
-            body = statement::Block::new_wrapped(vec![
-                body,
-                statement::Expression::new_wrapped(increment),
-            ]);
+            body = statement::Block::new_wrapped(
+                span,
+                vec![
+                    body,
+                    statement::Expression::new_wrapped(increment_span, increment),
+                ],
+            );
         }
 
-        let condition = condition.unwrap_or(expr::Literal::new_wrapped(Literal::Boolean(true)));
-        body = statement::While::new_wrapped(condition, body);
+        let condition =
+            condition.unwrap_or(expr::Literal::new_wrapped(span, Literal::Boolean(true)));
+        body = statement::While::new_wrapped(span, condition, body);
 
-        if let Some(initializer) = initializer {
-            body = statement::Block::new_wrapped(vec![initializer, body]);
+        if !initializer.is_empty() {
+            let mut statements = initializer;
+            statements.push(body);
+            body = statement::Block::new_wrapped(span, statements);
         }
 
         Ok(body)
     }
 
     fn parse_return_statement(&mut self) -> ParseStmtResult {
-        let mut expr = None;
+        let span = self.previous().span();
+        let mut builder = statement::Return::builder().span(span);
         if !self.check(&TokenKind::Semicolon) {
-            expr = Some(self.parse_expression()?);
+            builder = builder.value(self.parse_expression()?);
         }
         self.consume(&TokenKind::Semicolon, "Expect ';' after return value.")?;
-        Ok(statement::Return::new_wrapped(expr))
+        Ok(builder.build_wrapped())
     }
 
     /*
@@ -218,15 +419,23 @@ impl Parser {
 
     program        → declaration* EOF ;
 
-    declaration    → funDecl
+    declaration    → classDecl
+                   | funDecl
                    | varDecl
+                   | constDecl
                    | statement ;
 
-    varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+    classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )?
+                     "{" ( "static"? function )* "}" ;
+
+    varDecl        → "var" declarator ( "," declarator )* ";" ;
+    declarator     → IDENTIFIER ( "=" assignment )? ;
+    constDecl      → "const" IDENTIFIER "=" assignment ( "," IDENTIFIER "=" assignment )* ";" ;
 
     funDecl        → "fun" function ;
     function       → IDENTIFIER "(" parameters? ")" block ;
-    parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
+    parameters     → IDENTIFIER ( "," IDENTIFIER )* ( "," "..." IDENTIFIER )?
+                   | "..." IDENTIFIER ;
 
     statement      → exprStmt
                    | forStmt
@@ -244,11 +453,12 @@ impl Parser {
     ifStmt         → "if" "(" expression ")" statement
                    ( "else" statement )? ;
     exprStmt       → expression ";" ;
-    printStmt      → "print" expression ";" ;
+    printStmt      → "print" assignment ( "," assignment )* ";" ;
     block          → "{" declaration* "}" ;
 
-    expression     → assignment ;
-    assignment     → IDENTIFIER "=" assignment
+    expression     → assignment ( "," assignment )* ;
+    assignment     → ( call "." )? IDENTIFIER "=" assignment
+                   | call "[" expression "]" "=" assignment
                    | logic_or ;
     logic_or       → logic_and ( "or" logic_and )* ;
     logic_and      → equality ( "and" equality )* ;
@@ -257,16 +467,32 @@ impl Parser {
     term           → factor ( ( "-" | "+" ) factor )* ;
     factor         → unary ( ( "/" | "*" ) unary )* ;
     unary          → ( "!" | "-" ) unary | call ;
-    call           → primary ( "(" arguments? ")" )* ;
-    arguments      → expression ( "," expression )* ;
-    primary        → NUMBER | STRING | "true" | "false" | "nil"
-                   | "(" expression ")"
-                   | IDENTIFIER ;
+    call           → primary ( "(" arguments? ")" | ( "." | "?." ) IDENTIFIER
+                   | "[" subscript "]" )* ;
+    subscript      → expression | expression? ":" expression? ;
+    arguments      → assignment ( "," assignment )* ;
+    primary        → NUMBER | STRING | "true" | "false" | "nil" | "this"
+                   | "(" expression ")" | "[" ( assignment ( "," assignment )* )? "]"
+                   | IDENTIFIER | "super" "." IDENTIFIER
+                   | "fun" "(" parameters? ")" "->" assignment ;
     */
 
-    /// expression     → equality ;
+    /// expression     → assignment ( "," assignment )* ;
+    ///
+    /// The comma operator evaluates both operands and results in the right one. Where commas
+    /// separate items, i.e. arguments, printed values and initializers of variables, the items
+    /// are assignments, so that a comma expression must be in parentheses there.
     fn parse_expression(&mut self) -> ParseExprResult {
-        self.parse_assignment()
+        let mut expr = self.parse_assignment()?;
+
+        while self.match_(&[TokenKind::Comma]) {
+            let operator = self.previous().kind;
+            let span = self.previous().span();
+            let right = self.parse_assignment()?;
+            expr = expr::Binary::new_wrapped(span, expr, operator, right);
+        }
+
+        Ok(expr)
     }
 
     fn parse_assignment(&mut self) -> ParseExprResult {
@@ -277,14 +503,37 @@ impl Parser {
             // Assign operator is right-associative
             let value = self.parse_assignment()?;
 
-            if let Expr::Variable(var) = expr {
-                return Ok(expr::Assign::new_wrapped(var.name, value, 0));
+            match expr {
+                Expr::Variable(var) => {
+                    return Ok(expr::Assign::new_wrapped(
+                        var.span, var.name, value, 0, None,
+                    ));
+                }
+                // The object of `a?.b = c` may be nil, leaving nothing to assign
+                Expr::Get(get) if !get.optional => {
+                    return Ok(expr::Set::new_wrapped(
+                        get.span, get.object, get.name, value,
+                    ));
+                }
+                Expr::Index(index) => {
+                    return Ok(expr::IndexSet::new_wrapped(
+                        index.span,
+                        index.object,
+                        index.index,
+                        value,
+                    ));
+                }
+                _ => {}
             }
 
-            return Self::error(&equals, "Invalid assignment target.");
+            return Self::error(
+                &equals,
+                codes::INVALID_ASSIGNMENT_TARGET,
+                "Invalid assignment target.",
+            );
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     fn parse_or(&mut self) -> ParseExprResult {
@@ -292,8 +541,9 @@ impl Parser {
 
         while self.match_(&[TokenKind::Or]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_and()?;
-            expr = expr::Logical::new_wrapped(expr, operator, right);
+            expr = expr::Logical::new_wrapped(span, expr, operator, right);
         }
 
         Ok(expr)
@@ -304,8 +554,9 @@ impl Parser {
 
         while self.match_(&[TokenKind::And]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_equality()?;
-            expr = expr::Logical::new_wrapped(expr, operator, right);
+            expr = expr::Logical::new_wrapped(span, expr, operator, right);
         }
 
         Ok(expr)
@@ -317,12 +568,13 @@ impl Parser {
 
         while self.match_(&[TokenKind::BangEqual, TokenKind::EqualEqual]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_comparison()?;
 
-            expr = expr::Binary::new_wrapped(expr, operator, right);
+            expr = expr::Binary::new_wrapped(span, expr, operator, right);
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     /// comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
@@ -336,11 +588,12 @@ impl Parser {
             TokenKind::GreaterEqual,
         ]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_term()?;
-            expr = expr::Binary::new_wrapped(expr, operator, right);
+            expr = expr::Binary::new_wrapped(span, expr, operator, right);
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     /// term           → factor ( ( "-" | "+" ) factor )* ;
@@ -349,11 +602,12 @@ impl Parser {
 
         while self.match_(&[TokenKind::Minus, TokenKind::Plus]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_factor()?;
-            expr = expr::Binary::new_wrapped(expr, operator, right);
+            expr = expr::Binary::new_wrapped(span, expr, operator, right);
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     /// factor         → unary ( ( "/" | "*" ) unary )* ;
@@ -362,39 +616,48 @@ impl Parser {
 
         while self.match_(&[TokenKind::Slash, TokenKind::Star]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_unary()?;
-            expr = expr::Binary::new_wrapped(expr, operator, right);
+            expr = expr::Binary::new_wrapped(span, expr, operator, right);
         }
 
-        return Ok(expr);
+        Ok(expr)
     }
 
     /// unary          → ( "!" | "-" ) unary | call ;
     fn parse_unary(&mut self) -> ParseExprResult {
         if self.match_(&[TokenKind::Bang, TokenKind::Minus]) {
             let operator = self.previous().kind;
+            let span = self.previous().span();
             let right = self.parse_unary()?;
-            Ok(expr::Unary::new_wrapped(operator, right))
+            Ok(expr::Unary::new_wrapped(span, operator, right))
         } else {
             self.parse_call()
         }
     }
 
-    /// call           → primary ( "(" arguments? ")" )* ;
-    /// arguments      → expression ( "," expression )* ;
+    /// call           → primary ( "(" arguments? ")" | ( "." | "?." ) IDENTIFIER
+    ///                | "[" subscript "]" )* ;
+    /// subscript      → expression | expression? ":" expression? ;
+    /// arguments      → assignment ( "," assignment )* ;
     fn parse_call(&mut self) -> ParseExprResult {
         let mut expr = self.parse_primary()?;
 
         loop {
             if self.match_(&[TokenKind::LeftParen]) {
+                let span = self.previous().span();
                 let mut arguments = Vec::new();
                 if !self.check(&TokenKind::RightParen) {
                     loop {
                         if arguments.len() >= 255 {
-                            Self::error(self.peek(), "Can't have more than 255 arguments.")?;
+                            Self::error(
+                                self.peek(),
+                                codes::TOO_MANY_ARGUMENTS,
+                                "Can't have more than 255 arguments.",
+                            )?;
                         }
 
-                        arguments.push(self.parse_expression()?);
+                        arguments.push(self.parse_assignment()?);
                         if !self.match_(&[TokenKind::Comma]) {
                             break;
                         }
@@ -403,7 +666,37 @@ impl Parser {
 
                 self.consume(&TokenKind::RightParen, "Expect ')' after arguments")?;
 
-                expr = expr::Call::new_wrapped(expr, arguments);
+                expr = expr::Call::new_wrapped(span, expr, arguments);
+            } else if self.match_(&[TokenKind::Dot, TokenKind::QuestionDot]) {
+                let optional = self.previous().kind == TokenKind::QuestionDot;
+                let name =
+                    self.consume(&TokenKind::Identifier, "Expect property name after '.'.")?;
+                let span = name.span();
+                let name = name.lexeme().to_owned();
+                expr = expr::Get::new_wrapped(span, expr, name, optional);
+            } else if self.match_(&[TokenKind::LeftBracket]) {
+                let span = self.previous().span();
+                let start = if self.check(&TokenKind::Colon) {
+                    None
+                } else {
+                    Some(self.parse_expression()?)
+                };
+                // Without a start, the colon was checked
+                expr = match (start, self.match_(&[TokenKind::Colon])) {
+                    (Some(index), false) => {
+                        self.consume(&TokenKind::RightBracket, "Expect ']' after index.")?;
+                        expr::Index::new_wrapped(span, expr, index)
+                    }
+                    (start, _) => {
+                        let end = if self.check(&TokenKind::RightBracket) {
+                            None
+                        } else {
+                            Some(self.parse_expression()?)
+                        };
+                        self.consume(&TokenKind::RightBracket, "Expect ']' after slice.")?;
+                        expr::Slice::new_wrapped(span, expr, start, end)
+                    }
+                };
             } else {
                 break;
             }
@@ -412,32 +705,92 @@ impl Parser {
         Ok(expr)
     }
 
-    /// primary        → NUMBER | STRING | "true" | "false" | "nil"
-    //                 | "(" expression ")" ;
+    /// primary        → NUMBER | STRING | "true" | "false" | "nil" | "this"
+    //                 | "(" expression ")" | "[" ( assignment ( "," assignment )* )? "]"
+    //                 | IDENTIFIER | "super" "." IDENTIFIER
+    //                 | "fun" "(" parameters? ")" "->" assignment ;
     fn parse_primary(&mut self) -> ParseExprResult {
+        let span = self.peek().span();
         let expr: Expr = if self.match_(&[TokenKind::Number, TokenKind::String]) {
-            expr::Literal::new_wrapped(self.previous().literal.clone().unwrap())
+            let Some(literal) = self.previous().literal.clone() else {
+                return Self::error(
+                    self.previous(),
+                    codes::EXPECT_LITERAL,
+                    "Expect literal value.",
+                );
+            };
+            expr::Literal::new_wrapped(span, literal)
         } else if self.match_(&[TokenKind::True]) {
-            expr::Literal::new_wrapped(Literal::Boolean(true))
+            expr::Literal::new_wrapped(span, Literal::Boolean(true))
         } else if self.match_(&[TokenKind::False]) {
-            expr::Literal::new_wrapped(Literal::Boolean(false))
+            expr::Literal::new_wrapped(span, Literal::Boolean(false))
         } else if self.match_(&[TokenKind::Nil]) {
-            expr::Literal::new_wrapped(Literal::Nil)
+            expr::Literal::new_wrapped(span, Literal::Nil)
         } else if self.match_(&[TokenKind::LeftParen]) {
             let expr = self.parse_expression()?;
             self.consume(&TokenKind::RightParen, "Expect ')' after expression")?;
-            expr::Grouping::new_wrapped(expr)
+            expr::Grouping::new_wrapped(span, expr)
+        } else if self.match_(&[TokenKind::LeftBracket]) {
+            let mut elements = Vec::new();
+            if !self.check(&TokenKind::RightBracket) {
+                loop {
+                    elements.push(self.parse_assignment()?);
+                    if !self.match_(&[TokenKind::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(&TokenKind::RightBracket, "Expect ']' after list elements.")?;
+            expr::List::new_wrapped(span, elements)
+        } else if self.match_(&[TokenKind::This]) {
+            expr::This::new_wrapped(span, 0)
+        } else if self.match_(&[TokenKind::Super]) {
+            self.consume(&TokenKind::Dot, "Expect '.' after 'super'.")?;
+            let method = self
+                .consume(&TokenKind::Identifier, "Expect superclass method name.")?
+                .lexeme()
+                .to_owned();
+            expr::Super::new_wrapped(span, method, 0)
+        } else if self.match_(&[TokenKind::Fun]) {
+            self.parse_lambda(span)?
         } else if self.match_(&[TokenKind::Identifier]) {
-            expr::Variable::new_wrapped(self.previous().lexeme.to_owned(), 0)
+            expr::Variable::new_wrapped(span, self.previous().lexeme().to_owned(), 0, None)
         } else {
-            return Self::error(self.peek(), "Expect expression.");
+            return Self::error(self.peek(), codes::EXPECT_EXPRESSION, "Expect expression.");
         };
 
         Ok(expr)
     }
 
-    fn error<T>(token: &Token, message: &str) -> anyhow::Result<T> {
-        bail!("Line {}, at '{}', {}", token.line, token.lexeme, message)
+    /// Parses a lambda after `fun`, desugaring its body to a block returning the expression
+    /// after `->`, which spans the arrow.
+    fn parse_lambda(&mut self, span: Span) -> ParseExprResult {
+        self.consume(&TokenKind::LeftParen, "Expect '(' after 'fun'.")?;
+        let (params, variadic) = self.parse_parameters()?;
+        let arrow = self
+            .consume(&TokenKind::Arrow, "Expect '->' after lambda parameters.")?
+            .span();
+        let value = self.parse_assignment()?;
+        let body = statement::Block::new_wrapped(
+            arrow,
+            vec![statement::Return::new_wrapped(arrow, Some(value))],
+        );
+        Ok(expr::Lambda::new_wrapped(
+            span,
+            params,
+            variadic,
+            Arc::new(RwLock::new(body)),
+        ))
+    }
+
+    fn error<T>(token: &Token, code: &'static str, message: &str) -> Result<T, LoxError> {
+        let message = if token.kind == TokenKind::Eof {
+            format!("at end, {}", message)
+        } else {
+            format!("at '{}', {}", token.lexeme(), message)
+        };
+        let diagnostic = Diagnostic::new(token.span(), message).with_code(code);
+        Err(LoxError::Parse(Box::new(diagnostic)))
     }
 
     fn match_(&mut self, kinds: &[TokenKind]) -> bool {
@@ -447,14 +800,14 @@ impl Parser {
                 return true;
             }
         }
-        return false;
+        false
     }
 
     fn check(&self, kind: &TokenKind) -> bool {
         if self.is_at_end() {
             return false;
         }
-        return self.peek().kind == *kind;
+        self.peek().kind == *kind
     }
 
     fn advance(&mut self) -> &Token {
@@ -476,13 +829,40 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
-    fn consume(&mut self, kind: &TokenKind, message: &str) -> anyhow::Result<&Token> {
+    fn consume(&mut self, kind: &TokenKind, message: &str) -> Result<&Token, LoxError> {
         if self.check(kind) {
             return Ok(self.advance());
         }
 
-        Self::error(self.peek(), message)
+        let code = match kind {
+            TokenKind::Semicolon => codes::EXPECT_SEMICOLON,
+            TokenKind::Identifier => codes::EXPECT_NAME,
+            _ => codes::EXPECT_TOKEN,
+        };
+        Self::error(self.peek(), code, message)
     }
 
-    // TODO: synchronize
+    /// Skips the tokens up to the start of what is likely the next statement.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().kind == TokenKind::Semicolon {
+                return;
+            }
+            match self.peek().kind {
+                TokenKind::Class
+                | TokenKind::Fun
+                | TokenKind::Var
+                | TokenKind::Const
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Print
+                | TokenKind::Return => return,
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
 }
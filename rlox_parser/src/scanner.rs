@@ -1,57 +1,133 @@
-use anyhow::bail;
 use rlox_syntax::*;
+use std::sync::Arc;
 
 pub struct Scanner {
+    // Shared by the tokens, which refer to their lexemes by byte range
+    text: Arc<str>,
     source: Vec<char>,
     start: usize,
     current: usize,
+    // Byte offsets of `start` and `current` in `text`
+    start_byte: usize,
+    current_byte: usize,
     line: usize,
+    // Index of the first character of the current line
+    line_start: usize,
+    // Added to the columns of the first line, when the source doesn't start at column 1
+    column_offset: usize,
+    // Position of `start`
+    start_line: usize,
+    start_column: usize,
     tokens: Vec<Token>,
+    comments: Vec<Comment>,
 }
 
 impl Scanner {
     pub fn new(source: &str) -> Self {
         Self {
+            text: source.into(),
             source: source.chars().collect(),
             start: 0,
             current: 0,
+            start_byte: 0,
+            current_byte: 0,
             line: 1,
+            line_start: 0,
+            column_offset: 0,
+            start_line: 1,
+            start_column: 1,
             tokens: Vec::new(),
+            comments: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(mut self) -> anyhow::Result<Vec<Token>> {
+    /// Reports positions relative to `origin` rather than to the start of the source.
+    pub fn with_origin(mut self, origin: &SourceOrigin) -> Self {
+        self.line = origin.line;
+        self.start_line = origin.line;
+        self.column_offset = origin.column.saturating_sub(1);
+        self
+    }
+
+    pub fn scan_tokens(self) -> Result<Vec<Token>, LoxError> {
+        Ok(self.scan_tokens_with_comments()?.0)
+    }
+
+    /// Same as [`Scanner::scan_tokens`], but also returns the comments which are otherwise
+    /// discarded.
+    pub fn scan_tokens_with_comments(mut self) -> Result<(Vec<Token>, Vec<Comment>), LoxError> {
+        if let Some(error) = self.scan().into_iter().next() {
+            return Err(error);
+        }
+        Ok((self.tokens, self.comments))
+    }
+
+    /// Same as [`Scanner::scan_tokens`], but goes on past invalid characters, returning every
+    /// error along with the tokens scanned around them.
+    pub fn scan_tokens_recovering(mut self) -> (Vec<Token>, Vec<LoxError>) {
+        let errors = self.scan();
+        (self.tokens, errors)
+    }
+
+    fn scan(&mut self) -> Vec<LoxError> {
+        let mut errors = Vec::new();
         while !self.is_at_end() {
             self.start = self.current;
-            self.scan_token()?;
+            self.start_byte = self.current_byte;
+            self.start_line = self.line;
+            self.start_column = self.start - self.line_start + 1 + self.column_offset;
+            if let Err(error) = self.scan_token() {
+                errors.push(error);
+            }
         }
 
         self.tokens.push(Token {
             kind: TokenKind::Eof,
-            lexeme: "".to_string(),
+            source: self.text.clone(),
+            range: self.current_byte..self.current_byte,
             literal: None,
             line: self.line,
+            column: self.current - self.line_start + 1 + self.column_offset,
         });
 
-        Ok(self.tokens)
+        errors
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
-    fn scan_token(&mut self) -> anyhow::Result<()> {
+    fn scan_token(&mut self) -> Result<(), LoxError> {
         let c = self.advance();
         match c {
             '(' => self.add_empty_token(TokenKind::LeftParen),
             ')' => self.add_empty_token(TokenKind::RightParen),
             '{' => self.add_empty_token(TokenKind::LeftBrace),
             '}' => self.add_empty_token(TokenKind::RightBrace),
+            '[' => self.add_empty_token(TokenKind::LeftBracket),
+            ']' => self.add_empty_token(TokenKind::RightBracket),
             ',' => self.add_empty_token(TokenKind::Comma),
-            '.' => self.add_empty_token(TokenKind::Dot),
-            '-' => self.add_empty_token(TokenKind::Minus),
+            '.' => {
+                if self.peek() == Some('.') && self.peek_next() == Some('.') {
+                    self.advance();
+                    self.advance();
+                    self.add_empty_token(TokenKind::Ellipsis)
+                } else {
+                    self.add_empty_token(TokenKind::Dot)
+                }
+            }
+            // Without a `.` following it, `?` is an unsupported character
+            '?' if self.match_('.') => self.add_empty_token(TokenKind::QuestionDot),
+            '-' => {
+                if self.match_('>') {
+                    self.add_empty_token(TokenKind::Arrow)
+                } else {
+                    self.add_empty_token(TokenKind::Minus)
+                }
+            }
             '+' => self.add_empty_token(TokenKind::Plus),
             ';' => self.add_empty_token(TokenKind::Semicolon),
+            ':' => self.add_empty_token(TokenKind::Colon),
             '*' => self.add_empty_token(TokenKind::Star),
             '!' => {
                 if self.match_('=') {
@@ -87,6 +163,9 @@ impl Scanner {
                     while self.peek() != Some('\n') && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_comment();
+                } else if self.match_('*') {
+                    self.block_comment()?;
                 } else {
                     self.add_empty_token(TokenKind::Slash);
                 }
@@ -95,60 +174,111 @@ impl Scanner {
                 // skip
             }
             '\n' => {
-                self.line += 1;
+                self.new_line();
             }
             '"' => {
                 self.string()?;
             }
             _ => {
-                if c.is_digit(10) {
-                    self.number();
+                if c.is_ascii_digit() {
+                    self.number()?;
                 } else if c.is_alphabetic() {
                     self.identifier();
                 } else {
-                    bail!("Unsupported character");
+                    return self.error(codes::UNSUPPORTED_CHARACTER, "Unsupported character.");
                 }
             }
         }
         Ok(())
     }
 
-    fn string(&mut self) -> anyhow::Result<()> {
+    fn string(&mut self) -> Result<(), LoxError> {
         while self.peek() != Some('"') && !self.is_at_end() {
-            if self.peek() == Some('\n') {
-                self.line += 1;
+            if self.advance() == '\n' {
+                self.new_line();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            bail!("Unterminated string.");
+            return self.error(codes::UNTERMINATED_STRING, "Unterminated string.");
         }
 
         self.advance(); // Closing "
-        let value = self.source[self.start + 1..self.current - 1]
-            .iter()
-            .collect::<String>();
-        self.add_literal_token(TokenKind::String, Literal::String(value));
+                        // Without the quotes
+        let value = &self.text[self.start_byte + 1..self.current_byte - 1];
+        self.add_literal_token(TokenKind::String, Literal::String(value.into()));
         Ok(())
     }
 
-    fn number(&mut self) {
-        while self.peek().map(|c| c.is_digit(10)).unwrap_or(false) {
-            self.advance();
+    /// Scans a comment from `/*` to the next `*/`, which may span lines. Comments don't nest.
+    fn block_comment(&mut self) -> Result<(), LoxError> {
+        loop {
+            if self.is_at_end() {
+                return self.error(codes::UNTERMINATED_COMMENT, "Unterminated block comment.");
+            }
+            match self.advance() {
+                '*' if self.match_('/') => break,
+                '\n' => self.new_line(),
+                _ => {}
+            }
         }
+        self.add_comment();
+        Ok(())
+    }
+
+    /// Scans a number, whose digits may be separated by `_`, e.g. `1_000`.
+    fn number(&mut self) -> Result<(), LoxError> {
+        self.digits();
 
-        if self.peek() == Some('.') && self.peek_next().map(|c| c.is_digit(10)).unwrap_or(false) {
+        if self.peek() == Some('.') && self.digits_at(self.current + 1) {
             self.advance();
+            self.digits();
+        }
 
-            while self.peek().map(|c| c.is_digit(10)).unwrap_or(false) {
+        // An exponent, e.g. `e9` or `E-3`. Without digits, the `e` starts an identifier
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let sign = matches!(self.peek_next(), Some('+' | '-'));
+            if self.digits_at(self.current + 1 + usize::from(sign)) {
                 self.advance();
+                if sign {
+                    self.advance();
+                }
+                self.digits();
             }
         }
 
-        let value_str: String = self.source[self.start..self.current].iter().collect();
-        let value = value_str.parse::<f64>().unwrap();
+        let lexeme: Vec<char> = self.lexeme().chars().collect();
+        let misplaced = lexeme.iter().enumerate().any(|(i, c)| {
+            *c == '_'
+                && !(i > 0
+                    && lexeme[i - 1].is_ascii_digit()
+                    && lexeme.get(i + 1).is_some_and(char::is_ascii_digit))
+        });
+        // The number is still valid without its separators, so parsing goes on past the error
+        let value = self.lexeme().replace('_', "").parse::<f64>().unwrap();
         self.add_literal_token(TokenKind::Number, Literal::Number(value));
+        if misplaced {
+            return self.error(
+                codes::MISPLACED_SEPARATOR,
+                "Digit separator '_' must be between digits.",
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether a digit is at `index`, possibly after misplaced separators.
+    fn digits_at(&self, index: usize) -> bool {
+        self.source[index.min(self.source.len())..]
+            .iter()
+            .find(|c| **c != '_')
+            .is_some_and(char::is_ascii_digit)
+    }
+
+    /// Scans digits and the separators between them.
+    fn digits(&mut self) {
+        while self.peek().is_some_and(is_digit) {
+            self.advance();
+        }
     }
 
     fn identifier(&mut self) {
@@ -156,14 +286,15 @@ impl Scanner {
             self.advance();
         }
 
-        let lexeme: String = self.source[self.start..self.current].iter().collect();
-        self.add_empty_token(Self::keyword_to_token(&lexeme).unwrap_or(TokenKind::Identifier));
+        let kind = TokenKind::keyword(self.lexeme()).unwrap_or(TokenKind::Identifier);
+        self.add_empty_token(kind);
         // TODO: not empty!
     }
 
     fn advance(&mut self) -> char {
         let result = self.source[self.current];
         self.current += 1;
+        self.current_byte += result.len_utf8();
         result
     }
 
@@ -175,7 +306,8 @@ impl Scanner {
             return false;
         }
         self.current += 1;
-        return true;
+        self.current_byte += expected.len_utf8();
+        true
     }
 
     fn peek(&self) -> Option<char> {
@@ -194,35 +326,54 @@ impl Scanner {
         self._add_token(kind, Some(literal));
     }
 
+    /// The token being scanned.
+    fn lexeme(&self) -> &str {
+        &self.text[self.start_byte..self.current_byte]
+    }
+
     fn _add_token(&mut self, kind: TokenKind, literal: Option<Literal>) {
-        let lexeme = &self.source[self.start..self.current];
         self.tokens.push(Token {
             kind,
-            lexeme: lexeme.iter().collect(),
+            source: self.text.clone(),
+            range: self.start_byte..self.current_byte,
             literal,
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
         })
     }
 
-    fn keyword_to_token(candidate: &str) -> Option<TokenKind> {
-        match candidate {
-            "and" => Some(TokenKind::And),
-            "class" => Some(TokenKind::Class),
-            "else" => Some(TokenKind::Else),
-            "false" => Some(TokenKind::False),
-            "for" => Some(TokenKind::For),
-            "fun" => Some(TokenKind::Fun),
-            "if" => Some(TokenKind::If),
-            "nil" => Some(TokenKind::Nil),
-            "or" => Some(TokenKind::Or),
-            "print" => Some(TokenKind::Print),
-            "return" => Some(TokenKind::Return),
-            "super" => Some(TokenKind::Super),
-            "this" => Some(TokenKind::This),
-            "true" => Some(TokenKind::True),
-            "var" => Some(TokenKind::Var),
-            "while" => Some(TokenKind::While),
-            _ => None,
-        }
+    fn add_comment(&mut self) {
+        let text = self.lexeme().to_owned();
+        let trailing = self
+            .tokens
+            .last()
+            .is_some_and(|t| t.line == self.start_line);
+        self.comments.push(Comment {
+            span: Span::new(self.start_line, self.start_column, text.chars().count()),
+            text,
+            trailing,
+        });
     }
+
+    /// Reports an error for the token being scanned.
+    fn error<T>(&self, code: &'static str, message: &str) -> Result<T, LoxError> {
+        let span = Span::new(
+            self.start_line,
+            self.start_column,
+            self.current - self.start,
+        );
+        let diagnostic = Diagnostic::new(span, message).with_code(code);
+        Err(LoxError::Scan(Box::new(diagnostic)))
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+        self.column_offset = 0;
+    }
+}
+
+/// Whether `c` is part of the digits of a number.
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit() || c == '_'
 }
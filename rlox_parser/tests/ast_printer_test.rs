@@ -0,0 +1,238 @@
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::AstPrinter;
+
+fn dump(source: &str) -> String {
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let statements = Parser::new(tokens).parse().unwrap();
+    AstPrinter::default().print_program(&statements)
+}
+
+#[test]
+fn test_expression() {
+    assert_eq!(
+        "(print (+ 1 (* (group (- a 2)) \"s\")))\n",
+        dump(r#"print 1 + (a - 2) * "s";"#)
+    );
+    assert_eq!(
+        "(print a (call f 1) \"s\")\n",
+        dump(r#"print a, f(1), "s";"#)
+    );
+}
+
+#[test]
+fn test_comma() {
+    assert_eq!("(expr (, (, a (= b 1)) c))\n", dump("a, b = 1, c;"));
+    // Commas between items separate them unless in parentheses
+    assert_eq!(
+        "(print (call f (group (, a b)) c) d)\n",
+        dump("print f((a, b), c), d;")
+    );
+    assert_eq!("(var x (group (, a b)))\n", dump("var x = (a, b);"));
+
+    // In a `var`, the comma declares another variable
+    let tokens = Scanner::new("var x = a, 1;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Expect variable name."));
+}
+
+#[test]
+fn test_const() {
+    assert_eq!(
+        "(const a 1)\n(const b (+ a 1))\n",
+        dump("const a = 1, b = a + 1;")
+    );
+
+    let tokens = Scanner::new("const a;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Expect '=' after constant name."));
+}
+
+#[test]
+fn test_multiple_declarators() {
+    assert_eq!(
+        "(var a 1)\n(var b (+ a 1))\n(var c)\n",
+        dump("var a = 1, b = a + 1, c;")
+    );
+    assert_eq!(
+        "(block\n  (var i 0)\n  (var j 9)\n  (while (< i j)\n    (expr (= i (+ i 1)))))\n",
+        dump("for (var i = 0, j = 9; i < j;) i = i + 1;")
+    );
+}
+
+#[test]
+fn test_lambda() {
+    assert_eq!(
+        "(var double (lambda (x) (* x 2)))\n",
+        dump("var double = fun (x) -> x * 2;")
+    );
+    // The body takes in what follows, up to a comma
+    assert_eq!(
+        "(expr (call f (lambda () (= a b)) c))\n",
+        dump("f(fun () -> a = b, c);")
+    );
+    assert_eq!(
+        "(expr (call (group (lambda (a b) a)) 1 2))\n",
+        dump("(fun (a, b) -> a)(1, 2);")
+    );
+
+    let tokens = Scanner::new("var f = fun (x) { return x; };")
+        .scan_tokens()
+        .unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Expect '->' after lambda parameters."));
+}
+
+#[test]
+fn test_rest_parameters() {
+    assert_eq!(
+        "(fun log (level ...args)\n  (block))\n(expr (lambda (...all) all))\n",
+        dump("fun log(level, ...args) {} fun (...all) -> all;")
+    );
+
+    let tokens = Scanner::new("fun f(...a, b) {}").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Expect ')' after rest parameter."));
+}
+
+#[test]
+fn test_lists() {
+    assert_eq!(
+        "(var a (list 1 (list) (+ 2 3)))\n(print (index (index a 1) (- i 1)))\n",
+        dump("var a = [1, [], 2 + 3]; print a[1][i - 1];")
+    );
+    assert_eq!(
+        "(expr (call (index (get o fs) 0) (list x)))\n",
+        dump("o.fs[0]([x]);")
+    );
+
+    // Assignment is right-associative, and the target may be any index expression
+    assert_eq!(
+        "(expr (index-set (index a 0) (+ i 1) (index-set (get o xs) 0 nil)))\n",
+        dump("a[0][i + 1] = o.xs[0] = nil;")
+    );
+
+    assert_eq!(
+        "(print (slice a 1 (- n 1)) (slice a _ 2) (slice (slice s 1 _) _ _))\n",
+        dump("print a[1:n - 1], a[:2], s[1:][:];")
+    );
+
+    let tokens = Scanner::new("a[0] + 1 = 2;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Invalid assignment target."));
+    let tokens = Scanner::new("a[1:] = 2;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Invalid assignment target."));
+    let tokens = Scanner::new("print a[1:2:3];").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Expect ']' after slice."));
+    let tokens = Scanner::new("print [1, 2;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Expect ']' after list elements."));
+}
+
+#[test]
+fn test_nested_statements() {
+    let source = r"
+fun f(a, b) {
+    if (a and !b) return a; else b = nil;
+}
+";
+    let expected = r"(fun f (a b)
+  (block
+    (if (and a (! b))
+      (return a)
+      (expr (= b nil)))))
+";
+    assert_eq!(expected, dump(source));
+}
+
+#[test]
+fn test_class() {
+    assert_eq!(
+        "(class Foo)\n(var foo (call Foo))\n",
+        dump("class Foo {} var foo = Foo();")
+    );
+
+    let tokens = Scanner::new("class {}").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Expect class name."));
+}
+
+#[test]
+fn test_methods() {
+    let source = r"
+class Point {
+    move(dx) { this.x = this.x + dx; }
+    origin() {}
+}
+";
+    let expected = r"(class Point
+  (fun move (dx)
+    (block
+      (expr (set this x (+ (get this x) dx)))))
+  (fun origin ()
+    (block)))
+";
+    assert_eq!(expected, dump(source));
+
+    assert_eq!(
+        "(class B < A\n  (fun f ()\n    (block\n      (return (call (super f))))))\n",
+        dump("class B < A { f() { return super.f(); } }")
+    );
+    // `static` before a method name only
+    assert_eq!(
+        "(class Math
+  (static fun zero ()
+    (block))
+  (fun static ()
+    (block)))
+",
+        dump("class Math { static zero() {} static() {} }")
+    );
+
+    let tokens = Scanner::new("class Point { fun move() {} }")
+        .scan_tokens()
+        .unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Expect function name."));
+    let tokens = Scanner::new("class B < A { f() { super; } }")
+        .scan_tokens()
+        .unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Expect '.' after 'super'."));
+}
+
+#[test]
+fn test_properties() {
+    assert_eq!(
+        "(expr (set (get (call a) b) c (get d e)))\n",
+        dump("a().b.c = d.e;")
+    );
+
+    let tokens = Scanner::new("a.b() = 1;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Invalid assignment target."));
+}
+
+#[test]
+fn test_optional_chaining() {
+    assert_eq!(
+        "(expr (call (get? (get (get? a b) c) d) 1))
+",
+        dump("a?.b.c?.d(1);")
+    );
+
+    let tokens = Scanner::new("a?.b = 1;").scan_tokens().unwrap();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(error.to_string().contains("Invalid assignment target."));
+    let error = Scanner::new("a ? b;").scan_tokens().unwrap_err();
+    assert!(error.to_string().contains("Unsupported character."));
+}
@@ -0,0 +1,71 @@
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{index_nodes, Node, NodeMap, Span, Statement};
+
+fn parse(source: &str) -> Vec<Statement> {
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    Parser::new(tokens).parse().unwrap()
+}
+
+#[test]
+fn test_index_nodes() {
+    let statements = parse("print 1;\nfun f() { return a + 2; }");
+    let nodes = index_nodes(&statements);
+    // print, 1, fun, its block, return, +, a, 2
+    assert_eq!(8, nodes.len());
+
+    let Statement::Print(print) = &statements[0] else {
+        panic!("Expected a print statement");
+    };
+    assert_eq!(None, nodes.get(print.id).unwrap().parent);
+    let literal = nodes.get(print.exprs[0].id()).unwrap();
+    assert_eq!(Some(print.id), literal.parent);
+    assert_eq!(Span::new(1, 7, 1), literal.span);
+
+    let Statement::Function(function) = &statements[1] else {
+        panic!("Expected a function");
+    };
+    let body = function.body.read().unwrap();
+    let Statement::Block(block) = &*body else {
+        panic!("Expected a block");
+    };
+    let Statement::Return(ret) = &block.statements[0] else {
+        panic!("Expected a return statement");
+    };
+    let ancestors: Vec<_> = nodes.ancestors(ret.value.as_ref().unwrap().id()).collect();
+    assert_eq!(vec![ret.id, block.id, function.id], ancestors);
+}
+
+#[test]
+fn test_node_map() {
+    let mut map = NodeMap::new();
+    assert!(map.is_empty());
+    assert_eq!(None, map.insert(1, "a"));
+    assert_eq!(Some("a"), map.insert(1, "b"));
+    *map.get_mut(1).unwrap() = "c";
+    assert_eq!(Some(&"c"), map.get(1));
+    assert!(!map.contains(2));
+    assert_eq!(Some("c"), map.remove(1));
+    assert!(map.is_empty());
+}
+
+fn depth(node: Node) -> usize {
+    let mut depth = 0;
+    node.for_each_child(|child| depth = depth.max(self::depth(child)));
+    depth + 1
+}
+
+#[test]
+fn test_children() {
+    let statements = parse("if (a) print f(1, 2); else {}\nfun g() { return; }");
+    let kinds: Vec<_> = Node::Statement(&statements[0])
+        .children()
+        .map(|child| child.kind())
+        .collect();
+    assert_eq!(vec!["Variable", "Print", "Block"], kinds);
+    // if, print, call, 1
+    assert_eq!(4, depth(Node::Statement(&statements[0])));
+
+    // The body is only reached by for_each_child
+    assert_eq!(0, Node::Statement(&statements[1]).children().count());
+    assert_eq!(3, depth(Node::Statement(&statements[1])));
+}
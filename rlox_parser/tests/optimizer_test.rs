@@ -0,0 +1,29 @@
+use rlox_parser::{optimize, Parser, Scanner};
+use rlox_syntax::AstPrinter;
+
+fn optimized(source: &str) -> String {
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let mut statements = Parser::new(tokens).parse().unwrap();
+    optimize(&mut statements);
+    AstPrinter::new(false).print_program(&statements)
+}
+
+#[test]
+fn test_optimize() {
+    let printed = optimized(
+        r#"
+if (false) print 1; else print 2;
+if ((true)) print 3;
+while (false) print 4;
+print false or x;
+print true and (false or y);
+print x and true;
+"#,
+    );
+    let expected = optimized("print 2; print 3; print x; print (y); print x and true;");
+    assert_eq!(expected, printed);
+    assert_eq!(
+        optimized("while (true) print 1;"),
+        optimized("while ((true)) print 1;")
+    );
+}
@@ -0,0 +1,341 @@
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{
+    diff_programs, expr, query, statement, AstChange, ChangeKind, Expr, ExprVisitor, LoxError,
+    Node, Selector, Span, Statement, StatementVisitor, Token, TokenKind, Visitor,
+};
+use std::sync::PoisonError;
+
+#[test]
+fn test_tokens_without_eof() {
+    assert!(Parser::new(vec![]).parse().unwrap().is_empty());
+
+    let mut tokens = Scanner::new("print 1").scan_tokens().unwrap();
+    tokens.pop();
+    let error = Parser::new(tokens).parse().unwrap_err();
+    assert!(matches!(error, LoxError::Parse(_)));
+    assert_eq!((1, 8), (error.span().line, error.span().column));
+
+    let number = Token::new(TokenKind::Number, "1", None, 1, 1);
+    let error = Parser::new(vec![number]).parse().unwrap_err();
+    assert!(error.to_string().contains("Expect literal value."));
+}
+
+#[test]
+fn test_parse_recovering() {
+    let source = "print 1 +;\nvar a = 1;\n{ var = 2; print a; }\nprint 3";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let (statements, errors) = Parser::new(tokens).parse_recovering();
+    let positions: Vec<_> = errors
+        .iter()
+        .map(|e| (e.span().line, e.span().column))
+        .collect();
+    assert_eq!(vec![(1, 10), (3, 7), (4, 8)], positions);
+    // The declaration of `a`, and the block with the print
+    assert_eq!(2, statements.len());
+}
+
+#[test]
+fn test_doc_comments() {
+    let source = r#"
+/// Adds two numbers.
+///
+///   Indented.
+fun add(a, b) { return a + b; }
+
+/// Detached.
+
+// Not documentation.
+fun sub(a, b) { return a - b; } /// Trailing.
+fun noop() {}
+"#;
+    let (tokens, comments) = Scanner::new(source).scan_tokens_with_comments().unwrap();
+    let statements = Parser::new(tokens)
+        .with_comments(&comments)
+        .parse()
+        .unwrap();
+    let docs: Vec<_> = statements
+        .iter()
+        .map(|s| match s {
+            Statement::Function(f) => f.doc.clone(),
+            _ => panic!("expected a function"),
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            Some("Adds two numbers.\n\n  Indented.".to_owned()),
+            None,
+            None
+        ],
+        docs
+    );
+}
+
+#[test]
+fn test_diff_programs() {
+    let parse = |source: &str| {
+        Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap()
+    };
+    let old = parse(
+        r#"
+var a = 1;
+fun f(x) {
+    print x;
+    return x;
+}
+print a;
+"#,
+    );
+    let new = parse(
+        r#"
+// Moved and reformatted, but the same
+var a = 1; fun f(x) {
+    print x;
+    return x + 1;
+}
+print "added";
+"#,
+    );
+    let change = |kind,
+                  node,
+                  old: Option<(usize, usize, usize)>,
+                  new: Option<(usize, usize, usize)>| AstChange {
+        kind,
+        node,
+        old: old.map(|(line, column, len)| Span::new(line, column, len)),
+        new: new.map(|(line, column, len)| Span::new(line, column, len)),
+    };
+    assert_eq!(
+        vec![
+            change(
+                ChangeKind::Changed,
+                "return",
+                Some((5, 5, 6)),
+                Some((5, 5, 6))
+            ),
+            change(
+                ChangeKind::Changed,
+                "print",
+                Some((7, 1, 5)),
+                Some((7, 1, 5))
+            ),
+        ],
+        diff_programs(&old, &new)
+    );
+    assert!(diff_programs(&old, &old).is_empty());
+
+    let new = parse("fun f(x, y) { print x; }\nfun g() {}\n");
+    assert_eq!(
+        vec![
+            change(ChangeKind::Removed, "var", Some((2, 1, 3)), None),
+            change(ChangeKind::Changed, "fun", Some((3, 1, 3)), Some((1, 1, 3))),
+            change(ChangeKind::Removed, "return", Some((5, 5, 6)), None),
+            change(ChangeKind::Removed, "print", Some((7, 1, 5)), None),
+            change(ChangeKind::Added, "fun", None, Some((2, 1, 3))),
+        ],
+        diff_programs(&old, &new)
+    );
+}
+
+#[test]
+fn test_query() {
+    let source = r#"
+fun f(x, y) {
+    if (x) { return x; }
+    return x + y;
+}
+var x = f(1, 2) - 3;
+print -x;
+"#;
+    let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+        .parse()
+        .unwrap();
+    let spans = |selector: &str| -> Vec<(&'static str, usize, usize)> {
+        query(&statements, selector)
+            .unwrap()
+            .iter()
+            .map(|m| (m.kind, m.span.line, m.span.column))
+            .collect()
+    };
+    // The first return is in a block of the if, so not a child of the function.
+    assert_eq!(
+        vec![("Variable", 4, 12)],
+        spans("Function > Return Variable[name=x]")
+    );
+    assert_eq!(
+        vec![("Variable", 3, 9), ("Variable", 3, 21), ("Variable", 4, 12)],
+        spans("Function Variable[name=x]")
+    );
+    assert_eq!(
+        vec![("Call", 6, 10)],
+        spans("VariableDecl[name=x] > Binary[operator=-] > Call[arity=2]")
+    );
+    assert_eq!(vec![("Literal", 6, 14)], spans("Call > Literal[value=2]"));
+    assert_eq!(vec![("Unary", 7, 7)], spans("Print > *"));
+    assert_eq!(2, spans("Function[arity=2] Return").len());
+    assert_eq!(vec![("Function", 2, 1)], spans("Function[static=false]"));
+    assert!(spans("While").is_empty());
+
+    let selector = Selector::parse("Return").unwrap();
+    let mut kinds = Vec::new();
+    selector.for_each(&statements, |node| {
+        if let Node::Statement(Statement::Return(r)) = node {
+            kinds.push(r.value.is_some());
+        }
+    });
+    assert_eq!(vec![true, true], kinds);
+
+    assert_eq!(
+        Err("Unknown node kind 'Switch'.".to_owned()),
+        Selector::parse("Switch").map(|_| ())
+    );
+    assert!(Selector::parse("Class[name=Point]").is_ok());
+    assert!(Selector::parse("Function >").is_err());
+    assert!(Selector::parse("> Return").is_err());
+    assert!(Selector::parse("Variable[name").is_err());
+    assert!(Selector::parse("Variable[name]").is_err());
+}
+
+/// Names of the variables a program reads, in evaluation order.
+#[derive(Default)]
+struct ReadVariables(Vec<String>);
+
+impl ReadVariables {
+    fn all<V: Visitor>(visitor: &mut V, statements: &[Statement]) {
+        for s in statements {
+            s.accept(visitor);
+        }
+    }
+}
+
+impl StatementVisitor for ReadVariables {
+    type Output = ();
+
+    fn visit_expression(&mut self, node: &statement::Expression) {
+        node.expr.accept(self)
+    }
+    fn visit_print(&mut self, node: &statement::Print) {
+        node.exprs.iter().for_each(|e| e.accept(self))
+    }
+    fn visit_variable_decl(&mut self, node: &statement::VariableDecl) {
+        node.expr.iter().for_each(|e| e.accept(self))
+    }
+    fn visit_block(&mut self, node: &statement::Block) {
+        Self::all(self, &node.statements)
+    }
+    fn visit_if(&mut self, node: &statement::If) {
+        node.condition.accept(self);
+        node.then_branch.accept(self);
+        node.else_branch.iter().for_each(|s| s.accept(self))
+    }
+    fn visit_while(&mut self, node: &statement::While) {
+        node.condition.accept(self);
+        node.body.accept(self)
+    }
+    fn visit_function(&mut self, node: &statement::Function) {
+        let body = node.body.read().unwrap_or_else(PoisonError::into_inner);
+        body.accept(self)
+    }
+    fn visit_return(&mut self, node: &statement::Return) {
+        node.value.iter().for_each(|e| e.accept(self))
+    }
+    fn visit_class(&mut self, node: &statement::Class) {
+        node.superclass.iter().for_each(|e| e.accept(self));
+        Self::all(self, &node.methods)
+    }
+}
+
+impl ExprVisitor for ReadVariables {
+    type Output = ();
+
+    fn visit_binary(&mut self, node: &expr::Binary) {
+        node.left.accept(self);
+        node.right.accept(self)
+    }
+    fn visit_grouping(&mut self, node: &expr::Grouping) {
+        node.expr.accept(self)
+    }
+    fn visit_literal(&mut self, _node: &expr::Literal) {}
+    fn visit_unary(&mut self, node: &expr::Unary) {
+        node.right.accept(self)
+    }
+    fn visit_variable(&mut self, node: &expr::Variable) {
+        self.0.push(node.name.clone())
+    }
+    fn visit_assign(&mut self, node: &expr::Assign) {
+        node.value.accept(self)
+    }
+    fn visit_logical(&mut self, node: &expr::Logical) {
+        node.left.accept(self);
+        node.right.accept(self)
+    }
+    fn visit_call(&mut self, node: &expr::Call) {
+        node.callee.accept(self);
+        node.arguments.iter().for_each(|e| e.accept(self))
+    }
+    fn visit_get(&mut self, node: &expr::Get) {
+        node.object.accept(self)
+    }
+    fn visit_set(&mut self, node: &expr::Set) {
+        node.object.accept(self);
+        node.value.accept(self)
+    }
+    fn visit_this(&mut self, _node: &expr::This) {}
+    fn visit_super(&mut self, _node: &expr::Super) {}
+    fn visit_list(&mut self, node: &expr::List) {
+        node.elements.iter().for_each(|e| e.accept(self))
+    }
+    fn visit_index(&mut self, node: &expr::Index) {
+        node.object.accept(self);
+        node.index.accept(self)
+    }
+    fn visit_slice(&mut self, node: &expr::Slice) {
+        node.object.accept(self);
+        node.start
+            .iter()
+            .chain(&node.end)
+            .for_each(|e| e.accept(self))
+    }
+    fn visit_index_set(&mut self, node: &expr::IndexSet) {
+        node.object.accept(self);
+        node.index.accept(self);
+        node.value.accept(self)
+    }
+    fn visit_lambda(&mut self, node: &expr::Lambda) {
+        let body = node.body.read().unwrap_or_else(PoisonError::into_inner);
+        body.accept(self)
+    }
+}
+
+#[test]
+fn test_visitor() {
+    let source = r#"
+var a = 1;
+fun f(x) {
+    if (x > a) return g(x);
+    while (!x) x = a or b;
+}
+print -(f(a));
+"#;
+    let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+        .parse()
+        .unwrap();
+    let mut visitor = ReadVariables::default();
+    ReadVariables::all(&mut visitor, &statements);
+    assert_eq!(vec!["x", "a", "g", "x", "x", "a", "b", "f", "a"], visitor.0);
+
+    // Nodes accept visitors directly too.
+    let Statement::Print(print) = &statements[2] else {
+        panic!("expected print");
+    };
+    let mut visitor = ReadVariables::default();
+    print.accept(&mut visitor);
+    assert_eq!(vec!["f", "a"], visitor.0);
+    let Expr::Unary(unary) = &print.exprs[0] else {
+        panic!("expected unary");
+    };
+    let mut visitor = ReadVariables::default();
+    unary.accept(&mut visitor);
+    assert_eq!(vec!["f", "a"], visitor.0);
+}
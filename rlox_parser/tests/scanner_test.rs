@@ -0,0 +1,180 @@
+use rlox_parser::Scanner;
+use rlox_syntax::{codes, Literal, SourceOrigin, TokenKind};
+use std::sync::Arc;
+
+#[test]
+fn test_token_positions() {
+    let source = "var a = \"x\ny\";\n  print a;";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let positions: Vec<_> = tokens.iter().map(|t| (t.kind, t.line, t.column)).collect();
+    assert_eq!(
+        vec![
+            (TokenKind::Var, 1, 1),
+            (TokenKind::Identifier, 1, 5),
+            (TokenKind::Equal, 1, 7),
+            (TokenKind::String, 1, 9),
+            (TokenKind::Semicolon, 2, 3),
+            (TokenKind::Print, 3, 3),
+            (TokenKind::Identifier, 3, 9),
+            (TokenKind::Semicolon, 3, 10),
+            (TokenKind::Eof, 3, 11),
+        ],
+        positions
+    );
+}
+
+#[test]
+fn test_token_positions_with_origin() {
+    let origin = SourceOrigin::new("doc.md").at(10, 5);
+    let tokens = Scanner::new("print 1;\nx;")
+        .with_origin(&origin)
+        .scan_tokens()
+        .unwrap();
+    let positions: Vec<_> = tokens.iter().map(|t| (t.line, t.column)).collect();
+    assert_eq!(
+        vec![(10, 5), (10, 11), (10, 12), (11, 1), (11, 2), (11, 3)],
+        positions
+    );
+}
+
+#[test]
+fn test_lexemes() {
+    let source = "var ñandú = \"héllo\" + 1.5;";
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let lexemes: Vec<_> = tokens.iter().map(|t| t.lexeme()).collect();
+    assert_eq!(
+        vec!["var", "ñandú", "=", "\"héllo\"", "+", "1.5", ";", ""],
+        lexemes
+    );
+    assert_eq!(
+        Some(&Literal::String("héllo".into())),
+        tokens[3].literal.as_ref()
+    );
+    // The lexemes refer to a single copy of the source.
+    assert!(tokens
+        .iter()
+        .all(|t| Arc::ptr_eq(&t.source, &tokens[0].source)));
+}
+
+#[test]
+fn test_numbers() {
+    let tokens = Scanner::new("1e9 2.5e-3 4E+2 1.5e 2e-")
+        .scan_tokens()
+        .unwrap();
+    let numbers: Vec<_> = tokens.iter().map(|t| t.literal.clone()).collect();
+    let lexemes: Vec<_> = tokens.iter().map(|t| t.lexeme()).collect();
+    assert_eq!(
+        vec!["1e9", "2.5e-3", "4E+2", "1.5", "e", "2", "e", "-", ""],
+        lexemes
+    );
+    assert_eq!(
+        vec![
+            Some(Literal::Number(1e9)),
+            Some(Literal::Number(2.5e-3)),
+            Some(Literal::Number(400.0)),
+            Some(Literal::Number(1.5)),
+        ],
+        numbers[..4]
+    );
+}
+
+#[test]
+fn test_digit_separators() {
+    let tokens = Scanner::new("1_000_000 0.000_1 1_0e1_0")
+        .scan_tokens()
+        .unwrap();
+    let numbers: Vec<_> = tokens.iter().filter_map(|t| t.literal.clone()).collect();
+    assert_eq!(
+        vec![
+            Literal::Number(1e6),
+            Literal::Number(1e-4),
+            Literal::Number(1e11)
+        ],
+        numbers
+    );
+
+    for source in ["1_", "1__0", "1_.5", "1._5", "1_e5", "1e_5", "1e-_5"] {
+        let error = Scanner::new(source).scan_tokens().unwrap_err();
+        assert_eq!(
+            "Digit separator '_' must be between digits.",
+            error.diagnostic().message,
+            "{source}"
+        );
+        assert_eq!(Some(codes::MISPLACED_SEPARATOR), error.diagnostic().code);
+        assert_eq!(source.len(), error.span().len, "{source}");
+    }
+
+    // The number is still scanned, so that it's the only error reported
+    let (tokens, errors) = Scanner::new("print 1__0 + 1_.5;").scan_tokens_recovering();
+    assert_eq!(2, errors.len());
+    let numbers: Vec<_> = tokens.iter().filter_map(|t| t.literal.clone()).collect();
+    assert_eq!(vec![Literal::Number(10.0), Literal::Number(1.5)], numbers);
+    let (_, errors) = Scanner::new("1e_;").scan_tokens_recovering();
+    assert_eq!(
+        Some(codes::UNSUPPORTED_CHARACTER),
+        errors[0].diagnostic().code
+    );
+}
+
+#[test]
+fn test_token_lexemes() {
+    for kind in TokenKind::ALL {
+        let Some(lexeme) = kind.lexeme() else {
+            continue;
+        };
+        let tokens = Scanner::new(lexeme).scan_tokens().unwrap();
+        assert_eq!(*kind, tokens[0].kind, "{lexeme}");
+        assert_eq!(kind.is_keyword(), TokenKind::keyword(lexeme).is_some());
+    }
+    assert_eq!(None, TokenKind::keyword("then"));
+}
+
+#[test]
+fn test_scan_tokens_recovering() {
+    let (tokens, errors) = Scanner::new("print @1 # 2;").scan_tokens_recovering();
+    let columns: Vec<_> = errors.iter().map(|e| e.span().column).collect();
+    assert_eq!(vec![7, 10], columns);
+    let kinds: Vec<_> = tokens.iter().map(|t| t.kind).collect();
+    assert_eq!(
+        vec![
+            TokenKind::Print,
+            TokenKind::Number,
+            TokenKind::Number,
+            TokenKind::Semicolon,
+            TokenKind::Eof
+        ],
+        kinds
+    );
+}
+
+#[test]
+fn test_block_comments() {
+    let source = "var a /* one */ = 1;\n/* two\nlines */ print a / /**/ 2;";
+    let (tokens, comments) = Scanner::new(source).scan_tokens_with_comments().unwrap();
+    let positions: Vec<_> = tokens.iter().map(|t| (t.kind, t.line, t.column)).collect();
+    assert_eq!(
+        vec![
+            (TokenKind::Var, 1, 1),
+            (TokenKind::Identifier, 1, 5),
+            (TokenKind::Equal, 1, 17),
+            (TokenKind::Number, 1, 19),
+            (TokenKind::Semicolon, 1, 20),
+            (TokenKind::Print, 3, 10),
+            (TokenKind::Identifier, 3, 16),
+            (TokenKind::Slash, 3, 18),
+            (TokenKind::Number, 3, 25),
+            (TokenKind::Semicolon, 3, 26),
+            (TokenKind::Eof, 3, 27),
+        ],
+        positions
+    );
+    let texts: Vec<_> = comments.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(vec!["/* one */", "/* two\nlines */", "/**/"], texts);
+
+    let error = Scanner::new("print 1; /* open\n*")
+        .scan_tokens()
+        .unwrap_err();
+    assert_eq!("Unterminated block comment.", error.diagnostic().message);
+    assert_eq!(Some(codes::UNTERMINATED_COMMENT), error.diagnostic().code);
+    assert_eq!((1, 10), (error.span().line, error.span().column));
+}
@@ -0,0 +1,53 @@
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{AstPrinter, Statement};
+
+fn parse(source: &str) -> Vec<Statement> {
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    Parser::new(tokens).parse().unwrap()
+}
+
+fn unparse(source: &str) -> String {
+    let statements: Vec<_> = parse(source).iter().map(Statement::to_string).collect();
+    statements.join("\n")
+}
+
+#[test]
+fn test_expression() {
+    let statements = parse(r#"print 1+(a -2)* "s";"#);
+    let Statement::Print(print) = &statements[0] else {
+        panic!("Expected a print statement");
+    };
+    assert_eq!(r#"1 + (a - 2) * "s""#, print.exprs[0].to_string());
+}
+
+#[test]
+fn test_statements() {
+    let source = r"
+var a;
+fun f(a, b) {
+    if (a and !b) return a; else { b = g(a, -1.5); }
+    while (true) {}
+    return;
+}
+";
+    let expected = r"var a;
+fun f(a, b) {
+    if (a and !b) return a; else {
+        b = g(a, -1.5);
+    }
+    while (true) {}
+    return;
+}";
+    assert_eq!(expected, unparse(source));
+}
+
+#[test]
+fn test_round_trip() {
+    let source = r#"
+for (var i = 0; i < 10; i = i + 1) print i == nil or "a" != "b";
+print "i:", i, -i;
+"#;
+    let printer = AstPrinter::new(false);
+    let expected = printer.print_program(&parse(source));
+    assert_eq!(expected, printer.print_program(&parse(&unparse(source))));
+}
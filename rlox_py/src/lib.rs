@@ -0,0 +1,113 @@
+//! Python bindings:
+//!
+//! ```python
+//! import rlox
+//!
+//! print(rlox.run("print 1 + 2;"))  # Number(3.0)
+//!
+//! lox = rlox.Lox()
+//! lox.register("shout", 1, lambda s: s.upper())
+//! lox.run('var greeting = shout("hi");')
+//! assert lox.get_global("greeting") == "HI"
+//! ```
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use rlox_interpreter::{Lox, LoxError, Value, WritePrinter};
+
+fn to_py_err(error: LoxError) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+/// Numbers, strings, booleans and nil map to their Python counterparts. Other values, such as
+/// functions, are given as their debug representation.
+fn to_python(py: Python<'_>, value: &Value) -> PyObject {
+    match value {
+        Value::Number(n) => n.into_py(py),
+        Value::String(s) => s.as_ref().into_py(py),
+        Value::Boolean(b) => b.into_py(py),
+        Value::Nil => py.None(),
+        other => format!("{other:?}").into_py(py),
+    }
+}
+
+fn from_python(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if object.is_none() {
+        Ok(Value::Nil)
+    } else if let Ok(b) = object.extract::<bool>() {
+        // Before numbers, as Python booleans are integers.
+        Ok(Value::Boolean(b))
+    } else if let Ok(n) = object.extract::<f64>() {
+        Ok(Value::Number(n))
+    } else if let Ok(s) = object.extract::<String>() {
+        Ok(Value::from(s))
+    } else {
+        let type_name = object.get_type().name()?.to_string();
+        Err(PyRuntimeError::new_err(format!(
+            "Cannot convert {type_name} to a Lox value."
+        )))
+    }
+}
+
+/// An interpreter keeping its globals between calls, printing to stdout.
+#[pyclass(name = "Lox")]
+struct PyLox {
+    lox: Lox<'static>,
+}
+
+#[pymethods]
+impl PyLox {
+    #[new]
+    fn new() -> Self {
+        Self { lox: Lox::new() }
+    }
+
+    fn run(&mut self, source: &str) -> PyResult<()> {
+        self.lox.run(source).map_err(to_py_err)
+    }
+
+    fn eval(&mut self, py: Python<'_>, source: &str) -> PyResult<PyObject> {
+        let value = self.lox.eval(source).map_err(to_py_err)?;
+        Ok(to_python(py, &value))
+    }
+
+    fn get_global(&self, py: Python<'_>, name: &str) -> Option<PyObject> {
+        self.lox.get_global(name).map(|value| to_python(py, &value))
+    }
+
+    fn set_global(&mut self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.lox.set_global(name, from_python(value)?);
+        Ok(())
+    }
+
+    /// Defines a global function calling the Python callable `func` with `arity` arguments.
+    fn register(&mut self, name: &str, arity: usize, func: PyObject) {
+        self.lox
+            .register_function(name, arity, move |_interpreter, args| {
+                Python::with_gil(|py| {
+                    let args = PyTuple::new_bound(py, args.iter().map(|a| to_python(py, a)));
+                    let result = func.call1(py, args)?;
+                    from_python(result.bind(py))
+                })
+                .map_err(|e| anyhow::anyhow!("{e}"))
+            });
+    }
+}
+
+/// Runs `source` on a fresh interpreter, returning what it printed.
+#[pyfunction]
+fn run(source: &str) -> PyResult<String> {
+    let mut printer = WritePrinter::new(Vec::new());
+    Lox::with_printer(&mut printer)
+        .run(source)
+        .map_err(to_py_err)?;
+    Ok(String::from_utf8_lossy(printer.get_ref()).into_owned())
+}
+
+#[pymodule]
+fn rlox(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyLox>()?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    Ok(())
+}
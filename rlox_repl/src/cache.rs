@@ -0,0 +1,76 @@
+//! Disk cache of scripts compiled for the VM backend, so that running an unchanged script skips
+//! scanning, parsing, resolving and compiling it.
+//!
+//! Entries are named after the path of the script and keyed by its source and the options which
+//! affect compilation; an entry with another key is simply overwritten.
+
+use rlox_compiler::{fingerprint, FunctionProto};
+use std::path::{Path, PathBuf};
+
+pub struct ProgramCache {
+    dir: PathBuf,
+}
+
+impl ProgramCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Cache under `$XDG_CACHE_HOME/rlox` or `~/.cache/rlox`, if either directory is known.
+    pub fn from_env() -> Option<Self> {
+        let base = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(Self::new(base.join("rlox")))
+    }
+
+    /// Key of `source` compiled with or without the standard library, which changes the global
+    /// variables the resolver accepts.
+    pub fn key(source: &str, stdlib: bool) -> u64 {
+        let identity = format!("{}\0{stdlib}\0{source}", env!("CARGO_PKG_VERSION"));
+        fingerprint(identity.as_bytes())
+    }
+
+    pub fn load(&self, script: &Path, key: u64) -> Option<FunctionProto> {
+        let bytes = std::fs::read(self.entry(script)).ok()?;
+        FunctionProto::decode(&bytes, key)
+    }
+
+    pub fn store(&self, script: &Path, key: u64, function: &FunctionProto) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.entry(script), function.encode(key))
+    }
+
+    fn entry(&self, script: &Path) -> PathBuf {
+        let script = script.canonicalize().unwrap_or_else(|_| script.to_owned());
+        let name = fingerprint(script.as_os_str().as_encoded_bytes());
+        self.dir.join(format!("{name:016x}.rloxc"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_compiler::Compiler;
+    use rlox_parser::{Parser, Scanner};
+
+    #[test]
+    fn test_store_and_load() {
+        let dir = std::env::temp_dir().join(format!("rlox-cache-test-{}", std::process::id()));
+        let cache = ProgramCache::new(&dir);
+        let source = "print 1 + 2;";
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let script = Compiler::compile(&Parser::new(tokens).parse().unwrap()).unwrap();
+        let path = Path::new("script.lox");
+
+        let key = ProgramCache::key(source, true);
+        assert_eq!(None, cache.load(path, key));
+        cache.store(path, key, &script).unwrap();
+        assert_eq!(Some(script), cache.load(path, key));
+        assert_eq!(None, cache.load(path, ProgramCache::key("print 3;", true)));
+        assert_eq!(None, cache.load(path, ProgramCache::key(source, false)));
+        assert_eq!(None, cache.load(Path::new("other.lox"), key));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,749 @@
+//! Command line parsing.
+//!
+//! ```text
+//! rlox [options] <command> [args...]
+//! rlox [options] <script> [args...]   # same as `rlox run`
+//! rlox [options]                      # same as `rlox repl`
+//! ```
+
+use crate::project::{Manifest, MANIFEST_FILE_NAME};
+use rlox_fmt::{BraceStyle, FormatConfig};
+use std::io::IsTerminal;
+
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub about: &'static str,
+    /// Flags accepted in addition to the global ones.
+    pub flags: &'static [(&'static str, &'static str)],
+}
+
+const RUN_FLAGS: &[(&str, &str)] = &[
+    (
+        "--watch",
+        "Run the script again whenever it changes on disk",
+    ),
+    (
+        "--no-stdlib",
+        "Start without native functions such as `clock`",
+    ),
+    ("--trace", "Log every executed statement with its line"),
+    (
+        "--backend=<tree|vm>",
+        "Run on the tree-walking interpreter (default) or the bytecode VM",
+    ),
+    (
+        "--no-cache",
+        "Compile for the VM without reading or writing the cache in ~/.cache/rlox",
+    ),
+];
+
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "run",
+        usage: "rlox run [flags] <script> [args...]",
+        about: "Run a script, passing the remaining arguments to it",
+        flags: RUN_FLAGS,
+    },
+    CommandInfo {
+        name: "repl",
+        usage: "rlox repl [flags]",
+        about: "Start an interactive session",
+        flags: &[
+            (
+                "--no-stdlib",
+                "Start without native functions such as `clock`",
+            ),
+            ("--trace", "Log every executed statement with its line"),
+        ],
+    },
+    CommandInfo {
+        name: "debug",
+        usage: "rlox debug [flags] <script>",
+        about: "Run a script under an interactive debugger",
+        flags: &[(
+            "--no-stdlib",
+            "Start without native functions such as `clock`",
+        )],
+    },
+    CommandInfo {
+        name: "check",
+        usage: "rlox check [flags] <scripts...>",
+        about: "Scan, parse and resolve scripts without running them",
+        flags: &[("--no-stdlib", "Check against an empty global environment")],
+    },
+    CommandInfo {
+        name: "lint",
+        usage: "rlox lint [flags] <scripts...>",
+        about: "Check scripts for likely mistakes, with the rules configured by .rloxlint",
+        flags: &[
+            ("--no-stdlib", "Check against an empty global environment"),
+            (
+                "--config=<path>",
+                "Read the rules from this file instead of ./.rloxlint",
+            ),
+        ],
+    },
+    CommandInfo {
+        name: "doc",
+        usage: "rlox doc [flags] <scripts...>",
+        about: "Print the signatures and `///` comments of the top level functions",
+        flags: &[(
+            "--format=<markdown|json>",
+            "Output format (default: markdown)",
+        )],
+    },
+    CommandInfo {
+        name: "build",
+        usage: "rlox build [flags] <script>",
+        about: "Transpile a script, writing the output next to it",
+        flags: &[(
+            "--target=<js>",
+            "Language to transpile to (default: js, written to <script>.js)",
+        )],
+    },
+    CommandInfo {
+        name: "graph",
+        usage: "rlox graph <script>",
+        about: "Print the call graph of a script in Graphviz DOT format",
+        flags: &[],
+    },
+    CommandInfo {
+        name: "highlight",
+        usage: "rlox highlight [flags] <script>",
+        about: "Print a script as syntax-highlighted HTML",
+        flags: &[(
+            "--standalone",
+            "Print a whole page with a default stylesheet rather than a <pre> element",
+        )],
+    },
+    CommandInfo {
+        name: "ast",
+        usage: "rlox ast [flags] <script>",
+        about: "Print the syntax tree of a script as S-expressions",
+        flags: &[(
+            "--resolved",
+            "Resolve variables and show their scope distances",
+        )],
+    },
+    CommandInfo {
+        name: "fmt",
+        usage: "rlox fmt [flags] <files...>",
+        about: "Format scripts in place",
+        flags: &[
+            (
+                "--check",
+                "Only report files which would change, failing if there is any",
+            ),
+            ("--indent=<n>", "Spaces per indentation level (default: 4)"),
+            (
+                "--max-width=<n>",
+                "Put call arguments and parameters one per line in longer statements (default: 100)",
+            ),
+            (
+                "--brace-style=<same-line|next-line>",
+                "Where the opening brace of a block goes (default: same-line)",
+            ),
+            (
+                "--minify",
+                "Print the files on one line without comments instead of formatting them",
+            ),
+            (
+                "--rename-locals",
+                "With --minify, shorten the names of variables and functions which aren't global",
+            ),
+        ],
+    },
+    CommandInfo {
+        name: "new",
+        usage: "rlox new <path>",
+        about: "Create a project with a lox.toml manifest, a main script and tests",
+        flags: &[],
+    },
+    CommandInfo {
+        name: "test",
+        usage: "rlox test [flags] [paths...]",
+        about: "Run scripts under the paths and check their `// expect:` comments",
+        flags: &[(
+            "--mutate",
+            "Run the scripts again with small changes, reporting the ones the tests miss",
+        )],
+    },
+    CommandInfo {
+        name: "tokens",
+        usage: "rlox tokens <script>",
+        about: "Print the tokens of a script with their positions",
+        flags: &[],
+    },
+];
+
+const GLOBAL_FLAGS: &[(&str, &str)] = &[
+    (
+        "--color=<auto|always|never>",
+        "Color diagnostics (default: auto)",
+    ),
+    (
+        "--diagnostics=<human|json>",
+        "Print diagnostics as text or as a JSON object per line (default: human)",
+    ),
+    ("-h, --help", "Print help"),
+];
+
+pub enum Command {
+    Run {
+        script: String,
+        args: Vec<String>,
+    },
+    Repl,
+    Debug {
+        script: String,
+    },
+    Check {
+        scripts: Vec<String>,
+    },
+    Lint {
+        scripts: Vec<String>,
+    },
+    Doc {
+        scripts: Vec<String>,
+    },
+    Ast {
+        script: String,
+        resolved: bool,
+    },
+    Tokens {
+        script: String,
+    },
+    Highlight {
+        script: String,
+        standalone: bool,
+    },
+    Graph {
+        script: String,
+    },
+    Build {
+        script: String,
+    },
+    Fmt {
+        files: Vec<String>,
+        check: bool,
+    },
+    /// Print the minified files, a mode of `rlox fmt`.
+    Minify {
+        files: Vec<String>,
+        rename_locals: bool,
+    },
+    New {
+        path: String,
+    },
+    Test {
+        paths: Vec<String>,
+        /// Run mutation tests instead.
+        mutate: bool,
+    },
+    /// Print help, for a single command if given.
+    Help(Option<&'static CommandInfo>),
+}
+
+#[derive(Default)]
+pub enum ColorChoice {
+    /// Color if stderr is a terminal and NO_COLOR isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How diagnostics are printed.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticFormat {
+    /// Rendered with the source, for people.
+    #[default]
+    Human,
+    /// A JSON object per line, for tools.
+    Json,
+}
+
+/// What runs scripts.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Tree,
+    /// Compile to bytecode and run it on `rlox_vm`.
+    Vm,
+}
+
+/// Output of `rlox doc`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+/// Output language of `rlox build`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildTarget {
+    #[default]
+    Js,
+}
+
+impl BuildTarget {
+    /// Extension of the files written for this target.
+    pub fn extension(self) -> &'static str {
+        match self {
+            BuildTarget::Js => "js",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Options {
+    /// Run the script again whenever it changes on disk.
+    pub watch: bool,
+    /// Start with an empty global environment, without native functions.
+    pub no_stdlib: bool,
+    /// Don't use the disk cache of compiled scripts.
+    pub no_cache: bool,
+    /// Log every executed statement with its line.
+    pub trace: bool,
+    /// Annotate dumped syntax trees with resolution distances.
+    pub resolved: bool,
+    /// Print highlighted HTML as a whole page.
+    pub standalone: bool,
+    /// Don't modify files but report whether they would change.
+    pub check: bool,
+    pub minify: bool,
+    pub rename_locals: bool,
+    pub mutate: bool,
+    pub color: ColorChoice,
+    pub diagnostics: DiagnosticFormat,
+    pub backend: Backend,
+    /// Style of `rlox fmt`.
+    pub format: FormatConfig,
+    /// Configuration file of `rlox lint`.
+    pub lint_config: Option<String>,
+    pub doc_format: DocFormat,
+    pub build_target: BuildTarget,
+    /// The lox.toml of the current directory.
+    pub manifest: Option<Manifest>,
+}
+
+impl Options {
+    /// Whether diagnostics should be colored.
+    pub fn color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                !no_color && std::io::stderr().is_terminal()
+            }
+        }
+    }
+}
+
+pub struct Cli {
+    pub command: Command,
+    pub options: Options,
+}
+
+impl Cli {
+    /// Parses `args`, the settings of `manifest` being the defaults of the flags.
+    pub fn parse(
+        mut args: impl Iterator<Item = String>,
+        manifest: Option<Manifest>,
+    ) -> Result<Self, String> {
+        let mut options = Options::default();
+        if let Some(manifest) = &manifest {
+            options.format = manifest.format.clone();
+        }
+        options.manifest = manifest;
+        let mut info: Option<&'static CommandInfo> = None;
+        let mut positional = Vec::new();
+
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Ok(Cli {
+                    command: Command::Help(info),
+                    options,
+                });
+            }
+            if let Some(choice) = arg.strip_prefix("--color=") {
+                options.color = match choice {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => return Err(format!("Invalid color choice: {choice}")),
+                };
+                continue;
+            }
+            if let Some(format) = arg.strip_prefix("--diagnostics=") {
+                options.diagnostics = match format {
+                    "human" => DiagnosticFormat::Human,
+                    "json" => DiagnosticFormat::Json,
+                    _ => return Err(format!("Invalid diagnostics format: {format}")),
+                };
+                continue;
+            }
+            if let Some((name, value)) = arg.split_once('=').filter(|_| arg.starts_with("--")) {
+                let accepted = info.map_or(RUN_FLAGS, |info| info.flags);
+                let prefix = format!("{name}=");
+                if !accepted.iter().any(|(flag, _)| flag.starts_with(&prefix)) {
+                    return Err(format!("Unknown flag: {arg}"));
+                }
+                match name {
+                    "--backend" => {
+                        options.backend = match value {
+                            "tree" => Backend::Tree,
+                            "vm" => Backend::Vm,
+                            _ => return Err(format!("Invalid backend: {value}")),
+                        }
+                    }
+                    "--format" => {
+                        options.doc_format = match value {
+                            "markdown" => DocFormat::Markdown,
+                            "json" => DocFormat::Json,
+                            _ => return Err(format!("Invalid format: {value}")),
+                        }
+                    }
+                    "--target" => {
+                        options.build_target = match value {
+                            "js" => BuildTarget::Js,
+                            _ => return Err(format!("Invalid target: {value}")),
+                        }
+                    }
+                    "--config" => options.lint_config = Some(value.to_owned()),
+                    "--indent" => options.format.indent_width = parse_number(name, value)?,
+                    "--max-width" => options.format.max_line_length = parse_number(name, value)?,
+                    "--brace-style" => {
+                        options.format.brace_style = BraceStyle::from_name(value)
+                            .ok_or_else(|| format!("Invalid brace style: {value}"))?
+                    }
+                    _ => unreachable!("flag {name} is accepted but not handled"),
+                }
+                continue;
+            }
+            if arg.starts_with('-') {
+                let accepted = info.map_or(RUN_FLAGS, |info| info.flags);
+                if !accepted.iter().any(|(flag, _)| *flag == arg) {
+                    return Err(format!("Unknown flag: {arg}"));
+                }
+                match arg.as_str() {
+                    "--watch" => options.watch = true,
+                    "--no-stdlib" => options.no_stdlib = true,
+                    "--no-cache" => options.no_cache = true,
+                    "--trace" => options.trace = true,
+                    "--resolved" => options.resolved = true,
+                    "--standalone" => options.standalone = true,
+                    "--check" => options.check = true,
+                    "--minify" => options.minify = true,
+                    "--rename-locals" => options.rename_locals = true,
+                    "--mutate" => options.mutate = true,
+                    _ => unreachable!("flag {arg} is accepted but not handled"),
+                }
+                continue;
+            }
+            if info.is_none() && positional.is_empty() {
+                if arg == "help" {
+                    let topic = args.next();
+                    let info = match topic {
+                        Some(name) => Some(find_command(&name)?),
+                        None => None,
+                    };
+                    return Ok(Cli {
+                        command: Command::Help(info),
+                        options,
+                    });
+                }
+                if let Ok(found) = find_command(&arg) {
+                    info = Some(found);
+                    continue;
+                }
+            }
+            positional.push(arg);
+            // Everything after the script of `run` belongs to the script.
+            if info.is_none_or(|info| info.name == "run") {
+                positional.extend(args.by_ref());
+            }
+        }
+
+        let command = match info.map(|info| info.name) {
+            None if positional.is_empty() => Command::Repl,
+            None | Some("run") => {
+                let mut positional = positional.into_iter();
+                let script = positional.next().ok_or("A script must be given")?;
+                Command::Run {
+                    script,
+                    args: positional.collect(),
+                }
+            }
+            Some("fmt") => {
+                if positional.is_empty() {
+                    return Err("At least one file must be given".to_owned());
+                }
+                if options.minify {
+                    if options.check {
+                        return Err("--check can't be used with --minify".to_owned());
+                    }
+                    Command::Minify {
+                        files: positional,
+                        rename_locals: options.rename_locals,
+                    }
+                } else if options.rename_locals {
+                    return Err("--rename-locals needs --minify".to_owned());
+                } else {
+                    Command::Fmt {
+                        files: positional,
+                        check: options.check,
+                    }
+                }
+            }
+            Some("check") => {
+                if positional.is_empty() {
+                    return Err("A script must be given".to_owned());
+                }
+                Command::Check {
+                    scripts: positional,
+                }
+            }
+            Some("lint") => {
+                if positional.is_empty() {
+                    return Err("A script must be given".to_owned());
+                }
+                Command::Lint {
+                    scripts: positional,
+                }
+            }
+            Some("doc") => {
+                if positional.is_empty() {
+                    return Err("A script must be given".to_owned());
+                }
+                Command::Doc {
+                    scripts: positional,
+                }
+            }
+            Some("test") => {
+                if positional.is_empty() {
+                    match &options.manifest {
+                        Some(manifest) => positional = manifest.test_paths.clone(),
+                        None => {
+                            return Err(format!(
+                                "At least one path must be given outside of a project with {MANIFEST_FILE_NAME}"
+                            ))
+                        }
+                    }
+                }
+                Command::Test {
+                    paths: positional,
+                    mutate: options.mutate,
+                }
+            }
+            Some("repl") => {
+                expect_arguments(&positional, 0)?;
+                Command::Repl
+            }
+            Some(name) => {
+                expect_arguments(&positional, 1)?;
+                let script = positional.remove(0);
+                match name {
+                    "debug" => Command::Debug { script },
+                    "ast" => Command::Ast {
+                        script,
+                        resolved: options.resolved,
+                    },
+                    "tokens" => Command::Tokens { script },
+                    "highlight" => Command::Highlight {
+                        script,
+                        standalone: options.standalone,
+                    },
+                    "graph" => Command::Graph { script },
+                    "build" => Command::Build { script },
+                    "new" => Command::New { path: script },
+                    _ => unreachable!("command {name} is not handled"),
+                }
+            }
+        };
+        Ok(Cli { command, options })
+    }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number for {flag}: {value}"))
+}
+
+fn find_command(name: &str) -> Result<&'static CommandInfo, String> {
+    COMMANDS
+        .iter()
+        .find(|info| info.name == name)
+        .ok_or_else(|| format!("Unknown command: {name}"))
+}
+
+fn expect_arguments(positional: &[String], count: usize) -> Result<(), String> {
+    match positional.len().cmp(&count) {
+        std::cmp::Ordering::Less => Err("A script must be given".to_owned()),
+        std::cmp::Ordering::Greater => Err(format!("Unexpected argument: {}", positional[count])),
+        std::cmp::Ordering::Equal => Ok(()),
+    }
+}
+
+pub fn help(info: Option<&CommandInfo>) -> String {
+    let mut out = String::new();
+    match info {
+        Some(info) => {
+            out.push_str(&format!("{}\n\nUsage: {}\n", info.about, info.usage));
+            if !info.flags.is_empty() {
+                out.push_str("\nFlags:\n");
+                out.push_str(&flag_lines(info.flags));
+            }
+        }
+        None => {
+            out.push_str("A Lox interpreter\n\n");
+            out.push_str("Usage: rlox [flags] <command> [args...]\n");
+            out.push_str("       rlox [flags] <script> [args...]\n");
+            out.push_str("       rlox [flags]\n\nCommands:\n");
+            for info in COMMANDS {
+                out.push_str(&format!("  {:<30} {}\n", info.name, info.about));
+            }
+            out.push_str(&format!("  {:<30} {}\n", "help", "Print help of a command"));
+        }
+    }
+    out.push_str("\nGlobal flags:\n");
+    out.push_str(&flag_lines(GLOBAL_FLAGS));
+    out
+}
+
+fn flag_lines(flags: &[(&str, &str)]) -> String {
+    flags
+        .iter()
+        .map(|(flag, about)| format!("  {flag:<30} {about}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        Cli::parse(args.split_whitespace().map(str::to_owned), None)
+    }
+
+    #[test]
+    fn test_script_shorthand() {
+        let cli = parse("--trace script.lox foo --bar").unwrap();
+        let Command::Run { script, args } = cli.command else {
+            panic!("expected run command");
+        };
+        assert_eq!("script.lox", script);
+        assert_eq!(vec!["foo", "--bar"], args);
+        assert!(cli.options.trace);
+        assert_eq!(Backend::Tree, cli.options.backend);
+        let cli = parse("run --backend=vm script.lox").unwrap();
+        assert_eq!(Backend::Vm, cli.options.backend);
+    }
+
+    #[test]
+    fn test_subcommand() {
+        let cli = parse("ast --resolved script.lox").unwrap();
+        assert!(matches!(cli.command, Command::Ast { resolved: true, .. }));
+        assert!(matches!(
+            parse("graph script.lox").unwrap().command,
+            Command::Graph { .. }
+        ));
+        let cli = parse("highlight --standalone script.lox").unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Highlight {
+                standalone: true,
+                ..
+            }
+        ));
+        let cli = parse("build --target=js script.lox").unwrap();
+        assert!(matches!(cli.command, Command::Build { .. }));
+        assert_eq!(BuildTarget::Js, cli.options.build_target);
+        assert!(parse("build --target=wasm script.lox").is_err());
+        let cli = parse("fmt --minify --rename-locals a.lox").unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Minify {
+                rename_locals: true,
+                ..
+            }
+        ));
+        assert!(parse("fmt --rename-locals a.lox").is_err());
+        assert!(matches!(
+            parse("test --mutate tests").unwrap().command,
+            Command::Test { mutate: true, .. }
+        ));
+        assert!(matches!(parse("").unwrap().command, Command::Repl));
+        assert!(matches!(
+            parse("debug --no-stdlib script.lox").unwrap().command,
+            Command::Debug { .. }
+        ));
+        assert!(matches!(
+            parse("help check").unwrap().command,
+            Command::Help(Some(CommandInfo { name: "check", .. }))
+        ));
+        let Command::Check { scripts } = parse("check a.lox b.lox").unwrap().command else {
+            panic!("expected check command");
+        };
+        assert_eq!(vec!["a.lox", "b.lox"], scripts);
+        let cli = parse("lint --config=lint.conf a.lox").unwrap();
+        assert!(matches!(cli.command, Command::Lint { .. }));
+        assert_eq!(Some("lint.conf"), cli.options.lint_config.as_deref());
+        let cli = parse("doc --format=json a.lox").unwrap();
+        assert!(matches!(cli.command, Command::Doc { .. }));
+        assert_eq!(DocFormat::Json, cli.options.doc_format);
+        let cli = parse("check --diagnostics=json a.lox").unwrap();
+        assert_eq!(DiagnosticFormat::Json, cli.options.diagnostics);
+        let cli = parse("fmt --indent=2 --max-width=80 --brace-style=next-line a.lox").unwrap();
+        assert_eq!(
+            FormatConfig {
+                indent_width: 2,
+                max_line_length: 80,
+                brace_style: BraceStyle::NextLine,
+            },
+            cli.options.format
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse("[fmt]\nindent = 2\n[test]\npaths = [\"t\"]").unwrap();
+        let parse = |args: &str| {
+            Cli::parse(
+                args.split_whitespace().map(str::to_owned),
+                Some(manifest.clone()),
+            )
+            .unwrap()
+        };
+        assert_eq!(2, parse("fmt a.lox").options.format.indent_width);
+        assert_eq!(3, parse("fmt --indent=3 a.lox").options.format.indent_width);
+        let Command::Test { paths, .. } = parse("test").command else {
+            panic!("expected test command");
+        };
+        assert_eq!(vec!["t"], paths);
+        let Command::Test { paths, .. } = parse("test other").command else {
+            panic!("expected test command");
+        };
+        assert_eq!(vec!["other"], paths);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse("test").is_err());
+        assert!(parse("check --watch script.lox").is_err());
+        assert!(parse("tokens").is_err());
+        assert!(parse("check").is_err());
+        assert!(parse("tokens a.lox b.lox").is_err());
+        assert!(parse("--color=sometimes").is_err());
+        assert!(parse("--diagnostics=xml script.lox").is_err());
+        assert!(parse("--backend=jit script.lox").is_err());
+        assert!(parse("check --backend=vm script.lox").is_err());
+        assert!(parse("run --indent=2 script.lox").is_err());
+        assert!(parse("fmt --indent=two a.lox").is_err());
+        assert!(parse("fmt --brace-style=k&r a.lox").is_err());
+    }
+}
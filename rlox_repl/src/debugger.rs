@@ -0,0 +1,241 @@
+//! Interactive debugger behind `rlox debug`.
+
+use rlox_interpreter::{CallFrame, Debugger, EnvironmentPtr, Interpreter};
+use rlox_parser::{Parser, Scanner};
+use rlox_syntax::{Diagnostic, Expr, LoxError, Statement};
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+break <line>    Pause before statements on the line (alias: b)
+delete <line>   Remove a breakpoint
+step            Run until the next statement (alias: s)
+next            Run until the next statement, stepping over calls (alias: n)
+continue        Run until a breakpoint (alias: c)
+print <expr>    Evaluate an expression in the current scope (alias: p)
+backtrace       Show the active function calls (alias: bt)
+quit            Abort the program (alias: q)";
+
+enum Mode {
+    Continue,
+    Step,
+    // Pause again once the call stack is at most this deep
+    Next(usize),
+}
+
+pub struct CliDebugger {
+    lines: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+}
+
+impl CliDebugger {
+    pub fn new(source: &str) -> Self {
+        Self {
+            lines: source.lines().map(str::to_owned).collect(),
+            breakpoints: BTreeSet::new(),
+            // Pause before the first statement so that breakpoints can be set.
+            mode: Mode::Step,
+        }
+    }
+
+    fn should_pause(&self, interpreter: &Interpreter, line: usize) -> bool {
+        match self.mode {
+            _ if self.breakpoints.contains(&line) => true,
+            Mode::Continue => false,
+            Mode::Step => true,
+            Mode::Next(depth) => interpreter.call_stack().len() <= depth,
+        }
+    }
+
+    fn show_line(&self, line: usize) {
+        let text = self.lines.get(line - 1).map_or("", |s| s.as_str());
+        println!("{line:>4} | {text}");
+    }
+}
+
+impl Debugger for CliDebugger {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        environment: &EnvironmentPtr,
+        stmt: &Statement,
+    ) -> Result<(), LoxError> {
+        // Blocks only group statements, which are paused at on their own.
+        if let Statement::Block(_) = stmt {
+            return Ok(());
+        }
+        let line = stmt.span().line;
+        if !self.should_pause(interpreter, line) {
+            return Ok(());
+        }
+        self.show_line(line);
+
+        let stdin = std::io::stdin();
+        loop {
+            print!("(ldb) ");
+            let _ = std::io::stdout().flush();
+            let mut input = String::new();
+            // Reading errors are treated like the end of input.
+            if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+                self.mode = Mode::Continue;
+                return Ok(());
+            }
+            let (command, argument) = input
+                .trim()
+                .split_once(' ')
+                .map_or((input.trim(), ""), |(c, a)| (c, a.trim()));
+            match command {
+                "break" | "b" | "delete" => match argument.parse::<usize>() {
+                    Ok(line) if command == "delete" => {
+                        self.breakpoints.remove(&line);
+                    }
+                    Ok(line) => {
+                        self.breakpoints.insert(line);
+                    }
+                    Err(_) => println!("Expected a line number"),
+                },
+                "step" | "s" => {
+                    self.mode = Mode::Step;
+                    return Ok(());
+                }
+                "next" | "n" => {
+                    self.mode = Mode::Next(interpreter.call_stack().len());
+                    return Ok(());
+                }
+                "continue" | "c" => {
+                    self.mode = Mode::Continue;
+                    return Ok(());
+                }
+                "print" | "p" => match evaluate(interpreter, environment, argument) {
+                    Ok(value) => println!("{value}"),
+                    Err(e) => println!("{e}"),
+                },
+                "backtrace" | "bt" => {
+                    println!("  at line {line}");
+                    for CallFrame { name, line } in interpreter.call_stack().iter().rev() {
+                        println!("  in {name}, called at line {line}");
+                    }
+                }
+                "quit" | "q" => {
+                    let diagnostic = Diagnostic::new(stmt.span(), "Aborted by the debugger.");
+                    return Err(LoxError::Runtime(Box::new(diagnostic)));
+                }
+                "help" | "h" => println!("{HELP}"),
+                "" => {}
+                _ => println!("Unknown command '{command}', type 'help' for the list"),
+            }
+        }
+    }
+}
+
+fn evaluate(
+    interpreter: &mut Interpreter,
+    environment: &EnvironmentPtr,
+    source: &str,
+) -> anyhow::Result<String> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let mut expr = Parser::new(tokens).parse_single_expression()?;
+    resolve(environment, &mut expr)?;
+    Ok(format!(
+        "{:?}",
+        interpreter.evaluate_expr(environment, &expr)?
+    ))
+}
+
+/// Resolves variables by looking them up in the environments at hand, as the scopes of the
+/// resolver are long gone.
+fn resolve(environment: &EnvironmentPtr, expr: &mut Expr) -> anyhow::Result<()> {
+    let depth_of = |name: &str| {
+        environment
+            .lock()
+            .unwrap()
+            .depth_of(name)
+            .ok_or_else(|| anyhow::anyhow!("Undefined variable '{name}'."))
+    };
+    match expr {
+        Expr::Binary(e) => {
+            resolve(environment, &mut e.left)?;
+            resolve(environment, &mut e.right)?;
+        }
+        Expr::Logical(e) => {
+            resolve(environment, &mut e.left)?;
+            resolve(environment, &mut e.right)?;
+        }
+        Expr::Grouping(e) => resolve(environment, &mut e.expr)?,
+        Expr::Unary(e) => resolve(environment, &mut e.right)?,
+        Expr::Literal(_) => {}
+        Expr::Variable(e) => e.resolution = depth_of(&e.name)?,
+        Expr::This(e) => e.resolution = depth_of("this")?,
+        Expr::Super(e) => e.resolution = depth_of("super")?,
+        Expr::Assign(e) => {
+            e.resolution = depth_of(&e.name)?;
+            resolve(environment, &mut e.value)?;
+        }
+        Expr::Call(e) => {
+            resolve(environment, &mut e.callee)?;
+            for argument in &mut e.arguments {
+                resolve(environment, argument)?;
+            }
+        }
+        Expr::Get(e) => resolve(environment, &mut e.object)?,
+        Expr::Set(e) => {
+            resolve(environment, &mut e.object)?;
+            resolve(environment, &mut e.value)?;
+        }
+        Expr::List(e) => {
+            for element in &mut e.elements {
+                resolve(environment, element)?;
+            }
+        }
+        Expr::Index(e) => {
+            resolve(environment, &mut e.object)?;
+            resolve(environment, &mut e.index)?;
+        }
+        Expr::Slice(e) => {
+            resolve(environment, &mut e.object)?;
+            for bound in e.start.iter_mut().chain(&mut e.end) {
+                resolve(environment, bound)?;
+            }
+        }
+        Expr::IndexSet(e) => {
+            resolve(environment, &mut e.object)?;
+            resolve(environment, &mut e.index)?;
+            resolve(environment, &mut e.value)?;
+        }
+        // Its parameters aren't in any environment at hand
+        Expr::Lambda(_) => anyhow::bail!("Can't evaluate a lambda in the debugger."),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_interpreter::{Environment, StdOutPrinter, Value};
+
+    #[test]
+    fn test_evaluate() {
+        let mut printer = StdOutPrinter;
+        let mut interpreter = Interpreter::new(&mut printer);
+        let globals = Environment::new_globals_ptr();
+        let local = Environment::new_ptr(globals.clone());
+        globals
+            .lock()
+            .unwrap()
+            .define_variable("a", Value::Number(1.0))
+            .unwrap();
+        local
+            .lock()
+            .unwrap()
+            .define_variable("b", Value::Number(2.0))
+            .unwrap();
+
+        assert_eq!(
+            "Number(3.0)",
+            evaluate(&mut interpreter, &local, "a + b").unwrap()
+        );
+        assert!(evaluate(&mut interpreter, &local, "c").is_err());
+        assert!(evaluate(&mut interpreter, &local, "a b").is_err());
+    }
+}
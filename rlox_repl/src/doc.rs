@@ -0,0 +1,129 @@
+//! Documentation of the functions declared at the top level of scripts, from their `///`
+//! comments.
+
+use rlox_syntax::Statement;
+use std::fmt::Write;
+
+pub struct FunctionDoc {
+    pub name: String,
+    pub params: Vec<String>,
+    pub line: usize,
+    pub doc: Option<String>,
+}
+
+impl FunctionDoc {
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+}
+
+/// Documentation of a script.
+pub struct ModuleDoc {
+    pub name: String,
+    pub functions: Vec<FunctionDoc>,
+}
+
+impl ModuleDoc {
+    /// Collects the top level functions of `statements`, which must have been parsed with
+    /// their comments.
+    pub fn new(name: &str, statements: &[Statement]) -> Self {
+        let functions = statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Function(f) => Some(FunctionDoc {
+                    name: f.name.clone(),
+                    params: f.params.clone(),
+                    line: f.span.line,
+                    doc: f.doc.clone(),
+                }),
+                _ => None,
+            })
+            .collect();
+        Self {
+            name: name.to_owned(),
+            functions,
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.name);
+        for f in &self.functions {
+            write!(out, "\n## `{}`\n", f.signature()).unwrap();
+            if let Some(doc) = &f.doc {
+                write!(out, "\n{doc}\n").unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Modules as a JSON array of `{"module", "functions"}` objects, each function having a
+/// `name`, `signature`, `line` and `doc`, which is null if undocumented.
+pub fn json(modules: &[ModuleDoc]) -> String {
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|m| {
+            let functions: Vec<_> = m
+                .functions
+                .iter()
+                .map(|f| {
+                    let doc = f.doc.as_deref().map_or("null".to_owned(), json_string);
+                    format!(
+                        r#"{{"name":{},"signature":{},"line":{},"doc":{doc}}}"#,
+                        json_string(&f.name),
+                        json_string(&f.signature()),
+                        f.line
+                    )
+                })
+                .collect();
+            format!(
+                r#"{{"module":{},"functions":[{}]}}"#,
+                json_string(&m.name),
+                functions.join(",")
+            )
+        })
+        .collect();
+    format!("[{}]", modules.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_parser::{Parser, Scanner};
+
+    fn module(source: &str) -> ModuleDoc {
+        let (tokens, comments) = Scanner::new(source).scan_tokens_with_comments().unwrap();
+        let statements = Parser::new(tokens)
+            .with_comments(&comments)
+            .parse()
+            .unwrap();
+        ModuleDoc::new("math.lox", &statements)
+    }
+
+    #[test]
+    fn test_doc() {
+        let module = module(
+            "/// Adds \"two\" numbers.\n/// Or strings.\nfun add(a, b) { return a + b; }\nfun zero() { return 0; }\n",
+        );
+        let expected =
+            "# math.lox\n\n## `add(a, b)`\n\nAdds \"two\" numbers.\nOr strings.\n\n## `zero()`\n";
+        assert_eq!(expected, module.markdown());
+        let expected = r#"[{"module":"math.lox","functions":[{"name":"add","signature":"add(a, b)","line":3,"doc":"Adds \"two\" numbers.\nOr strings."},{"name":"zero","signature":"zero()","line":4,"doc":null}]}]"#;
+        assert_eq!(expected, json(&[module]));
+    }
+}
@@ -1,62 +1,691 @@
-use rlox_interpreter::{Environment, Interpreter, StdOutPrinter};
-use rlox_parser::{Parser, Scanner};
+mod cache;
+mod cli;
+mod debugger;
+mod doc;
+mod mutate;
+mod parallel;
+mod project;
+mod test_runner;
+
+use cache::ProgramCache;
+use cli::{Backend, BuildTarget, Cli, Command, DiagnosticFormat, DocFormat, Options};
+use project::{Manifest, MANIFEST_FILE_NAME};
+use rlox_compiler::{Compiler, FunctionProto};
+use rlox_interpreter::{
+    analyze, call_graph_dot, highlight_html, Environment, EnvironmentPtr, Interpreter, Printer,
+    Resolver, Scope, ScopePtr, StdOutPrinter, Symbols, HIGHLIGHT_CSS,
+};
+use rlox_json::Json;
+use rlox_lint::LintConfig;
+use rlox_parser::{optimize, Parser, Scanner};
+use rlox_syntax::{AstPrinter, Diagnostic, LoxError, Severity, SourceOrigin, Span, Statement};
+use rlox_vm::Vm;
 use std::io::{BufRead, Write};
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
+
+// Exit codes follow sysexits.h, as in the book.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_SOFTWARE: i32 = 70;
+
+const REPL_SOURCE_NAME: &str = "<repl>";
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(300);
 
 fn main() -> anyhow::Result<()> {
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() > 2 {
-        eprintln!("Usage: rlox [script]");
-        std::process::exit(64);
-    } else if args.len() == 2 {
-        println!("Reading {}", args[1]);
-        run_file(&args[1])?;
+    let manifest = load_manifest()?;
+    let Cli { command, options } = match Cli::parse(std::env::args().skip(1), manifest) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("{message}");
+            eprintln!("Run 'rlox --help' for usage.");
+            exit(EX_USAGE);
+        }
+    };
+    match command {
+        Command::Run { script, args } if options.watch => watch_file(&script, &args, &options)?,
+        Command::Run { script, args } => {
+            println!("Reading {}", script);
+            run_file(&script, &args, &options)?;
+        }
+        Command::Repl => run_prompt(&options)?,
+        Command::Debug { script } => debug_file(&script, &options)?,
+        Command::Check { scripts } => check_files(&scripts, &options)?,
+        Command::Lint { scripts } => lint_files(&scripts, &options)?,
+        Command::Doc { scripts } => document_files(&scripts, &options)?,
+        Command::Ast { script, resolved } => dump_ast(&script, resolved, &options)?,
+        Command::Tokens { script } => dump_tokens(&script, &options)?,
+        Command::Highlight { script, standalone } => highlight_file(&script, standalone, &options)?,
+        Command::Graph { script } => dump_call_graph(&script, &options)?,
+        Command::Build { script } => build_file(&script, &options)?,
+        Command::Fmt { files, check } => format_files(&files, check, &options)?,
+        Command::Minify {
+            files,
+            rename_locals,
+        } => minify_files(&files, rename_locals, &options)?,
+        Command::New { path } => {
+            for file in project::create_project(Path::new(&path))? {
+                println!("Created {file}");
+            }
+        }
+        Command::Test { paths, mutate } => {
+            let passed = if mutate {
+                mutate::run_mutation_tests(&paths)?
+            } else {
+                test_runner::run_tests(&paths)?
+            };
+            if !passed {
+                exit(1);
+            }
+        }
+        Command::Help(info) => print!("{}", cli::help(info)),
+    }
+    Ok(())
+}
+
+/// Reads the lox.toml of the current directory, exiting if it's invalid.
+fn load_manifest() -> anyhow::Result<Option<Manifest>> {
+    if !Path::new(MANIFEST_FILE_NAME).exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(MANIFEST_FILE_NAME)?;
+    match Manifest::parse(&text) {
+        Ok(manifest) => Ok(Some(manifest)),
+        Err(diagnostic) => {
+            eprintln!("{}", diagnostic.render(MANIFEST_FILE_NAME, &text));
+            exit(EX_USAGE);
+        }
+    }
+}
+
+fn exit_code(error: &LoxError) -> i32 {
+    if error.is_static() {
+        EX_DATAERR
     } else {
-        run_prompt()?;
+        EX_SOFTWARE
+    }
+}
+
+fn report(error: &LoxError, source_name: &str, source: &str, options: &Options) {
+    report_diagnostic(error.diagnostic(), source_name, source, options);
+}
+
+/// Reports `errors` in the order they were found, returning the exit code for the worst.
+fn report_all(errors: &[LoxError], source_name: &str, source: &str, options: &Options) -> i32 {
+    for error in errors {
+        report(error, source_name, source, options);
+    }
+    errors.iter().map(exit_code).max().unwrap_or(EX_SOFTWARE)
+}
+
+/// Prints `diagnostic` to stderr in the format chosen with `--diagnostics`.
+fn report_diagnostic(diagnostic: &Diagnostic, source_name: &str, source: &str, options: &Options) {
+    match options.diagnostics {
+        DiagnosticFormat::Human => {
+            eprintln!(
+                "{}",
+                diagnostic.render_with_color(source_name, source, options.color())
+            );
+        }
+        DiagnosticFormat::Json => eprintln!("{}", diagnostic_json(diagnostic, source_name)),
+    }
+}
+
+/// Single line JSON object describing `diagnostic`, for `--diagnostics=json`.
+fn diagnostic_json(diagnostic: &Diagnostic, source_name: &str) -> Json {
+    let span = |span: Span| {
+        Json::object([
+            ("line", span.line.into()),
+            ("column", span.column.into()),
+            ("length", span.len.into()),
+        ])
+    };
+    let labels = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            Json::object([
+                ("span", span(label.span)),
+                ("message", label.message.as_str().into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let notes = diagnostic
+        .notes
+        .iter()
+        .map(|note| note.as_str().into())
+        .collect::<Vec<_>>();
+    let backtrace = diagnostic
+        .backtrace
+        .iter()
+        .map(|frame| {
+            Json::object([
+                ("function", frame.function.as_str().into()),
+                ("line", frame.line.into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    Json::object([
+        (
+            "file",
+            diagnostic
+                .source_name
+                .as_deref()
+                .unwrap_or(source_name)
+                .into(),
+        ),
+        ("severity", diagnostic.severity.label().into()),
+        ("code", diagnostic.code.map_or(Json::Null, Json::from)),
+        ("message", diagnostic.message.as_str().into()),
+        ("span", span(diagnostic.span)),
+        ("labels", labels.into()),
+        ("notes", notes.into()),
+        ("backtrace", backtrace.into()),
+    ])
+}
+
+/// Global state which outlives a single `run`, so that the REPL remembers definitions.
+struct Session {
+    environment: EnvironmentPtr,
+    scope: ScopePtr,
+    // Line the next source starts at, so that REPL inputs are numbered as a single history
+    line: usize,
+}
+
+impl Session {
+    fn new(stdlib: bool) -> Self {
+        let (environment, scope) = if stdlib {
+            (Environment::new_globals_ptr(), Scope::new_globals_ptr())
+        } else {
+            (Environment::new_empty_globals_ptr(), Scope::new_ptr(None))
+        };
+        scope
+            .lock()
+            .unwrap()
+            .use_slots_of(&environment.lock().unwrap());
+        Self {
+            environment,
+            scope,
+            line: 1,
+        }
+    }
+}
+
+/// Creates an interpreter running in the global environment of `session`.
+fn new_interpreter<'p>(
+    printer: &'p mut dyn Printer,
+    session: &Session,
+    args: &[String],
+    options: &Options,
+) -> Interpreter<'p> {
+    Interpreter::builder()
+        .printer(printer)
+        .globals(session.environment.clone())
+        .arguments(args.to_vec())
+        .trace(options.trace)
+        .build()
+}
+
+/// Runs the whole script at `path` on the backend chosen by `options`.
+fn run_script(
+    path: &str,
+    source: &str,
+    args: &[String],
+    options: &Options,
+) -> Result<(), Vec<LoxError>> {
+    let session = Session::new(!options.no_stdlib);
+    let mut printer = StdOutPrinter;
+    match options.backend {
+        Backend::Tree => {
+            let mut interpreter = new_interpreter(&mut printer, &session, args, options);
+            run(source, &session, &mut interpreter)
+        }
+        Backend::Vm => {
+            let script = compile_for_vm(path, source, &session, options)?;
+            let mut vm = if options.no_stdlib {
+                Vm::without_stdlib(&mut printer)
+            } else {
+                Vm::new(&mut printer)
+            };
+            vm.set_arguments(args.to_vec());
+            vm.interpret(script).map_err(|e| vec![e])
+        }
+    }
+}
+
+/// Compiles the script at `path` to bytecode, reusing the result of an earlier run if the
+/// script hasn't changed since.
+fn compile_for_vm(
+    path: &str,
+    source: &str,
+    session: &Session,
+    options: &Options,
+) -> Result<FunctionProto, Vec<LoxError>> {
+    let cache = ProgramCache::from_env().filter(|_| !options.no_cache);
+    let key = ProgramCache::key(source, !options.no_stdlib);
+    if let Some(script) = cache.as_ref().and_then(|c| c.load(Path::new(path), key)) {
+        return Ok(script);
+    }
+    let mut statements = compile(source, session)?;
+    optimize(&mut statements);
+    let script = Compiler::compile(&statements).map_err(|e| vec![e])?;
+    if let Some(cache) = &cache {
+        // Failing to write the cache only makes the next run slower.
+        let _ = cache.store(Path::new(path), key, &script);
+    }
+    Ok(script)
+}
+
+fn run_file(path: &str, args: &[String], options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    if let Err(errors) = run_script(path, &source, args, options) {
+        exit(report_all(&errors, path, &source, options));
     }
     Ok(())
 }
 
-fn run_file(path: &str) -> anyhow::Result<()> {
-    let source = std::fs::read_to_string(path).unwrap();
+fn debug_file(path: &str, options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
     let mut printer = StdOutPrinter;
-    let mut interpreter = Interpreter::new(&mut printer);
-    run(&source, &mut interpreter)?;
+    let mut debugger = debugger::CliDebugger::new(&source);
+    let session = Session::new(!options.no_stdlib);
+    let mut interpreter = new_interpreter(&mut printer, &session, &[], options);
+    interpreter.set_debugger(&mut debugger);
+    println!("Debugging {path}, type 'help' for the commands");
+    if let Err(errors) = run(&source, &session, &mut interpreter) {
+        exit(report_all(&errors, path, &source, options));
+    }
+    Ok(())
+}
+
+fn watch_file(path: &str, args: &[String], options: &Options) -> anyhow::Result<()> {
+    let mut last_modified = None;
+    loop {
+        // The file may be missing for a moment while an editor replaces it.
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+            println!("[watch] Running {path}");
+            match std::fs::read_to_string(path) {
+                Ok(source) => {
+                    // Every run starts from a clean state.
+                    if let Err(errors) = run_script(path, &source, args, options) {
+                        report_all(&errors, path, &source, options);
+                    }
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+            println!("[watch] Waiting for changes...");
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Checks the files on several threads, as they don't depend on each other, then reports the
+/// errors of all of them in the order they were given.
+fn check_files(paths: &[String], options: &Options) -> anyhow::Result<()> {
+    let results = parallel::map_parallel(paths, |path| {
+        let source = std::fs::read_to_string(path)?;
+        let session = Session::new(!options.no_stdlib);
+        let result = compile(&source, &session).map(|_| ());
+        Ok::<_, std::io::Error>((source, result))
+    });
+    let mut failure = None;
+    for (path, result) in paths.iter().zip(results) {
+        let (source, result) = result?;
+        if let Err(errors) = result {
+            let code = report_all(&errors, path, &source, options);
+            failure = failure.max(Some(code));
+        }
+    }
+    if let Some(code) = failure {
+        exit(code);
+    }
     Ok(())
 }
 
-fn run(source: &str, interpreter: &mut Interpreter) -> anyhow::Result<()> {
-    let scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens()?;
-    let mut parser = Parser::new(tokens);
-    let environment = Environment::new_globals_ptr();
-    match parser.parse() {
-        Ok(statements) => {
-            // println!("{:?}", &statements);
-            for s in &statements {
-                interpreter.evaluate_stmt(&environment, s)?;
+/// Lints the files on several threads like [`check_files`], failing if a script doesn't compile
+/// or if a rule configured as an error is broken.
+fn lint_files(paths: &[String], options: &Options) -> anyhow::Result<()> {
+    let manifest_config = options.manifest.as_ref().and_then(|m| m.lint.clone());
+    let config_path = match &options.lint_config {
+        Some(path) => Some(path.as_str()),
+        None if manifest_config.is_some() => None,
+        None => Path::new(rlox_lint::CONFIG_FILE_NAME)
+            .exists()
+            .then_some(rlox_lint::CONFIG_FILE_NAME),
+    };
+    let config = match config_path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)?;
+            match LintConfig::parse(&text) {
+                Ok(config) => config,
+                Err(diagnostic) => {
+                    report_diagnostic(&diagnostic, path, &text, options);
+                    exit(EX_USAGE);
+                }
             }
         }
+        None => manifest_config.unwrap_or_default(),
+    };
+
+    let results = parallel::map_parallel(paths, |path| {
+        let source = std::fs::read_to_string(path)?;
+        let session = Session::new(!options.no_stdlib);
+        let result = compile(&source, &session).map(|s| rlox_lint::lint(&s, &config));
+        Ok::<_, std::io::Error>((source, result))
+    });
+    let mut failure = None;
+    for (path, result) in paths.iter().zip(results) {
+        let (source, result) = result?;
+        match result {
+            Ok(diagnostics) => {
+                for diagnostic in diagnostics {
+                    report_diagnostic(&diagnostic, path, &source, options);
+                    if diagnostic.severity == Severity::Error {
+                        failure = failure.max(Some(EX_DATAERR));
+                    }
+                }
+            }
+            Err(errors) => {
+                let code = report_all(&errors, path, &source, options);
+                failure = failure.max(Some(code));
+            }
+        }
+    }
+    if let Some(code) = failure {
+        exit(code);
+    }
+    Ok(())
+}
+
+fn document_files(paths: &[String], options: &Options) -> anyhow::Result<()> {
+    let mut modules = Vec::new();
+    for path in paths {
+        let source = std::fs::read_to_string(path)?;
+        let parsed = Scanner::new(&source)
+            .scan_tokens_with_comments()
+            .and_then(|(tokens, comments)| Parser::new(tokens).with_comments(&comments).parse());
+        match parsed {
+            Ok(statements) => modules.push(doc::ModuleDoc::new(path, &statements)),
+            Err(e) => {
+                report(&e, path, &source, options);
+                exit(exit_code(&e));
+            }
+        }
+    }
+    match options.doc_format {
+        DocFormat::Markdown => {
+            let pages: Vec<_> = modules.iter().map(doc::ModuleDoc::markdown).collect();
+            print!("{}", pages.join("\n"));
+        }
+        DocFormat::Json => println!("{}", doc::json(&modules)),
+    }
+    Ok(())
+}
+
+fn dump_ast(path: &str, resolved: bool, options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let session = Session::new(!options.no_stdlib);
+    let result = if resolved {
+        compile(&source, &session)
+    } else {
+        parse(&source)
+    };
+    match result {
+        Ok(statements) => print!("{}", AstPrinter::new(resolved).print_program(&statements)),
+        Err(errors) => exit(report_all(&errors, path, &source, options)),
+    }
+    Ok(())
+}
+
+fn dump_call_graph(path: &str, options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let result = Scanner::new(&source).scan_tokens().and_then(|tokens| {
+        let mut statements = Parser::new(tokens.clone()).parse()?;
+        let scope = Scope::new_globals_ptr();
+        for s in &mut statements {
+            Resolver.resolve_statement(&scope, s)?;
+        }
+        Ok(Symbols::collect(&tokens, &statements))
+    });
+    match result {
+        Ok(symbols) => print!("{}", call_graph_dot(&symbols)),
+        Err(e) => {
+            report(&e, path, &source, options);
+            exit(exit_code(&e));
+        }
+    }
+    Ok(())
+}
+
+fn build_file(path: &str, options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let statements = match analyze(Scanner::new(&source), &Scope::new_globals_ptr()) {
+        Ok(statements) => statements,
+        Err(errors) => exit(report_all(&errors, path, &source, options)),
+    };
+    let output = match options.build_target {
+        BuildTarget::Js => rlox_transpile::to_javascript(&statements),
+    };
+    let output_path = Path::new(path).with_extension(options.build_target.extension());
+    std::fs::write(&output_path, output)?;
+    println!("Wrote {}", output_path.display());
+    Ok(())
+}
+
+fn highlight_file(path: &str, standalone: bool, options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    let html = match highlight_html(&source) {
+        Ok(html) => html,
         Err(e) => {
-            eprintln!("{}", e.to_string());
+            report(&e, path, &source, options);
+            exit(exit_code(&e));
+        }
+    };
+    if standalone {
+        let title = html_escape(path);
+        print!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>\n{HIGHLIGHT_CSS}</style>\n</head>\n<body>\n{html}</body>\n</html>\n"
+        );
+    } else {
+        print!("{html}");
+    }
+    Ok(())
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn dump_tokens(path: &str, options: &Options) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path)?;
+    if let Err(e) = print_tokens(&source) {
+        report(&e, path, &source, options);
+        exit(exit_code(&e));
+    }
+    Ok(())
+}
+
+fn format_files(paths: &[String], check: bool, options: &Options) -> anyhow::Result<()> {
+    let results = parallel::map_parallel(paths, |path| {
+        let source = std::fs::read_to_string(path)?;
+        let formatted = rlox_fmt::format_source(&source, &options.format);
+        Ok::<_, std::io::Error>((source, formatted))
+    });
+    let mut changed = false;
+    for (path, result) in paths.iter().zip(results) {
+        let (source, formatted) = result?;
+        let formatted = match formatted {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                report(&e, path, &source, options);
+                exit(exit_code(&e));
+            }
+        };
+        if formatted == source {
+            continue;
         }
+        changed = true;
+        if check {
+            println!("Would reformat {path}");
+        } else {
+            std::fs::write(path, formatted)?;
+            println!("Reformatted {path}");
+        }
+    }
+    if check && changed {
+        exit(1);
     }
+    Ok(())
+}
 
+fn minify_files(paths: &[String], rename_locals: bool, options: &Options) -> anyhow::Result<()> {
+    for path in paths {
+        let source = std::fs::read_to_string(path)?;
+        match rlox_fmt::minify_source(&source, rename_locals) {
+            Ok(minified) => print!("{minified}"),
+            Err(e) => {
+                report(&e, path, &source, options);
+                exit(exit_code(&e));
+            }
+        }
+    }
     Ok(())
 }
 
-fn run_prompt() -> anyhow::Result<()> {
+/// File argument of a REPL command such as `:save`, `None` if it's missing.
+fn repl_command_path(rest: &str) -> Option<&str> {
+    let path = rest.trim();
+    (!path.is_empty() && rest.starts_with(char::is_whitespace)).then_some(path)
+}
+
+/// Prints one token per line, shared by `rlox tokens` and the REPL `:tokens` command.
+fn print_tokens(source: &str) -> Result<(), LoxError> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    for token in &tokens {
+        let position = format!("{}:{}", token.line, token.column);
+        let kind = format!("{:?}", token.kind);
+        print!("{position:<8} {kind:<14} {}", token.lexeme().escape_debug());
+        if let Some(literal) = &token.literal {
+            print!("  {literal:?}");
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Scans and parses `source`, reporting every syntax error.
+fn parse(source: &str) -> Result<Vec<Statement>, Vec<LoxError>> {
+    let (tokens, mut errors) = Scanner::new(source).scan_tokens_recovering();
+    let (statements, parse_errors) = Parser::new(tokens).parse_recovering();
+    errors.extend(parse_errors);
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Scans, parses and resolves `source` against the session's global scope, reporting every
+/// error before anything runs.
+fn compile(source: &str, session: &Session) -> Result<Vec<Statement>, Vec<LoxError>> {
+    let origin = SourceOrigin::new("").at(session.line, 1);
+    analyze(Scanner::new(source).with_origin(&origin), &session.scope)
+}
+
+fn run(
+    source: &str,
+    session: &Session,
+    interpreter: &mut Interpreter,
+) -> Result<(), Vec<LoxError>> {
+    let result = compile(source, session).and_then(|mut statements| {
+        optimize(&mut statements);
+        for s in &statements {
+            interpreter
+                .evaluate_stmt(&session.environment, s)
+                .map_err(|e| vec![e])?;
+        }
+        Ok(())
+    });
+    if result.is_err() {
+        // So that the names declared by a failed line can be declared again
+        session
+            .scope
+            .lock()
+            .unwrap()
+            .forget_undefined(&session.environment.lock().unwrap());
+    }
+    result
+}
+
+fn run_prompt(options: &Options) -> anyhow::Result<()> {
     let stdin = std::io::stdin();
     let mut printer = StdOutPrinter;
-    let mut interpreter = Interpreter::new(&mut printer);
+    let mut session = Session::new(!options.no_stdlib);
+    let mut interpreter = new_interpreter(&mut printer, &session, &[], options);
+    // Every input, so that errors can show lines entered earlier
+    let mut history = String::new();
+    // Inputs which ran without errors, written by `:save`
+    let mut executed = String::new();
 
     loop {
         let mut buf = String::new();
 
         print!(">>> ");
-        std::io::stdout().flush().unwrap();
+        std::io::stdout().flush()?;
         match stdin.lock().read_line(&mut buf) {
+            Ok(0) => return Ok(()),
             Ok(_n) => {
-                run(&buf, &mut interpreter)?;
+                if let Some(source) = buf.strip_prefix(":tokens") {
+                    if let Err(e) = print_tokens(source.trim_start()) {
+                        report(&e, REPL_SOURCE_NAME, &buf, options);
+                    }
+                    continue;
+                }
+                if let Some(rest) = buf.strip_prefix(":save") {
+                    match repl_command_path(rest) {
+                        Some(path) => match std::fs::write(path, &executed) {
+                            Ok(()) => println!("Saved the session to {path}"),
+                            Err(error) => eprintln!("Error: {error}"),
+                        },
+                        None => eprintln!("Usage: :save <file>"),
+                    }
+                    continue;
+                }
+                // `:replay` runs a file as if its content had been typed in.
+                let mut input = match buf.strip_prefix(":replay").map(repl_command_path) {
+                    Some(Some(path)) => match std::fs::read_to_string(path) {
+                        Ok(source) => source,
+                        Err(error) => {
+                            eprintln!("Error: {error}");
+                            continue;
+                        }
+                    },
+                    Some(None) => {
+                        eprintln!("Usage: :replay <file>");
+                        continue;
+                    }
+                    None => buf,
+                };
+                if !input.ends_with('\n') {
+                    input.push('\n');
+                }
+                history.push_str(&input);
+                let result = run(&input, &session, &mut interpreter);
+                session.line += input.lines().count().max(1);
+                // An error in the REPL must not end the session.
+                match result {
+                    Ok(()) => executed.push_str(&input),
+                    Err(errors) => {
+                        report_all(&errors, REPL_SOURCE_NAME, &history, options);
+                    }
+                }
             }
             Err(error) => {
                 eprintln!("Error: {error}");
@@ -0,0 +1,412 @@
+//! Mutation testing behind `rlox test --mutate`.
+//!
+//! Every test script is run again once per mutation of its syntax tree, each mutation being one
+//! small change a bug could have made:
+//!
+//! - an arithmetic, comparison or equality operator swapped for its counterpart, e.g. `<` for
+//!   `<=`, or `and` for `or`,
+//! - the condition of an `if` negated by swapping its branches, or the one of a `while` compared
+//!   with `false`,
+//! - an expression, `print` or `return` statement dropped.
+//!
+//! A mutant is killed when the expectations of the script fail, and survives otherwise, which
+//! points at behavior the tests don't pin down.
+
+use crate::test_runner::{check, collect_scripts, CapturePrinter};
+use crate::{parse, Session};
+use rlox_conformance::Expectations;
+use rlox_interpreter::{Interpreter, Limits, Resolver};
+use rlox_parser::optimize;
+use rlox_syntax::*;
+use std::path::Path;
+
+// Bounds on the run of a mutant, which may well loop or recurse forever
+const MUTANT_LIMITS: Limits = Limits {
+    max_steps: Some(1_000_000),
+    max_call_depth: Some(1_000),
+};
+
+// Deep enough for MUTANT_LIMITS.max_call_depth calls of the tree-walking interpreter
+const MUTANT_STACK_SIZE: usize = 512 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mutation {
+    line: usize,
+    description: String,
+}
+
+/// Walks a syntax tree counting the places where it can be mutated, mutating the one numbered
+/// `target` on the way.
+struct Mutator {
+    target: usize,
+    next: usize,
+    applied: Option<Mutation>,
+}
+
+impl Mutator {
+    fn count(statements: &mut [Statement]) -> usize {
+        let mut mutator = Self {
+            target: usize::MAX,
+            next: 0,
+            applied: None,
+        };
+        mutator.statements(statements);
+        mutator.next
+    }
+
+    fn apply(statements: &mut [Statement], target: usize) -> Option<Mutation> {
+        let mut mutator = Self {
+            target,
+            next: 0,
+            applied: None,
+        };
+        mutator.statements(statements);
+        mutator.applied
+    }
+
+    /// Whether the next place is the one to mutate.
+    fn site(&mut self) -> bool {
+        let hit = self.next == self.target;
+        self.next += 1;
+        hit
+    }
+
+    fn applied(&mut self, span: Span, description: String) {
+        self.applied = Some(Mutation {
+            line: span.line,
+            description,
+        });
+    }
+
+    fn statements(&mut self, statements: &mut [Statement]) {
+        for s in statements {
+            self.statement(s);
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Statement) {
+        let span = stmt.span();
+        let droppable = matches!(
+            stmt,
+            Statement::Expression(_) | Statement::Print(_) | Statement::Return(_)
+        );
+        if droppable && self.site() {
+            *stmt = statement::Block::new_wrapped(span, vec![]);
+            self.applied(span, "removed the statement".to_owned());
+            return;
+        }
+        match stmt {
+            Statement::Expression(s) => self.expr(&mut s.expr),
+            Statement::Print(s) => {
+                for expr in &mut s.exprs {
+                    self.expr(expr);
+                }
+            }
+            Statement::VariableDecl(s) => {
+                if let Some(e) = &mut s.expr {
+                    self.expr(e);
+                }
+            }
+            Statement::Block(s) => self.statements(&mut s.statements),
+            Statement::If(s) => {
+                if self.site() {
+                    let empty = || statement::Block::new_wrapped(span, vec![]);
+                    let then_branch = std::mem::replace(&mut s.then_branch, empty());
+                    s.then_branch = s.else_branch.take().unwrap_or_else(empty);
+                    s.else_branch = Some(then_branch);
+                    self.applied(span, "negated the condition of `if`".to_owned());
+                }
+                self.expr(&mut s.condition);
+                self.statement(&mut s.then_branch);
+                if let Some(else_branch) = &mut s.else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While(s) => {
+                if self.site() {
+                    let condition = std::mem::replace(
+                        &mut s.condition,
+                        expr::Literal::new_wrapped(span, Literal::Nil),
+                    );
+                    s.condition = expr::Binary::new_wrapped(
+                        span,
+                        expr::Grouping::new_wrapped(span, condition),
+                        TokenKind::EqualEqual,
+                        expr::Literal::new_wrapped(span, Literal::Boolean(false)),
+                    );
+                    self.applied(span, "negated the condition of `while`".to_owned());
+                }
+                self.expr(&mut s.condition);
+                self.statement(&mut s.body);
+            }
+            Statement::Function(s) => {
+                let mut body = s.body.write().unwrap_or_else(|e| e.into_inner());
+                self.statement(&mut body);
+            }
+            Statement::Return(s) => {
+                if let Some(value) = &mut s.value {
+                    self.expr(value);
+                }
+            }
+            Statement::Class(s) => {
+                for method in &mut s.methods {
+                    self.statement(method);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &mut Expr) {
+        match e {
+            Expr::Binary(e) => {
+                if let Some(swapped) = swapped_operator(e.operator) {
+                    if self.site() {
+                        let description = format!(
+                            "replaced `{}` with `{}`",
+                            lexeme(e.operator),
+                            lexeme(swapped)
+                        );
+                        e.operator = swapped;
+                        self.applied(e.span, description);
+                    }
+                }
+                self.expr(&mut e.left);
+                self.expr(&mut e.right);
+            }
+            Expr::Logical(e) => {
+                if self.site() {
+                    let swapped = if e.operator == TokenKind::And {
+                        TokenKind::Or
+                    } else {
+                        TokenKind::And
+                    };
+                    let description = format!(
+                        "replaced `{}` with `{}`",
+                        lexeme(e.operator),
+                        lexeme(swapped)
+                    );
+                    e.operator = swapped;
+                    self.applied(e.span, description);
+                }
+                self.expr(&mut e.left);
+                self.expr(&mut e.right);
+            }
+            Expr::Grouping(e) => self.expr(&mut e.expr),
+            Expr::Unary(e) => self.expr(&mut e.right),
+            Expr::Assign(e) => self.expr(&mut e.value),
+            Expr::Call(e) => {
+                self.expr(&mut e.callee);
+                for argument in &mut e.arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get(e) => self.expr(&mut e.object),
+            Expr::Set(e) => {
+                self.expr(&mut e.object);
+                self.expr(&mut e.value);
+            }
+            Expr::List(e) => {
+                for element in &mut e.elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Index(e) => {
+                self.expr(&mut e.object);
+                self.expr(&mut e.index);
+            }
+            Expr::Slice(e) => {
+                self.expr(&mut e.object);
+                for bound in e.start.iter_mut().chain(&mut e.end) {
+                    self.expr(bound);
+                }
+            }
+            Expr::IndexSet(e) => {
+                self.expr(&mut e.object);
+                self.expr(&mut e.index);
+                self.expr(&mut e.value);
+            }
+            Expr::Lambda(e) => {
+                let mut body = e.body.write().unwrap_or_else(|e| e.into_inner());
+                self.statement(&mut body);
+            }
+            Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super(_) => {}
+        }
+    }
+}
+
+fn swapped_operator(operator: TokenKind) -> Option<TokenKind> {
+    Some(match operator {
+        TokenKind::Plus => TokenKind::Minus,
+        TokenKind::Minus => TokenKind::Plus,
+        TokenKind::Star => TokenKind::Slash,
+        TokenKind::Slash => TokenKind::Star,
+        TokenKind::Less => TokenKind::LessEqual,
+        TokenKind::LessEqual => TokenKind::Less,
+        TokenKind::Greater => TokenKind::GreaterEqual,
+        TokenKind::GreaterEqual => TokenKind::Greater,
+        TokenKind::EqualEqual => TokenKind::BangEqual,
+        TokenKind::BangEqual => TokenKind::EqualEqual,
+        _ => return None,
+    })
+}
+
+fn lexeme(operator: TokenKind) -> &'static str {
+    match operator {
+        TokenKind::Plus => "+",
+        TokenKind::Minus => "-",
+        TokenKind::Star => "*",
+        TokenKind::Slash => "/",
+        TokenKind::Less => "<",
+        TokenKind::LessEqual => "<=",
+        TokenKind::Greater => ">",
+        TokenKind::GreaterEqual => ">=",
+        TokenKind::EqualEqual => "==",
+        TokenKind::BangEqual => "!=",
+        TokenKind::And => "and",
+        TokenKind::Or => "or",
+        _ => unreachable!("{operator:?} is not mutated"),
+    }
+}
+
+/// Runs `statements` as a test with `expectations`, returning the reasons of failure.
+fn run_mutant(expectations: &Expectations, mut statements: Vec<Statement>) -> Vec<String> {
+    let session = Session::new(true);
+    let mut printer = CapturePrinter { lines: Vec::new() };
+    let result = statements
+        .iter_mut()
+        .try_for_each(|s| Resolver.resolve_statement(&session.scope, s))
+        .and_then(|()| {
+            optimize(&mut statements);
+            let mut interpreter = Interpreter::builder()
+                .printer(&mut printer)
+                .globals(session.environment.clone())
+                .limits(MUTANT_LIMITS)
+                .build();
+            statements
+                .iter()
+                .try_for_each(|s| interpreter.evaluate_stmt(&session.environment, s))
+        })
+        .map_err(|e| vec![e]);
+    check(expectations, &printer.lines, &result)
+}
+
+/// Mutations of `source` which its expectations don't catch, or `None` if they fail without
+/// any mutation. The second value is the number of mutants.
+fn surviving_mutations(source: &str) -> Result<Option<(Vec<Mutation>, usize)>, Vec<LoxError>> {
+    let expectations = Expectations::parse(source);
+    let mut statements = parse(source)?;
+    let count = Mutator::count(&mut statements);
+    if !run_mutant(&expectations, statements).is_empty() {
+        return Ok(None);
+    }
+    let mut survivors = Vec::new();
+    for target in 0..count {
+        let mut statements = parse(source)?;
+        let mutation = Mutator::apply(&mut statements, target).expect("counted mutation");
+        if run_mutant(&expectations, statements).is_empty() {
+            survivors.push(mutation);
+        }
+    }
+    Ok(Some((survivors, count)))
+}
+
+/// Runs the mutants of every script under `paths` and prints the ones which survive with a
+/// summary. Returns whether the scripts pass without mutations.
+pub fn run_mutation_tests(paths: &[String]) -> anyhow::Result<bool> {
+    let paths = paths.to_vec();
+    std::thread::Builder::new()
+        .stack_size(MUTANT_STACK_SIZE)
+        .spawn(move || mutation_tests(&paths))?
+        .join()
+        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+fn mutation_tests(paths: &[String]) -> anyhow::Result<bool> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        collect_scripts(Path::new(path), &mut scripts)?;
+    }
+
+    let mut passed = true;
+    let (mut total, mut survived) = (0, 0);
+    for script in &scripts {
+        let source = std::fs::read_to_string(script)?;
+        match surviving_mutations(&source) {
+            Ok(Some((survivors, count))) => {
+                for mutation in &survivors {
+                    println!(
+                        "SURVIVED {}:{}: {}",
+                        script.display(),
+                        mutation.line,
+                        mutation.description
+                    );
+                }
+                total += count;
+                survived += survivors.len();
+            }
+            Ok(None) | Err(_) => {
+                passed = false;
+                println!("FAIL {} (fails without mutations)", script.display());
+            }
+        }
+    }
+    let killed = total - survived;
+    let score = if total == 0 {
+        100.0
+    } else {
+        killed as f64 * 100.0 / total as f64
+    };
+    println!("{total} mutants, {killed} killed, {survived} survived ({score:.1}% killed)");
+    Ok(passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations() {
+        let source = r#"
+fun max(a, b) {
+    if (a > b) return a;
+    return b;
+}
+print max(1, 2); // expect: Number(2.0)
+"#;
+        let mut statements = parse(source).unwrap();
+        // `if`, `>`, both returns, and the print
+        assert_eq!(5, Mutator::count(&mut statements));
+        let (survivors, count) = surviving_mutations(source).unwrap().unwrap();
+        assert_eq!(5, count);
+        // Only the case where b is greater is tested.
+        let expected = vec![
+            Mutation {
+                line: 3,
+                description: "replaced `>` with `>=`".to_owned(),
+            },
+            Mutation {
+                line: 3,
+                description: "removed the statement".to_owned(),
+            },
+        ];
+        assert_eq!(expected, survivors);
+
+        // Which only leaves a mutant no test can kill
+
+        let killing = format!("{source}print max(3, 2); // expect: Number(3.0)\n");
+        let (survivors, _) = surviving_mutations(&killing).unwrap().unwrap();
+        assert_eq!(
+            vec!["replaced `>` with `>=`"],
+            survivors.iter().map(|m| &m.description).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_infinite_mutant() {
+        let source = "var i = 0;\nwhile (i < 3) i = i + 1;\nprint i; // expect: Number(3.0)\n";
+        let (survivors, count) = surviving_mutations(source).unwrap().unwrap();
+        // `while`, `<`, the assignment, `+` and the print
+        assert_eq!(5, count);
+        assert!(survivors.is_empty(), "{survivors:?}");
+    }
+}
@@ -0,0 +1,304 @@
+//! Projects: a directory with a `lox.toml` manifest, as created by `rlox new`.
+//!
+//! The manifest is a small subset of TOML, whose sections configure the commands run from the
+//! project directory, with the same names as their flags:
+//!
+//! ```toml
+//! [project]
+//! name = "hello"
+//!
+//! [fmt]
+//! indent = 4
+//! max-width = 100
+//! brace-style = "same-line"
+//!
+//! # Rules as in .rloxlint, which is ignored when this section is present
+//! [lint]
+//! shadowing = "warning"
+//!
+//! [test]
+//! paths = ["tests"]
+//! ```
+//!
+//! Flags given on the command line take precedence over the manifest.
+
+use rlox_fmt::{BraceStyle, FormatConfig};
+use rlox_lint::LintConfig;
+use rlox_syntax::{Diagnostic, Span};
+use std::path::Path;
+
+/// Name of the manifest looked up in the current directory.
+pub const MANIFEST_FILE_NAME: &str = "lox.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub format: FormatConfig,
+    /// Rules of the `[lint]` section, if there is one.
+    pub lint: Option<LintConfig>,
+    /// What `rlox test` runs when no path is given.
+    pub test_paths: Vec<String>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            format: FormatConfig::default(),
+            lint: None,
+            test_paths: vec!["tests".to_owned()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(usize),
+    Array(Vec<String>),
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Self, Box<Diagnostic>> {
+        let mut manifest = Self::default();
+        let mut section = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let column = line.len() - line.trim_start().len() + 1;
+            let span = Span::new(index + 1, column, trimmed.chars().count());
+            let error = |message: String| Box::new(Diagnostic::new(span, message));
+
+            if let Some(name) = trimmed.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("Expected `]` after the section name.".to_owned()))?;
+                section = name.trim().to_owned();
+                if !["project", "fmt", "lint", "test"].contains(&section.as_str()) {
+                    return Err(Box::new(
+                        Diagnostic::new(span, format!("Unknown section: {section}"))
+                            .with_note("Sections are project, fmt, lint, test."),
+                    ));
+                }
+                if section == "lint" {
+                    manifest.lint.get_or_insert_with(LintConfig::default);
+                }
+                continue;
+            }
+            let (key, value) = trimmed
+                .split_once('=')
+                .ok_or_else(|| error("Expected `key = value`.".to_owned()))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).map_err(error)?;
+            match (section.as_str(), key, value) {
+                ("project", "name", Value::String(name)) => manifest.name = name,
+                ("fmt", "indent", Value::Integer(n)) => manifest.format.indent_width = n,
+                ("fmt", "max-width", Value::Integer(n)) => manifest.format.max_line_length = n,
+                ("fmt", "brace-style", Value::String(style)) => {
+                    manifest.format.brace_style = BraceStyle::from_name(&style)
+                        .ok_or_else(|| error(format!("Invalid brace style: {style}")))?;
+                }
+                ("lint", rule, Value::String(level)) => {
+                    let lint = manifest.lint.as_mut().expect("created with the section");
+                    lint.configure(rule, &level, span)?;
+                }
+                ("test", "paths", Value::Array(paths)) => manifest.test_paths = paths,
+                ("", _, _) => return Err(error(format!("`{key}` is outside of any section."))),
+                (section, key, value) => {
+                    return Err(error(format!(
+                        "Unexpected setting `{key}` of [{section}], or value {value:?}"
+                    )))
+                }
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// A string, a non-negative integer or an array of strings, possibly followed by a comment.
+fn parse_value(text: &str) -> Result<Value, String> {
+    let mut chars = text.chars().peekable();
+    let value = match chars.peek() {
+        Some('"') => Value::String(parse_string(&mut chars)?),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                match chars.peek() {
+                    Some(']') => {
+                        chars.next();
+                        break;
+                    }
+                    Some('"') => items.push(parse_string(&mut chars)?),
+                    _ => return Err("Expected a string or `]` in the array.".to_owned()),
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next_if_eq(&',').is_none() && chars.peek() != Some(&']') {
+                    return Err("Expected `,` or `]` after the array item.".to_owned());
+                }
+            }
+            Value::Array(items)
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let mut digits = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '_') {
+                digits.push(c);
+            }
+            let number = digits.replace('_', "");
+            Value::Integer(
+                number
+                    .parse()
+                    .map_err(|_| format!("Invalid number: {digits}"))?,
+            )
+        }
+        _ => return Err(format!("Invalid value: {text}")),
+    };
+    let rest: String = chars.collect();
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("Unexpected `{rest}` after the value."));
+    }
+    Ok(value)
+}
+
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    chars.next();
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                _ => return Err("Unsupported escape sequence in string.".to_owned()),
+            },
+            Some(c) => string.push(c),
+            None => return Err("Unterminated string.".to_owned()),
+        }
+    }
+}
+
+/// Files of a new project, relative to its directory.
+fn template(name: &str) -> Vec<(&'static str, String)> {
+    let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+    vec![
+        (
+            MANIFEST_FILE_NAME,
+            format!(
+                "[project]\n\
+                 name = \"{name}\"\n\
+                 \n\
+                 [fmt]\n\
+                 indent = 4\n\
+                 max-width = 100\n\
+                 brace-style = \"same-line\"\n\
+                 \n\
+                 [lint]\n\
+                 empty-block = \"warning\"\n\
+                 constant-condition = \"warning\"\n\
+                 shadowing = \"off\"\n\
+                 mixed-equality = \"error\"\n\
+                 \n\
+                 [test]\n\
+                 paths = [\"tests\"]\n"
+            ),
+        ),
+        (
+            "main.lox",
+            "// Run with `rlox main.lox`.\n\
+             print \"Hello, world!\";\n"
+                .to_owned(),
+        ),
+        (
+            "lib/greeting.lox",
+            "// Scripts can't import each other yet, so functions shared by several scripts are\n\
+             // kept here to be copied from.\n\
+             \n\
+             /// The greeting of `name`.\n\
+             fun greet(name) {\n    return \"Hello, \" + name + \"!\";\n}\n"
+                .to_owned(),
+        ),
+        (
+            "tests/greeting.lox",
+            "// Run with `rlox test`, which checks the output against the `expect` comments.\n\
+             fun greet(name) {\n    return \"Hello, \" + name + \"!\";\n}\n\
+             \n\
+             print greet(\"Lox\"); // expect: String(\"Hello, Lox!\")\n\
+             print 1 + 2; // expect: Number(3.0)\n"
+                .to_owned(),
+        ),
+    ]
+}
+
+/// Creates a project in the new directory `path`, named after it. Returns the created files.
+pub fn create_project(path: &Path) -> anyhow::Result<Vec<String>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid project path: {}", path.display()))?;
+    if path.exists() {
+        anyhow::bail!("{} already exists", path.display());
+    }
+    let mut created = Vec::new();
+    for (file, content) in template(name) {
+        let file_path = path.join(file);
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&file_path, content)?;
+        created.push(file_path.display().to_string());
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_lint::Rule;
+    use rlox_syntax::Severity;
+
+    #[test]
+    fn test_template() {
+        let files = template("hello");
+        let manifest = Manifest::parse(&files[0].1).unwrap();
+        assert_eq!("hello", manifest.name);
+        assert_eq!(FormatConfig::default(), manifest.format);
+        let lint = manifest.lint.unwrap();
+        assert_eq!(Some(Severity::Error), lint.severity(Rule::MixedEquality));
+        assert_eq!(None, lint.severity(Rule::Shadowing));
+        assert_eq!(vec!["tests"], manifest.test_paths);
+        // The generated scripts are formatted and pass their tests.
+        for (file, content) in &files[1..] {
+            let formatted = rlox_fmt::format_source(content, &manifest.format).unwrap();
+            assert_eq!(content, &formatted, "{file}");
+        }
+        let (_, test) = &files[3];
+        assert!(crate::test_runner::run_test(test).is_empty());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::parse(
+            "# Comment\n[fmt]\nindent = 2 # Trailing\nbrace-style = \"next-line\"\n\n[test]\npaths = [\"a\", \"b\\\"c\",]\n",
+        )
+        .unwrap();
+        assert_eq!(2, manifest.format.indent_width);
+        assert_eq!(BraceStyle::NextLine, manifest.format.brace_style);
+        assert_eq!(None, manifest.lint);
+        assert_eq!(vec!["a", "b\"c"], manifest.test_paths);
+
+        let error = Manifest::parse("[lint]\nshadowing = \"loud\"").unwrap_err();
+        assert_eq!("Unknown level: loud", error.message);
+        assert_eq!(Span::new(2, 1, 18), error.span);
+        assert!(Manifest::parse("name = \"a\"").is_err());
+        assert!(Manifest::parse("[package]").is_err());
+        assert!(Manifest::parse("[fmt]\nindent = \"2\"").is_err());
+        assert!(Manifest::parse("[fmt]\nindent = 2 3").is_err());
+        assert!(Manifest::parse("[test]\npaths = [\"a\"").is_err());
+    }
+}
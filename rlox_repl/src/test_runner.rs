@@ -0,0 +1,188 @@
+//! Test runner behind `rlox test`.
+//!
+//! Every `.lox` file is a test case whose expectations are written as comments, in the format of
+//! the Crafting Interpreters test suite:
+//!
+//! ```text
+//! print 1 + 2; // expect: 3
+//! print nil + 1; // expect runtime error: Operands must be numbers.
+//! var a = ; // Error at ';': Expect expression.
+//! // [line 5] Error at end: Expect '}' after block.
+//! ```
+//!
+//! Printed lines are compared exactly. Errors are matched by the line they are reported at,
+//! since the messages of this interpreter don't follow the book word for word; `rlox_conformance`
+//! runs the book's own suite strictly.
+
+use crate::{run, Session};
+use rlox_conformance::Expectations;
+use rlox_interpreter::{Interpreter, Printer};
+use rlox_syntax::LoxError;
+use std::path::{Path, PathBuf};
+
+pub(crate) struct CapturePrinter {
+    pub lines: Vec<String>,
+}
+
+impl Printer for CapturePrinter {
+    fn print(&mut self, message: &str) {
+        self.lines.push(message.to_owned());
+    }
+}
+
+/// Runs a single test, returning the reasons of failure.
+pub(crate) fn run_test(source: &str) -> Vec<String> {
+    let expectations = Expectations::parse(source);
+    let mut printer = CapturePrinter { lines: Vec::new() };
+    let result = {
+        let session = Session::new(true);
+        let mut interpreter = Interpreter::builder()
+            .printer(&mut printer)
+            .globals(session.environment.clone())
+            .build();
+        run(source, &session, &mut interpreter)
+    };
+    check(&expectations, &printer.lines, &result)
+}
+
+/// Reasons why a run which printed `lines` and ended with `result` doesn't meet `expectations`.
+pub(crate) fn check(
+    expectations: &Expectations,
+    lines: &[String],
+    result: &Result<(), Vec<LoxError>>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    for (index, expected) in expectations.output.iter().enumerate() {
+        match lines.get(index) {
+            Some(actual) if actual == expected => {}
+            Some(actual) => failures.push(format!("Expected output '{expected}', got '{actual}'")),
+            None => failures.push(format!("Missing expected output '{expected}'")),
+        }
+    }
+    for extra in lines.iter().skip(expectations.output.len()) {
+        failures.push(format!("Unexpected output '{extra}'"));
+    }
+
+    let errors = result.as_ref().err().map(Vec::as_slice).unwrap_or_default();
+    for e in errors {
+        match (e, &expectations.runtime_error) {
+            (LoxError::Runtime(_), Some((line, message))) => {
+                if e.span().line != *line {
+                    failures.push(format!(
+                        "Expected runtime error '{message}' on line {line}, got '{e}'"
+                    ));
+                }
+            }
+            (LoxError::Runtime(_), None) => {
+                failures.push(format!("Unexpected runtime error '{e}'"));
+            }
+            _ => {
+                if !expectations
+                    .static_errors
+                    .iter()
+                    .any(|(line, _)| *line == e.span().line)
+                {
+                    failures.push(format!("Unexpected error '{e}'"));
+                }
+            }
+        }
+    }
+    if let Some((line, message)) = &expectations.runtime_error {
+        if !errors.iter().any(|e| matches!(e, LoxError::Runtime(_))) {
+            failures.push(format!("Expected runtime error '{message}' on line {line}"));
+        }
+    }
+    // Every static error is reported at once, so each expected one must be there.
+    for (line, _) in &expectations.static_errors {
+        if !errors
+            .iter()
+            .any(|e| e.is_static() && e.span().line == *line)
+        {
+            failures.push(format!("Expected an error on line {line}"));
+        }
+    }
+    failures
+}
+
+pub(crate) fn collect_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            collect_scripts(&entry, scripts)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "lox") {
+        scripts.push(path.to_owned());
+    }
+    Ok(())
+}
+
+/// Runs every script under `paths` and prints a summary. Returns whether all tests passed.
+pub fn run_tests(paths: &[String]) -> anyhow::Result<bool> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        collect_scripts(Path::new(path), &mut scripts)?;
+    }
+
+    let mut failed = 0;
+    for script in &scripts {
+        let source = std::fs::read_to_string(script)?;
+        let failures = run_test(&source);
+        if failures.is_empty() {
+            continue;
+        }
+        failed += 1;
+        println!("FAIL {}", script.display());
+        for failure in failures {
+            println!("    {failure}");
+        }
+    }
+    println!(
+        "{} passed, {} failed, {} total",
+        scripts.len() - failed,
+        failed,
+        scripts.len()
+    );
+    Ok(failed == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passing() {
+        let source = r#"
+print 1 + 2; // expect: Number(3.0)
+print "a"; // expect: String("a")
+print nil + 1; // expect runtime error: Operands must be numbers.
+"#;
+        assert!(run_test(source).is_empty());
+    }
+
+    #[test]
+    fn test_static_error() {
+        assert!(run_test("print undefined; // Error at 'undefined': Undefined.").is_empty());
+        assert!(run_test("{\n// [line 2] Error at end: Expect '}' after block.").is_empty());
+        let source = "print 1 +; // Error at ';': Expect expression.\nvar = 1; // Error: Expect.";
+        assert!(run_test(source).is_empty());
+    }
+
+    #[test]
+    fn test_failing() {
+        let failures = run_test("print 1; // expect: 1\nprint 2;");
+        assert_eq!(
+            vec![
+                "Expected output '1', got 'Number(1.0)'",
+                "Unexpected output 'Number(2.0)'"
+            ],
+            failures
+        );
+        let failures = run_test("var a = 1; // Error: Expected.");
+        assert_eq!(vec!["Expected an error on line 1"], failures);
+        let failures = run_test("print 1 +; // Error: Expected.\nprint 2; // Error: Expected.");
+        assert_eq!(vec!["Expected an error on line 2"], failures);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::span::Span;
 use crate::syntax_node::*;
 use crate::token::TokenKind;
 use std::sync::Arc;
 
+#[syntax_enum]
 #[derive(Debug)]
 pub enum Statement {
     Expression(Ptr<statement::Expression>),
@@ -12,6 +14,38 @@ pub enum Statement {
     While(Ptr<statement::While>),
     Function(Ptr<statement::Function>),
     Return(Ptr<statement::Return>),
+    Class(Ptr<statement::Class>),
+}
+
+impl Statement {
+    /// Id of the node, see [`SyntaxNode::generate_id`].
+    pub fn id(&self) -> usize {
+        match self {
+            Statement::Expression(s) => s.id,
+            Statement::Print(s) => s.id,
+            Statement::VariableDecl(s) => s.id,
+            Statement::Block(s) => s.id,
+            Statement::If(s) => s.id,
+            Statement::While(s) => s.id,
+            Statement::Function(s) => s.id,
+            Statement::Return(s) => s.id,
+            Statement::Class(s) => s.id,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression(s) => s.span,
+            Statement::Print(s) => s.span,
+            Statement::VariableDecl(s) => s.span,
+            Statement::Block(s) => s.span,
+            Statement::If(s) => s.span,
+            Statement::While(s) => s.span,
+            Statement::Function(s) => s.span,
+            Statement::Return(s) => s.span,
+            Statement::Class(s) => s.span,
+        }
+    }
 }
 
 pub mod statement {
@@ -22,6 +56,7 @@ pub mod statement {
     #[derive(Debug)]
     pub struct Expression {
         pub id: usize,
+        pub span: Span,
         pub expr: Expr,
     }
 
@@ -29,21 +64,39 @@ pub mod statement {
     #[derive(Debug)]
     pub struct Print {
         pub id: usize,
-        pub expr: Expr,
+        pub span: Span,
+        /// Values printed on a single line, separated by spaces.
+        pub exprs: Vec<Expr>,
     }
 
+    /// Declaration of a variable by `var`, or by `const` if `constant`, in which case it has an
+    /// initializer and can't be assigned.
     #[syntax_node(Statement::VariableDecl)]
     #[derive(Debug)]
     pub struct VariableDecl {
         pub id: usize,
+        pub span: Span,
         pub name: String,
         pub expr: Option<Expr>,
+        pub constant: bool,
+    }
+
+    impl VariableDecl {
+        /// `const` or `var`, whichever declared the variable.
+        pub fn keyword(&self) -> &'static str {
+            if self.constant {
+                "const"
+            } else {
+                "var"
+            }
+        }
     }
 
     #[syntax_node(Statement::Block)]
     #[derive(Debug)]
     pub struct Block {
         pub id: usize,
+        pub span: Span,
         pub statements: Vec<Statement>,
     }
 
@@ -51,15 +104,32 @@ pub mod statement {
     #[derive(Debug)]
     pub struct Function {
         pub id: usize,
+        pub span: Span,
         pub name: String,
         pub params: Vec<String>,
+        /// Whether the last of `params` is a rest parameter, e.g. `...args`, collecting the
+        /// remaining arguments into a list.
+        pub variadic: bool,
         pub body: Arc<RwLock<Statement>>,
+        /// Whether the function is a method declared `static`, which is called on the class
+        /// rather than on instances and has no `this`. Always `false` outside classes.
+        pub is_static: bool,
+        /// Text of the `///` comments right above the declaration, without the slashes.
+        pub doc: Option<String>,
+    }
+
+    impl Function {
+        /// The parameters as written, e.g. `...args` for a rest parameter.
+        pub fn written_params(&self) -> Vec<String> {
+            written_params(&self.params, self.variadic)
+        }
     }
 
     #[syntax_node(Statement::If)]
     #[derive(Debug)]
     pub struct If {
         pub id: usize,
+        pub span: Span,
         pub condition: Expr,
         pub then_branch: Statement,
         pub else_branch: Option<Statement>,
@@ -69,6 +139,7 @@ pub mod statement {
     #[derive(Debug)]
     pub struct While {
         pub id: usize,
+        pub span: Span,
         pub condition: Expr,
         pub body: Statement,
     }
@@ -77,17 +148,32 @@ pub mod statement {
     #[derive(Debug)]
     pub struct Return {
         pub id: usize,
+        pub span: Span,
         pub value: Option<Expr>,
     }
+
+    #[syntax_node(Statement::Class)]
+    #[derive(Debug)]
+    pub struct Class {
+        pub id: usize,
+        pub span: Span,
+        pub name: String,
+        /// The `Expr::Variable` after `<`, whose methods are inherited.
+        pub superclass: Option<Expr>,
+        /// Each a `Statement::Function`, whose `this` is the instance the method is called on.
+        pub methods: Vec<Statement>,
+    }
 }
 
 pub mod expr {
     use super::*;
+    use std::sync::RwLock;
 
     #[syntax_node(Expr::Binary)]
     #[derive(Debug)]
     pub struct Binary {
         pub id: usize,
+        pub span: Span,
         pub left: Expr,
         pub operator: TokenKind,
         pub right: Expr,
@@ -97,6 +183,7 @@ pub mod expr {
     #[derive(Debug)]
     pub struct Grouping {
         pub id: usize,
+        pub span: Span,
         pub expr: Expr,
     }
 
@@ -104,6 +191,7 @@ pub mod expr {
     #[derive(Debug)]
     pub struct Literal {
         pub id: usize,
+        pub span: Span,
         pub literal: super::Literal,
     }
 
@@ -111,6 +199,7 @@ pub mod expr {
     #[derive(Debug)]
     pub struct Unary {
         pub id: usize,
+        pub span: Span,
         pub operator: TokenKind,
         pub right: Expr,
     }
@@ -119,27 +208,32 @@ pub mod expr {
     #[derive(Debug)]
     pub struct Variable {
         pub id: usize,
+        pub span: Span,
         pub name: String,
         // How many levels should be escalated to resolve this variable
         pub resolution: usize,
+        // Slot of the variable among the globals, if the resolver found it to be one
+        pub slot: Option<usize>,
     }
 
     #[syntax_node(Expr::Assign)]
     #[derive(Debug)]
     pub struct Assign {
         pub id: usize,
+        pub span: Span,
         pub name: String,
         pub value: Expr,
-        // TODO: There are more things to which values can be assigned
-        // e.g. instance.method()
         // How many levels should be escalated to resolve this variable
         pub resolution: usize,
+        // Slot of the variable among the globals, if the resolver found it to be one
+        pub slot: Option<usize>,
     }
 
     #[syntax_node(Expr::Logical)]
     #[derive(Debug)]
     pub struct Logical {
         pub id: usize,
+        pub span: Span,
         pub left: Expr,
         pub operator: TokenKind,
         pub right: Expr,
@@ -149,11 +243,150 @@ pub mod expr {
     #[derive(Debug)]
     pub struct Call {
         pub id: usize,
+        pub span: Span,
         pub callee: Expr,
         pub arguments: Vec<Expr>,
     }
+
+    /// Reading the property `name` of an instance, e.g. `point.x`. If `optional`, written
+    /// `point?.x`, it's `nil` when the object is `nil`, and so is the call of it.
+    #[syntax_node(Expr::Get)]
+    #[derive(Debug)]
+    pub struct Get {
+        pub id: usize,
+        pub span: Span,
+        pub object: Expr,
+        pub name: String,
+        pub optional: bool,
+    }
+
+    impl Get {
+        /// `?.` or `.`, whichever reads the property.
+        pub fn dot(&self) -> &'static str {
+            if self.optional {
+                "?."
+            } else {
+                "."
+            }
+        }
+    }
+
+    /// Assignment of the field `name` of an instance, e.g. `point.x = 1`.
+    #[syntax_node(Expr::Set)]
+    #[derive(Debug)]
+    pub struct Set {
+        pub id: usize,
+        pub span: Span,
+        pub object: Expr,
+        pub name: String,
+        pub value: Expr,
+    }
+
+    /// The instance a method is called on, found like a variable declared around the method.
+    #[syntax_node(Expr::This)]
+    #[derive(Debug)]
+    pub struct This {
+        pub id: usize,
+        pub span: Span,
+        // How many levels should be escalated to resolve this variable
+        pub resolution: usize,
+    }
+
+    /// The method `method` of the superclass, bound to `this`, e.g. `super.init`.
+    #[syntax_node(Expr::Super)]
+    #[derive(Debug)]
+    pub struct Super {
+        pub id: usize,
+        pub span: Span,
+        pub method: String,
+        // How many levels should be escalated to resolve the superclass, `this` being one less
+        pub resolution: usize,
+    }
+
+    /// A list of the values of `elements`, e.g. `[1, 2, 3]`.
+    #[syntax_node(Expr::List)]
+    #[derive(Debug)]
+    pub struct List {
+        pub id: usize,
+        pub span: Span,
+        pub elements: Vec<Expr>,
+    }
+
+    /// Reading the element at `index` of a list, e.g. `items[0]`.
+    #[syntax_node(Expr::Index)]
+    #[derive(Debug)]
+    pub struct Index {
+        pub id: usize,
+        pub span: Span,
+        pub object: Expr,
+        pub index: Expr,
+    }
+
+    /// A copy of the elements of a list or the characters of a string from `start` up to `end`,
+    /// excluded, e.g. `items[1:3]`. Omitted bounds are the start and the end of the value.
+    #[syntax_node(Expr::Slice)]
+    #[derive(Debug)]
+    pub struct Slice {
+        pub id: usize,
+        pub span: Span,
+        pub object: Expr,
+        pub start: Option<Expr>,
+        pub end: Option<Expr>,
+    }
+
+    /// Assignment of the element at `index` of a list, e.g. `items[0] = 1`.
+    #[syntax_node(Expr::IndexSet)]
+    #[derive(Debug)]
+    pub struct IndexSet {
+        pub id: usize,
+        pub span: Span,
+        pub object: Expr,
+        pub index: Expr,
+        pub value: Expr,
+    }
+
+    /// An anonymous function, e.g. `fun (x) -> x * 2`, whose body is the block returning the
+    /// expression after `->`.
+    #[syntax_node(Expr::Lambda)]
+    #[derive(Debug)]
+    pub struct Lambda {
+        pub id: usize,
+        pub span: Span,
+        pub params: Vec<String>,
+        /// Whether the last of `params` is a rest parameter, as for functions.
+        pub variadic: bool,
+        pub body: Arc<RwLock<Statement>>,
+    }
+
+    impl Lambda {
+        /// The parameters as written, e.g. `...args` for a rest parameter.
+        pub fn written_params(&self) -> Vec<String> {
+            written_params(&self.params, self.variadic)
+        }
+
+        /// The expression after `->` returned by `body`, the body of a lambda.
+        pub fn returned(body: &Statement) -> Option<&Expr> {
+            let Statement::Block(block) = body else {
+                return None;
+            };
+            match block.statements.as_slice() {
+                [Statement::Return(r)] => r.value.as_ref(),
+                _ => None,
+            }
+        }
+    }
 }
 
+/// `params`, the last one preceded by `...` if `variadic`.
+fn written_params(params: &[String], variadic: bool) -> Vec<String> {
+    let mut written = params.to_vec();
+    if let (true, Some(rest)) = (variadic, written.last_mut()) {
+        rest.insert_str(0, "...");
+    }
+    written
+}
+
+#[syntax_enum]
 #[derive(Debug)]
 pub enum Expr {
     Binary(Box<expr::Binary>),
@@ -164,12 +397,69 @@ pub enum Expr {
     Assign(Box<expr::Assign>),
     Logical(Box<expr::Logical>),
     Call(Box<expr::Call>),
+    Get(Box<expr::Get>),
+    Set(Box<expr::Set>),
+    This(Box<expr::This>),
+    Super(Box<expr::Super>),
+    Lambda(Box<expr::Lambda>),
+    List(Box<expr::List>),
+    Index(Box<expr::Index>),
+    IndexSet(Box<expr::IndexSet>),
+    Slice(Box<expr::Slice>),
+}
+
+impl Expr {
+    /// Id of the node, see [`SyntaxNode::generate_id`].
+    pub fn id(&self) -> usize {
+        match self {
+            Expr::Binary(e) => e.id,
+            Expr::Grouping(e) => e.id,
+            Expr::Literal(e) => e.id,
+            Expr::Unary(e) => e.id,
+            Expr::Variable(e) => e.id,
+            Expr::Assign(e) => e.id,
+            Expr::Logical(e) => e.id,
+            Expr::Call(e) => e.id,
+            Expr::Get(e) => e.id,
+            Expr::Set(e) => e.id,
+            Expr::This(e) => e.id,
+            Expr::Super(e) => e.id,
+            Expr::Lambda(e) => e.id,
+            Expr::List(e) => e.id,
+            Expr::Index(e) => e.id,
+            Expr::IndexSet(e) => e.id,
+            Expr::Slice(e) => e.id,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary(e) => e.span,
+            Expr::Grouping(e) => e.span,
+            Expr::Literal(e) => e.span,
+            Expr::Unary(e) => e.span,
+            Expr::Variable(e) => e.span,
+            Expr::Assign(e) => e.span,
+            Expr::Logical(e) => e.span,
+            Expr::Call(e) => e.span,
+            Expr::Get(e) => e.span,
+            Expr::Set(e) => e.span,
+            Expr::This(e) => e.span,
+            Expr::Super(e) => e.span,
+            Expr::Lambda(e) => e.span,
+            Expr::List(e) => e.span,
+            Expr::Index(e) => e.span,
+            Expr::IndexSet(e) => e.span,
+            Expr::Slice(e) => e.span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Number(f64),
-    String(String),
+    /// Shared with the values created from it, which evaluating the literal doesn't copy.
+    String(Arc<str>),
     Boolean(bool),
     Nil,
 }
@@ -0,0 +1,77 @@
+//! Stable codes of diagnostics, for tools to match on rather than on messages, which may be
+//! reworded. `L00xx` are about names, `L01xx` syntax, `L02xx` scanning, `L03xx` runtime
+//! errors, `L04xx` compilation to bytecode, `L05xx` lint rules and `L09xx` bugs of the
+//! interpreter.
+
+/// A variable is used without being declared.
+pub const UNDEFINED_VARIABLE: &str = "L0001";
+/// A variable is declared twice in the same scope.
+pub const REDECLARED_VARIABLE: &str = "L0002";
+/// `this` is used outside of the methods of a class, or in a static method.
+pub const THIS_OUTSIDE_CLASS: &str = "L0003";
+/// `super` is used outside of the methods of a class with a superclass, or in a static method.
+pub const SUPER_OUTSIDE_SUBCLASS: &str = "L0004";
+/// A class names itself as its superclass.
+pub const INHERITS_FROM_ITSELF: &str = "L0005";
+/// `return` with a value in the `init` method of a class, which always returns `this`.
+pub const RETURN_FROM_INITIALIZER: &str = "L0006";
+/// A variable declared by `const` is assigned.
+pub const ASSIGN_TO_CONSTANT: &str = "L0007";
+
+pub const EXPECT_EXPRESSION: &str = "L0101";
+pub const EXPECT_SEMICOLON: &str = "L0102";
+/// A token other than `;` or a name is missing, e.g. `)` or `}`.
+pub const EXPECT_TOKEN: &str = "L0103";
+/// The name of a variable, function or parameter is missing.
+pub const EXPECT_NAME: &str = "L0104";
+pub const INVALID_ASSIGNMENT_TARGET: &str = "L0105";
+pub const TOO_MANY_PARAMETERS: &str = "L0106";
+pub const TOO_MANY_ARGUMENTS: &str = "L0107";
+/// Something follows an expression evaluated on its own, e.g. by the REPL.
+pub const EXPECT_END_OF_EXPRESSION: &str = "L0108";
+/// A literal token doesn't carry its value, which only happens with tokens made by hand.
+pub const EXPECT_LITERAL: &str = "L0109";
+
+pub const UNSUPPORTED_CHARACTER: &str = "L0201";
+pub const UNTERMINATED_STRING: &str = "L0202";
+/// A `/*` comment isn't closed by `*/` before the end of the source.
+pub const UNTERMINATED_COMMENT: &str = "L0203";
+/// A `_` in a number isn't between two digits, e.g. `1_` or `1_.5`.
+pub const MISPLACED_SEPARATOR: &str = "L0204";
+
+/// Any runtime error without a more specific code, such as one raised by a native function.
+pub const RUNTIME_ERROR: &str = "L0300";
+pub const DIVISION_BY_ZERO: &str = "L0301";
+/// An operator is applied to values of the wrong types, e.g. a list is indexed by a string.
+pub const INVALID_OPERANDS: &str = "L0302";
+pub const NOT_CALLABLE: &str = "L0303";
+/// A step or call depth limit set by the host is reached.
+pub const LIMIT_REACHED: &str = "L0304";
+pub const STACK_OVERFLOW: &str = "L0305";
+/// A condition isn't a boolean in strict mode.
+pub const EXPECTED_BOOLEAN: &str = "L0306";
+/// Definition or assignment of a global variable when the host froze them.
+pub const FROZEN_GLOBAL: &str = "L0307";
+/// A property read from an instance which has no such field or method, or from a class which
+/// has no such static method.
+pub const UNDEFINED_PROPERTY: &str = "L0308";
+/// A property of something other than an instance, or a field set on a class.
+pub const NOT_AN_INSTANCE: &str = "L0309";
+/// What follows `<` in a class declaration isn't a class.
+pub const SUPERCLASS_NOT_A_CLASS: &str = "L0310";
+/// Something other than a list is indexed, or other than a list or a string sliced, e.g.
+/// `nil[0]`.
+pub const NOT_A_LIST: &str = "L0311";
+/// An index isn't a whole number from 0 up to the length of the list, excluded, or a bound of a
+/// slice isn't a whole number.
+pub const INDEX_OUT_OF_RANGE: &str = "L0312";
+
+/// Code which only the tree-walking interpreter can run, e.g. a class declaration.
+pub const UNSUPPORTED_BY_VM: &str = "L0401";
+
+pub const EMPTY_BLOCK: &str = "L0501";
+pub const CONSTANT_CONDITION: &str = "L0502";
+pub const SHADOWING: &str = "L0503";
+pub const MIXED_EQUALITY: &str = "L0504";
+
+pub const INTERNAL_ERROR: &str = "L0901";
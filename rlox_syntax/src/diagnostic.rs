@@ -0,0 +1,316 @@
+use crate::Span;
+use std::fmt::Formatter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    /// Lowercase name, as in the header of rendered diagnostics.
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+
+    // ANSI escape sequence for the foreground color
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[31m",
+            Severity::Warning => "\x1b[33m",
+            Severity::Note => "\x1b[34m",
+        }
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Backtraces of deep recursions are cut, their outer frames rarely helping.
+const MAX_RENDERED_FRAMES: usize = 20;
+
+/// A secondary location related to a diagnostic, such as a previous declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A function call which was active when a runtime error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    pub function: String,
+    /// Line the function was running when the error unwound it.
+    pub line: usize,
+}
+
+/// A message pointing at a location in the source, produced by any phase from scanning to
+/// execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier of the kind of diagnostic, one of [`codes`](crate::codes).
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    /// Additional explanations shown after the source.
+    pub notes: Vec<String>,
+    /// Function calls the error went through, innermost first. Empty for errors at the top
+    /// level and for static errors.
+    pub backtrace: Vec<TraceFrame>,
+    /// Name of the source the span refers to, if known. Takes precedence over the name given
+    /// when rendering.
+    pub source_name: Option<String>,
+}
+
+impl Diagnostic {
+    /// Creates an error diagnostic.
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self::with_severity(Severity::Error, span, message)
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self::with_severity(Severity::Warning, span, message)
+    }
+
+    pub fn note(span: Span, message: impl Into<String>) -> Self {
+        Self::with_severity(Severity::Note, span, message)
+    }
+
+    pub fn with_severity(severity: Severity, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            backtrace: Vec::new(),
+            source_name: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(message.into());
+        self
+    }
+
+    /// Adds a "did you mean" note if one of `candidates` looks like what was meant by the
+    /// unknown `name`, see [`closest_name`](crate::closest_name).
+    pub fn with_name_suggestion<'a>(
+        self,
+        name: &str,
+        candidates: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        match crate::closest_name(name, candidates) {
+            Some(candidate) => self.with_note(format!("did you mean '{candidate}'?")),
+            None => self,
+        }
+    }
+
+    pub fn with_source_name(mut self, name: impl Into<String>) -> Self {
+        self.source_name = Some(name.into());
+        self
+    }
+
+    /// Renders the diagnostic with its code, the offending line of `source` and a caret
+    /// underline, followed by the lines of its labels, its notes and its backtrace:
+    ///
+    /// ```text
+    /// error[L0002]: Already a variable with this name in this scope: a
+    ///  --> script.lox:2:1
+    ///   |
+    /// 1 | var a = 1;
+    ///   | --- previously declared here
+    /// 2 | var a = 2;
+    ///   | ^~~
+    ///   = note: Use assignment to change the value.
+    /// ```
+    pub fn render(&self, source_name: &str, source: &str) -> String {
+        self.render_with_color(source_name, source, false)
+    }
+
+    /// Same as [`Diagnostic::render`], but colors the severity and the underline with ANSI
+    /// escape sequences if `color` is set.
+    pub fn render_with_color(&self, source_name: &str, source: &str, color: bool) -> String {
+        let (severity_color, label_color, bold, reset) = if color {
+            (self.severity.color(), Severity::Note.color(), BOLD, RESET)
+        } else {
+            ("", "", "", "")
+        };
+        let source_name = self.source_name.as_deref().unwrap_or(source_name);
+        let Span { line, column, .. } = self.span;
+        let code = self
+            .code
+            .map(|code| format!("[{code}]"))
+            .unwrap_or_default();
+        let mut lines = vec![format!(
+            "{bold}{severity_color}{}{code}{reset}{bold}: {}{reset}",
+            self.severity.label(),
+            self.message
+        )];
+
+        // Primary span first, so that it wins when a label is on the same line.
+        let mut snippets = vec![(self.span, '^', '~', severity_color, "")];
+        for label in &self.labels {
+            snippets.push((label.span, '-', '-', label_color, label.message.as_str()));
+        }
+        snippets.sort_by_key(|(span, ..)| span.line);
+        let width = snippets
+            .iter()
+            .map(|(span, ..)| span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+        lines.push(format!("{gutter}--> {source_name}:{line}:{column}"));
+
+        let mut shown_header = false;
+        let mut previous_line = None;
+        for (span, first, rest, mark_color, message) in snippets {
+            let Some(text) = source.lines().nth(span.line.wrapping_sub(1)) else {
+                continue;
+            };
+            if !shown_header {
+                lines.push(format!("{gutter} |"));
+                shown_header = true;
+            }
+            if previous_line != Some(span.line) {
+                lines.push(format!("{:>width$} | {text}", span.line));
+                previous_line = Some(span.line);
+            }
+            // Keep tabs so that the underline stays aligned with the text above.
+            let padding: String = text
+                .chars()
+                .take(span.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let available = text
+                .chars()
+                .count()
+                .saturating_sub(span.column.saturating_sub(1));
+            let marks = span.len.min(available).max(1);
+            let rest: String = std::iter::repeat_n(rest, marks - 1).collect();
+            let message = if message.is_empty() {
+                String::new()
+            } else {
+                format!(" {message}")
+            };
+            lines.push(format!(
+                "{gutter} | {padding}{mark_color}{first}{rest}{message}{reset}"
+            ));
+        }
+        for note in &self.notes {
+            lines.push(format!("{gutter} = {bold}note{reset}: {note}"));
+        }
+        for TraceFrame { function, line } in self.backtrace.iter().take(MAX_RENDERED_FRAMES) {
+            lines.push(format!("{gutter}   at {function} ({source_name}:{line})"));
+        }
+        if self.backtrace.len() > MAX_RENDERED_FRAMES {
+            let hidden = self.backtrace.len() - MAX_RENDERED_FRAMES;
+            lines.push(format!("{gutter}   ... {hidden} more frames"));
+        }
+        lines.join("\n")
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}, {}", self.span.line, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let diagnostic = Diagnostic::new(Span::new(2, 7, 3), "Undefined variable 'foo'.");
+        let expected = "\
+error: Undefined variable 'foo'.
+ --> test.lox:2:7
+  |
+2 | print foo;
+  |       ^~~";
+        assert_eq!(
+            expected,
+            diagnostic.render("test.lox", "var a;\nprint foo;\n")
+        );
+    }
+
+    #[test]
+    fn test_render_code() {
+        let diagnostic = Diagnostic::new(Span::new(1, 7, 3), "Undefined variable 'foo'.")
+            .with_code(crate::codes::UNDEFINED_VARIABLE);
+        let rendered = diagnostic.render("test.lox", "print foo;\n");
+        assert!(rendered.starts_with("error[L0001]: Undefined variable 'foo'.\n"));
+    }
+
+    #[test]
+    fn test_render_with_color() {
+        let diagnostic = Diagnostic::warning(Span::new(1, 1, 1), "Unused.");
+        let rendered = diagnostic.render_with_color("test.lox", "a;", true);
+        assert!(rendered.starts_with("\x1b[1m\x1b[33mwarning\x1b[0m\x1b[1m: Unused.\x1b[0m\n"));
+        assert!(rendered.ends_with(" | \x1b[33m^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_labels_and_notes() {
+        let diagnostic = Diagnostic::new(Span::new(3, 5, 1), "Already a variable.")
+            .with_label(Span::new(1, 5, 1), "first declared here")
+            .with_note("Use assignment instead.");
+        let expected = "\
+error: Already a variable.
+ --> test.lox:3:5
+  |
+1 | var a = 1;
+  |     - first declared here
+3 | var a = 2;
+  |     ^
+  = note: Use assignment instead.";
+        assert_eq!(
+            expected,
+            diagnostic.render("test.lox", "var a = 1;\n\nvar a = 2;\n")
+        );
+    }
+
+    #[test]
+    fn test_render_backtrace() {
+        let mut diagnostic = Diagnostic::new(Span::new(2, 12, 1), "Divided by zero");
+        diagnostic.backtrace = vec![
+            TraceFrame {
+                function: "inc".to_owned(),
+                line: 2,
+            },
+            TraceFrame {
+                function: "counter".to_owned(),
+                line: 5,
+            },
+        ];
+        let rendered = diagnostic.render("script.lox", "fun inc() {\n  return 1 / 0;\n}\n");
+        assert!(rendered.ends_with(
+            "  |            ^\n    at inc (script.lox:2)\n    at counter (script.lox:5)"
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::ast::*;
+use crate::printer::AstPrinter;
+use crate::span::Span;
+use std::slice;
+use std::sync::PoisonError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// The node is in both programs but differs, not counting the nodes nested in it, which
+    /// have changes of their own.
+    Changed,
+}
+
+/// A statement which differs between two versions of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AstChange {
+    pub kind: ChangeKind,
+    /// Kind of the statement, as named by the [`AstPrinter`], e.g. `fun` or `print`.
+    pub node: &'static str,
+    /// Where the statement is in the old program, `None` if it was added.
+    pub old: Option<Span>,
+    /// Where the statement is in the new program, `None` if it was removed.
+    pub new: Option<Span>,
+}
+
+/// Structural differences between `old` and `new`, in the order of the statements. Statements
+/// are compared ignoring their positions, so moving code to other lines, reformatting it or
+/// changing comments makes no difference.
+///
+/// Statements which aren't found unchanged in the other program are paired with one of the
+/// same kind, and for functions and variables the same name, to be reported as changed. The
+/// statements nested in paired blocks, functions, `if` and `while` are compared in turn, so
+/// that a change deep in a function is reported where it is rather than as a change of the
+/// whole function.
+pub fn diff_programs(old: &[Statement], new: &[Statement]) -> Vec<AstChange> {
+    let mut differ = Differ {
+        printer: AstPrinter::new(false),
+        changes: Vec::new(),
+    };
+    differ.statements(old, new);
+    differ.changes
+}
+
+struct Differ {
+    printer: AstPrinter,
+    changes: Vec<AstChange>,
+}
+
+impl Differ {
+    fn statements(&mut self, old: &[Statement], new: &[Statement]) {
+        let old_keys: Vec<_> = old
+            .iter()
+            .map(|s| self.printer.print_statement(s))
+            .collect();
+        let new_keys: Vec<_> = new
+            .iter()
+            .map(|s| self.printer.print_statement(s))
+            .collect();
+
+        // Longest common subsequence of unchanged statements, lengths[i][j] being the one of
+        // old[i..] and new[j..]
+        let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lengths[i][j] = if old_keys[i] == new_keys[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old_keys[i] == new_keys[j] {
+                self.pair(&removed, &added);
+                removed.clear();
+                added.clear();
+                i += 1;
+                j += 1;
+            } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+                removed.push(&old[i]);
+                i += 1;
+            } else {
+                added.push(&new[j]);
+                j += 1;
+            }
+        }
+        self.pair(&removed, &added);
+    }
+
+    /// Reports the statements replaced between two unchanged ones.
+    fn pair(&mut self, removed: &[&Statement], added: &[&Statement]) {
+        let mut unpaired: Vec<Option<&Statement>> = added.iter().copied().map(Some).collect();
+        for old in removed {
+            let counterpart = unpaired
+                .iter_mut()
+                .find(|new| new.is_some_and(|new| same_identity(old, new)))
+                .and_then(Option::take);
+            match counterpart {
+                Some(new) => self.statement(old, new),
+                None => self.report(ChangeKind::Removed, old, Some(old.span()), None),
+            }
+        }
+        for new in unpaired.into_iter().flatten() {
+            self.report(ChangeKind::Added, new, None, Some(new.span()));
+        }
+    }
+
+    /// Compares statements of the same kind.
+    fn statement(&mut self, old: &Statement, new: &Statement) {
+        let changed = |differ: &mut Self| {
+            differ.report(ChangeKind::Changed, old, Some(old.span()), Some(new.span()))
+        };
+        match (old, new) {
+            (Statement::Block(o), Statement::Block(n)) => {
+                self.statements(&o.statements, &n.statements)
+            }
+            (Statement::Function(o), Statement::Function(n)) => {
+                if o.params != n.params || o.variadic != n.variadic || o.is_static != n.is_static {
+                    changed(self);
+                }
+                let old_body = o.body.read().unwrap_or_else(PoisonError::into_inner);
+                let new_body = n.body.read().unwrap_or_else(PoisonError::into_inner);
+                self.branch(&old_body, &new_body);
+            }
+            (Statement::Class(o), Statement::Class(n)) => {
+                let same_superclass = match (&o.superclass, &n.superclass) {
+                    (Some(o), Some(n)) => self.same_expr(o, n),
+                    (o, n) => o.is_none() && n.is_none(),
+                };
+                if !same_superclass {
+                    changed(self);
+                }
+                self.statements(&o.methods, &n.methods)
+            }
+            (Statement::If(o), Statement::If(n)) => {
+                if !self.same_expr(&o.condition, &n.condition) {
+                    changed(self);
+                }
+                self.branch(&o.then_branch, &n.then_branch);
+                match (&o.else_branch, &n.else_branch) {
+                    (Some(o), Some(n)) => self.branch(o, n),
+                    (Some(o), None) => self.report(ChangeKind::Removed, o, Some(o.span()), None),
+                    (None, Some(n)) => self.report(ChangeKind::Added, n, None, Some(n.span())),
+                    (None, None) => {}
+                }
+            }
+            (Statement::While(o), Statement::While(n)) => {
+                if !self.same_expr(&o.condition, &n.condition) {
+                    changed(self);
+                }
+                self.branch(&o.body, &n.body);
+            }
+            _ => changed(self),
+        }
+    }
+
+    fn branch(&mut self, old: &Statement, new: &Statement) {
+        self.statements(slice::from_ref(old), slice::from_ref(new));
+    }
+
+    fn same_expr(&self, old: &Expr, new: &Expr) -> bool {
+        self.printer.print_expr(old) == self.printer.print_expr(new)
+    }
+
+    fn report(&mut self, kind: ChangeKind, stmt: &Statement, old: Option<Span>, new: Option<Span>) {
+        self.changes.push(AstChange {
+            kind,
+            node: node_kind(stmt),
+            old,
+            new,
+        });
+    }
+}
+
+/// Whether two statements are versions of the same one.
+fn same_identity(old: &Statement, new: &Statement) -> bool {
+    match (old, new) {
+        (Statement::Function(o), Statement::Function(n)) => o.name == n.name,
+        (Statement::VariableDecl(o), Statement::VariableDecl(n)) => {
+            o.name == n.name && o.constant == n.constant
+        }
+        (Statement::Class(o), Statement::Class(n)) => o.name == n.name,
+        _ => node_kind(old) == node_kind(new),
+    }
+}
+
+fn node_kind(stmt: &Statement) -> &'static str {
+    match stmt {
+        Statement::Expression(_) => "expr",
+        Statement::Print(_) => "print",
+        Statement::VariableDecl(_) => "var",
+        Statement::Block(_) => "block",
+        Statement::If(_) => "if",
+        Statement::While(_) => "while",
+        Statement::Function(_) => "fun",
+        Statement::Return(_) => "return",
+        Statement::Class(_) => "class",
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{Diagnostic, Span};
+use std::fmt::Formatter;
+
+/// An error of any phase, telling which phase failed and where. Diagnostics are boxed to keep
+/// results small.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxError {
+    Scan(Box<Diagnostic>),
+    Parse(Box<Diagnostic>),
+    Resolve(Box<Diagnostic>),
+    /// Code which the bytecode compiler doesn't support.
+    Compile(Box<Diagnostic>),
+    Runtime(Box<Diagnostic>),
+    /// A bug of the interpreter or of a host function, such as a panic, rather than of the script.
+    Internal(Box<Diagnostic>),
+}
+
+impl LoxError {
+    pub fn diagnostic(&self) -> &Diagnostic {
+        match self {
+            LoxError::Scan(d)
+            | LoxError::Parse(d)
+            | LoxError::Resolve(d)
+            | LoxError::Compile(d)
+            | LoxError::Runtime(d)
+            | LoxError::Internal(d) => d,
+        }
+    }
+
+    /// Attributes the error to the source `name`, unless it already belongs to one.
+    pub fn with_source_name(mut self, name: &str) -> Self {
+        let (LoxError::Scan(d)
+        | LoxError::Parse(d)
+        | LoxError::Resolve(d)
+        | LoxError::Compile(d)
+        | LoxError::Runtime(d)
+        | LoxError::Internal(d)) = &mut self;
+        d.source_name.get_or_insert_with(|| name.to_owned());
+        self
+    }
+
+    pub fn span(&self) -> Span {
+        self.diagnostic().span
+    }
+
+    /// Whether the error was found before anything was executed.
+    pub fn is_static(&self) -> bool {
+        !matches!(self, LoxError::Runtime(_) | LoxError::Internal(_))
+    }
+}
+
+impl std::fmt::Display for LoxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.diagnostic())
+    }
+}
+
+impl std::error::Error for LoxError {}
@@ -1,6 +1,25 @@
 mod ast;
+pub mod codes;
+mod diagnostic;
+mod diff;
+mod error;
+mod node_map;
+mod printer;
+mod query;
+mod span;
+mod suggest;
 mod syntax_node;
 mod token;
+mod unparse;
 
 pub use ast::*;
+pub use diagnostic::*;
+pub use diff::*;
+pub use error::*;
+pub use node_map::*;
+pub use printer::*;
+pub use query::*;
+pub use span::*;
+pub use suggest::*;
+pub use syntax_node::*;
 pub use token::*;
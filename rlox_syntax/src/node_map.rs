@@ -0,0 +1,100 @@
+use crate::ast::*;
+use crate::query::Node;
+use crate::span::Span;
+use std::collections::HashMap;
+
+/// Side table holding a `T` per syntax node, keyed by the ids given by
+/// [`SyntaxNode::generate_id`](crate::SyntaxNode::generate_id), so that passes can attach data
+/// to nodes without mutating the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeMap<T> {
+    entries: HashMap<usize, T>,
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of the node `id`, returning the previous one if any.
+    pub fn insert(&mut self, id: usize, value: T) -> Option<T> {
+        self.entries.insert(id, value)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&T> {
+        self.entries.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut T> {
+        self.entries.get_mut(&id)
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<T> {
+        self.entries.remove(&id)
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Ids and values, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.entries.iter().map(|(id, value)| (*id, value))
+    }
+}
+
+/// What [`index_nodes`] records about a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeInfo {
+    /// Id of the node containing this one, `None` for top-level statements.
+    pub parent: Option<usize>,
+    pub span: Span,
+}
+
+impl NodeMap<NodeInfo> {
+    /// Ids of the nodes containing `id`, from its parent up to a top-level statement.
+    pub fn ancestors(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.get(id).and_then(|info| info.parent), |id| {
+            self.get(*id).and_then(|info| info.parent)
+        })
+    }
+}
+
+/// Parent and span of every node of `statements`. The body of a function is the `Block`
+/// written there, whose parent is the function.
+pub fn index_nodes(statements: &[Statement]) -> NodeMap<NodeInfo> {
+    let mut map = NodeMap::new();
+    for s in statements {
+        index_node(&mut map, Node::Statement(s), None);
+    }
+    map
+}
+
+fn index_node(map: &mut NodeMap<NodeInfo>, node: Node, parent: Option<usize>) {
+    let id = node.id();
+    map.insert(
+        id,
+        NodeInfo {
+            parent,
+            span: node.span(),
+        },
+    );
+
+    node.for_each_child(|child| index_node(map, child, Some(id)));
+}
@@ -0,0 +1,254 @@
+use crate::ast::*;
+use crate::token::TokenKind;
+use std::fmt::Write;
+use std::sync::PoisonError;
+
+/// Renders syntax trees as S-expressions, e.g. `(print (+ a 1))`.
+///
+/// Nested statements are put on their own lines and indented, while expressions stay inline.
+#[derive(Default)]
+pub struct AstPrinter {
+    /// Annotate variable references with the number of scopes escalated to resolve them,
+    /// e.g. `a@2`. Only meaningful after the resolver has run.
+    pub show_resolution: bool,
+}
+
+impl AstPrinter {
+    pub fn new(show_resolution: bool) -> Self {
+        Self { show_resolution }
+    }
+
+    pub fn print_program(&self, statements: &[Statement]) -> String {
+        let mut out = String::new();
+        for s in statements {
+            self.write_statement(&mut out, s, 0);
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn print_statement(&self, stmt: &Statement) -> String {
+        let mut out = String::new();
+        self.write_statement(&mut out, stmt, 0);
+        out
+    }
+
+    pub fn print_expr(&self, expr: &Expr) -> String {
+        let mut out = String::new();
+        self.write_expr(&mut out, expr);
+        out
+    }
+
+    fn write_statement(&self, out: &mut String, stmt: &Statement, depth: usize) {
+        match stmt {
+            Statement::Expression(s) => {
+                out.push_str("(expr ");
+                self.write_expr(out, &s.expr);
+                out.push(')');
+            }
+            Statement::Print(s) => {
+                out.push_str("(print");
+                for expr in &s.exprs {
+                    out.push(' ');
+                    self.write_expr(out, expr);
+                }
+                out.push(')');
+            }
+            Statement::VariableDecl(s) => {
+                write!(out, "({} {}", s.keyword(), s.name).unwrap();
+                if let Some(expr) = &s.expr {
+                    out.push(' ');
+                    self.write_expr(out, expr);
+                }
+                out.push(')');
+            }
+            Statement::Block(s) => {
+                out.push_str("(block");
+                for s in &s.statements {
+                    self.write_nested(out, s, depth + 1);
+                }
+                out.push(')');
+            }
+            Statement::If(s) => {
+                out.push_str("(if ");
+                self.write_expr(out, &s.condition);
+                self.write_nested(out, &s.then_branch, depth + 1);
+                if let Some(else_branch) = &s.else_branch {
+                    self.write_nested(out, else_branch, depth + 1);
+                }
+                out.push(')');
+            }
+            Statement::While(s) => {
+                out.push_str("(while ");
+                self.write_expr(out, &s.condition);
+                self.write_nested(out, &s.body, depth + 1);
+                out.push(')');
+            }
+            Statement::Function(s) => {
+                let keyword = if s.is_static { "static fun" } else { "fun" };
+                write!(
+                    out,
+                    "({keyword} {} ({})",
+                    s.name,
+                    s.written_params().join(" ")
+                )
+                .unwrap();
+                let body = s.body.read().unwrap_or_else(PoisonError::into_inner);
+                self.write_nested(out, &body, depth + 1);
+                out.push(')');
+            }
+            Statement::Return(s) => {
+                out.push_str("(return");
+                if let Some(value) = &s.value {
+                    out.push(' ');
+                    self.write_expr(out, value);
+                }
+                out.push(')');
+            }
+            Statement::Class(s) => {
+                write!(out, "(class {}", s.name).unwrap();
+                if let Some(superclass) = &s.superclass {
+                    out.push_str(" < ");
+                    self.write_expr(out, superclass);
+                }
+                for method in &s.methods {
+                    self.write_nested(out, method, depth + 1);
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    fn write_nested(&self, out: &mut String, stmt: &Statement, depth: usize) {
+        out.push('\n');
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+        self.write_statement(out, stmt, depth);
+    }
+
+    fn write_expr(&self, out: &mut String, expr: &Expr) {
+        match expr {
+            Expr::Binary(e) => self.write_operation(out, e.operator, &[&e.left, &e.right]),
+            Expr::Logical(e) => self.write_operation(out, e.operator, &[&e.left, &e.right]),
+            Expr::Unary(e) => self.write_operation(out, e.operator, &[&e.right]),
+            Expr::Grouping(e) => {
+                out.push_str("(group ");
+                self.write_expr(out, &e.expr);
+                out.push(')');
+            }
+            Expr::Literal(e) => match &e.literal {
+                Literal::Number(n) => write!(out, "{n}").unwrap(),
+                Literal::String(s) => write!(out, "{s:?}").unwrap(),
+                Literal::Boolean(b) => write!(out, "{b}").unwrap(),
+                Literal::Nil => out.push_str("nil"),
+            },
+            Expr::Variable(e) => {
+                out.push_str(&e.name);
+                self.write_resolution(out, e.resolution);
+            }
+            Expr::Assign(e) => {
+                write!(out, "(= {}", e.name).unwrap();
+                self.write_resolution(out, e.resolution);
+                out.push(' ');
+                self.write_expr(out, &e.value);
+                out.push(')');
+            }
+            Expr::Call(e) => {
+                out.push_str("(call ");
+                self.write_expr(out, &e.callee);
+                for arg in &e.arguments {
+                    out.push(' ');
+                    self.write_expr(out, arg);
+                }
+                out.push(')');
+            }
+            Expr::Get(e) => {
+                out.push_str(if e.optional { "(get? " } else { "(get " });
+                self.write_expr(out, &e.object);
+                write!(out, " {})", e.name).unwrap();
+            }
+            Expr::Set(e) => {
+                out.push_str("(set ");
+                self.write_expr(out, &e.object);
+                write!(out, " {} ", e.name).unwrap();
+                self.write_expr(out, &e.value);
+                out.push(')');
+            }
+            Expr::This(e) => {
+                out.push_str("this");
+                self.write_resolution(out, e.resolution);
+            }
+            Expr::Super(e) => {
+                out.push_str("(super");
+                self.write_resolution(out, e.resolution);
+                write!(out, " {})", e.method).unwrap();
+            }
+            Expr::List(e) => {
+                out.push_str("(list");
+                for element in &e.elements {
+                    out.push(' ');
+                    self.write_expr(out, element);
+                }
+                out.push(')');
+            }
+            Expr::Index(e) => {
+                out.push_str("(index ");
+                self.write_expr(out, &e.object);
+                out.push(' ');
+                self.write_expr(out, &e.index);
+                out.push(')');
+            }
+            // Omitted bounds are written `_`
+            Expr::Slice(e) => {
+                out.push_str("(slice ");
+                self.write_expr(out, &e.object);
+                for bound in [&e.start, &e.end] {
+                    out.push(' ');
+                    match bound {
+                        Some(bound) => self.write_expr(out, bound),
+                        None => out.push('_'),
+                    }
+                }
+                out.push(')');
+            }
+            Expr::IndexSet(e) => {
+                out.push_str("(index-set ");
+                self.write_expr(out, &e.object);
+                out.push(' ');
+                self.write_expr(out, &e.index);
+                out.push(' ');
+                self.write_expr(out, &e.value);
+                out.push(')');
+            }
+            Expr::Lambda(e) => {
+                write!(out, "(lambda ({}) ", e.written_params().join(" ")).unwrap();
+                let body = e.body.read().unwrap_or_else(PoisonError::into_inner);
+                match expr::Lambda::returned(&body) {
+                    Some(value) => self.write_expr(out, value),
+                    None => self.write_statement(out, &body, 0),
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    fn write_operation(&self, out: &mut String, operator: TokenKind, operands: &[&Expr]) {
+        write!(out, "({}", operator_lexeme(operator)).unwrap();
+        for operand in operands {
+            out.push(' ');
+            self.write_expr(out, operand);
+        }
+        out.push(')');
+    }
+
+    fn write_resolution(&self, out: &mut String, resolution: usize) {
+        if self.show_resolution {
+            write!(out, "@{resolution}").unwrap();
+        }
+    }
+}
+
+pub(crate) fn operator_lexeme(kind: TokenKind) -> &'static str {
+    kind.lexeme().unwrap_or("?")
+}
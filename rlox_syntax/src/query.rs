@@ -0,0 +1,380 @@
+//! CSS-like selectors over syntax trees, to find patterns without writing a visitor:
+//!
+//! ```text
+//! Function > Return Variable[name=x]
+//! ```
+//!
+//! matches the variables named `x` anywhere in the value of a `return` statement which is
+//! directly in the body of a function. A selector is a sequence of node kinds, or `*` for any
+//! node, each with optional `[attribute=value]` filters, separated by whitespace for descendants
+//! or by `>` for direct children. The statements of a function's body are its children, while
+//! the body of `if` and `while` is the statement written there, often a `Block`, and the methods
+//! of a class are `Function` children of the `Class`, after the `Variable` of its superclass.
+//! The child of a `Lambda` is the `Block` returning the expression after `->`.
+//! Attributes are:
+//!
+//! - `name`, of `Function`, `Class`, `VariableDecl`, `Variable` and `Assign`, the property of
+//!   `Get` and `Set`, and the method of `Super`.
+//! - `operator`, the lexeme of the operator of `Binary`, `Logical` and `Unary`, e.g. `+`.
+//! - `value`, of `Literal`, e.g. `1`, `true`, `nil` or the text of a string without quotes.
+//! - `arity`, the number of parameters of `Function` and `Lambda` or of arguments of `Call`.
+//! - `static`, `true` for the `Function` of a static method and `false` otherwise.
+//! - `const`, `true` for the `VariableDecl` of a constant and `false` otherwise.
+//! - `optional`, `true` for the `Get` of `?.` and `false` otherwise.
+
+use crate::ast::*;
+use crate::printer::operator_lexeme;
+use crate::span::Span;
+use std::sync::PoisonError;
+
+const KINDS: &[&str] = &[
+    "Expression",
+    "Print",
+    "VariableDecl",
+    "Block",
+    "If",
+    "While",
+    "Function",
+    "Return",
+    "Class",
+    "Binary",
+    "Grouping",
+    "Literal",
+    "Unary",
+    "Variable",
+    "Assign",
+    "Logical",
+    "Call",
+    "Get",
+    "Set",
+    "This",
+    "Super",
+    "Lambda",
+    "List",
+    "Index",
+    "IndexSet",
+    "Slice",
+];
+
+/// A node of either kind of syntax tree.
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Statement(&'a Statement),
+    Expr(&'a Expr),
+}
+
+impl<'a> Node<'a> {
+    /// Name of the node type, as used by selectors, e.g. `VariableDecl`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Node::Statement(s) => match s {
+                Statement::Expression(_) => "Expression",
+                Statement::Print(_) => "Print",
+                Statement::VariableDecl(_) => "VariableDecl",
+                Statement::Block(_) => "Block",
+                Statement::If(_) => "If",
+                Statement::While(_) => "While",
+                Statement::Function(_) => "Function",
+                Statement::Return(_) => "Return",
+                Statement::Class(_) => "Class",
+            },
+            Node::Expr(e) => match e {
+                Expr::Binary(_) => "Binary",
+                Expr::Grouping(_) => "Grouping",
+                Expr::Literal(_) => "Literal",
+                Expr::Unary(_) => "Unary",
+                Expr::Variable(_) => "Variable",
+                Expr::Assign(_) => "Assign",
+                Expr::Logical(_) => "Logical",
+                Expr::Call(_) => "Call",
+                Expr::Get(_) => "Get",
+                Expr::Set(_) => "Set",
+                Expr::This(_) => "This",
+                Expr::Super(_) => "Super",
+                Expr::Lambda(_) => "Lambda",
+                Expr::List(_) => "List",
+                Expr::Index(_) => "Index",
+                Expr::IndexSet(_) => "IndexSet",
+                Expr::Slice(_) => "Slice",
+            },
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        match self {
+            Node::Statement(s) => s.id(),
+            Node::Expr(e) => e.id(),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Statement(s) => s.span(),
+            Node::Expr(e) => e.span(),
+        }
+    }
+
+    /// The nodes directly in this one, in source order. The body of a function or lambda is
+    /// behind a lock, so it isn't among them; [`Node::for_each_child`] reaches it too.
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> {
+        let mut children = Vec::new();
+        match *self {
+            Node::Statement(s) => match s {
+                Statement::Expression(s) => children.push(Node::Expr(&s.expr)),
+                Statement::Print(s) => children.extend(s.exprs.iter().map(Node::Expr)),
+                Statement::VariableDecl(s) => children.extend(s.expr.as_ref().map(Node::Expr)),
+                Statement::Block(s) => children.extend(s.statements.iter().map(Node::Statement)),
+                Statement::If(s) => {
+                    children.push(Node::Expr(&s.condition));
+                    children.push(Node::Statement(&s.then_branch));
+                    children.extend(s.else_branch.as_ref().map(Node::Statement));
+                }
+                Statement::While(s) => {
+                    children.push(Node::Expr(&s.condition));
+                    children.push(Node::Statement(&s.body));
+                }
+                Statement::Function(_) => {}
+                Statement::Return(s) => children.extend(s.value.as_ref().map(Node::Expr)),
+                Statement::Class(s) => {
+                    children.extend(s.superclass.as_ref().map(Node::Expr));
+                    children.extend(s.methods.iter().map(Node::Statement));
+                }
+            },
+            Node::Expr(e) => match e {
+                Expr::Binary(e) => children.extend([Node::Expr(&e.left), Node::Expr(&e.right)]),
+                Expr::Logical(e) => children.extend([Node::Expr(&e.left), Node::Expr(&e.right)]),
+                Expr::Grouping(e) => children.push(Node::Expr(&e.expr)),
+                Expr::Unary(e) => children.push(Node::Expr(&e.right)),
+                Expr::Assign(e) => children.push(Node::Expr(&e.value)),
+                Expr::Call(e) => {
+                    children.push(Node::Expr(&e.callee));
+                    children.extend(e.arguments.iter().map(Node::Expr));
+                }
+                Expr::Get(e) => children.push(Node::Expr(&e.object)),
+                Expr::Set(e) => children.extend([Node::Expr(&e.object), Node::Expr(&e.value)]),
+                Expr::List(e) => children.extend(e.elements.iter().map(Node::Expr)),
+                Expr::Index(e) => children.extend([Node::Expr(&e.object), Node::Expr(&e.index)]),
+                Expr::Slice(e) => {
+                    children.push(Node::Expr(&e.object));
+                    children.extend(e.start.iter().chain(&e.end).map(Node::Expr));
+                }
+                Expr::IndexSet(e) => children.extend([
+                    Node::Expr(&e.object),
+                    Node::Expr(&e.index),
+                    Node::Expr(&e.value),
+                ]),
+                Expr::Literal(_)
+                | Expr::Variable(_)
+                | Expr::This(_)
+                | Expr::Super(_)
+                | Expr::Lambda(_) => {}
+            },
+        }
+        children.into_iter()
+    }
+
+    /// Calls `f` with each of the nodes directly in this one, in source order, including the
+    /// body of a function or lambda, which stays read-locked meanwhile.
+    pub fn for_each_child(&self, mut f: impl FnMut(Node<'_>)) {
+        match self {
+            Node::Statement(Statement::Function(s)) => {
+                let body = s.body.read().unwrap_or_else(PoisonError::into_inner);
+                f(Node::Statement(&body));
+            }
+            Node::Expr(Expr::Lambda(e)) => {
+                let body = e.body.read().unwrap_or_else(PoisonError::into_inner);
+                f(Node::Statement(&body));
+            }
+            node => node.children().for_each(f),
+        }
+    }
+
+    /// Value of a selector attribute, `None` if the node doesn't have it.
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        match (name, self) {
+            ("name", Node::Statement(Statement::Function(s))) => Some(s.name.clone()),
+            ("name", Node::Statement(Statement::Class(s))) => Some(s.name.clone()),
+            ("name", Node::Statement(Statement::VariableDecl(s))) => Some(s.name.clone()),
+            ("name", Node::Expr(Expr::Variable(e))) => Some(e.name.clone()),
+            ("name", Node::Expr(Expr::Assign(e))) => Some(e.name.clone()),
+            ("name", Node::Expr(Expr::Get(e))) => Some(e.name.clone()),
+            ("name", Node::Expr(Expr::Set(e))) => Some(e.name.clone()),
+            ("name", Node::Expr(Expr::Super(e))) => Some(e.method.clone()),
+            ("operator", Node::Expr(Expr::Binary(e))) => Some(operator_lexeme(e.operator).into()),
+            ("operator", Node::Expr(Expr::Logical(e))) => Some(operator_lexeme(e.operator).into()),
+            ("operator", Node::Expr(Expr::Unary(e))) => Some(operator_lexeme(e.operator).into()),
+            ("value", Node::Expr(Expr::Literal(e))) => Some(match &e.literal {
+                Literal::Number(n) => n.to_string(),
+                Literal::String(s) => s.to_string(),
+                Literal::Boolean(b) => b.to_string(),
+                Literal::Nil => "nil".to_owned(),
+            }),
+            ("arity", Node::Statement(Statement::Function(s))) => Some(s.params.len().to_string()),
+            ("arity", Node::Expr(Expr::Lambda(e))) => Some(e.params.len().to_string()),
+            ("arity", Node::Expr(Expr::Call(e))) => Some(e.arguments.len().to_string()),
+            ("static", Node::Statement(Statement::Function(s))) => Some(s.is_static.to_string()),
+            ("const", Node::Statement(Statement::VariableDecl(s))) => Some(s.constant.to_string()),
+            ("optional", Node::Expr(Expr::Get(e))) => Some(e.optional.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// A node found by [`query`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryMatch {
+    pub kind: &'static str,
+    pub span: Span,
+}
+
+/// The nodes of `statements` matching `selector`, in source order, or why the selector is
+/// invalid. Use [`Selector::for_each`] to get at the nodes themselves.
+pub fn query(statements: &[Statement], selector: &str) -> Result<Vec<QueryMatch>, String> {
+    let selector = Selector::parse(selector)?;
+    let mut matches = Vec::new();
+    selector.for_each(statements, |node| {
+        matches.push(QueryMatch {
+            kind: node.kind(),
+            span: node.span(),
+        })
+    });
+    Ok(matches)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Compound {
+    // `None` for `*`
+    kind: Option<&'static str>,
+    attributes: Vec<(String, String)>,
+    // How this relates to the previous compound, ignored for the first one
+    combinator: Combinator,
+}
+
+impl Compound {
+    fn matches(&self, node: &Node) -> bool {
+        self.kind.is_none_or(|kind| kind == node.kind())
+            && self
+                .attributes
+                .iter()
+                .all(|(name, value)| node.attribute(name).as_ref() == Some(value))
+    }
+}
+
+/// A parsed selector, reusable across trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    compounds: Vec<Compound>,
+}
+
+// Sets of compound indices, which limits how long selectors can be
+type Matched = u64;
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, String> {
+        let mut compounds = Vec::new();
+        let mut combinator = Combinator::Descendant;
+        let mut chars = selector.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '>' {
+                if compounds.is_empty() || combinator == Combinator::Child {
+                    return Err("Expect a node kind before '>'.".to_owned());
+                }
+                combinator = Combinator::Child;
+                chars.next();
+            } else if c == '*' || c.is_alphabetic() {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| *c == '*' || c.is_alphanumeric()) {
+                    name.push(c);
+                }
+                let kind = match name.as_str() {
+                    "*" => None,
+                    _ => Some(
+                        *KINDS
+                            .iter()
+                            .find(|kind| **kind == name)
+                            .ok_or_else(|| format!("Unknown node kind '{name}'."))?,
+                    ),
+                };
+                let mut attributes = Vec::new();
+                while chars.next_if_eq(&'[').is_some() {
+                    let mut attribute = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(c) => attribute.push(c),
+                            None => return Err("Expect ']' after attribute.".to_owned()),
+                        }
+                    }
+                    let (name, value) = attribute
+                        .split_once('=')
+                        .ok_or_else(|| format!("Expect '=' in attribute '{attribute}'."))?;
+                    attributes.push((name.trim().to_owned(), value.trim().to_owned()));
+                }
+                compounds.push(Compound {
+                    kind,
+                    attributes,
+                    combinator,
+                });
+                combinator = Combinator::Descendant;
+            } else {
+                return Err(format!("Unexpected character '{c}' in selector."));
+            }
+        }
+        if compounds.is_empty() || combinator == Combinator::Child {
+            return Err("Expect a node kind at the end of the selector.".to_owned());
+        }
+        if compounds.len() > Matched::BITS as usize {
+            return Err("Selector is too long.".to_owned());
+        }
+        Ok(Self { compounds })
+    }
+
+    /// Calls `f` with every node of `statements` matching the selector, in source order.
+    pub fn for_each(&self, statements: &[Statement], mut f: impl FnMut(Node<'_>)) {
+        for s in statements {
+            self.visit(Node::Statement(s), 0, 0, &mut f);
+        }
+    }
+
+    /// `parent` and `ancestors` are the compounds matched, along with all of the ones before
+    /// them, by the parent of `node` and by any of its ancestors.
+    fn visit(&self, node: Node, parent: Matched, ancestors: Matched, f: &mut dyn FnMut(Node<'_>)) {
+        let mut matched: Matched = 0;
+        for (i, compound) in self.compounds.iter().enumerate() {
+            let follows = i == 0
+                || match compound.combinator {
+                    Combinator::Child => parent & (1 << (i - 1)) != 0,
+                    Combinator::Descendant => ancestors & (1 << (i - 1)) != 0,
+                };
+            if follows && compound.matches(&node) {
+                matched |= 1 << i;
+            }
+        }
+        if matched & (1 << (self.compounds.len() - 1)) != 0 {
+            f(node);
+        }
+
+        let ancestors = ancestors | matched;
+        let mut visit = |child: Node| self.visit(child, matched, ancestors, f);
+        match node {
+            Node::Statement(Statement::Function(s)) => {
+                let body = s.body.read().unwrap_or_else(PoisonError::into_inner);
+                match &*body {
+                    Statement::Block(b) => {
+                        b.statements.iter().for_each(|s| visit(Node::Statement(s)))
+                    }
+                    body => visit(Node::Statement(body)),
+                }
+            }
+            node => node.children().for_each(visit),
+        }
+    }
+}
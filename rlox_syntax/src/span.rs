@@ -0,0 +1,44 @@
+/// Location of a piece of source code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    /// 1-based line of the first character.
+    pub line: usize,
+    /// 1-based column of the first character.
+    pub column: usize,
+    /// Number of characters covered.
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, len: usize) -> Self {
+        Self { line, column, len }
+    }
+}
+
+/// Name and starting position of a source which is part of something larger, such as a script
+/// embedded in a document, so that locations are reported in terms of the whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceOrigin {
+    pub name: String,
+    /// 1-based line of the first character.
+    pub line: usize,
+    /// 1-based column of the first character. Following lines start at column 1.
+    pub column: usize,
+}
+
+impl SourceOrigin {
+    /// Origin of a source starting at the top of `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn at(mut self, line: usize, column: usize) -> Self {
+        self.line = line;
+        self.column = column;
+        self
+    }
+}
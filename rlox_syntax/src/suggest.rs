@@ -0,0 +1,71 @@
+/// The candidate closest to `name`, if it's close enough to be what was meant by a typo: at most
+/// a third of the characters of `name` differ, one for short names, and they have a character in
+/// common, so that a one-letter name isn't taken for any other. Ties go to the first candidate in
+/// alphabetical order, so that the suggestion doesn't depend on the order of `candidates`.
+pub fn closest_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name && candidate.chars().any(|c| name.contains(c)))
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// How many characters must be inserted, removed or replaced, or adjacent characters swapped,
+/// to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance from the first i characters of `a` to the first j of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replaced = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = replaced
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(0, edit_distance("count", "count"));
+        assert_eq!(1, edit_distance("cout", "count"));
+        assert_eq!(1, edit_distance("conut", "count"));
+        assert_eq!(3, edit_distance("", "abc"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+
+    #[test]
+    fn test_closest_name() {
+        let names = ["count", "counter", "clock", "print"];
+        assert_eq!(Some("count"), closest_name("cout", names));
+        assert_eq!(Some("counter"), closest_name("counterr", names));
+        assert_eq!(None, closest_name("total", names));
+        assert_eq!(None, closest_name("count", ["count"]));
+        assert_eq!(Some("ac"), closest_name("ab", ["cb", "ac"]));
+        assert_eq!(None, closest_name("n", ["A"]));
+        assert_eq!(Some("xs"), closest_name("x", ["A", "xs"]));
+    }
+}
@@ -1,13 +1,32 @@
+use crate::span::Span;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub use rlox_derive::*;
 
 pub type Ptr<T> = Box<T>;
 
+/// Both [`StatementVisitor`](crate::StatementVisitor) and [`ExprVisitor`](crate::ExprVisitor),
+/// which `#[syntax_enum]` generates from the variants of `Statement` and `Expr`, so that a new
+/// node declared with `#[syntax_node]` adds a method to implement.
+pub trait Visitor: crate::StatementVisitor + crate::ExprVisitor {}
+
+impl<V: crate::StatementVisitor + crate::ExprVisitor> Visitor for V {}
+
 static ID: AtomicUsize = AtomicUsize::new(0);
 
 pub trait SyntaxNode {
-    fn id(&self) -> usize;
+    /// Type of the id field, which `#[syntax_node]` creates from a [`generate_id`] value.
+    ///
+    /// [`generate_id`]: SyntaxNode::generate_id
+    type Id: Copy + From<usize>;
+
+    fn id(&self) -> Self::Id;
+
+    /// Where the node is in the source. `#[syntax_node]` implements it for nodes with a
+    /// `span` field, which `new_wrapped` takes as its first argument.
+    fn span(&self) -> Span {
+        Span::default()
+    }
 
     fn generate_id() -> usize {
         let result = ID.fetch_add(1, Ordering::SeqCst);
@@ -29,4 +48,16 @@ mod tests {
         assert_eq!(expr::Binary::generate_id(), 1);
         assert_eq!(statement::If::generate_id(), 2);
     }
+
+    #[test]
+    fn test_span() {
+        let span = Span::new(1, 2, 3);
+        // Not with new_wrapped, which would take an id from test_syntax_node
+        let literal = expr::Literal {
+            id: 0,
+            span,
+            literal: Literal::Nil,
+        };
+        assert_eq!(span, literal.span());
+    }
 }
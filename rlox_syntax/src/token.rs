@@ -1,20 +1,80 @@
-use crate::Literal;
-use std::fmt::Formatter;
+use crate::{Literal, Span};
+use std::fmt::{Debug, Formatter};
+use std::ops::Range;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Token {
     pub kind: TokenKind,
-    pub lexeme: String,
+    /// Source the token was scanned from, shared by all of its tokens.
+    pub source: Arc<str>,
+    /// Byte range of the lexeme in `source`.
+    pub range: Range<usize>,
     pub literal: Option<Literal>,
     pub line: usize,
+    /// 1-based column of the first character of the token.
+    pub column: usize,
+}
+
+impl Token {
+    /// Creates a token which isn't part of a scanned source, with `lexeme` as its source.
+    pub fn new(
+        kind: TokenKind,
+        lexeme: &str,
+        literal: Option<Literal>,
+        line: usize,
+        column: usize,
+    ) -> Self {
+        Self {
+            kind,
+            source: lexeme.into(),
+            range: 0..lexeme.len(),
+            literal,
+            line,
+            column,
+        }
+    }
+
+    pub fn lexeme(&self) -> &str {
+        &self.source[self.range.clone()]
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.column, self.lexeme().chars().count())
+    }
+}
+
+/// Shows the lexeme rather than the whole source.
+impl Debug for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Token")
+            .field("kind", &self.kind)
+            .field("lexeme", &self.lexeme())
+            .field("literal", &self.literal)
+            .field("line", &self.line)
+            .field("column", &self.column)
+            .finish()
+    }
 }
 
 impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?} {} {:?}", self.kind, self.lexeme, self.literal)
+        write!(f, "{:?} {} {:?}", self.kind, self.lexeme(), self.literal)
     }
 }
 
+/// A comment, kept apart from the tokens for tools which reproduce the source such as the
+/// formatter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// Text of the comment including the leading `//`, or the `/*` and `*/` around a block
+    /// comment, whose text may span lines.
+    pub text: String,
+    pub span: Span,
+    /// Whether the comment follows code on the same line.
+    pub trailing: bool,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum TokenKind {
@@ -23,11 +83,15 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
+    QuestionDot,
     Minus,
     Plus,
     Semicolon,
+    Colon,
     Slash,
     Star,
 
@@ -40,6 +104,8 @@ pub enum TokenKind {
     GreaterEqual,
     Less,
     LessEqual,
+    Arrow,
+    Ellipsis,
 
     // Literals.
     Identifier,
@@ -49,6 +115,7 @@ pub enum TokenKind {
     // Keywords.
     And,
     Class,
+    Const,
     Else,
     False,
     Fun,
@@ -66,3 +133,205 @@ pub enum TokenKind {
 
     Eof,
 }
+
+/// Lexical category of a [`TokenKind`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TokenCategory {
+    /// Brackets, `,`, `.`, `;`, `->` and `...`.
+    Punctuation,
+    /// Operators written with symbols, e.g. `+` or `!=`. `and` and `or` are keywords.
+    Operator,
+    Keyword,
+    Identifier,
+    /// Strings and numbers. `true`, `false` and `nil` are keywords.
+    Literal,
+    Eof,
+}
+
+/// Binding power of a [`TokenKind`] as a binary operator, higher binding tighter. All of them
+/// are left-associative.
+pub mod binding_power {
+    pub const OR: u8 = 1;
+    pub const AND: u8 = 2;
+    pub const EQUALITY: u8 = 3;
+    pub const COMPARISON: u8 = 4;
+    pub const TERM: u8 = 5;
+    pub const FACTOR: u8 = 6;
+    /// Of `!` and `-` as prefix operators, binding tighter than any binary operator.
+    pub const UNARY: u8 = 7;
+}
+
+impl TokenKind {
+    /// Every kind, in declaration order.
+    pub const ALL: &'static [TokenKind] = &[
+        TokenKind::LeftParen,
+        TokenKind::RightParen,
+        TokenKind::LeftBrace,
+        TokenKind::RightBrace,
+        TokenKind::LeftBracket,
+        TokenKind::RightBracket,
+        TokenKind::Comma,
+        TokenKind::Dot,
+        TokenKind::QuestionDot,
+        TokenKind::Minus,
+        TokenKind::Plus,
+        TokenKind::Semicolon,
+        TokenKind::Colon,
+        TokenKind::Slash,
+        TokenKind::Star,
+        TokenKind::Bang,
+        TokenKind::BangEqual,
+        TokenKind::Equal,
+        TokenKind::EqualEqual,
+        TokenKind::Greater,
+        TokenKind::GreaterEqual,
+        TokenKind::Less,
+        TokenKind::LessEqual,
+        TokenKind::Arrow,
+        TokenKind::Ellipsis,
+        TokenKind::Identifier,
+        TokenKind::String,
+        TokenKind::Number,
+        TokenKind::And,
+        TokenKind::Class,
+        TokenKind::Const,
+        TokenKind::Else,
+        TokenKind::False,
+        TokenKind::Fun,
+        TokenKind::For,
+        TokenKind::If,
+        TokenKind::Nil,
+        TokenKind::Or,
+        TokenKind::Print,
+        TokenKind::Return,
+        TokenKind::Super,
+        TokenKind::This,
+        TokenKind::True,
+        TokenKind::Var,
+        TokenKind::While,
+        TokenKind::Eof,
+    ];
+
+    /// The text of every token of this kind, `None` for identifiers, literals and the end of
+    /// file, whose text varies or doesn't exist.
+    pub fn lexeme(self) -> Option<&'static str> {
+        let lexeme = match self {
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Dot => ".",
+            TokenKind::QuestionDot => "?.",
+            TokenKind::Minus => "-",
+            TokenKind::Plus => "+",
+            TokenKind::Semicolon => ";",
+            TokenKind::Colon => ":",
+            TokenKind::Slash => "/",
+            TokenKind::Star => "*",
+            TokenKind::Bang => "!",
+            TokenKind::BangEqual => "!=",
+            TokenKind::Equal => "=",
+            TokenKind::EqualEqual => "==",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::Arrow => "->",
+            TokenKind::Ellipsis => "...",
+            TokenKind::And => "and",
+            TokenKind::Class => "class",
+            TokenKind::Const => "const",
+            TokenKind::Else => "else",
+            TokenKind::False => "false",
+            TokenKind::Fun => "fun",
+            TokenKind::For => "for",
+            TokenKind::If => "if",
+            TokenKind::Nil => "nil",
+            TokenKind::Or => "or",
+            TokenKind::Print => "print",
+            TokenKind::Return => "return",
+            TokenKind::Super => "super",
+            TokenKind::This => "this",
+            TokenKind::True => "true",
+            TokenKind::Var => "var",
+            TokenKind::While => "while",
+            TokenKind::Identifier | TokenKind::String | TokenKind::Number | TokenKind::Eof => {
+                return None
+            }
+        };
+        Some(lexeme)
+    }
+
+    /// The keyword written `text`, if it is one.
+    pub fn keyword(text: &str) -> Option<TokenKind> {
+        TokenKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.is_keyword() && kind.lexeme() == Some(text))
+    }
+
+    pub fn category(self) -> TokenCategory {
+        match self {
+            TokenKind::LeftParen
+            | TokenKind::RightParen
+            | TokenKind::LeftBrace
+            | TokenKind::RightBrace
+            | TokenKind::LeftBracket
+            | TokenKind::RightBracket
+            | TokenKind::Comma
+            | TokenKind::Dot
+            | TokenKind::QuestionDot
+            | TokenKind::Semicolon
+            | TokenKind::Colon
+            | TokenKind::Arrow
+            | TokenKind::Ellipsis => TokenCategory::Punctuation,
+            TokenKind::Minus
+            | TokenKind::Plus
+            | TokenKind::Slash
+            | TokenKind::Star
+            | TokenKind::Bang
+            | TokenKind::BangEqual
+            | TokenKind::Equal
+            | TokenKind::EqualEqual
+            | TokenKind::Greater
+            | TokenKind::GreaterEqual
+            | TokenKind::Less
+            | TokenKind::LessEqual => TokenCategory::Operator,
+            TokenKind::Identifier => TokenCategory::Identifier,
+            TokenKind::String | TokenKind::Number => TokenCategory::Literal,
+            TokenKind::Eof => TokenCategory::Eof,
+            _ => TokenCategory::Keyword,
+        }
+    }
+
+    pub fn is_keyword(self) -> bool {
+        self.category() == TokenCategory::Keyword
+    }
+
+    /// Binding power as a binary operator, including `and` and `or`, from [`binding_power`].
+    pub fn infix_binding_power(self) -> Option<u8> {
+        match self {
+            TokenKind::Or => Some(binding_power::OR),
+            TokenKind::And => Some(binding_power::AND),
+            TokenKind::BangEqual | TokenKind::EqualEqual => Some(binding_power::EQUALITY),
+            TokenKind::Greater
+            | TokenKind::GreaterEqual
+            | TokenKind::Less
+            | TokenKind::LessEqual => Some(binding_power::COMPARISON),
+            TokenKind::Minus | TokenKind::Plus => Some(binding_power::TERM),
+            TokenKind::Slash | TokenKind::Star => Some(binding_power::FACTOR),
+            _ => None,
+        }
+    }
+
+    /// Binding power as a prefix operator, from [`binding_power`].
+    pub fn prefix_binding_power(self) -> Option<u8> {
+        match self {
+            TokenKind::Bang | TokenKind::Minus => Some(binding_power::UNARY),
+            _ => None,
+        }
+    }
+}
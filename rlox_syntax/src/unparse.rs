@@ -0,0 +1,177 @@
+use crate::ast::*;
+use crate::printer::operator_lexeme;
+use crate::token::TokenKind;
+use std::fmt::{Display, Formatter, Result};
+use std::sync::PoisonError;
+
+const INDENT: &str = "    ";
+
+/// Lox source of the expression, e.g. `a + b * 2`. Parentheses are the groupings of the tree,
+/// so the source parses back to the same tree.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Expr::Binary(e) if e.operator == TokenKind::Comma => {
+                write!(f, "{}, {}", e.left, e.right)
+            }
+            Expr::Binary(e) => write!(f, "{} {} {}", e.left, operator_lexeme(e.operator), e.right),
+            Expr::Logical(e) => write!(f, "{} {} {}", e.left, operator_lexeme(e.operator), e.right),
+            Expr::Unary(e) => write!(f, "{}{}", operator_lexeme(e.operator), e.right),
+            Expr::Grouping(e) => write!(f, "({})", e.expr),
+            Expr::Literal(e) => write!(f, "{}", e.literal),
+            Expr::Variable(e) => f.write_str(&e.name),
+            Expr::Assign(e) => write!(f, "{} = {}", e.name, e.value),
+            Expr::Call(e) => {
+                write!(f, "{}(", e.callee)?;
+                write_list(f, &e.arguments)?;
+                f.write_str(")")
+            }
+            Expr::Get(e) => write!(f, "{}{}{}", e.object, e.dot(), e.name),
+            Expr::Set(e) => write!(f, "{}.{} = {}", e.object, e.name, e.value),
+            Expr::This(_) => f.write_str("this"),
+            Expr::Super(e) => write!(f, "super.{}", e.method),
+            Expr::List(e) => {
+                f.write_str("[")?;
+                write_list(f, &e.elements)?;
+                f.write_str("]")
+            }
+            Expr::Index(e) => write!(f, "{}[{}]", e.object, e.index),
+            Expr::Slice(e) => {
+                write!(f, "{}[", e.object)?;
+                if let Some(start) = &e.start {
+                    write!(f, "{start}")?;
+                }
+                f.write_str(":")?;
+                if let Some(end) = &e.end {
+                    write!(f, "{end}")?;
+                }
+                f.write_str("]")
+            }
+            Expr::IndexSet(e) => write!(f, "{}[{}] = {}", e.object, e.index, e.value),
+            Expr::Lambda(e) => {
+                write!(f, "fun ({}) ", e.written_params().join(", "))?;
+                let body = e.body.read().unwrap_or_else(PoisonError::into_inner);
+                match expr::Lambda::returned(&body) {
+                    Some(value) => write!(f, "-> {value}"),
+                    None => write_statement(f, &body, 0),
+                }
+            }
+        }
+    }
+}
+
+/// Lox source of the literal, as written in code.
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Literal::Number(n) => write!(f, "{n}"),
+            // Lox strings have no escapes
+            Literal::String(s) => write!(f, "\"{s}\""),
+            Literal::Boolean(b) => write!(f, "{b}"),
+            Literal::Nil => f.write_str("nil"),
+        }
+    }
+}
+
+/// Lox source of the statement, with the statements of blocks on their own lines. `for`
+/// loops come out as the `while` loops the parser desugared them into.
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write_statement(f, self, 0)
+    }
+}
+
+/// Writes `exprs` separated by commas.
+fn write_list(f: &mut Formatter<'_>, exprs: &[Expr]) -> Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{expr}")?;
+    }
+    Ok(())
+}
+
+/// Writes `stmt` from the current position, with lines inside it indented `depth` levels.
+fn write_statement(f: &mut Formatter<'_>, stmt: &Statement, depth: usize) -> Result {
+    match stmt {
+        Statement::Expression(s) => write!(f, "{};", s.expr),
+        Statement::Print(s) => {
+            f.write_str("print ")?;
+            write_list(f, &s.exprs)?;
+            f.write_str(";")
+        }
+        Statement::VariableDecl(s) => match &s.expr {
+            Some(expr) => write!(f, "{} {} = {expr};", s.keyword(), s.name),
+            None => write!(f, "var {};", s.name),
+        },
+        Statement::Block(s) => {
+            if s.statements.is_empty() {
+                return f.write_str("{}");
+            }
+            f.write_str("{\n")?;
+            for s in &s.statements {
+                f.write_str(&INDENT.repeat(depth + 1))?;
+                write_statement(f, s, depth + 1)?;
+                f.write_str("\n")?;
+            }
+            write!(f, "{}}}", INDENT.repeat(depth))
+        }
+        Statement::If(s) => {
+            write!(f, "if ({}) ", s.condition)?;
+            write_statement(f, &s.then_branch, depth)?;
+            if let Some(else_branch) = &s.else_branch {
+                f.write_str(" else ")?;
+                write_statement(f, else_branch, depth)?;
+            }
+            Ok(())
+        }
+        Statement::While(s) => {
+            write!(f, "while ({}) ", s.condition)?;
+            write_statement(f, &s.body, depth)
+        }
+        Statement::Function(s) => {
+            f.write_str("fun ")?;
+            write_function(f, s, depth)
+        }
+        Statement::Return(s) => match &s.value {
+            Some(value) => write!(f, "return {value};"),
+            None => f.write_str("return;"),
+        },
+        Statement::Class(s) => {
+            write!(f, "class {} ", s.name)?;
+            if let Some(superclass) = &s.superclass {
+                write!(f, "< {superclass} ")?;
+            }
+            if s.methods.is_empty() {
+                return f.write_str("{}");
+            }
+            f.write_str("{\n")?;
+            for method in &s.methods {
+                f.write_str(&INDENT.repeat(depth + 1))?;
+                match method {
+                    Statement::Function(method) => write_function(f, method, depth + 1)?,
+                    method => write_statement(f, method, depth + 1)?,
+                }
+                f.write_str("\n")?;
+            }
+            write!(f, "{}}}", INDENT.repeat(depth))
+        }
+    }
+}
+
+/// Writes the name, parameters and body of a function, which methods aren't preceded by `fun`.
+/// Static methods are preceded by `static`.
+fn write_function(f: &mut Formatter<'_>, function: &statement::Function, depth: usize) -> Result {
+    if function.is_static {
+        f.write_str("static ")?;
+    }
+    write!(
+        f,
+        "{}({}) ",
+        function.name,
+        function.written_params().join(", ")
+    )?;
+    let body = function.body.read().unwrap_or_else(PoisonError::into_inner);
+    write_statement(f, &body, depth)
+}
@@ -0,0 +1,1015 @@
+//! Lowers resolved programs to readable JavaScript, behind `rlox build --target=js`.
+//!
+//! Lox functions become JavaScript functions, whose closures capture variables the same way,
+//! and `print` becomes `console.log`. Where JavaScript differs, small helpers prefixed with `$`,
+//! which Lox names can't contain, are defined at the top of the output and used only when the
+//! types involved aren't known to be booleans:
+//!
+//! - `$truthy` for conditions, as only `nil` and `false` are falsy in Lox. `!` converts its
+//!   operand the same way, as it does in this interpreter.
+//! - `$and` and `$or`, which return one of their operands as Lox does.
+//! - `$str` for printing values the way Lox does, including the arrays which lists become.
+//! - `$index` and `$setIndex` for reading and assigning elements of lists, which is an error
+//!   out of range in Lox.
+//! - `$slice` for slices, which Lox only takes of lists and strings, counting negative bounds
+//!   from the end as `slice` does.
+//! - `$class` for classes, which Lox calls without `new`, running their `init` method. It also
+//!   names them, as they are anonymous so that their methods find the callable class.
+//!
+//! Methods become the methods of JavaScript classes, and functions declared in them arrow
+//! functions, so that `this` stays the instance, as do lambdas anywhere. Subclasses extend their
+//! superclass, whose methods `super` reads the same way in both languages, and static methods
+//! stay static. Unlike in Lox, a method read from an instance without being called isn't bound
+//! to it.
+//!
+//! Variables declared again in an inner scope are renamed with a `$` suffix, as are names
+//! reserved by JavaScript, so that each one keeps referring to what the resolver found.
+
+use rlox_syntax::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::sync::PoisonError;
+
+const INDENT: &str = "    ";
+
+/// Natives, which are defined by the output if the program uses them.
+const NATIVES: &[(&str, &str)] = &[
+    (
+        "clock",
+        "function clock() {\n    return Date.now() / 1000;\n}\n",
+    ),
+    (
+        "args",
+        "const $arguments = typeof process === \"undefined\" ? [] : process.argv.slice(2);\n\
+         function args(i) {\n    return $arguments[i] ?? null;\n}\n",
+    ),
+    // Nothing to read from outside of a terminal
+    ("input", "function input() {\n    return null;\n}\n"),
+    (
+        "memoryStats",
+        "function memoryStats() {\n    return \"\";\n}\n",
+    ),
+];
+
+const HELPERS: &[(&str, &str)] = &[
+    (
+        "$truthy",
+        "function $truthy(value) {\n    return value !== null && value !== undefined && value !== false;\n}\n",
+    ),
+    (
+        "$and",
+        "function $and(left, right) {\n    return $truthy(left) ? right() : left;\n}\n",
+    ),
+    (
+        "$or",
+        "function $or(left, right) {\n    return $truthy(left) ? left : right();\n}\n",
+    ),
+    (
+        "$isClass",
+        // Unlike the prototype of functions, the one of classes can't be replaced.
+        "function $isClass(value) {\n    return Object.getOwnPropertyDescriptor(value, \"prototype\")?.writable === false;\n}\n",
+    ),
+    (
+        "$str",
+        "function $str(value) {\n    if (value === null || value === undefined) return \"nil\";\n    if (typeof value === \"function\") return $isClass(value) ? value.name : `<fn ${value.name}>`;\n    if (Array.isArray(value)) return `[${value.map($str).join(\", \")}]`;\n    if (typeof value === \"object\") return `${value.constructor.name} instance`;\n    return String(value);\n}\n",
+    ),
+    (
+        "$checkIndex",
+        "function $checkIndex(list, index) {\n    if (!Array.isArray(list)) throw new TypeError(\"Can only index lists.\");\n    if (!Number.isInteger(index) || index < 0 || index >= list.length) {\n        throw new RangeError(`Index ${index} is out of range for a list of length ${list.length}.`);\n    }\n}\n",
+    ),
+    (
+        "$index",
+        "function $index(list, index) {\n    $checkIndex(list, index);\n    return list[index];\n}\n",
+    ),
+    (
+        "$setIndex",
+        "function $setIndex(list, index, value) {\n    $checkIndex(list, index);\n    return list[index] = value;\n}\n",
+    ),
+    (
+        "$slice",
+        "function $slice(value, start, end) {\n    if (!Array.isArray(value) && typeof value !== \"string\") throw new TypeError(\"Can only slice lists and strings.\");\n    return value.slice(start, end);\n}\n",
+    ),
+    (
+        "$class",
+        "function $class(name, cls) {\n    Object.defineProperty(cls, \"name\", { value: name });\n    return new Proxy(cls, {\n        apply(target, _, args) {\n            const instance = new target();\n            instance.init?.(...args);\n            return instance;\n        },\n    });\n}\n",
+    ),
+];
+
+/// Words which can't name JavaScript variables, or which name globals the output relies on.
+const RESERVED: &[&str] = &[
+    "arguments",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "console",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "enum",
+    "eval",
+    "export",
+    "extends",
+    "finally",
+    "function",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "new",
+    "null",
+    "package",
+    "private",
+    "process",
+    "protected",
+    "public",
+    "static",
+    "switch",
+    "throw",
+    "try",
+    "typeof",
+    "undefined",
+    "void",
+    "with",
+    "yield",
+    "Date",
+    "Infinity",
+    "NaN",
+    "Object",
+    "Proxy",
+    "String",
+];
+
+/// JavaScript for `statements`, which must have been resolved in a global scope with the
+/// natives, such as the one of [`Scope::new_globals_ptr`](../rlox_interpreter/struct.Scope.html).
+pub fn to_javascript(statements: &[Statement]) -> String {
+    let mut transpiler = Transpiler {
+        out: String::new(),
+        depth: 0,
+        scopes: vec![HashMap::new()],
+        declarations: HashMap::new(),
+        helpers: BTreeSet::new(),
+        natives: BTreeSet::new(),
+        methods: 0,
+        initializer: false,
+    };
+    for (name, _) in NATIVES {
+        transpiler.declare(name);
+    }
+    for s in statements {
+        transpiler.statement(s);
+    }
+
+    let mut out = String::from("\"use strict\";\n");
+    let definitions = HELPERS
+        .iter()
+        .filter(|(name, _)| transpiler.helpers.contains(name))
+        .chain(
+            NATIVES
+                .iter()
+                .filter(|(name, _)| transpiler.natives.contains(name)),
+        );
+    for (_, definition) in definitions {
+        out.push('\n');
+        out.push_str(definition);
+    }
+    out.push('\n');
+    out.push_str(&transpiler.out);
+    out
+}
+
+struct Transpiler {
+    out: String,
+    depth: usize,
+    // JavaScript names by Lox name, for the same scopes as the resolver's
+    scopes: Vec<HashMap<String, String>>,
+    // How many times each Lox name was declared so far
+    declarations: HashMap<String, usize>,
+    helpers: BTreeSet<&'static str>,
+    natives: BTreeSet<&'static str>,
+    // Methods whose body is being printed
+    methods: usize,
+    // Printing the body of an `init` method, outside of the functions declared in it
+    initializer: bool,
+}
+
+impl Transpiler {
+    /// JavaScript name of a new variable `name` in the current scope.
+    fn declare(&mut self, name: &str) -> String {
+        let count = self.declarations.entry(name.to_owned()).or_default();
+        let mut js_name = name.to_owned();
+        if RESERVED.contains(&name) {
+            js_name.push('$');
+        }
+        if *count > 0 {
+            write!(js_name, "${count}").unwrap();
+        }
+        *count += 1;
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_owned(), js_name.clone());
+        js_name
+    }
+
+    /// Declares `params` in the current scope, returning their JavaScript list. A rest parameter
+    /// becomes one of JavaScript, whose array is the list of Lox.
+    fn params(&mut self, params: &[String], variadic: bool) -> String {
+        let mut params: Vec<_> = params.iter().map(|p| self.declare(p)).collect();
+        if let (true, Some(rest)) = (variadic, params.last_mut()) {
+            rest.insert_str(0, "...");
+        }
+        params.join(", ")
+    }
+
+    /// JavaScript name of the variable `name` found `resolution` scopes above the current one.
+    fn lookup(&mut self, name: &str, resolution: usize) -> String {
+        let depth = self.scopes.len().checked_sub(resolution + 1);
+        let found = depth
+            .and_then(|depth| self.scopes[depth].get(name))
+            .cloned();
+        if depth == Some(0) {
+            if let Some(&(native, _)) = NATIVES.iter().find(|(n, _)| *n == name) {
+                self.natives.insert(native);
+            }
+        }
+        // Not resolved, e.g. a global of the host, which keeps its name.
+        found.unwrap_or_else(|| name.to_owned())
+    }
+
+    fn helper(&mut self, name: &'static str) -> &'static str {
+        self.helpers.insert(name);
+        if name == "$and" || name == "$or" {
+            self.helpers.insert("$truthy");
+        }
+        if name == "$str" {
+            self.helpers.insert("$isClass");
+        }
+        if name == "$index" || name == "$setIndex" {
+            self.helpers.insert("$checkIndex");
+        }
+        name
+    }
+
+    fn line(&mut self, text: &str) {
+        self.out.push_str(&INDENT.repeat(self.depth));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression(s) => {
+                let text = format!("{};", self.expr(&s.expr));
+                self.line(&text);
+            }
+            Statement::Print(s) => {
+                // console.log separates its arguments with spaces, as print does.
+                let values: Vec<_> = s
+                    .exprs
+                    .iter()
+                    .map(|e| {
+                        let value = self.expr(e);
+                        match e {
+                            // Printed the same way
+                            Expr::Literal(e) if matches!(e.literal, Literal::String(_)) => value,
+                            _ => format!("{}({value})", self.helper("$str")),
+                        }
+                    })
+                    .collect();
+                self.line(&format!("console.log({});", values.join(", ")));
+            }
+            Statement::VariableDecl(s) => {
+                let value = match &s.expr {
+                    Some(e) => self.expr(e),
+                    None => "null".to_owned(),
+                };
+                let name = self.declare(&s.name);
+                let keyword = if s.constant { "const" } else { "let" };
+                self.line(&format!("{keyword} {name} = {value};"));
+            }
+            Statement::Block(s) => {
+                self.line("{");
+                self.block(&s.statements);
+                self.line("}");
+            }
+            Statement::If(s) => {
+                let condition = self.condition(&s.condition);
+                self.line(&format!("if ({condition}) {{"));
+                self.branch(&s.then_branch);
+                if let Some(else_branch) = &s.else_branch {
+                    self.line("} else {");
+                    self.branch(else_branch);
+                }
+                self.line("}");
+            }
+            Statement::While(s) => {
+                let condition = self.condition(&s.condition);
+                self.line(&format!("while ({condition}) {{"));
+                self.branch(&s.body);
+                self.line("}");
+            }
+            Statement::Function(s) => {
+                let initializer = std::mem::replace(&mut self.initializer, false);
+                let name = self.declare(&s.name);
+                self.scopes.push(HashMap::new());
+                let params = self.params(&s.params, s.variadic);
+                if self.methods > 0 {
+                    self.line(&format!("let {name} = ({params}) => {{"));
+                } else {
+                    self.line(&format!("function {name}({params}) {{"));
+                }
+                self.branch(&s.body.read().unwrap_or_else(PoisonError::into_inner));
+                self.line(if self.methods > 0 { "};" } else { "}" });
+                self.scopes.pop();
+                self.initializer = initializer;
+            }
+            Statement::Return(s) => {
+                let text = match &s.value {
+                    Some(value) => format!("return {};", self.expr(value)),
+                    None if self.initializer => "return this;".to_owned(),
+                    None => "return null;".to_owned(),
+                };
+                self.line(&text);
+            }
+            Statement::Class(s) => {
+                let name = self.declare(&s.name);
+                let helper = self.helper("$class");
+                let extends = match &s.superclass {
+                    Some(superclass) => format!(" extends {}", self.expr(superclass)),
+                    None => String::new(),
+                };
+                // Anonymous, so that its methods find the class through the variable as in Lox,
+                // rather than without the helper
+                let class = format!("let {name} = {helper}(\"{}\", class{extends} {{", s.name);
+                if s.methods.is_empty() {
+                    self.line(&format!("{class}}});"));
+                    return;
+                }
+                self.line(&class);
+                // The scope of `super`, which JavaScript provides
+                let scopes = usize::from(s.superclass.is_some());
+                self.scopes.extend((0..scopes).map(|_| HashMap::new()));
+                self.depth += 1;
+                self.methods += 1;
+                for method in &s.methods {
+                    if let Statement::Function(method) = method {
+                        self.method(method);
+                    }
+                }
+                self.methods -= 1;
+                self.depth -= 1;
+                self.scopes.truncate(self.scopes.len() - scopes);
+                self.line("});");
+            }
+        }
+    }
+
+    /// Prints a method, `init` returning `this` as it does in Lox.
+    fn method(&mut self, method: &statement::Function) {
+        // The scopes of `this`, which static methods don't have, and of the parameters
+        let scopes = if method.is_static { 1 } else { 2 };
+        self.scopes.extend((0..scopes).map(|_| HashMap::new()));
+        let params = self.params(&method.params, method.variadic);
+        let keyword = if method.is_static { "static " } else { "" };
+        self.line(&format!("{keyword}{}({params}) {{", method.name));
+        self.initializer = !method.is_static && method.name == "init";
+        self.branch(&method.body.read().unwrap_or_else(PoisonError::into_inner));
+        if self.initializer {
+            self.depth += 1;
+            self.line("return this;");
+            self.depth -= 1;
+        }
+        self.initializer = false;
+        self.line("}");
+        self.scopes.truncate(self.scopes.len() - scopes);
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.depth += 1;
+        self.scopes.push(HashMap::new());
+        for s in statements {
+            self.statement(s);
+        }
+        self.scopes.pop();
+        self.depth -= 1;
+    }
+
+    /// Statement between braces printed by the caller, without adding braces for blocks.
+    fn branch(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(s) => self.block(&s.statements),
+            _ => {
+                self.depth += 1;
+                self.statement(stmt);
+                self.depth -= 1;
+            }
+        }
+    }
+
+    fn condition(&mut self, e: &Expr) -> String {
+        let condition = self.expr(e);
+        if is_boolean(e) {
+            condition
+        } else {
+            format!("{}({condition})", self.helper("$truthy"))
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) -> String {
+        match e {
+            // Parenthesized, as it may be the argument of a helper such as `$truthy`
+            Expr::Binary(e) if e.operator == TokenKind::Comma => {
+                format!("({}, {})", self.expr(&e.left), self.expr(&e.right))
+            }
+            Expr::Binary(e) => {
+                let left = self.expr(&e.left);
+                let right = self.expr(&e.right);
+                let is_nil = |e: &Expr| matches!(e, Expr::Literal(l) if l.literal == Literal::Nil);
+                // Also matches `undefined`, returned by functions which end without `return`.
+                let nil_comparison = is_nil(&e.left) || is_nil(&e.right);
+                let operator = match e.operator {
+                    TokenKind::EqualEqual if nil_comparison => "==",
+                    TokenKind::BangEqual if nil_comparison => "!=",
+                    TokenKind::EqualEqual => "===",
+                    TokenKind::BangEqual => "!==",
+                    kind => operator(kind),
+                };
+                format!("{left} {operator} {right}")
+            }
+            Expr::Logical(e) => {
+                let left = self.expr(&e.left);
+                let right = self.expr(&e.right);
+                if is_boolean(&e.left) && is_boolean(&e.right) {
+                    let operator = if e.operator == TokenKind::And {
+                        "&&"
+                    } else {
+                        "||"
+                    };
+                    format!("{left} {operator} {right}")
+                } else {
+                    let helper = if e.operator == TokenKind::And {
+                        self.helper("$and")
+                    } else {
+                        self.helper("$or")
+                    };
+                    format!("{helper}({left}, () => {right})")
+                }
+            }
+            Expr::Unary(e) => {
+                let right = self.expr(&e.right);
+                match e.operator {
+                    TokenKind::Minus => format!("-{right}"),
+                    _ if is_boolean(&e.right) => right,
+                    _ => format!("{}({right})", self.helper("$truthy")),
+                }
+            }
+            Expr::Grouping(e) => format!("({})", self.expr(&e.expr)),
+            Expr::Literal(e) => match &e.literal {
+                Literal::Number(n) => n.to_string(),
+                Literal::String(s) => string_literal(s),
+                Literal::Boolean(b) => b.to_string(),
+                Literal::Nil => "null".to_owned(),
+            },
+            Expr::Variable(e) => self.lookup(&e.name, e.resolution),
+            Expr::Assign(e) => {
+                let value = self.expr(&e.value);
+                format!("{} = {value}", self.lookup(&e.name, e.resolution))
+            }
+            // The call of `a?.m` is part of its chain, while calling the result of a chain isn't
+            Expr::Call(e) => {
+                let callee = match &e.callee {
+                    Expr::Get(_) => self.expr(&e.callee),
+                    callee => self.chain_object(callee),
+                };
+                let arguments: Vec<_> = e.arguments.iter().map(|a| self.expr(a)).collect();
+                format!("{callee}({})", arguments.join(", "))
+            }
+            Expr::Get(e) => format!("{}{}{}", self.chain_object(&e.object), e.dot(), e.name),
+            Expr::Set(e) => {
+                let object = self.chain_object(&e.object);
+                format!("{object}.{} = {}", e.name, self.expr(&e.value))
+            }
+            Expr::This(_) => "this".to_owned(),
+            Expr::Super(e) => format!("super.{}", e.method),
+            Expr::List(e) => {
+                let elements: Vec<_> = e.elements.iter().map(|e| self.expr(e)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Expr::Index(e) => {
+                let object = self.expr(&e.object);
+                let index = self.expr(&e.index);
+                format!("{}({object}, {index})", self.helper("$index"))
+            }
+            // Without an end, `slice` takes the rest of the value
+            Expr::Slice(e) => {
+                let object = self.expr(&e.object);
+                let start = e.start.as_ref().map_or("0".to_owned(), |e| self.expr(e));
+                let mut arguments = format!("{object}, {start}");
+                if let Some(end) = &e.end {
+                    write!(arguments, ", {}", self.expr(end)).unwrap();
+                }
+                format!("{}({arguments})", self.helper("$slice"))
+            }
+            Expr::IndexSet(e) => {
+                let object = self.expr(&e.object);
+                let index = self.expr(&e.index);
+                let value = self.expr(&e.value);
+                format!("{}({object}, {index}, {value})", self.helper("$setIndex"))
+            }
+            // Parenthesized, as the body of the arrow function would take in what follows
+            Expr::Lambda(e) => {
+                let body = e.body.read().unwrap_or_else(PoisonError::into_inner);
+                let Some(value) = expr::Lambda::returned(&body) else {
+                    unreachable!("body of lambda must be desugared from an expression");
+                };
+                // The scopes of the parameters and of the block returning the value
+                self.scopes.push(HashMap::new());
+                let params = self.params(&e.params, e.variadic);
+                self.scopes.push(HashMap::new());
+                let value = self.expr(value);
+                self.scopes.truncate(self.scopes.len() - 2);
+                format!("(({params}) => {value})")
+            }
+        }
+    }
+
+    /// JS of `e` read from or called. An optional chain is parenthesized, as JS would skip the
+    /// rest of the chain when its object is `null`, where Lox only skips the `?.` itself.
+    fn chain_object(&mut self, e: &Expr) -> String {
+        let js = self.expr(e);
+        let optional = match e {
+            Expr::Get(e) => e.optional,
+            Expr::Call(e) => matches!(&e.callee, Expr::Get(get) if get.optional),
+            _ => false,
+        };
+        if optional {
+            format!("({js})")
+        } else {
+            js
+        }
+    }
+}
+
+/// Whether `e` always evaluates to a boolean.
+fn is_boolean(e: &Expr) -> bool {
+    match e {
+        Expr::Literal(e) => matches!(e.literal, Literal::Boolean(_)),
+        Expr::Binary(e) => matches!(
+            e.operator,
+            TokenKind::EqualEqual
+                | TokenKind::BangEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual
+        ),
+        Expr::Unary(e) => e.operator == TokenKind::Bang,
+        Expr::Grouping(e) => is_boolean(&e.expr),
+        Expr::Logical(e) => is_boolean(&e.left) && is_boolean(&e.right),
+        Expr::Variable(_)
+        | Expr::Assign(_)
+        | Expr::Call(_)
+        | Expr::Get(_)
+        | Expr::Set(_)
+        | Expr::This(_)
+        | Expr::Super(_)
+        | Expr::List(_)
+        | Expr::Index(_)
+        | Expr::IndexSet(_)
+        | Expr::Slice(_)
+        | Expr::Lambda(_) => false,
+    }
+}
+
+fn operator(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Minus => "-",
+        TokenKind::Plus => "+",
+        TokenKind::Slash => "/",
+        TokenKind::Star => "*",
+        TokenKind::Greater => ">",
+        TokenKind::GreaterEqual => ">=",
+        TokenKind::Less => "<",
+        TokenKind::LessEqual => "<=",
+        _ => unreachable!("{kind:?} is not a binary operator"),
+    }
+}
+
+fn string_literal(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox_interpreter::{Resolver, Scope};
+    use rlox_parser::{Parser, Scanner};
+
+    fn transpile(source: &str) -> String {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut statements = Parser::new(tokens).parse().unwrap();
+        let scope = Scope::new_globals_ptr();
+        for s in &mut statements {
+            Resolver.resolve_statement(&scope, s).unwrap();
+        }
+        to_javascript(&statements)
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = r#"
+fun counter(new) {
+    var count = 0;
+    fun increment() { count = count + new; return count; }
+    return increment;
+}
+var next = counter(2);
+if (next() > 1 and true) print "big"; else print next();
+print "next:", next();
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function counter(new$) {
+    let count = 0;
+    function increment() {
+        count = count + new$;
+        return count;
+    }
+    return increment;
+}
+let next = counter(2);
+if (next() > 1 && true) {
+    console.log("big");
+} else {
+    console.log($str(next()));
+}
+console.log("next:", $str(next()));
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let source = r#"
+var x = 1;
+var add = fun (a, x) -> a + x;
+print add(x, 2) + (fun () -> x)();
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+let x = 1;
+let add = ((a, x$1) => a + x$1);
+console.log($str(add(x, 2) + ((() => x))()));
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_rest_parameters() {
+        let source = r#"
+fun log(level, ...args) {
+    print level, args;
+}
+log("info", 1, fun (...xs) -> xs);
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function log(level, ...args$1) {
+    console.log($str(level), $str(args$1));
+}
+log("info", 1, ((...xs) => xs));
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_const() {
+        let source = "const a = 1, b = 2; { var a = b; }";
+        let expected = r#""use strict";
+
+const a = 1;
+const b = 2;
+{
+    let a$1 = b;
+}
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_lists() {
+        let source = r#"
+var a = [1, "two", [3]];
+a[1] = a[2][0] = 4;
+print a[2][0], a;
+print a[1:], a[:-1], "lox"[-2:];
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function $checkIndex(list, index) {
+    if (!Array.isArray(list)) throw new TypeError("Can only index lists.");
+    if (!Number.isInteger(index) || index < 0 || index >= list.length) {
+        throw new RangeError(`Index ${index} is out of range for a list of length ${list.length}.`);
+    }
+}
+
+function $index(list, index) {
+    $checkIndex(list, index);
+    return list[index];
+}
+
+function $setIndex(list, index, value) {
+    $checkIndex(list, index);
+    return list[index] = value;
+}
+
+function $slice(value, start, end) {
+    if (!Array.isArray(value) && typeof value !== "string") throw new TypeError("Can only slice lists and strings.");
+    return value.slice(start, end);
+}
+
+let a = [1, "two", [3]];
+$setIndex(a, 1, $setIndex($index(a, 2), 0, 4));
+console.log($str($index($index(a, 2), 0)), $str(a));
+console.log($str($slice(a, 1)), $str($slice(a, 0, -1)), $str($slice("lox", -2)));
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_lox_semantics() {
+        let source = r#"
+var a = "outer";
+{
+    var a = a + "!";
+    for (var i = 0; i, i < 1; i = i + 1) print clock() or a;
+    if (!a and a != nil) print a;
+}
+"#;
+        let expected = r#""use strict";
+
+function $truthy(value) {
+    return value !== null && value !== undefined && value !== false;
+}
+
+function $or(left, right) {
+    return $truthy(left) ? left : right();
+}
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function clock() {
+    return Date.now() / 1000;
+}
+
+let a = "outer";
+{
+    let a$1 = a + "!";
+    {
+        let i = 0;
+        while ($truthy((i, i < 1))) {
+            console.log($str($or(clock(), () => a$1)));
+            i = i + 1;
+        }
+    }
+    if ($truthy(a$1) && a$1 != null) {
+        console.log($str(a$1));
+    }
+}
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = r#"
+class Point {}
+var p = Point();
+print Point, p;
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function $class(name, cls) {
+    Object.defineProperty(cls, "name", { value: name });
+    return new Proxy(cls, {
+        apply(target, _, args) {
+            const instance = new target();
+            instance.init?.(...args);
+            return instance;
+        },
+    });
+}
+
+let Point = $class("Point", class {});
+let p = Point();
+console.log($str(Point), $str(p));
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    // The rest of a chain after `?.` is parenthesized, as Lox reads it even if `?.` gives nil
+    #[test]
+    fn test_optional_chaining() {
+        let source = r#"
+class Point {}
+var p = Point();
+p.next = nil;
+print p?.next, p.next?.x, p.next?.m(1);
+p.next?.m(1).x = p.next?.x.y;
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function $class(name, cls) {
+    Object.defineProperty(cls, "name", { value: name });
+    return new Proxy(cls, {
+        apply(target, _, args) {
+            const instance = new target();
+            instance.init?.(...args);
+            return instance;
+        },
+    });
+}
+
+let Point = $class("Point", class {});
+let p = Point();
+p.next = null;
+console.log($str(p?.next), $str(p.next?.x), $str(p.next?.m(1)));
+(p.next?.m(1)).x = (p.next?.x).y;
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+
+    #[test]
+    fn test_methods() {
+        let source = r#"
+class Counter {
+    init(count) {
+        this.count = count;
+        if (count > 0) return;
+    }
+    add(n) {
+        fun step() { this.count = this.count + n; }
+        step();
+        return this;
+    }
+}
+class Twice < Counter {
+    add(n) { return super.add(n * 2); }
+    static of(n) { return Twice(n); }
+}
+var c = Twice.of(0);
+print c.add(2).add(3).count;
+"#;
+        let expected = r#""use strict";
+
+function $isClass(value) {
+    return Object.getOwnPropertyDescriptor(value, "prototype")?.writable === false;
+}
+
+function $str(value) {
+    if (value === null || value === undefined) return "nil";
+    if (typeof value === "function") return $isClass(value) ? value.name : `<fn ${value.name}>`;
+    if (Array.isArray(value)) return `[${value.map($str).join(", ")}]`;
+    if (typeof value === "object") return `${value.constructor.name} instance`;
+    return String(value);
+}
+
+function $class(name, cls) {
+    Object.defineProperty(cls, "name", { value: name });
+    return new Proxy(cls, {
+        apply(target, _, args) {
+            const instance = new target();
+            instance.init?.(...args);
+            return instance;
+        },
+    });
+}
+
+let Counter = $class("Counter", class {
+    init(count) {
+        this.count = count;
+        if (count > 0) {
+            return this;
+        }
+        return this;
+    }
+    add(n) {
+        let step = () => {
+            this.count = this.count + n;
+        };
+        step();
+        return this;
+    }
+});
+let Twice = $class("Twice", class extends Counter {
+    add(n$1) {
+        return super.add(n$1 * 2);
+    }
+    static of(n$2) {
+        return Twice(n$2);
+    }
+});
+let c = Twice.of(0);
+console.log($str(c.add(2).add(3).count));
+"#;
+        assert_eq!(expected, transpile(source));
+    }
+}
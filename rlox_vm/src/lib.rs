@@ -0,0 +1,13 @@
+//! Stack based virtual machine running the bytecode of `rlox_compiler`, an alternative to the
+//! tree-walking interpreter of `rlox_interpreter` for programs spending their time in loops and
+//! calls.
+
+mod native;
+mod strings;
+mod value;
+mod vm;
+
+pub use native::*;
+pub use strings::InternedString;
+pub use value::*;
+pub use vm::*;
@@ -0,0 +1,82 @@
+use crate::value::Value;
+use crate::vm::Vm;
+use std::fmt::{Debug, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type NativeFuncPtr = fn(&mut Vm, &[Value]) -> Result<Value, String>;
+
+/// Function implemented in Rust, mirroring the natives of the tree-walking interpreter.
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub func: NativeFuncPtr,
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeFunction({:?})", self.name)
+    }
+}
+
+pub mod impls {
+    use super::*;
+
+    pub static CLOCK: NativeFunction = NativeFunction {
+        name: "clock",
+        arity: 0,
+        func: |_vm, _args| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?;
+            Ok(Value::Number(now.as_secs_f64()))
+        },
+    };
+
+    /// `args(i)` returns the i-th argument given to the script, or nil if there is none.
+    pub static ARGS: NativeFunction = NativeFunction {
+        name: "args",
+        arity: 1,
+        func: |vm, args| {
+            let Value::Number(index) = args[0] else {
+                return Err("Expected a number.".to_owned());
+            };
+            let argument = if index >= 0.0 && index.fract() == 0.0 {
+                vm.arguments().get(index as usize)
+            } else {
+                None
+            };
+            let argument = argument.cloned();
+            Ok(argument.map_or(Value::Nil, |a| Value::String(vm.intern(&a))))
+        },
+    };
+
+    /// `input()` returns the next line of stdin without its line ending, or nil at the end.
+    pub static INPUT: NativeFunction = NativeFunction {
+        name: "input",
+        arity: 0,
+        func: |vm, _args| {
+            let mut line = String::new();
+            let read = std::io::stdin()
+                .read_line(&mut line)
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Ok(Value::Nil);
+            }
+            let trimmed = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed);
+            Ok(Value::String(vm.intern(&line)))
+        },
+    };
+
+    /// `memoryStats()` describes what the VM retains, see [`Vm::heap_stats`].
+    pub static MEMORY_STATS: NativeFunction = NativeFunction {
+        name: "memoryStats",
+        arity: 0,
+        func: |vm, _args| {
+            let stats = vm.heap_stats().to_string();
+            Ok(Value::String(vm.intern(&stats)))
+        },
+    };
+
+    pub static ALL_FUNCS: &[&NativeFunction] = &[&CLOCK, &ARGS, &INPUT, &MEMORY_STATS];
+}
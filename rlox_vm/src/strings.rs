@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+/// Number of strings below which the table isn't purged.
+const MIN_PURGE_SIZE: usize = 1024;
+
+/// A string owned by the string table of a [`Vm`](crate::Vm). Equal strings share the same
+/// allocation, so they are compared by pointer.
+#[derive(Clone)]
+pub struct InternedString(Rc<str>);
+
+impl InternedString {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for InternedString {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for InternedString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl Display for InternedString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Every string alive in a VM, from constants as well as from concatenations and natives.
+#[derive(Default)]
+pub(crate) struct StringTable {
+    strings: HashSet<Rc<str>>,
+    // Size at which strings only referenced by the table are dropped
+    purge_size: usize,
+}
+
+impl StringTable {
+    pub(crate) fn intern(&mut self, s: &str) -> InternedString {
+        match self.strings.get(s) {
+            Some(existing) => InternedString(existing.clone()),
+            None => self.insert(s.into()),
+        }
+    }
+
+    /// Same as [`StringTable::intern`] for a string to be kept as a constant, reusing the
+    /// allocation of `s` if it's new.
+    pub(crate) fn intern_rc(&mut self, s: &Rc<str>) -> Rc<str> {
+        match self.strings.get(s) {
+            Some(existing) => existing.clone(),
+            None => self.insert(s.clone()).0,
+        }
+    }
+
+    fn insert(&mut self, s: Rc<str>) -> InternedString {
+        if self.strings.len() >= self.purge_size {
+            self.strings.retain(|s| Rc::strong_count(s) > 1);
+            self.purge_size = (self.strings.len() * 2).max(MIN_PURGE_SIZE);
+        }
+        self.strings.insert(s.clone());
+        InternedString(s)
+    }
+
+    /// Strings referenced by anything other than the table.
+    pub(crate) fn live(&self) -> impl Iterator<Item = &str> {
+        self.strings
+            .iter()
+            .filter(|s| Rc::strong_count(s) > 1)
+            .map(|s| &**s)
+    }
+
+    /// Wraps a string which is known to be in the table.
+    pub(crate) fn interned(s: &Rc<str>) -> InternedString {
+        InternedString(s.clone())
+    }
+}
@@ -0,0 +1,68 @@
+use crate::native::NativeFunction;
+use crate::strings::InternedString;
+use rlox_compiler::FunctionProto;
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+/// A value on the stack of the virtual machine. Its debug representation is what `print` shows,
+/// the same as for the values of the tree-walking interpreter.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    String(InternedString),
+    Boolean(bool),
+    Nil,
+    NativeFunction(&'static NativeFunction),
+    Closure(Rc<Closure>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    /// Name of the type for messages, the same as in the tree-walking interpreter.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::NativeFunction(_) | Value::Closure(_) => "function",
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => std::ptr::eq(*l, *r),
+            (Value::Closure(l), Value::Closure(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+/// A variable captured by a closure. It stays on the stack while the function declaring it runs,
+/// and is moved into the upvalue when it goes out of scope.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Debug for Closure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Closure({:?})", self.function.name)
+    }
+}
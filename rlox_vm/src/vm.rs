@@ -0,0 +1,523 @@
+use crate::native::impls;
+use crate::strings::{InternedString, StringTable};
+use crate::value::{Closure, Upvalue, Value};
+use rlox_compiler::{Compiler, Constant, FunctionProto, OpCode};
+use rlox_interpreter::{HeapStats, Printer, Resolver, Scope, ScopePtr};
+use rlox_parser::{optimize, Parser, Scanner};
+use rlox_syntax::{codes, Diagnostic, LoxError};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// Maximum number of nested calls, beyond which the script is aborted.
+const FRAMES_MAX: usize = 1024;
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Index of the slot holding the function being called
+    base: usize,
+}
+
+/// Runs compiled scripts, keeping the global variables from one script to the next.
+///
+/// ```
+/// use rlox_interpreter::StdOutPrinter;
+/// use rlox_vm::{Value, Vm};
+///
+/// let mut printer = StdOutPrinter;
+/// let mut vm = Vm::new(&mut printer);
+/// vm.run("var a = 0; while (a < 10) a = a + 1;").unwrap();
+/// assert_eq!(Some(Value::Number(10.0)), vm.get_global("a"));
+/// ```
+pub struct Vm<'p> {
+    printer: &'p mut dyn Printer,
+    stack: Vec<Value>,
+    // Callers of the running function, which is kept out of this list while it runs
+    frames: Vec<CallFrame>,
+    // Values of the global variables by slot, None until they are defined
+    globals: Vec<Option<Value>>,
+    global_names: Vec<Rc<str>>,
+    global_slots: HashMap<Rc<str>, usize>,
+    // Upvalues pointing to the stack, to be closed when their variables go out of scope
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    strings: StringTable,
+    arguments: Vec<String>,
+    // Global scope of the resolver, used by `run`
+    scope: ScopePtr,
+}
+
+impl<'p> Vm<'p> {
+    /// Creates a VM with the native functions defined as globals.
+    pub fn new(printer: &'p mut dyn Printer) -> Self {
+        let mut vm = Self::without_stdlib(printer);
+        vm.scope = Scope::new_globals_ptr();
+        for f in impls::ALL_FUNCS {
+            let slot = vm.global_slot(f.name);
+            vm.globals[slot] = Some(Value::NativeFunction(f));
+        }
+        vm
+    }
+
+    /// Creates a VM with no global variables.
+    pub fn without_stdlib(printer: &'p mut dyn Printer) -> Self {
+        Self {
+            printer,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: Vec::new(),
+            global_names: Vec::new(),
+            global_slots: HashMap::new(),
+            open_upvalues: Vec::new(),
+            strings: StringTable::default(),
+            arguments: Vec::new(),
+            scope: Scope::new_ptr(None),
+        }
+    }
+
+    /// Sets the values returned by the `args` native function.
+    pub fn set_arguments(&mut self, arguments: Vec<String>) {
+        self.arguments = arguments;
+    }
+
+    pub fn arguments(&self) -> &[String] {
+        &self.arguments
+    }
+
+    /// The string equal to `s` in the string table of this VM, which new string values must be.
+    pub fn intern(&mut self, s: &str) -> InternedString {
+        self.strings.intern(s)
+    }
+
+    /// What the VM retains, in the terms of the tree-walking interpreter: the globals are its
+    /// only environment, closures capturing single variables instead, and strings are counted
+    /// among the interned ones.
+    pub fn heap_stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            environments: 1,
+            ..HeapStats::default()
+        };
+        for value in self.globals.iter().flatten() {
+            *stats.values.entry(value.type_name()).or_default() += 1;
+        }
+        for s in self.strings.live() {
+            stats.strings += 1;
+            stats.string_bytes += s.len();
+        }
+        stats
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let slot = *self.global_slots.get(name)?;
+        self.globals[slot].clone()
+    }
+
+    /// Scans, parses, resolves, compiles and runs `source`.
+    pub fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let mut statements = Parser::new(tokens).parse()?;
+        for s in &mut statements {
+            Resolver.resolve_statement(&self.scope, s)?;
+        }
+        optimize(&mut statements);
+        self.interpret(Compiler::compile(&statements)?)
+    }
+
+    /// Runs a script compiled by [`Compiler::compile`].
+    pub fn interpret(&mut self, mut script: FunctionProto) -> Result<(), LoxError> {
+        self.link(&mut script);
+        let closure = Rc::new(Closure {
+            function: Rc::new(script),
+            upvalues: Vec::new(),
+        });
+        let frame = CallFrame {
+            closure: closure.clone(),
+            ip: 0,
+            base: self.stack.len(),
+        };
+        self.stack.push(Value::Closure(closure));
+
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| self.execute(frame))) {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                let diagnostic =
+                    Diagnostic::new(Default::default(), format!("Internal error: {message}"))
+                        .with_code(codes::INTERNAL_ERROR);
+                Err(LoxError::Internal(Box::new(diagnostic)))
+            }
+        };
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn execute(&mut self, mut frame: CallFrame) -> Result<(), LoxError> {
+        loop {
+            let op = frame.closure.function.chunk.code[frame.ip];
+            frame.ip += 1;
+            match op {
+                OpCode::Constant(index) => {
+                    let value = match &frame.closure.function.chunk.constants[index] {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::String(s) => Value::String(StringTable::interned(s)),
+                        Constant::Function(f) => {
+                            panic!("function {} must be loaded as a closure", f.name)
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal(slot) => {
+                    let value = self.stack[frame.base + slot].clone();
+                    self.stack.push(value);
+                }
+                OpCode::SetLocal(slot) => {
+                    self.stack[frame.base + slot] = self.peek().clone();
+                }
+                OpCode::GetGlobal(slot) => match &self.globals[slot] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(self.undefined_global(&frame, slot)),
+                },
+                OpCode::DefineGlobal(slot) => {
+                    self.globals[slot] = Some(self.pop());
+                }
+                OpCode::SetGlobal(slot) => {
+                    let value = self.peek().clone();
+                    match &mut self.globals[slot] {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined_global(&frame, slot)),
+                    }
+                }
+                OpCode::GetUpvalue(index) => {
+                    let value = match &*frame.closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue(index) => {
+                    let value = self.peek().clone();
+                    match &mut *frame.closure.upvalues[index].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left == right));
+                }
+                OpCode::NotEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left != right));
+                }
+                OpCode::Greater => self.comparison(&frame, Ordering::is_gt)?,
+                OpCode::GreaterEqual => self.comparison(&frame, Ordering::is_ge)?,
+                OpCode::Less => self.comparison(&frame, Ordering::is_lt)?,
+                OpCode::LessEqual => self.comparison(&frame, Ordering::is_le)?,
+                OpCode::Subtract => self.binary(&frame, |l, r| Value::Number(l - r))?,
+                OpCode::Multiply => self.binary(&frame, |l, r| Value::Number(l * r))?,
+                OpCode::Divide => {
+                    if let Value::Number(r) = self.peek() {
+                        if *r == 0.0 {
+                            return Err(Self::runtime_error(
+                                &frame,
+                                codes::DIVISION_BY_ZERO,
+                                "Divided by zero",
+                            ));
+                        }
+                    }
+                    self.binary(&frame, |l, r| Value::Number(l / r))?;
+                }
+                OpCode::Add => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = match (left, right) {
+                        (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+                        (Value::String(l), Value::String(r)) => {
+                            Value::String(self.strings.intern(&format!("{l}{r}")))
+                        }
+                        _ => {
+                            let message = "Operands must be two numbers or two strings.";
+                            return Err(Self::runtime_error(
+                                &frame,
+                                codes::INVALID_OPERANDS,
+                                message,
+                            ));
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Truthy => {
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(value.is_truthy()));
+                }
+                OpCode::Negate => match self.pop() {
+                    Value::Number(n) => self.stack.push(Value::Number(-n)),
+                    _ => {
+                        let message = "Operand must be a number.";
+                        return Err(Self::runtime_error(
+                            &frame,
+                            codes::INVALID_OPERANDS,
+                            message,
+                        ));
+                    }
+                },
+                OpCode::Print(count) => {
+                    let values = self.stack.split_off(self.stack.len() - count);
+                    let line: Vec<_> = values.iter().map(|v| format!("{v:?}")).collect();
+                    self.printer.print(&line.join(" "));
+                }
+                OpCode::Jump(target) => frame.ip = target,
+                OpCode::JumpIfFalse(target) => {
+                    if !self.peek().is_truthy() {
+                        frame.ip = target;
+                    }
+                }
+                OpCode::Call(count) => self.call(&mut frame, count)?,
+                OpCode::Closure(index) => {
+                    let Constant::Function(function) =
+                        &frame.closure.function.chunk.constants[index]
+                    else {
+                        panic!("closure of a constant which isn't a function");
+                    };
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|upvalue| {
+                            if upvalue.is_local {
+                                self.capture_upvalue(frame.base + upvalue.index)
+                            } else {
+                                frame.closure.upvalues[upvalue.index].clone()
+                            }
+                        })
+                        .collect();
+                    let closure = Closure {
+                        function: function.clone(),
+                        upvalues,
+                    };
+                    self.stack.push(Value::Closure(Rc::new(closure)));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            self.stack.push(result);
+                        }
+                        None => return Ok(()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls the value below the top `count` values of the stack, making `frame` the frame of the
+    /// callee if it's a Lox function.
+    fn call(&mut self, frame: &mut CallFrame, count: usize) -> Result<(), LoxError> {
+        let callee_slot = self.stack.len() - 1 - count;
+        match &self.stack[callee_slot] {
+            Value::Closure(closure) => {
+                let arity = closure.function.arity;
+                if count != arity {
+                    let message = format!("Expected {arity} arguments but got {count}.");
+                    return Err(Self::runtime_error(frame, codes::RUNTIME_ERROR, message));
+                }
+                if self.frames.len() >= FRAMES_MAX {
+                    return Err(Self::runtime_error(
+                        frame,
+                        codes::STACK_OVERFLOW,
+                        "Stack overflow.",
+                    ));
+                }
+                let callee = CallFrame {
+                    closure: closure.clone(),
+                    ip: 0,
+                    base: callee_slot,
+                };
+                self.frames.push(std::mem::replace(frame, callee));
+            }
+            Value::NativeFunction(native) => {
+                let native = *native;
+                if count != native.arity {
+                    let message = format!("Expected {} arguments but got {count}.", native.arity);
+                    return Err(Self::runtime_error(frame, codes::RUNTIME_ERROR, message));
+                }
+                let args = self.stack.split_off(callee_slot + 1);
+                self.pop();
+                let result = (native.func)(self, &args)
+                    .map_err(|m| Self::runtime_error(frame, codes::RUNTIME_ERROR, m))?;
+                self.stack.push(result);
+            }
+            _ => {
+                return Err(Self::runtime_error(
+                    frame,
+                    codes::NOT_CALLABLE,
+                    "Only function types can be called.",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Pops two numbers and pushes the result of `op` on them.
+    fn binary(&mut self, frame: &CallFrame, op: fn(f64, f64) -> Value) -> Result<(), LoxError> {
+        let right = self.pop();
+        let left = self.pop();
+        match (left, right) {
+            (Value::Number(l), Value::Number(r)) => {
+                self.stack.push(op(l, r));
+                Ok(())
+            }
+            _ => {
+                let message = "Operands must be numbers.";
+                Err(Self::runtime_error(frame, codes::INVALID_OPERANDS, message))
+            }
+        }
+    }
+
+    /// Compares two numbers, or two strings by their characters as the tree-walking interpreter
+    /// does. Numbers which aren't ordered, such as NaN, compare false.
+    fn comparison(&mut self, frame: &CallFrame, op: fn(Ordering) -> bool) -> Result<(), LoxError> {
+        let right = self.pop();
+        let left = self.pop();
+        let ordering = match (left, right) {
+            (Value::Number(l), Value::Number(r)) => l.partial_cmp(&r),
+            (Value::String(l), Value::String(r)) => Some(l.as_str().cmp(r.as_str())),
+            _ => {
+                let message = "Operands must be two numbers or two strings.";
+                return Err(Self::runtime_error(frame, codes::INVALID_OPERANDS, message));
+            }
+        };
+        self.stack.push(Value::Boolean(ordering.is_some_and(op)));
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(s) if s == slot));
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    /// Moves the variables from `first_slot` up into the upvalues which capture them.
+    fn close_upvalues(&mut self, first_slot: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= first_slot => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    /// Replaces the name constants of the global variable instructions in `function` and the
+    /// functions it defines with the slots of the variables, so that they aren't looked up by
+    /// name at runtime. String constants are moved to the string table.
+    fn link(&mut self, function: &mut FunctionProto) {
+        let chunk = &mut function.chunk;
+        for op in &mut chunk.code {
+            if let OpCode::GetGlobal(index)
+            | OpCode::DefineGlobal(index)
+            | OpCode::SetGlobal(index) = op
+            {
+                let Constant::String(name) = &chunk.constants[*index] else {
+                    panic!(
+                        "variable name is not a string: {:?}",
+                        chunk.constants[*index]
+                    );
+                };
+                *index = self.global_slot(name);
+            }
+        }
+        for constant in &mut chunk.constants {
+            match constant {
+                Constant::String(s) => *s = self.strings.intern_rc(s),
+                Constant::Function(f) => self.link(Rc::make_mut(f)),
+                Constant::Number(_) => {}
+            }
+        }
+    }
+
+    fn global_slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.global_slots.get(name) {
+            return slot;
+        }
+        let name: Rc<str> = name.into();
+        self.globals.push(None);
+        self.global_names.push(name.clone());
+        self.global_slots.insert(name, self.globals.len() - 1);
+        self.globals.len() - 1
+    }
+
+    fn undefined_global(&self, frame: &CallFrame, slot: usize) -> LoxError {
+        let name = &self.global_names[slot];
+        let message = format!("Undefined variable '{name}'.");
+        let diagnostic = Self::runtime_diagnostic(frame, codes::UNDEFINED_VARIABLE, message);
+        let defined = self
+            .global_names
+            .iter()
+            .zip(&self.globals)
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| &**name);
+        LoxError::Runtime(Box::new(diagnostic.with_name_suggestion(name, defined)))
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("stack underflow")
+    }
+
+    /// Error of the instruction which has just been read.
+    fn runtime_error(
+        frame: &CallFrame,
+        code: &'static str,
+        message: impl Into<String>,
+    ) -> LoxError {
+        LoxError::Runtime(Box::new(Self::runtime_diagnostic(frame, code, message)))
+    }
+
+    fn runtime_diagnostic(
+        frame: &CallFrame,
+        code: &'static str,
+        message: impl Into<String>,
+    ) -> Diagnostic {
+        let span = frame.closure.function.chunk.spans[frame.ip - 1];
+        Diagnostic::new(span, message).with_code(code)
+    }
+}
@@ -0,0 +1,190 @@
+use rlox_interpreter::{Lox, LoxError, Printer};
+use rlox_syntax::codes;
+use rlox_vm::{Value, Vm};
+
+struct TestPrinter {
+    messages: Vec<String>,
+}
+
+impl Printer for TestPrinter {
+    fn print(&mut self, message: &str) {
+        self.messages.push(message.to_owned());
+    }
+}
+
+fn run_vm(source: &str) -> (Vec<String>, Result<(), LoxError>) {
+    let mut printer = TestPrinter { messages: vec![] };
+    let result = Vm::new(&mut printer).run(source);
+    (printer.messages, result)
+}
+
+/// Checks that the VM prints what the tree-walking interpreter does.
+fn assert_same_output(source: &str) {
+    let mut printer = TestPrinter { messages: vec![] };
+    Lox::with_printer(&mut printer).run(source).unwrap();
+    let (messages, result) = run_vm(source);
+    result.unwrap();
+    assert_eq!(printer.messages, messages);
+}
+
+#[test]
+fn test_arithmetic_and_strings() {
+    assert_same_output(r#"print 1 + 2 * 3 - 4 / 2; print "a" + "b"; print 1 < 2 == true;"#);
+    assert_same_output("print nil == false; print !nil; print -(1 + 2);");
+    assert_same_output(r#"var a = 1; print "a is", a, a + 1;"#);
+    assert_same_output(
+        r#"print "a" < "b", "apple" <= "apple", "b" > "abc", "Z" >= "a", "" < "a";"#,
+    );
+}
+
+#[test]
+fn test_control_flow() {
+    assert_same_output(
+        r"
+var sum = 0;
+var i = 0;
+while (i < 10) {
+    if (i > 5 and i != 8) sum = sum + i; else sum = sum - 1;
+    i = i + 1;
+}
+print sum;
+print nil or 3;
+print 1 and false;
+",
+    );
+}
+
+#[test]
+fn test_functions_and_closures() {
+    assert_same_output(
+        r"
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+print fib(15);
+
+fun counter() {
+    var c = 0;
+    fun increment() {
+        c = c + 1;
+        return c;
+    }
+    return increment;
+}
+var first = counter();
+var second = counter();
+first();
+print first();
+print second();
+
+{
+    var shared = 1;
+    fun get() { return shared; }
+    fun set(v) { shared = v; }
+    set(5);
+    print get();
+}
+",
+    );
+}
+
+#[test]
+fn test_lambdas() {
+    assert_same_output(
+        r"
+fun adder(n) {
+    return fun (x) -> x + n;
+}
+print adder(1)(2);
+var twice = fun (f, x) -> f(f(x));
+print twice(fun (x) -> x * 2, 3);
+",
+    );
+}
+
+#[test]
+fn test_heap_stats() {
+    let (messages, result) = run_vm(r#"var a = "hello"; var b = a + "!"; print memoryStats();"#);
+    result.unwrap();
+    // The strings include the constants and the global names of the script.
+    assert_eq!(
+        vec![r#"String("environments: 1, strings: 6 (25 bytes), values: function 4, string 2")"#],
+        messages
+    );
+}
+
+#[test]
+fn test_runtime_errors() {
+    let (messages, result) = run_vm("print 1;\nprint 1 + nil;");
+    assert_eq!(vec!["Number(1.0)"], messages);
+    let error = result.unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+    assert_eq!(2, error.span().line);
+
+    let (_, result) = run_vm("fun f(a) {}\nf();");
+    assert_eq!(
+        "Expected 1 arguments but got 0.",
+        result.unwrap_err().diagnostic().message
+    );
+    let (_, result) = run_vm("fun f() { f(); }\nf();");
+    assert_eq!("Stack overflow.", result.unwrap_err().diagnostic().message);
+    let (_, result) = run_vm(r#"print "a" < 1;"#);
+    assert_eq!(
+        Some(codes::INVALID_OPERANDS),
+        result.unwrap_err().diagnostic().code
+    );
+    let (_, result) = run_vm("print undefined;");
+    assert!(matches!(result, Err(LoxError::Resolve(_))));
+}
+
+#[test]
+fn test_unsupported() {
+    // Rejected as a whole, before anything runs
+    let (messages, result) = run_vm("print 1;\nclass A {}");
+    assert!(messages.is_empty());
+    let error = result.unwrap_err();
+    assert!(matches!(error, LoxError::Compile(_)));
+    assert_eq!(Some(codes::UNSUPPORTED_BY_VM), error.diagnostic().code);
+    assert_eq!(2, error.span().line);
+
+    let (_, result) = run_vm("fun log(...args) {}");
+    let error = result.unwrap_err();
+    assert_eq!(
+        "Rest parameters are not supported by the VM.",
+        error.diagnostic().message
+    );
+
+    let (_, result) = run_vm("print [1][0];");
+    let error = result.unwrap_err();
+    assert_eq!(
+        "Lists are not supported by the VM.",
+        error.diagnostic().message
+    );
+}
+
+#[test]
+fn test_globals_persist() {
+    let mut printer = TestPrinter { messages: vec![] };
+    let mut vm = Vm::new(&mut printer);
+    vm.run("var a = 1;").unwrap();
+    assert!(vm.run("a = a + nil;").is_err());
+    vm.run("print a + 1;").unwrap();
+    drop(vm);
+    assert_eq!(vec!["Number(2.0)"], printer.messages);
+}
+
+#[test]
+fn test_interned_strings() {
+    assert_same_output(r#"var a = "a"; var b = a + "b"; print b == "ab"; print b != "a" + "b";"#);
+
+    let mut printer = TestPrinter { messages: vec![] };
+    let mut vm = Vm::new(&mut printer);
+    vm.run(r#"var a = "x" + "y"; var b = "xy";"#).unwrap();
+    let (Some(Value::String(a)), Some(Value::String(b))) = (vm.get_global("a"), vm.get_global("b"))
+    else {
+        panic!("strings are not defined");
+    };
+    assert_eq!(a, b);
+    assert_eq!(vm.intern("xy"), a);
+}
@@ -0,0 +1,118 @@
+//! Entry points for running the interpreter in a browser playground, built with
+//! `cargo build -p rlox_wasm --target wasm32-unknown-unknown --release`.
+//!
+//! The module exports a plain C ABI rather than wasm-bindgen glue, so it can be loaded with
+//! nothing but `WebAssembly.instantiate`:
+//!
+//! ```js
+//! const bytes = new TextEncoder().encode(source);
+//! const ptr = rlox_alloc(bytes.length);
+//! new Uint8Array(memory.buffer, ptr, bytes.length).set(bytes);
+//! const len = rlox_run(ptr, bytes.length);
+//! const output = new TextDecoder().decode(new Uint8Array(memory.buffer, rlox_output(), len));
+//! ```
+
+use rlox_interpreter::{highlight_html, Lox, WritePrinter};
+use std::cell::RefCell;
+
+const SOURCE_NAME: &str = "<playground>";
+
+/// Runs `source`, returning what it printed followed by the error which stopped it, if any.
+pub fn run(source: &str) -> String {
+    let mut printer = WritePrinter::new(Vec::new());
+    let result = Lox::with_printer(&mut printer).run(source);
+    let mut output = String::from_utf8_lossy(printer.get_ref()).into_owned();
+    if let Err(e) = result {
+        output.push_str(&e.diagnostic().render(SOURCE_NAME, source));
+        output.push('\n');
+    }
+    output
+}
+
+thread_local! {
+    // Output of the last `rlox_run`, kept until the next one so that the host can read it
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Allocates `len` bytes for the host to write the source into.
+#[no_mangle]
+pub extern "C" fn rlox_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Frees memory returned by [`rlox_alloc`].
+///
+/// # Safety
+///
+/// `ptr` must come from `rlox_alloc(len)` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rlox_dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Runs the UTF-8 source at `ptr`, returning the length of the output in bytes. The output
+/// itself is at [`rlox_output`].
+///
+/// # Safety
+///
+/// `ptr` must point to `len` initialized bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rlox_run(ptr: *const u8, len: usize) -> usize {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let output = run(&String::from_utf8_lossy(bytes));
+    OUTPUT.with(|o| {
+        *o.borrow_mut() = output;
+        o.borrow().len()
+    })
+}
+
+/// Highlights the UTF-8 source at `ptr` as HTML, like [`rlox_run`] but without running it.
+/// The output is empty if the source doesn't scan.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` initialized bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rlox_highlight(ptr: *const u8, len: usize) -> usize {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let output = highlight_html(&String::from_utf8_lossy(bytes)).unwrap_or_default();
+    OUTPUT.with(|o| {
+        *o.borrow_mut() = output;
+        o.borrow().len()
+    })
+}
+
+/// Address of the output of the last [`rlox_run`] or [`rlox_highlight`].
+#[no_mangle]
+pub extern "C" fn rlox_output() -> *const u8 {
+    OUTPUT.with(|o| o.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        assert_eq!("Number(3.0)\n", run("print 1 + 2;"));
+        let output = run("print 1;\nprint nil + 1;");
+        assert!(output.starts_with("Number(1.0)\nerror[L0302]: Unsupported binary operator"));
+        assert!(output.contains(" --> <playground>:2:11\n"));
+    }
+
+    #[test]
+    fn test_exports() {
+        let source = b"print true;";
+        unsafe {
+            let ptr = rlox_alloc(source.len());
+            std::ptr::copy_nonoverlapping(source.as_ptr(), ptr, source.len());
+            let len = rlox_run(ptr, source.len());
+            rlox_dealloc(ptr, source.len());
+            let output = std::slice::from_raw_parts(rlox_output(), len);
+            assert_eq!(b"Boolean(true)\n", output);
+        }
+    }
+}